@@ -0,0 +1,434 @@
+//! Loading of dex files from APK, JAR and other ZIP archives.
+//!
+//! Only the `classes.dex`, `classes2.dex`... entries at the root of the archive are read, in
+//! multidex order, as the runtime does. Entries can be stored or deflated.
+
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use miniz_oxide::inflate::decompress_to_vec_with_limit;
+
+use Dex;
+use error::*;
+use set::{ClassLocation, DexSet};
+use types::Type;
+
+/// Signature of the end of central directory record.
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+/// Size of the end of central directory record, without the comment.
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+/// Signature of a central directory file header.
+const CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0201_4b50;
+/// Signature of a local file header.
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+/// Size of a local file header, without the file name and the extra field.
+const LOCAL_HEADER_SIZE: usize = 30;
+
+/// Compression method of stored entries.
+const METHOD_STORED: u16 = 0;
+/// Compression method of deflated entries.
+const METHOD_DEFLATED: u16 = 8;
+
+/// Dex files of an archive, with the entry each of them came from.
+#[derive(Debug)]
+pub struct DexArchive {
+    set: DexSet<'static>,
+    entries: Vec<String>,
+}
+
+impl DexArchive {
+    /// Loads the dex files of the archive at the given path.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<DexArchive> {
+        let bytes = fs::read(path).chain_err(|| "could not read archive")?;
+        DexArchive::from_bytes(&bytes)
+    }
+
+    /// Loads the dex files of the archive in the given bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<DexArchive> {
+        let entries = read_central_directory(bytes)?;
+
+        let mut set = DexSet::new();
+        let mut dex_entries = Vec::new();
+        for number in 1.. {
+            let name = if number == 1 {
+                "classes.dex".to_owned()
+            } else {
+                format!("classes{}.dex", number)
+            };
+            let entry = match entries.iter().find(|entry| entry.name == name) {
+                Some(entry) => entry,
+                None => break,
+            };
+
+            for dex in Dex::all_from_contents(entry.read(bytes)?)
+                .chain_err(|| format!("could not read dex file in archive entry {}", name))?
+            {
+                set.add(dex)?;
+                dex_entries.push(name.clone());
+            }
+        }
+
+        if dex_entries.is_empty() {
+            return Err(ErrorKind::MissingArchiveEntry("classes.dex".to_owned()).into());
+        }
+        Ok(DexArchive {
+            set,
+            entries: dex_entries,
+        })
+    }
+
+    /// Gets the set of dex files of the archive, in multidex order.
+    pub fn set(&self) -> &DexSet<'static> {
+        &self.set
+    }
+
+    /// Converts the archive into its set of dex files.
+    pub fn into_set(self) -> DexSet<'static> {
+        self.set
+    }
+
+    /// Gets the name of the archive entry of the dex file at the given index of the set.
+    pub fn entry_name(&self, dex_index: usize) -> Option<&str> {
+        self.entries.get(dex_index).map(String::as_str)
+    }
+
+    /// Gets the name of the archive entry that defines the class at the given location.
+    pub fn class_entry(&self, location: ClassLocation) -> Option<&str> {
+        self.entry_name(location.dex_index())
+    }
+
+    /// Finds the name of the archive entry that defines the given class type, if any.
+    pub fn find_class_entry(&self, class_type: &Type<'static>) -> Option<&str> {
+        self.set
+            .find_class(class_type)
+            .and_then(|location| self.class_entry(location))
+    }
+}
+
+/// File entry of the central directory of an archive.
+#[derive(Debug)]
+struct Entry {
+    name: String,
+    flags: u16,
+    method: u16,
+    crc: u32,
+    compressed_size: u32,
+    uncompressed_size: u32,
+    local_header_offset: u32,
+}
+
+impl Entry {
+    /// Reads the uncompressed contents of the entry from the archive.
+    fn read(&self, archive: &[u8]) -> Result<Vec<u8>> {
+        if self.flags & 0b1 != 0 {
+            let error = format!("entry {} is encrypted", self.name);
+            return Err(ErrorKind::InvalidArchive(error).into());
+        }
+
+        let offset = self.local_header_offset as usize;
+        let mut cursor = Cursor::new(archive);
+        cursor.set_position(offset as u64);
+        if read_u32(&mut cursor)? != LOCAL_HEADER_SIGNATURE {
+            return Err(ErrorKind::InvalidArchive(format!(
+                "invalid local header for entry {}",
+                self.name
+            )).into());
+        }
+        cursor.set_position((offset + 26) as u64);
+        let name_size = usize::from(read_u16(&mut cursor)?);
+        let extra_size = usize::from(read_u16(&mut cursor)?);
+
+        let start = offset + LOCAL_HEADER_SIZE + name_size + extra_size;
+        let data = archive
+            .get(start..start + self.compressed_size as usize)
+            .ok_or_else(|| {
+                Error::from(ErrorKind::InvalidArchive(format!(
+                    "entry {} is out of bounds",
+                    self.name
+                )))
+            })?;
+
+        let contents = match self.method {
+            METHOD_STORED => data.to_vec(),
+            METHOD_DEFLATED => decompress_to_vec_with_limit(data, self.uncompressed_size as usize)
+                .map_err(|_| {
+                    Error::from(ErrorKind::InvalidArchive(format!(
+                        "could not inflate entry {}",
+                        self.name
+                    )))
+                })?,
+            method => {
+                return Err(ErrorKind::UnsupportedCompression(self.name.clone(), method).into())
+            }
+        };
+
+        let crc = crc32(&contents);
+        if contents.len() != self.uncompressed_size as usize || crc != self.crc {
+            return Err(ErrorKind::ArchiveCrcMismatch(self.name.clone(), self.crc, crc).into());
+        }
+        Ok(contents)
+    }
+}
+
+/// Reads the file entries of the central directory of the archive.
+fn read_central_directory(archive: &[u8]) -> Result<Vec<Entry>> {
+    // The end of central directory record is at the end, followed by a comment of up to 64 KiB.
+    let end_offset = (0..archive.len().saturating_sub(END_OF_CENTRAL_DIRECTORY_SIZE - 1))
+        .rev()
+        .take(usize::from(u16::MAX) + 1)
+        .find(|&offset| archive[offset..offset + 4] == [0x50, 0x4b, 0x05, 0x06])
+        .ok_or_else(|| {
+            Error::from(ErrorKind::InvalidArchive(
+                "could not find the end of central directory".to_owned(),
+            ))
+        })?;
+
+    let mut cursor = Cursor::new(archive);
+    cursor.set_position(end_offset as u64);
+    if read_u32(&mut cursor)? != END_OF_CENTRAL_DIRECTORY_SIGNATURE {
+        return Err(ErrorKind::InvalidArchive(format!(
+            "invalid end of central directory at offset {:#010x}",
+            end_offset
+        )).into());
+    }
+    cursor.set_position(end_offset as u64 + 10);
+    let entry_count = read_u16(&mut cursor)?;
+    let _directory_size = read_u32(&mut cursor)?;
+    let directory_offset = read_u32(&mut cursor)?;
+    if entry_count == u16::MAX || directory_offset == u32::MAX {
+        return Err(ErrorKind::InvalidArchive("ZIP64 archives are not supported".to_owned()).into());
+    }
+
+    cursor.set_position(u64::from(directory_offset));
+    let mut entries = Vec::with_capacity(usize::from(entry_count));
+    for _ in 0..entry_count {
+        let entry_offset = cursor.position();
+        if read_u32(&mut cursor)? != CENTRAL_DIRECTORY_SIGNATURE {
+            return Err(ErrorKind::InvalidArchive(format!(
+                "invalid central directory header at offset {:#010x}",
+                entry_offset
+            )).into());
+        }
+        cursor.set_position(entry_offset + 8);
+        let flags = read_u16(&mut cursor)?;
+        let method = read_u16(&mut cursor)?;
+        let _time = read_u16(&mut cursor)?;
+        let _date = read_u16(&mut cursor)?;
+        let crc = read_u32(&mut cursor)?;
+        let compressed_size = read_u32(&mut cursor)?;
+        let uncompressed_size = read_u32(&mut cursor)?;
+        let name_size = usize::from(read_u16(&mut cursor)?);
+        let extra_size = read_u16(&mut cursor)?;
+        let comment_size = read_u16(&mut cursor)?;
+        cursor.set_position(entry_offset + 42);
+        let local_header_offset = read_u32(&mut cursor)?;
+
+        let name_start = cursor.position() as usize;
+        let name = archive
+            .get(name_start..name_start + name_size)
+            .ok_or_else(|| {
+                Error::from(ErrorKind::InvalidArchive(format!(
+                    "entry name at offset {:#010x} is out of bounds",
+                    name_start
+                )))
+            })?;
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            flags,
+            method,
+            crc,
+            compressed_size,
+            uncompressed_size,
+            local_header_offset,
+        });
+        cursor.set_position(
+            (name_start + name_size) as u64 + u64::from(extra_size) + u64::from(comment_size),
+        );
+    }
+
+    Ok(entries)
+}
+
+fn read_u16(cursor: &mut Cursor<&[u8]>) -> Result<u16> {
+    let offset = cursor.position();
+    cursor.read_u16::<LittleEndian>().chain_err(|| {
+        ErrorKind::InvalidArchive(format!("unexpected end of archive at offset {:#010x}", offset))
+    })
+}
+
+fn read_u32(cursor: &mut Cursor<&[u8]>) -> Result<u32> {
+    let offset = cursor.position();
+    cursor.read_u32::<LittleEndian>().chain_err(|| {
+        ErrorKind::InvalidArchive(format!("unexpected end of archive at offset {:#010x}", offset))
+    })
+}
+
+/// Lookup table of the CRC-32 used by ZIP archives.
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+/// Computes the CRC-32 of the given data.
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| {
+        CRC_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miniz_oxide::deflate::compress_to_vec;
+
+    /// Builds a ZIP archive with the given entries, deflating the ones marked to be deflated.
+    fn build_archive(entries: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut archive = Vec::new();
+        let mut directory = Vec::new();
+        for &(name, contents, deflate) in entries {
+            let (method, data) = if deflate {
+                (METHOD_DEFLATED, compress_to_vec(contents, 6))
+            } else {
+                (METHOD_STORED, contents.to_vec())
+            };
+            let offset = archive.len() as u32;
+            push_u32(&mut archive, LOCAL_HEADER_SIGNATURE);
+            push_u16(&mut archive, 20);
+            push_u16(&mut archive, 0);
+            push_u16(&mut archive, method);
+            push_u32(&mut archive, 0);
+            push_u32(&mut archive, crc32(contents));
+            push_u32(&mut archive, data.len() as u32);
+            push_u32(&mut archive, contents.len() as u32);
+            push_u16(&mut archive, name.len() as u16);
+            push_u16(&mut archive, 0);
+            archive.extend_from_slice(name.as_bytes());
+            archive.extend_from_slice(&data);
+
+            push_u32(&mut directory, CENTRAL_DIRECTORY_SIGNATURE);
+            push_u16(&mut directory, 20);
+            push_u16(&mut directory, 20);
+            push_u16(&mut directory, 0);
+            push_u16(&mut directory, method);
+            push_u32(&mut directory, 0);
+            push_u32(&mut directory, crc32(contents));
+            push_u32(&mut directory, data.len() as u32);
+            push_u32(&mut directory, contents.len() as u32);
+            push_u16(&mut directory, name.len() as u16);
+            push_u16(&mut directory, 0);
+            push_u16(&mut directory, 0);
+            push_u16(&mut directory, 0);
+            push_u16(&mut directory, 0);
+            push_u32(&mut directory, 0);
+            push_u32(&mut directory, offset);
+            directory.extend_from_slice(name.as_bytes());
+        }
+
+        let directory_offset = archive.len() as u32;
+        archive.extend_from_slice(&directory);
+        push_u32(&mut archive, END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+        push_u16(&mut archive, 0);
+        push_u16(&mut archive, 0);
+        push_u16(&mut archive, entries.len() as u16);
+        push_u16(&mut archive, entries.len() as u16);
+        push_u32(&mut archive, directory.len() as u32);
+        push_u32(&mut archive, directory_offset);
+        push_u16(&mut archive, 0);
+        archive
+    }
+
+    #[test]
+    fn it_computes_crc32() {
+        assert_eq!(0, crc32(b""));
+        assert_eq!(0xcbf4_3926, crc32(b"123456789"));
+    }
+
+    #[test]
+    fn it_reads_stored_and_deflated_entries() {
+        let archive = build_archive(&[
+            ("stored.txt", b"stored contents", false),
+            ("deflated.txt", b"deflated deflated deflated contents", true),
+        ]);
+
+        let entries = read_central_directory(&archive).unwrap();
+
+        assert_eq!(2, entries.len());
+        assert_eq!("stored.txt", entries[0].name);
+        assert_eq!(b"stored contents".to_vec(), entries[0].read(&archive).unwrap());
+        assert_eq!("deflated.txt", entries[1].name);
+        assert_eq!(
+            b"deflated deflated deflated contents".to_vec(),
+            entries[1].read(&archive).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_loads_dex_files_in_multidex_order() {
+        let dex = fs::read("test.dex").unwrap();
+        let archive = build_archive(&[
+            ("AndroidManifest.xml", b"<manifest/>", false),
+            ("classes2.dex", &dex, true),
+            ("classes.dex", &dex, false),
+            ("classes4.dex", &dex, false),
+        ]);
+
+        let archive = DexArchive::from_bytes(&archive).unwrap();
+
+        // `classes4.dex` is not loaded, since there is no `classes3.dex`.
+        assert_eq!(2, archive.set().dexes().len());
+        assert_eq!(Some("classes.dex"), archive.entry_name(0));
+        assert_eq!(Some("classes2.dex"), archive.entry_name(1));
+        assert_eq!(None, archive.entry_name(2));
+
+        // Both files define the same classes, and the first definition wins.
+        let second = &archive.set().dexes()[1];
+        let class_index = second.get_class(0).unwrap().unwrap().class_index();
+        let class_type = second.get_type(class_index).unwrap();
+        assert_eq!(Some("classes.dex"), archive.find_class_entry(class_type));
+    }
+
+    #[test]
+    fn it_rejects_archives_without_dex_files() {
+        let archive = build_archive(&[("AndroidManifest.xml", b"<manifest/>", false)]);
+
+        let error = DexArchive::from_bytes(&archive).unwrap_err();
+
+        assert!(matches!(error.kind(), ErrorKind::MissingArchiveEntry(_)));
+    }
+
+    #[test]
+    fn it_rejects_corrupted_entries() {
+        let mut archive = build_archive(&[("classes.dex", b"not really a dex file", false)]);
+        archive[LOCAL_HEADER_SIZE + "classes.dex".len()] ^= 0xff;
+
+        let error = DexArchive::from_bytes(&archive).unwrap_err();
+
+        assert!(matches!(error.kind(), ErrorKind::ArchiveCrcMismatch(..)));
+    }
+}
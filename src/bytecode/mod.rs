@@ -1,8 +1,11 @@
 //! Representation of the Dalvik bytecodes and utilities to decode them
 
-use byteorder::{LittleEndian, ReadBytesExt};
-use std::io::Read;
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Read};
+use std::marker::PhantomData;
 use error::*;
+use types::read::CodeItem;
 
 #[derive(Debug)]
 #[allow(missing_docs)]
@@ -69,6 +72,9 @@ pub enum ByteCode {
     InvokePolymorphicRange(u16, u8, MethodReference, PrototypeReference),
     InvokeCustom(Vec<u8>, CallSiteReference),
     InvokeCustomRange(u16, u8, CallSiteReference),
+    ConstMethodHandle(u8, MethodHandleReference),
+    ConstMethodType(u8, PrototypeReference),
+    Payload(Payload),
 }
 
 #[derive(Debug)]
@@ -95,16 +101,16 @@ impl From<u8> for CompareType {
     }
 }
 
-impl ToString for CompareType {
-    fn to_string(&self) -> String {
-        match *self {
-            CompareType::LittleThanFloat => "cmpl-float".to_string(),
-            CompareType::GreaterThanFloat => "cmpg-float".to_string(),
-            CompareType::LittleThanDouble => "cmpl-double".to_string(),
-            CompareType::GreaterThanDouble => "cmpg-double".to_string(),
-            CompareType::Long => "cmp-long".to_string(),
-            CompareType::Unknown => "unknown".to_string(),
-        }
+impl Display for CompareType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            CompareType::LittleThanFloat => "cmpl-float",
+            CompareType::GreaterThanFloat => "cmpg-float",
+            CompareType::LittleThanDouble => "cmpl-double",
+            CompareType::GreaterThanDouble => "cmpg-double",
+            CompareType::Long => "cmp-long",
+            CompareType::Unknown => "unknown",
+        })
     }
 }
 
@@ -134,17 +140,17 @@ impl From<u8> for TestType {
     }
 }
 
-impl ToString for TestType {
-    fn to_string(&self) -> String {
-        match *self {
-            TestType::Equal => "if-eq".to_string(),
-            TestType::NonEqual => "if-ne".to_string(),
-            TestType::LittleThan => "if-lt".to_string(),
-            TestType::GreaterThanOrEqual => "if-ge".to_string(),
-            TestType::GreaterThan => "if-gt".to_string(),
-            TestType::LittleThanOrEqual => "if-le".to_string(),
-            TestType::Unknown => "unknown".to_string(),
-        }
+impl Display for TestType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            TestType::Equal => "if-eq",
+            TestType::NonEqual => "if-ne",
+            TestType::LittleThan => "if-lt",
+            TestType::GreaterThanOrEqual => "if-ge",
+            TestType::GreaterThan => "if-gt",
+            TestType::LittleThanOrEqual => "if-le",
+            TestType::Unknown => "unknown",
+        })
     }
 }
 
@@ -190,25 +196,25 @@ impl From<u8> for ArrayOperation {
     }
 }
 
-impl ToString for ArrayOperation {
-    fn to_string(&self) -> String {
-        match *self {
-            ArrayOperation::Get => "get".to_string(),
-            ArrayOperation::GetWide => "get-wide".to_string(),
-            ArrayOperation::GetObject => "get-object".to_string(),
-            ArrayOperation::GetBoolean => "get-boolean".to_string(),
-            ArrayOperation::GetByte => "get-byte".to_string(),
-            ArrayOperation::GetChar => "get-char".to_string(),
-            ArrayOperation::GetShort => "get-short".to_string(),
-            ArrayOperation::Put => "put".to_string(),
-            ArrayOperation::PutWide => "put-wide".to_string(),
-            ArrayOperation::PutObject => "put-object".to_string(),
-            ArrayOperation::PutBoolean => "put-boolean".to_string(),
-            ArrayOperation::PutByte => "put-byte".to_string(),
-            ArrayOperation::PutChar => "put-char".to_string(),
-            ArrayOperation::PutShort => "put-short".to_string(),
-            ArrayOperation::Unknown => "unknown".to_string(),
-        }
+impl Display for ArrayOperation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ArrayOperation::Get => "get",
+            ArrayOperation::GetWide => "get-wide",
+            ArrayOperation::GetObject => "get-object",
+            ArrayOperation::GetBoolean => "get-boolean",
+            ArrayOperation::GetByte => "get-byte",
+            ArrayOperation::GetChar => "get-char",
+            ArrayOperation::GetShort => "get-short",
+            ArrayOperation::Put => "put",
+            ArrayOperation::PutWide => "put-wide",
+            ArrayOperation::PutObject => "put-object",
+            ArrayOperation::PutBoolean => "put-boolean",
+            ArrayOperation::PutByte => "put-byte",
+            ArrayOperation::PutChar => "put-char",
+            ArrayOperation::PutShort => "put-short",
+            ArrayOperation::Unknown => "unknown",
+        })
     }
 }
 
@@ -236,16 +242,16 @@ impl From<u8> for InvokeKind {
     }
 }
 
-impl ToString for InvokeKind {
-    fn to_string(&self) -> String {
-        match *self {
-            InvokeKind::Virtual => "invoke-virtual".to_string(),
-            InvokeKind::Super => "invoke-super".to_string(),
-            InvokeKind::Direct => "invoke-direct".to_string(),
-            InvokeKind::Static => "invoke-static".to_string(),
-            InvokeKind::Interface => "invoke-interface".to_string(),
-            InvokeKind::Unknown => "unknown".to_string(),
-        }
+impl Display for InvokeKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            InvokeKind::Virtual => "invoke-virtual",
+            InvokeKind::Super => "invoke-super",
+            InvokeKind::Direct => "invoke-direct",
+            InvokeKind::Static => "invoke-static",
+            InvokeKind::Interface => "invoke-interface",
+            InvokeKind::Unknown => "unknown",
+        })
     }
 }
 
@@ -305,32 +311,32 @@ impl From<u8> for UnaryOperation {
     }
 }
 
-impl ToString for UnaryOperation {
-    fn to_string(&self) -> String {
-        match *self {
-            UnaryOperation::NegateInt => "neg-int".to_string(),
-            UnaryOperation::NotInt => "not-int".to_string(),
-            UnaryOperation::NegateLong => "neg-long".to_string(),
-            UnaryOperation::NotLong => "not-long".to_string(),
-            UnaryOperation::NegateFloat => "neg-float".to_string(),
-            UnaryOperation::NegateDouble => "neg-double".to_string(),
-            UnaryOperation::IntToLong => "int-to-long".to_string(),
-            UnaryOperation::IntToFloat => "int-to-float".to_string(),
-            UnaryOperation::IntToDouble => "int-to-double".to_string(),
-            UnaryOperation::LongToInt => "long-to-int".to_string(),
-            UnaryOperation::LongToFloat => "long-to-float".to_string(),
-            UnaryOperation::LongToDouble => "long-to-double".to_string(),
-            UnaryOperation::FloatToInt => "float-to-int".to_string(),
-            UnaryOperation::FloatToLong => "float-to-long".to_string(),
-            UnaryOperation::FloatToDouble => "float-to-double".to_string(),
-            UnaryOperation::DoubleToInt => "double-to-int".to_string(),
-            UnaryOperation::DoubleToLong => "double-to-long".to_string(),
-            UnaryOperation::DoubleToFloat => "double-to-float".to_string(),
-            UnaryOperation::IntToByte => "int-to-byte".to_string(),
-            UnaryOperation::IntToChar => "int-to-char".to_string(),
-            UnaryOperation::IntToShort => "int-to-short".to_string(),
-            UnaryOperation::Unknown => "unknown".to_string(),
-        }
+impl Display for UnaryOperation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            UnaryOperation::NegateInt => "neg-int",
+            UnaryOperation::NotInt => "not-int",
+            UnaryOperation::NegateLong => "neg-long",
+            UnaryOperation::NotLong => "not-long",
+            UnaryOperation::NegateFloat => "neg-float",
+            UnaryOperation::NegateDouble => "neg-double",
+            UnaryOperation::IntToLong => "int-to-long",
+            UnaryOperation::IntToFloat => "int-to-float",
+            UnaryOperation::IntToDouble => "int-to-double",
+            UnaryOperation::LongToInt => "long-to-int",
+            UnaryOperation::LongToFloat => "long-to-float",
+            UnaryOperation::LongToDouble => "long-to-double",
+            UnaryOperation::FloatToInt => "float-to-int",
+            UnaryOperation::FloatToLong => "float-to-long",
+            UnaryOperation::FloatToDouble => "float-to-double",
+            UnaryOperation::DoubleToInt => "double-to-int",
+            UnaryOperation::DoubleToLong => "double-to-long",
+            UnaryOperation::DoubleToFloat => "double-to-float",
+            UnaryOperation::IntToByte => "int-to-byte",
+            UnaryOperation::IntToChar => "int-to-char",
+            UnaryOperation::IntToShort => "int-to-short",
+            UnaryOperation::Unknown => "unknown",
+        })
     }
 }
 
@@ -412,43 +418,43 @@ impl From<u8> for BinaryOperation {
     }
 }
 
-impl ToString for BinaryOperation {
-    fn to_string(&self) -> String {
-        match *self {
-            BinaryOperation::AddInt => "add-int".to_string(),
-            BinaryOperation::SubInt => "sub-int".to_string(),
-            BinaryOperation::MulInt => "mul-int".to_string(),
-            BinaryOperation::DivInt => "div-int".to_string(),
-            BinaryOperation::RemInt => "rem-int".to_string(),
-            BinaryOperation::AndInt => "and-int".to_string(),
-            BinaryOperation::OrInt => "or-int".to_string(),
-            BinaryOperation::XorInt => "xor-int".to_string(),
-            BinaryOperation::ShlInt => "shl-int".to_string(),
-            BinaryOperation::ShrInt => "shr-int".to_string(),
-            BinaryOperation::UshrInt => "ushr-int".to_string(),
-            BinaryOperation::AddLong => "add-long".to_string(),
-            BinaryOperation::SubLong => "sub-long".to_string(),
-            BinaryOperation::MulLong => "mul-long".to_string(),
-            BinaryOperation::DivLong => "div-long".to_string(),
-            BinaryOperation::RemLong => "rem-long".to_string(),
-            BinaryOperation::AndLong => "and-long".to_string(),
-            BinaryOperation::OrLong => "or-long".to_string(),
-            BinaryOperation::XorLong => "xor-long".to_string(),
-            BinaryOperation::ShlLong => "shl-long".to_string(),
-            BinaryOperation::ShrLong => "shr-long".to_string(),
-            BinaryOperation::UshrLong => "ushr-long".to_string(),
-            BinaryOperation::AddFloat => "add-float".to_string(),
-            BinaryOperation::SubFloat => "sub-float".to_string(),
-            BinaryOperation::MulFloat => "mul-float".to_string(),
-            BinaryOperation::DivFloat => "div-float".to_string(),
-            BinaryOperation::RemFloat => "rem-float".to_string(),
-            BinaryOperation::AddDouble => "add-double".to_string(),
-            BinaryOperation::SubDouble => "sub-double".to_string(),
-            BinaryOperation::MulDouble => "mul-double".to_string(),
-            BinaryOperation::DivDouble => "div-double".to_string(),
-            BinaryOperation::RemDouble => "rem-double".to_string(),
-            BinaryOperation::Unknown => "unknown".to_string(),
-        }
+impl Display for BinaryOperation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            BinaryOperation::AddInt => "add-int",
+            BinaryOperation::SubInt => "sub-int",
+            BinaryOperation::MulInt => "mul-int",
+            BinaryOperation::DivInt => "div-int",
+            BinaryOperation::RemInt => "rem-int",
+            BinaryOperation::AndInt => "and-int",
+            BinaryOperation::OrInt => "or-int",
+            BinaryOperation::XorInt => "xor-int",
+            BinaryOperation::ShlInt => "shl-int",
+            BinaryOperation::ShrInt => "shr-int",
+            BinaryOperation::UshrInt => "ushr-int",
+            BinaryOperation::AddLong => "add-long",
+            BinaryOperation::SubLong => "sub-long",
+            BinaryOperation::MulLong => "mul-long",
+            BinaryOperation::DivLong => "div-long",
+            BinaryOperation::RemLong => "rem-long",
+            BinaryOperation::AndLong => "and-long",
+            BinaryOperation::OrLong => "or-long",
+            BinaryOperation::XorLong => "xor-long",
+            BinaryOperation::ShlLong => "shl-long",
+            BinaryOperation::ShrLong => "shr-long",
+            BinaryOperation::UshrLong => "ushr-long",
+            BinaryOperation::AddFloat => "add-float",
+            BinaryOperation::SubFloat => "sub-float",
+            BinaryOperation::MulFloat => "mul-float",
+            BinaryOperation::DivFloat => "div-float",
+            BinaryOperation::RemFloat => "rem-float",
+            BinaryOperation::AddDouble => "add-double",
+            BinaryOperation::SubDouble => "sub-double",
+            BinaryOperation::MulDouble => "mul-double",
+            BinaryOperation::DivDouble => "div-double",
+            BinaryOperation::RemDouble => "rem-double",
+            BinaryOperation::Unknown => "unknown",
+        })
     }
 }
 
@@ -466,10 +472,134 @@ pub type MethodReference = u32;
 pub type PrototypeReference = u32;
 /// Call site index on the Dex call site table
 pub type CallSiteReference = u32;
+/// Method handle index on the Dex method handle table
+pub type MethodHandleReference = u32;
+
+/// Payload pseudo-instruction, the data of a switch or `fill-array-data` instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Payload {
+    /// Payload of a `packed-switch` instruction.
+    PackedSwitch(SwitchPayload),
+    /// Payload of a `sparse-switch` instruction.
+    SparseSwitch(SwitchPayload),
+    /// Payload of a `fill-array-data` instruction.
+    FillArrayData(ArrayData),
+}
+
+impl Display for Payload {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Payload::PackedSwitch(ref switch) => write!(f, "packed-switch-payload {}", switch),
+            Payload::SparseSwitch(ref switch) => write!(f, "sparse-switch-payload {}", switch),
+            Payload::FillArrayData(ref data) => write!(
+                f,
+                "fill-array-data-payload {}-byte elements {}",
+                data.element_width(),
+                data
+            ),
+        }
+    }
+}
+
+/// Cases of a switch instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwitchPayload {
+    keys: Vec<i32>,
+    offsets: Vec<i32>,
+}
+
+impl SwitchPayload {
+    /// Gets the keys of the cases, in ascending order.
+    pub fn keys(&self) -> &[i32] {
+        &self.keys
+    }
+
+    /// Gets the offsets of the targets of the cases, relative to the address of the switch
+    /// instruction.
+    pub fn offsets(&self) -> &[i32] {
+        &self.offsets
+    }
+
+    /// Gets the key and the absolute target address of each case, for the switch instruction
+    /// at the given address.
+    pub fn cases(&self, switch_address: u32) -> Vec<(i32, i64)> {
+        self.keys
+            .iter()
+            .zip(&self.offsets)
+            .map(|(&key, &offset)| (key, i64::from(switch_address) + i64::from(offset)))
+            .collect()
+    }
+}
+
+impl Display for SwitchPayload {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let cases: Vec<String> = self.keys
+            .iter()
+            .zip(&self.offsets)
+            .map(|(key, offset)| format!("{}: {}", key, offset))
+            .collect();
+        write!(f, "{{{}}}", cases.join(", "))
+    }
+}
+
+/// Values of the array initialized by a `fill-array-data` instruction, by element width.
+///
+/// `char` and `boolean` arrays are read as unsigned values stored in signed integers, and
+/// `float` and `double` arrays as the bits of their values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArrayData {
+    /// 1-byte elements.
+    Byte(Vec<i8>),
+    /// 2-byte elements.
+    Short(Vec<i16>),
+    /// 4-byte elements.
+    Int(Vec<i32>),
+    /// 8-byte elements.
+    Long(Vec<i64>),
+}
 
-impl ToString for ByteCode {
-    fn to_string(&self) -> String {
+impl ArrayData {
+    /// Gets the width of the elements, in bytes.
+    pub fn element_width(&self) -> u16 {
         match *self {
+            ArrayData::Byte(_) => 1,
+            ArrayData::Short(_) => 2,
+            ArrayData::Int(_) => 4,
+            ArrayData::Long(_) => 8,
+        }
+    }
+
+    /// Gets the number of elements.
+    pub fn len(&self) -> usize {
+        match *self {
+            ArrayData::Byte(ref values) => values.len(),
+            ArrayData::Short(ref values) => values.len(),
+            ArrayData::Int(ref values) => values.len(),
+            ArrayData::Long(ref values) => values.len(),
+        }
+    }
+
+    /// Checks if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Display for ArrayData {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let values: Vec<String> = match *self {
+            ArrayData::Byte(ref values) => values.iter().map(ToString::to_string).collect(),
+            ArrayData::Short(ref values) => values.iter().map(ToString::to_string).collect(),
+            ArrayData::Int(ref values) => values.iter().map(ToString::to_string).collect(),
+            ArrayData::Long(ref values) => values.iter().map(ToString::to_string).collect(),
+        };
+        write!(f, "{{{}}}", values.join(", "))
+    }
+}
+
+impl Display for ByteCode {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let string = match *self {
             ByteCode::Nop => "nop".to_string(),
             ByteCode::Move(dest, source) => format!("move v{}, v{}", dest, source),
             ByteCode::MoveFrom16(dest, source) => format!("move/from16 v{}, v{}", dest, source),
@@ -542,8 +672,8 @@ impl ToString for ByteCode {
                 )
             }
             ByteCode::FilledNewArrayRange(first_reg, amount, reference) => {
-                let str_register: Vec<String> = (first_reg
-                    ..(first_reg as u16 + amount as u16 + 1) as u16)
+                let first_reg = u32::from(first_reg);
+                let str_register: Vec<String> = (first_reg..(first_reg + u32::from(amount)))
                     .map(|r| format!("v{}", r))
                     .collect();
                 format!(
@@ -560,62 +690,63 @@ impl ToString for ByteCode {
             ByteCode::PackedSwitch(reg, offset) => format!("packed-switch v{}, {}", reg, offset),
             ByteCode::SparseSwitch(reg, offset) => format!("sparse-switch v{}, {}", reg, offset),
             ByteCode::Compare(ref ct, dest, op1, op2) => {
-                format!("{} v{}, v{}, v{}", ct.to_string(), dest, op1, op2)
+                format!("{} v{}, v{}, v{}", ct, dest, op1, op2)
             }
             ByteCode::If(ref tt, dest, src, offset) => {
-                format!("{} v{}, v{}, {}", tt.to_string(), dest, src, offset)
+                format!("{} v{}, v{}, {}", tt, dest, src, offset)
             }
             ByteCode::If0(ref tt, dest, offset) => {
-                format!("{}z v{}, {}", tt.to_string(), dest, offset)
+                format!("{}z v{}, {}", tt, dest, offset)
             }
             ByteCode::Array(ref array_op, dest, op1, op2) => {
-                format!("a{} v{}, v{}, v{}", array_op.to_string(), dest, op1, op2)
+                format!("a{} v{}, v{}, v{}", array_op, dest, op1, op2)
             }
             ByteCode::Instance(ref array_op, dest, op1, field) => format!(
                 "i{} v{}, v{}, field@{}",
-                array_op.to_string(),
+                array_op,
                 dest,
                 op1,
                 field
             ),
             ByteCode::Static(ref array_op, dest, field) => {
-                format!("s{} v{}, field@{}", array_op.to_string(), dest, field)
+                format!("s{} v{}, field@{}", array_op, dest, field)
             }
             ByteCode::Invoke(ref invoke_kind, ref registers, method) => {
                 let str_register: Vec<String> =
                     registers.iter().map(|r| format!("v{}", r)).collect();
                 format!(
                     "{} {{{}}}, method@{}",
-                    invoke_kind.to_string(),
+                    invoke_kind,
                     str_register.join(", "),
                     method
                 )
             }
             ByteCode::InvokeRange(ref invoke_kind, first_reg, amount, reference) => {
-                let str_register: Vec<String> = (first_reg..(first_reg + amount as u16))
+                let first_reg = u32::from(first_reg);
+                let str_register: Vec<String> = (first_reg..(first_reg + u32::from(amount)))
                     .map(|r| format!("v{}", r))
                     .collect();
                 format!(
                     "{}/range {{{}}}, method@{}",
-                    invoke_kind.to_string(),
+                    invoke_kind,
                     str_register.join(", "),
                     reference
                 )
             }
             ByteCode::Unary(ref operation, dest, src) => {
-                format!("{} v{}, v{}", operation.to_string(), dest, src)
+                format!("{} v{}, v{}", operation, dest, src)
             }
             ByteCode::Binary(ref operation, dest, op1, op2) => {
-                format!("{} v{}, v{}, v{}", operation.to_string(), dest, op1, op2)
+                format!("{} v{}, v{}, v{}", operation, dest, op1, op2)
             }
             ByteCode::Binary2Addr(ref operation, src1, src2) => {
-                format!("{}/2addr v{}, v{}", operation.to_string(), src1, src2)
+                format!("{}/2addr v{}, v{}", operation, src1, src2)
             }
             ByteCode::BinaryLit16(ref operation, dest, src, literal) => match *operation {
                 BinaryOperation::SubInt => format!("rsub-int v{}, v{}, #{}", dest, src, literal),
                 _ => format!(
                     "{}/lit16 v{}, v{}, #{}",
-                    operation.to_string(),
+                    operation,
                     dest,
                     src,
                     literal
@@ -623,7 +754,7 @@ impl ToString for ByteCode {
             },
             ByteCode::BinaryLit8(ref operation, dest, src, literal) => format!(
                 "{}/lit8 v{}, v{}, #{}",
-                operation.to_string(),
+                operation,
                 dest,
                 src,
                 literal
@@ -639,7 +770,8 @@ impl ToString for ByteCode {
                 )
             }
             ByteCode::InvokePolymorphicRange(first_reg, amount, method, proto) => {
-                let str_register: Vec<String> = (first_reg..(first_reg + amount as u16))
+                let first_reg = u32::from(first_reg);
+                let str_register: Vec<String> = (first_reg..(first_reg + u32::from(amount)))
                     .map(|r| format!("v{}", r))
                     .collect();
                 format!(
@@ -659,7 +791,8 @@ impl ToString for ByteCode {
                 )
             }
             ByteCode::InvokeCustomRange(first_reg, amount, call_site) => {
-                let str_register: Vec<String> = (first_reg..(first_reg + amount as u16))
+                let first_reg = u32::from(first_reg);
+                let str_register: Vec<String> = (first_reg..(first_reg + u32::from(amount)))
                     .map(|r| format!("v{}", r))
                     .collect();
                 format!(
@@ -668,440 +801,558 @@ impl ToString for ByteCode {
                     call_site
                 )
             }
-        }
+            ByteCode::ConstMethodHandle(dest, reference) => {
+                format!("const-method-handle v{}, method_handle@{}", dest, reference)
+            }
+            ByteCode::ConstMethodType(dest, reference) => {
+                format!("const-method-type v{}, proto@{}", dest, reference)
+            }
+            ByteCode::Payload(ref payload) => payload.to_string(),
+        };
+        f.write_str(&string)
     }
 }
 
-/// Implementations of the distinct bytecodes data layouts
-/// It will read from the source and return the data destructured
-pub struct ByteCodeDecoder<R: Read> {
+/// Decoder of the bytecode of a method.
+///
+/// It reads the 16-bit code units of the bytecode in the byte order `B`, and yields each
+/// instruction with its address, in code units from the start of the bytecode. Payloads of
+/// switch and `fill-array-data` instructions are yielded as `ByteCode::Payload`, so that their
+/// data is not read as instructions. Decoding stops after the first error, such as an unknown
+/// opcode or a truncated instruction.
+pub struct ByteCodeDecoder<R: Read, B: ByteOrder = LittleEndian> {
     cursor: R,
+    address: u32,
+    finished: bool,
+    byte_order: PhantomData<B>,
 }
 
 impl<R: Read> ByteCodeDecoder<R> {
-    /// Creates a new ByteCodeDecoder given a `Read` input
+    /// Creates a new ByteCodeDecoder given a `Read` input, with the bytecode in little endian.
     pub fn new(buffer: R) -> Self {
-        ByteCodeDecoder { cursor: buffer }
+        ByteCodeDecoder::with_byte_order(buffer)
     }
+}
 
-    fn format10x(&mut self) -> Result<()> {
-        let _ = self.cursor.read_u8()?;
+impl<'a> ByteCodeDecoder<CodeUnitReader<'a>> {
+    /// Creates a new ByteCodeDecoder for the given code units, such as the ones of a
+    /// [`CodeItem`](../types/read/struct.CodeItem.html).
+    pub fn from_code_units(units: &'a [u16]) -> Self {
+        ByteCodeDecoder::new(CodeUnitReader { units, position: 0 })
+    }
+}
 
-        Ok(())
+impl<R: Read, B: ByteOrder> ByteCodeDecoder<R, B> {
+    /// Creates a new ByteCodeDecoder given a `Read` input, with the bytecode in the byte order
+    /// `B` of the dex file.
+    pub fn with_byte_order(buffer: R) -> Self {
+        ByteCodeDecoder {
+            cursor: buffer,
+            address: 0,
+            finished: false,
+            byte_order: PhantomData,
+        }
     }
 
-    fn format10t(&mut self) -> Result<i8> {
-        Ok(self.cursor.read_i8()?)
+    /// Gets the address of the next instruction, in code units.
+    pub fn address(&self) -> u32 {
+        self.address
     }
 
-    fn format11x(&mut self) -> Result<u8> {
-        Ok(self.cursor.read_u8()?)
+    /// Reads the first code unit of an instruction, or `None` at the end of the bytecode.
+    fn read_first_unit(&mut self) -> Result<Option<u16>> {
+        let mut unit = [0; 2];
+        let mut read = 0;
+        while read < unit.len() {
+            match self.cursor.read(&mut unit[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(size) => read += size,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.address += 1;
+
+        Ok(Some(B::read_u16(&unit)))
     }
 
-    fn format11n(&mut self) -> Result<(u8, i32)> {
-        let current_byte = self.cursor.read_u8()?;
+    fn read_unit(&mut self) -> Result<u16> {
+        let unit = self.cursor.read_u16::<B>()?;
+        self.address += 1;
 
-        let literal = ((current_byte & 0xF0) as i8 >> 4) as i32;
-        let register = current_byte & 0xF;
+        Ok(unit)
+    }
 
-        Ok((register, literal))
+    /// Reads a 32-bit value, stored as two code units, the low one first.
+    fn read_u32(&mut self) -> Result<u32> {
+        let low = u32::from(self.read_unit()?);
+        let high = u32::from(self.read_unit()?);
+
+        Ok(high << 16 | low)
     }
 
-    fn format12x(&mut self) -> Result<(u8, u8)> {
-        let current_byte = self.cursor.read_u8()?;
+    /// Reads a 64-bit value, stored as four code units, the low one first.
+    fn read_u64(&mut self) -> Result<u64> {
+        let low = u64::from(self.read_u32()?);
+        let high = u64::from(self.read_u32()?);
 
-        let source = (current_byte & 0xF0) >> 4;
-        let dest = current_byte & 0xF;
+        Ok(high << 32 | low)
+    }
 
-        Ok((dest, source))
+    fn format10t(&mut self, high: u8) -> Result<i8> {
+        Ok(high as i8)
     }
 
-    fn format20t(&mut self) -> Result<i16> {
-        let _ = self.cursor.read_u8()?;
-        // TODO: Make byteorder generic
-        let literal = self.cursor.read_i16::<LittleEndian>()?;
+    fn format11x(&mut self, high: u8) -> Result<u8> {
+        Ok(high)
+    }
+
+    fn format11n(&mut self, high: u8) -> Result<(u8, i32)> {
+        let literal = i32::from(high as i8 >> 4);
+        let register = high & 0xF;
 
-        Ok(literal)
+        Ok((register, literal))
     }
 
-    fn format21t(&mut self) -> Result<(u8, i16)> {
-        let dest = self.cursor.read_u8()?;
-        // TODO: Make byteorder generic
-        let offset = self.cursor.read_i16::<LittleEndian>()?;
+    fn format12x(&mut self, high: u8) -> Result<(u8, u8)> {
+        let source = (high & 0xF0) >> 4;
+        let dest = high & 0xF;
 
-        Ok((dest, offset))
+        Ok((dest, source))
+    }
+
+    fn format20t(&mut self) -> Result<i16> {
+        Ok(self.read_unit()? as i16)
     }
 
-    fn format21s(&mut self) -> Result<(u8, i32)> {
-        let dest = self.cursor.read_u8()?;
-        // TODO: Make byteorder generic
-        let literal = self.cursor.read_i16::<LittleEndian>()?;
+    fn format21t(&mut self, high: u8) -> Result<(u8, i16)> {
+        let offset = self.read_unit()? as i16;
 
-        Ok((dest, literal as i32))
+        Ok((high, offset))
     }
 
-    fn format21hw(&mut self) -> Result<(u8, i32)> {
-        let dest = self.cursor.read_u8()?;
-        // TODO: Make byteorder generic
-        let literal = (self.cursor.read_i16::<LittleEndian>()? as i32) << 16;
+    fn format21s(&mut self, high: u8) -> Result<(u8, i32)> {
+        let literal = self.read_unit()? as i16;
 
-        Ok((dest, literal))
+        Ok((high, i32::from(literal)))
     }
 
-    fn format21hd(&mut self) -> Result<(u8, i64)> {
-        let dest = self.cursor.read_u8()?;
-        // TODO: Make byteorder generic
-        let literal = (self.cursor.read_i16::<LittleEndian>()? as i64) << 48;
+    fn format21hw(&mut self, high: u8) -> Result<(u8, i32)> {
+        let literal = i32::from(self.read_unit()? as i16) << 16;
 
-        Ok((dest, literal))
+        Ok((high, literal))
     }
 
-    fn format21c(&mut self) -> Result<(u8, u16)> {
-        let dest = self.cursor.read_u8()?;
-        // TODO: Make byteorder generic
-        let literal = self.cursor.read_u16::<LittleEndian>()?;
+    fn format21hd(&mut self, high: u8) -> Result<(u8, i64)> {
+        let literal = i64::from(self.read_unit()? as i16) << 48;
 
-        Ok((dest, literal))
+        Ok((high, literal))
     }
 
-    fn format22c(&mut self) -> Result<(u8, u8, u16)> {
-        let current_byte = self.cursor.read_u8()?;
+    fn format21c(&mut self, high: u8) -> Result<(u8, u16)> {
+        let reference = self.read_unit()?;
 
-        let source = ((current_byte & 0xF0) as u8 >> 4) as u8;
-        let dest = current_byte & 0xF;
+        Ok((high, reference))
+    }
 
-        // TODO: Make byteorder generic
-        let reference = self.cursor.read_u16::<LittleEndian>()?;
+    fn format22c(&mut self, high: u8) -> Result<(u8, u8, u16)> {
+        let (dest, source) = self.format12x(high)?;
+        let reference = self.read_unit()?;
 
         Ok((dest, source, reference))
     }
 
-    fn format22x(&mut self) -> Result<(u8, u16)> {
-        let dest = self.cursor.read_u8()?;
-        // TODO: Make byteorder generic
-        let source = self.cursor.read_u16::<LittleEndian>()?;
+    fn format22x(&mut self, high: u8) -> Result<(u8, u16)> {
+        let source = self.read_unit()?;
 
-        Ok((dest, source))
+        Ok((high, source))
     }
 
-    fn format22t(&mut self) -> Result<(u8, u8, i16)> {
-        let current_byte = self.cursor.read_u8()?;
-
-        let source = ((current_byte & 0xF0) as u8 >> 4) as u8;
-        let dest = current_byte & 0xF;
-        // TODO: Make byteorder generic
-        let offset = self.cursor.read_i16::<LittleEndian>()?;
+    fn format22t(&mut self, high: u8) -> Result<(u8, u8, i16)> {
+        let (dest, source) = self.format12x(high)?;
+        let offset = self.read_unit()? as i16;
 
         Ok((dest, source, offset))
     }
 
-    fn format22s(&mut self) -> Result<(u8, u8, i16)> {
-        self.format22t()
+    fn format22s(&mut self, high: u8) -> Result<(u8, u8, i16)> {
+        self.format22t(high)
     }
 
-    fn format22b(&mut self) -> Result<(u8, u8, i8)> {
-        let dest = self.cursor.read_u8()?;
-        // TODO: Make byteorder generic
-        let operand1 = self.cursor.read_u8()?;
-        let literal = self.cursor.read_i8()?;
+    fn format22b(&mut self, high: u8) -> Result<(u8, u8, i8)> {
+        let unit = self.read_unit()?;
+        let operand1 = unit as u8;
+        let literal = (unit >> 8) as i8;
 
-        Ok((dest, operand1, literal))
+        Ok((high, operand1, literal))
     }
 
-    fn format23x(&mut self) -> Result<(u8, u8, u8)> {
-        let dest = self.cursor.read_u8()?;
-        // TODO: Make byteorder generic
-        let operand1 = self.cursor.read_u8()?;
-        let operand2 = self.cursor.read_u8()?;
+    fn format23x(&mut self, high: u8) -> Result<(u8, u8, u8)> {
+        let unit = self.read_unit()?;
+        let operand1 = unit as u8;
+        let operand2 = (unit >> 8) as u8;
 
-        Ok((dest, operand1, operand2))
+        Ok((high, operand1, operand2))
     }
 
     fn format30t(&mut self) -> Result<i32> {
-        let _ = self.cursor.read_u8()?;
-        let literal = self.cursor.read_i32::<LittleEndian>()?;
-
-        Ok(literal)
+        Ok(self.read_u32()? as i32)
     }
 
-    fn format31i(&mut self) -> Result<(u8, i32)> {
-        let dest = self.cursor.read_u8()?;
-        let literal = self.cursor.read_i32::<LittleEndian>()?;
+    fn format31i(&mut self, high: u8) -> Result<(u8, i32)> {
+        let literal = self.read_u32()? as i32;
 
-        Ok((dest, literal))
+        Ok((high, literal))
     }
 
-    fn format31t(&mut self) -> Result<(u8, i32)> {
-        let dest = self.cursor.read_u8()?;
-        let literal = self.cursor.read_i32::<LittleEndian>()?;
-
-        Ok((dest, literal))
+    fn format31t(&mut self, high: u8) -> Result<(u8, i32)> {
+        self.format31i(high)
     }
 
-    fn format31c(&mut self) -> Result<(u8, u32)> {
-        let dest = self.cursor.read_u8()?;
-        let reference = self.cursor.read_u32::<LittleEndian>()?;
+    fn format31c(&mut self, high: u8) -> Result<(u8, u32)> {
+        let reference = self.read_u32()?;
 
-        Ok((dest, reference))
+        Ok((high, reference))
     }
 
     fn format32x(&mut self) -> Result<(u16, u16)> {
-        // TODO: Make byteorder generic
-        let dest = self.cursor.read_u16::<LittleEndian>()?;
-        let source = self.cursor.read_u16::<LittleEndian>()?;
+        let dest = self.read_unit()?;
+        let source = self.read_unit()?;
 
         Ok((dest, source))
     }
 
-    fn format35c(&mut self) -> Result<(Vec<u8>, u16)> {
-        let mut arguments = Vec::new();
-        let first_byte = self.cursor.read_u8()?;
-
-        let count = (first_byte & 0xF0) >> 4;
-        let last_register = first_byte & 0xF;
+    fn format35c(&mut self, high: u8) -> Result<(Vec<u8>, u16)> {
+        let count = (high & 0xF0) >> 4;
+        let last_register = high & 0xF;
 
-        let reference = self.cursor.read_u16::<LittleEndian>()?;
+        let reference = self.read_unit()?;
+        let registers = self.read_unit()?;
 
-        let reg_array = self.read_4bit_array(4)?;
-        arguments.extend(reg_array);
-        arguments.push(last_register);
+        let arguments = (0..4)
+            .map(|i| ((registers >> (i * 4)) & 0xF) as u8)
+            .chain(Some(last_register))
+            .take(count as usize)
+            .collect();
 
-        let final_arguments = arguments.into_iter().take(count as usize).collect();
-
-        Ok((final_arguments, reference))
+        Ok((arguments, reference))
     }
 
-    fn format3rc(&mut self) -> Result<(u16, u8, u16)> {
-        let amount = self.cursor.read_u8()?;
-        let reference = self.cursor.read_u16::<LittleEndian>()?;
-        let first = self.cursor.read_u16::<LittleEndian>()?;
+    fn format3rc(&mut self, high: u8) -> Result<(u16, u8, u16)> {
+        let reference = self.read_unit()?;
+        let first = self.read_unit()?;
 
-        Ok((first, amount - 1, reference))
+        Ok((first, high, reference))
     }
 
-    fn format45cc(&mut self) -> Result<(Vec<u8>, u16, u16)> {
-        let (registers, method_ref) = self.format35c()?;
-        let proto_ref = self.cursor.read_u16::<LittleEndian>()?;
+    fn format45cc(&mut self, high: u8) -> Result<(Vec<u8>, u16, u16)> {
+        let (registers, method_ref) = self.format35c(high)?;
+        let proto_ref = self.read_unit()?;
 
         Ok((registers, method_ref, proto_ref))
     }
 
-    fn format4rcc(&mut self) -> Result<(u16, u8, u16, u16)> {
-        let (first, amount, method_ref) = self.format3rc()?;
-        let proto_ref = self.cursor.read_u16::<LittleEndian>()?;
+    fn format4rcc(&mut self, high: u8) -> Result<(u16, u8, u16, u16)> {
+        let (first, amount, method_ref) = self.format3rc(high)?;
+        let proto_ref = self.read_unit()?;
 
         Ok((first, amount, method_ref, proto_ref))
     }
 
-    fn format51l(&mut self) -> Result<(u8, i64)> {
-        // TODO: Make byteorder generic
-        let dest = self.cursor.read_u8()?;
-        let source = self.cursor.read_i64::<LittleEndian>()?;
+    fn format51l(&mut self, high: u8) -> Result<(u8, i64)> {
+        let literal = self.read_u64()? as i64;
 
-        Ok((dest, source))
+        Ok((high, literal))
     }
 
-    fn read_4bit_array(&mut self, amount: u8) -> Result<Vec<u8>> {
-        let mut values = Vec::new();
+    fn packed_switch_payload(&mut self) -> Result<SwitchPayload> {
+        let size = self.read_unit()?;
+        let first_key = self.read_u32()? as i32;
+        let keys = (0..size)
+            .map(|i| first_key.wrapping_add(i32::from(i)))
+            .collect();
+        let offsets = self.read_i32_array(size)?;
 
-        for _ in 0..(amount / 2) {
-            let current = self.cursor.read_u8()?;
+        Ok(SwitchPayload { keys, offsets })
+    }
 
-            let high = (current & 0xF0) >> 4;
-            let low = current & 0xF;
+    fn sparse_switch_payload(&mut self) -> Result<SwitchPayload> {
+        let size = self.read_unit()?;
+        let keys = self.read_i32_array(size)?;
+        let offsets = self.read_i32_array(size)?;
 
-            values.push(low);
-            values.push(high);
-        }
+        Ok(SwitchPayload { keys, offsets })
+    }
 
-        Ok(values)
+    fn read_i32_array(&mut self, size: u16) -> Result<Vec<i32>> {
+        (0..size).map(|_| Ok(self.read_u32()? as i32)).collect()
     }
-}
 
-impl<R: Read> Iterator for ByteCodeDecoder<R> {
-    type Item = ByteCode;
+    fn fill_array_data_payload(&mut self, address: u32) -> Result<ArrayData> {
+        let element_width = self.read_unit()?;
+        let size = self.read_u32()?;
+        if ![1, 2, 4, 8].contains(&element_width) {
+            return Err(ErrorKind::InvalidElementWidth(element_width, address).into());
+        }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let byte = self.cursor.read_u8();
-
-        match byte {
-            Ok(0x00) => self.format10x().ok().map(|_| ByteCode::Nop),
-            Ok(0x01) => self.format12x().ok().map(|(d, s)| ByteCode::Move(d, s)),
-            Ok(0x02) => self.format22x()
-                .ok()
-                .map(|(d, s)| ByteCode::MoveFrom16(d, s)),
-            Ok(0x03) => self.format32x().ok().map(|(d, s)| ByteCode::Move16(d, s)),
-            Ok(0x04) => self.format12x().ok().map(|(d, s)| ByteCode::MoveWide(d, s)),
-            Ok(0x05) => self.format22x()
-                .ok()
+        // The data is a byte array, padded to a whole number of code units.
+        let data_size = u64::from(size) * u64::from(element_width);
+        let mut data = Vec::new();
+        for _ in 0..(data_size + 1) / 2 {
+            let mut unit = [0; 2];
+            B::write_u16(&mut unit, self.read_unit()?);
+            data.extend_from_slice(&unit);
+        }
+        data.truncate(data_size as usize);
+
+        let values = data.chunks(element_width as usize);
+        Ok(match element_width {
+            1 => ArrayData::Byte(data.iter().map(|&value| value as i8).collect()),
+            2 => ArrayData::Short(values.map(B::read_i16).collect()),
+            4 => ArrayData::Int(values.map(B::read_i32).collect()),
+            _ => ArrayData::Long(values.map(B::read_i64).collect()),
+        })
+    }
+
+    /// Decodes the rest of the instruction with the given opcode and high byte of its first code
+    /// unit.
+    fn decode(&mut self, opcode: u8, high: u8) -> Result<ByteCode> {
+        match opcode {
+            0x00 if high == 0x01 => self.packed_switch_payload()
+                .map(|payload| ByteCode::Payload(Payload::PackedSwitch(payload))),
+            0x00 if high == 0x02 => self.sparse_switch_payload()
+                .map(|payload| ByteCode::Payload(Payload::SparseSwitch(payload))),
+            0x00 if high == 0x03 => {
+                let address = self.address - 1;
+                self.fill_array_data_payload(address)
+                    .map(|payload| ByteCode::Payload(Payload::FillArrayData(payload)))
+            }
+            0x00 => Ok(ByteCode::Nop),
+            0x01 => self.format12x(high).map(|(d, s)| ByteCode::Move(d, s)),
+            0x02 => self.format22x(high).map(|(d, s)| ByteCode::MoveFrom16(d, s)),
+            0x03 => self.format32x().map(|(d, s)| ByteCode::Move16(d, s)),
+            0x04 => self.format12x(high).map(|(d, s)| ByteCode::MoveWide(d, s)),
+            0x05 => self.format22x(high)
                 .map(|(d, s)| ByteCode::MoveWideFrom16(d, s)),
-            Ok(0x06) => self.format32x()
-                .ok()
-                .map(|(d, s)| ByteCode::MoveWide16(d, s)),
-            Ok(0x07) => self.format12x()
-                .ok()
-                .map(|(d, s)| ByteCode::MoveObject(d, s)),
-            Ok(0x08) => self.format22x()
-                .ok()
+            0x06 => self.format32x().map(|(d, s)| ByteCode::MoveWide16(d, s)),
+            0x07 => self.format12x(high).map(|(d, s)| ByteCode::MoveObject(d, s)),
+            0x08 => self.format22x(high)
                 .map(|(d, s)| ByteCode::MoveObjectFrom16(d, s)),
-            Ok(0x09) => self.format32x()
-                .ok()
-                .map(|(d, s)| ByteCode::MoveObject16(d, s)),
-            Ok(0x0A) => self.format11x().ok().map(ByteCode::MoveResult),
-            Ok(0x0B) => self.format11x().ok().map(ByteCode::MoveResultWide),
-            Ok(0x0C) => self.format11x().ok().map(ByteCode::MoveResultObject),
-            Ok(0x0D) => self.format11x().ok().map(ByteCode::MoveException),
-            Ok(0x0E) => self.format10x().ok().map(|_| ByteCode::ReturnVoid),
-            Ok(0x0F) => self.format11x().ok().map(ByteCode::Return),
-            Ok(0x10) => self.format11x().ok().map(ByteCode::ReturnWide),
-            Ok(0x11) => self.format11x().ok().map(ByteCode::ReturnObject),
-            Ok(0x12) => self.format11n()
-                .ok()
+            0x09 => self.format32x().map(|(d, s)| ByteCode::MoveObject16(d, s)),
+            0x0A => self.format11x(high).map(ByteCode::MoveResult),
+            0x0B => self.format11x(high).map(ByteCode::MoveResultWide),
+            0x0C => self.format11x(high).map(ByteCode::MoveResultObject),
+            0x0D => self.format11x(high).map(ByteCode::MoveException),
+            0x0E => Ok(ByteCode::ReturnVoid),
+            0x0F => self.format11x(high).map(ByteCode::Return),
+            0x10 => self.format11x(high).map(ByteCode::ReturnWide),
+            0x11 => self.format11x(high).map(ByteCode::ReturnObject),
+            0x12 => self.format11n(high)
                 .map(|(reg, lit)| ByteCode::Const4(reg, lit)),
-            Ok(0x13) => self.format21s()
-                .ok()
+            0x13 => self.format21s(high)
                 .map(|(reg, lit)| ByteCode::Const16(reg, lit)),
-            Ok(0x14) => self.format31i()
-                .ok()
+            0x14 => self.format31i(high)
                 .map(|(reg, lit)| ByteCode::Const(reg, lit)),
-            Ok(0x15) => self.format21hw()
-                .ok()
+            0x15 => self.format21hw(high)
                 .map(|(reg, lit)| ByteCode::ConstHigh16(reg, lit)),
-            Ok(0x16) => self.format21s()
-                .ok()
-                .map(|(reg, lit)| ByteCode::ConstWide16(reg, lit as i64)),
-            Ok(0x17) => self.format31i()
-                .ok()
-                .map(|(reg, lit)| ByteCode::ConstWide32(reg, lit as i64)),
-            Ok(0x18) => self.format51l()
-                .ok()
+            0x16 => self.format21s(high)
+                .map(|(reg, lit)| ByteCode::ConstWide16(reg, i64::from(lit))),
+            0x17 => self.format31i(high)
+                .map(|(reg, lit)| ByteCode::ConstWide32(reg, i64::from(lit))),
+            0x18 => self.format51l(high)
                 .map(|(reg, lit)| ByteCode::ConstWide(reg, lit)),
-            Ok(0x19) => self.format21hd()
-                .ok()
+            0x19 => self.format21hd(high)
                 .map(|(reg, lit)| ByteCode::ConstWideHigh16(reg, lit)),
-            Ok(0x1A) => self.format21c()
-                .ok()
-                .map(|(reg, reference)| ByteCode::ConstString(reg, reference as StringReference)),
-            Ok(0x1B) => self.format31c().ok().map(|(reg, reference)| {
-                ByteCode::ConstStringJumbo(reg, reference as StringReference)
+            0x1A => self.format21c(high).map(|(reg, reference)| {
+                ByteCode::ConstString(reg, StringReference::from(reference))
+            }),
+            0x1B => self.format31c(high)
+                .map(|(reg, reference)| ByteCode::ConstStringJumbo(reg, reference)),
+            0x1C => self.format21c(high).map(|(reg, reference)| {
+                ByteCode::ConstClass(reg, ClassReference::from(reference))
+            }),
+            0x1D => self.format11x(high).map(ByteCode::MonitorEnter),
+            0x1E => self.format11x(high).map(ByteCode::MonitorExit),
+            0x1F => self.format21c(high).map(|(reg, reference)| {
+                ByteCode::CheckCast(reg, TypeReference::from(reference))
             }),
-            Ok(0x1C) => self.format21c()
-                .ok()
-                .map(|(reg, reference)| ByteCode::ConstClass(reg, reference as ClassReference)),
-            Ok(0x1D) => self.format11x().ok().map(ByteCode::MonitorEnter),
-            Ok(0x1E) => self.format11x().ok().map(ByteCode::MonitorExit),
-            Ok(0x1F) => self.format21c()
-                .ok()
-                .map(|(reg, reference)| ByteCode::CheckCast(reg, reference as TypeReference)),
-            Ok(0x20) => self.format22c().ok().map(|(dest, src, reference)| {
-                ByteCode::InstanceOf(dest, src, reference as TypeReference)
+            0x20 => self.format22c(high).map(|(dest, src, reference)| {
+                ByteCode::InstanceOf(dest, src, TypeReference::from(reference))
             }),
-            Ok(0x21) => self.format12x()
-                .ok()
+            0x21 => self.format12x(high)
                 .map(|(dest, src)| ByteCode::ArrayLength(dest, src)),
-            Ok(0x22) => self.format21c()
-                .ok()
-                .map(|(dest, reference)| ByteCode::NewInstance(dest, reference as TypeReference)),
-            Ok(0x23) => self.format22c().ok().map(|(dest, size, reference)| {
-                ByteCode::NewArray(dest, size, reference as TypeReference)
+            0x22 => self.format21c(high).map(|(dest, reference)| {
+                ByteCode::NewInstance(dest, TypeReference::from(reference))
             }),
-            Ok(0x24) => self.format35c().ok().map(|(registers, reference)| {
-                ByteCode::FilledNewArray(registers, reference as TypeReference)
+            0x23 => self.format22c(high).map(|(dest, size, reference)| {
+                ByteCode::NewArray(dest, size, TypeReference::from(reference))
             }),
-            Ok(0x25) => self.format3rc().ok().map(|(first, amount, reference)| {
-                ByteCode::FilledNewArrayRange(first, amount, reference as TypeReference)
+            0x24 => self.format35c(high).map(|(registers, reference)| {
+                ByteCode::FilledNewArray(registers, TypeReference::from(reference))
             }),
-            Ok(0x26) => self.format31t()
-                .ok()
+            0x25 => self.format3rc(high).map(|(first, amount, reference)| {
+                ByteCode::FilledNewArrayRange(first, amount, TypeReference::from(reference))
+            }),
+            0x26 => self.format31t(high)
                 .map(|(reg, offset)| ByteCode::FillArrayData(reg, offset)),
-            Ok(0x27) => self.format11x().ok().map(ByteCode::Throw),
-            Ok(0x28) => self.format10t().ok().map(ByteCode::Goto),
-            Ok(0x29) => self.format20t().ok().map(ByteCode::Goto16),
-            Ok(0x2A) => self.format30t().ok().map(ByteCode::Goto32),
-            Ok(0x2B) => self.format31t()
-                .ok()
+            0x27 => self.format11x(high).map(ByteCode::Throw),
+            0x28 => self.format10t(high).map(ByteCode::Goto),
+            0x29 => self.format20t().map(ByteCode::Goto16),
+            0x2A => self.format30t().map(ByteCode::Goto32),
+            0x2B => self.format31t(high)
                 .map(|(reg, offset)| ByteCode::PackedSwitch(reg, offset)),
-            Ok(0x2C) => self.format31t()
-                .ok()
+            0x2C => self.format31t(high)
                 .map(|(reg, offset)| ByteCode::SparseSwitch(reg, offset)),
-            Ok(a @ 0x2D...0x31) => self.format23x()
-                .ok()
-                .map(|(dest, op1, op2)| ByteCode::Compare(CompareType::from(a), dest, op1, op2)),
-            Ok(a @ 0x32...0x37) => self.format22t()
-                .ok()
-                .map(|(dest, src, offset)| ByteCode::If(TestType::from(a), dest, src, offset)),
-            Ok(a @ 0x38...0x3D) => self.format21t()
-                .ok()
-                .map(|(dest, offset)| ByteCode::If0(TestType::from(a), dest, offset)),
-            Ok(a @ 0x44...0x51) => self.format23x()
-                .ok()
-                .map(|(dest, op1, op2)| ByteCode::Array(ArrayOperation::from(a), dest, op1, op2)),
-            Ok(a @ 0x52...0x5f) => self.format22c().ok().map(|(dest, op1, reference)| {
+            0x2D..=0x31 => self.format23x(high).map(|(dest, op1, op2)| {
+                ByteCode::Compare(CompareType::from(opcode), dest, op1, op2)
+            }),
+            0x32..=0x37 => self.format22t(high).map(|(dest, src, offset)| {
+                ByteCode::If(TestType::from(opcode), dest, src, offset)
+            }),
+            0x38..=0x3D => self.format21t(high)
+                .map(|(dest, offset)| ByteCode::If0(TestType::from(opcode), dest, offset)),
+            0x44..=0x51 => self.format23x(high).map(|(dest, op1, op2)| {
+                ByteCode::Array(ArrayOperation::from(opcode), dest, op1, op2)
+            }),
+            0x52..=0x5f => self.format22c(high).map(|(dest, op1, reference)| {
                 ByteCode::Instance(
-                    ArrayOperation::from(a),
+                    ArrayOperation::from(opcode),
                     dest,
                     op1,
-                    reference as FieldReference,
+                    FieldReference::from(reference),
                 )
             }),
-            Ok(a @ 0x60...0x6d) => self.format21c().ok().map(|(dest, reference)| {
-                ByteCode::Static(ArrayOperation::from(a), dest, reference as FieldReference)
+            0x60..=0x6d => self.format21c(high).map(|(dest, reference)| {
+                ByteCode::Static(
+                    ArrayOperation::from(opcode),
+                    dest,
+                    FieldReference::from(reference),
+                )
             }),
-            Ok(a @ 0x6e...0x72) => self.format35c().ok().map(|(registers, reference)| {
-                ByteCode::Invoke(InvokeKind::from(a), registers, reference as MethodReference)
+            0x6e..=0x72 => self.format35c(high).map(|(registers, reference)| {
+                ByteCode::Invoke(
+                    InvokeKind::from(opcode),
+                    registers,
+                    MethodReference::from(reference),
+                )
             }),
-            Ok(a @ 0x74...0x78) => self.format3rc().ok().map(|(first, amount, reference)| {
+            0x74..=0x78 => self.format3rc(high).map(|(first, amount, reference)| {
                 ByteCode::InvokeRange(
-                    InvokeKind::from(a),
+                    InvokeKind::from(opcode),
                     first,
                     amount,
-                    reference as FieldReference,
+                    MethodReference::from(reference),
                 )
             }),
-            Ok(op @ 0x7b...0x8f) => self.format12x()
-                .ok()
-                .map(|(dest, src)| ByteCode::Unary(UnaryOperation::from(op), dest, src)),
-            Ok(op @ 0x90...0xaf) => self.format23x().ok().map(|(dest, src1, src2)| {
-                ByteCode::Binary(BinaryOperation::from(op), dest, src1, src2)
+            0x7b..=0x8f => self.format12x(high)
+                .map(|(dest, src)| ByteCode::Unary(UnaryOperation::from(opcode), dest, src)),
+            0x90..=0xaf => self.format23x(high).map(|(dest, src1, src2)| {
+                ByteCode::Binary(BinaryOperation::from(opcode), dest, src1, src2)
+            }),
+            0xb0..=0xcf => self.format12x(high).map(|(srcdest, src)| {
+                ByteCode::Binary2Addr(BinaryOperation::from(opcode), srcdest, src)
+            }),
+            0xd0..=0xd7 => self.format22s(high).map(|(dest, src, literal)| {
+                ByteCode::BinaryLit16(BinaryOperation::from(opcode), dest, src, literal)
             }),
-            Ok(op @ 0xb0...0xcf) => self.format12x().ok().map(|(srcdest, src)| {
-                ByteCode::Binary2Addr(BinaryOperation::from(op), srcdest, src)
+            0xd8..=0xe2 => self.format22b(high).map(|(dest, src, literal)| {
+                ByteCode::BinaryLit8(BinaryOperation::from(opcode), dest, src, literal)
             }),
-            Ok(op @ 0xd0...0xd7) => self.format22s().ok().map(|(dest, src, literal)| {
-                ByteCode::BinaryLit16(BinaryOperation::from(op), dest, src, literal)
+            0xfa => self.format45cc(high).map(|(registers, method, proto)| {
+                ByteCode::InvokePolymorphic(registers, u32::from(method), u32::from(proto))
             }),
-            Ok(op @ 0xd8...0xe2) => self.format22b().ok().map(|(dest, src, literal)| {
-                ByteCode::BinaryLit8(BinaryOperation::from(op), dest, src, literal)
+            0xfb => self.format4rcc(high).map(|(first, amount, method, proto)| {
+                ByteCode::InvokePolymorphicRange(first, amount, u32::from(method), u32::from(proto))
             }),
-            Ok(0xfa) => self.format45cc().ok().map(|(registers, method, proto)| {
-                ByteCode::InvokePolymorphic(registers, method as u32, proto as u32)
+            0xfc => self.format35c(high).map(|(registers, call_site)| {
+                ByteCode::InvokeCustom(registers, u32::from(call_site))
             }),
-            Ok(0xfb) => self.format4rcc()
-                .ok()
-                .map(|(first, amount, method, proto)| {
-                    ByteCode::InvokePolymorphicRange(first, amount, method as u32, proto as u32)
-                }),
-            Ok(0xfc) => self.format35c()
-                .ok()
-                .map(|(registers, call_site)| ByteCode::InvokeCustom(registers, call_site as u32)),
-            Ok(0xfd) => self.format3rc().ok().map(|(first, amount, call_site)| {
-                ByteCode::InvokeCustomRange(first, amount, call_site as u32)
+            0xfd => self.format3rc(high).map(|(first, amount, call_site)| {
+                ByteCode::InvokeCustomRange(first, amount, u32::from(call_site))
             }),
-            _ => None,
+            0xfe => self.format21c(high).map(|(dest, reference)| {
+                ByteCode::ConstMethodHandle(dest, MethodHandleReference::from(reference))
+            }),
+            0xff => self.format21c(high).map(|(dest, reference)| {
+                ByteCode::ConstMethodType(dest, PrototypeReference::from(reference))
+            }),
+            _ => Err(ErrorKind::UnknownOpcode(opcode, self.address - 1).into()),
         }
     }
 }
 
+impl<R: Read, B: ByteOrder> Iterator for ByteCodeDecoder<R, B> {
+    type Item = Result<(u32, ByteCode)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let address = self.address;
+        let result = match self.read_first_unit() {
+            Ok(Some(unit)) => self.decode(unit as u8, (unit >> 8) as u8),
+            Ok(None) => {
+                self.finished = true;
+                return None;
+            }
+            Err(e) => Err(e),
+        };
+        let result = match result {
+            Ok(code) => Ok((address, code)),
+            Err(e @ Error(ErrorKind::Io(_), _)) => {
+                Err(e).chain_err(|| ErrorKind::TruncatedInstruction(address))
+            }
+            Err(e) => Err(e),
+        };
+        self.finished = result.is_err();
+
+        Some(result)
+    }
+}
+
+/// Reader of the bytes of an array of 16-bit code units, in little endian.
+#[derive(Debug)]
+pub struct CodeUnitReader<'a> {
+    units: &'a [u16],
+    position: usize,
+}
+
+impl<'a> Read for CodeUnitReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        for byte in buf.iter_mut() {
+            match self.units.get(self.position / 2) {
+                Some(unit) => *byte = unit.to_le_bytes()[self.position % 2],
+                None => break,
+            }
+            self.position += 1;
+            read += 1;
+        }
+
+        Ok(read)
+    }
+}
+
+impl CodeItem {
+    /// Decodes the bytecode of the method.
+    pub fn decode(&self) -> ByteCodeDecoder<CodeUnitReader<'_>> {
+        ByteCodeDecoder::from_code_units(self.insns())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use byteorder::BigEndian;
 
     #[test]
     fn it_can_decode_noop() {
         let raw_opcode: &[u8] = &[0x00, 0x00];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::Nop));
         assert_eq!("nop", opcode.to_string());
@@ -1112,7 +1363,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x0e, 0x00];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::ReturnVoid));
         assert_eq!("return-void", opcode.to_string());
@@ -1123,7 +1374,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x01, 0x3B];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::Move(d, s) if d == 0xB && s == 0x3));
         assert_eq!("move v11, v3", opcode.to_string());
@@ -1134,7 +1385,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x02, 0xAA, 0x12, 0x34];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::MoveFrom16(d, s) if d == 0xAA && s == 0x3412));
         assert_eq!("move/from16 v170, v13330", opcode.to_string());
@@ -1142,10 +1393,10 @@ mod tests {
 
     #[test]
     fn it_can_decode_move_16() {
-        let raw_opcode: &[u8] = &[0x03, 0xAA, 0x01, 0x12, 0x34];
+        let raw_opcode: &[u8] = &[0x03, 0x00, 0xAA, 0x01, 0x12, 0x34];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::Move16(d, s) if d == 0x01AA && s == 0x3412));
         assert_eq!("move/16 v426, v13330", opcode.to_string());
@@ -1156,7 +1407,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x04, 0x3B];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::MoveWide(d, s) if d == 0xB && s == 0x3));
         assert_eq!("move-wide v11, v3", opcode.to_string());
@@ -1167,7 +1418,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x05, 0xAA, 0x12, 0x34];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::MoveWideFrom16(d, s) if d == 0xAA && s == 0x3412));
         assert_eq!("move-wide/from16 v170, v13330", opcode.to_string());
@@ -1175,10 +1426,10 @@ mod tests {
 
     #[test]
     fn it_can_decode_move_wide_16() {
-        let raw_opcode: &[u8] = &[0x06, 0xAA, 0x01, 0x12, 0x34];
+        let raw_opcode: &[u8] = &[0x06, 0x00, 0xAA, 0x01, 0x12, 0x34];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::MoveWide16(d, s) if d == 0x01AA && s == 0x3412));
         assert_eq!("move-wide/16 v426, v13330", opcode.to_string());
@@ -1189,7 +1440,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x07, 0x3B];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::MoveObject(d, s) if d == 0xB && s == 0x3));
         assert_eq!("move-object v11, v3", opcode.to_string());
@@ -1200,7 +1451,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x08, 0xAA, 0x12, 0x34];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::MoveObjectFrom16(d, s) if d == 0xAA && s == 0x3412));
         assert_eq!("move-object/from16 v170, v13330", opcode.to_string());
@@ -1208,10 +1459,10 @@ mod tests {
 
     #[test]
     fn it_can_decode_move_object_16() {
-        let raw_opcode: &[u8] = &[0x09, 0xAA, 0x01, 0x12, 0x34];
+        let raw_opcode: &[u8] = &[0x09, 0x00, 0xAA, 0x01, 0x12, 0x34];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::MoveObject16(d, s) if d == 0x01AA && s == 0x3412));
         assert_eq!("move-object/16 v426, v13330", opcode.to_string());
@@ -1222,7 +1473,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x0A, 0x3B];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::MoveResult(d) if d == 0x3B));
         assert_eq!("move-result v59", opcode.to_string());
@@ -1233,7 +1484,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x0B, 0x12];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::MoveResultWide(d) if d == 0x12));
         assert_eq!("move-result-wide v18", opcode.to_string());
@@ -1244,7 +1495,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x0C, 0xFF];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::MoveResultObject(d) if d == 0xFF));
         assert_eq!("move-result-object v255", opcode.to_string());
@@ -1255,7 +1506,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x0D, 0x00];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::MoveException(d) if d == 0x00));
         assert_eq!("move-exception v0", opcode.to_string());
@@ -1266,7 +1517,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x0F, 0x23];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::Return(d) if d == 0x23));
         assert_eq!("return v35", opcode.to_string());
@@ -1277,7 +1528,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x10, 0x23];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::ReturnWide(d) if d == 0x23));
         assert_eq!("return-wide v35", opcode.to_string());
@@ -1288,7 +1539,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x11, 0x23];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::ReturnObject(d) if d == 0x23));
         assert_eq!("return-object v35", opcode.to_string());
@@ -1299,7 +1550,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x12, 0xF1];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::Const4(r, i) if r == 0x1 && i == -1));
         assert_eq!("const/4 v1, #-1", opcode.to_string());
@@ -1310,7 +1561,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x12, 0x71];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert!(matches!(opcode, ByteCode::Const4(r, i) if r == 0x1 && i == 7));
         assert_eq!("const/4 v1, #7", opcode.to_string());
@@ -1321,7 +1572,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x13, 0xF1, 0xFA, 0xFB];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("const/16 v241, #-1030", opcode.to_string());
         assert!(matches!(opcode, ByteCode::Const16(r, i) if r == 0xF1 && i == -1030));
@@ -1332,7 +1583,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x14, 0x44, 0xFA, 0xFB, 0x00, 0x00];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("const v68, #64506", opcode.to_string());
         assert!(matches!(opcode, ByteCode::Const(r, i) if r == 0x44 && i == 64506));
@@ -1343,7 +1594,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x15, 0x44, 0xFF, 0xFF];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("const/high16 v68, #-65536", opcode.to_string());
         assert!(matches!(opcode, ByteCode::ConstHigh16(r, i) if r == 0x44 && i == -65536));
@@ -1354,7 +1605,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x16, 0x44, 0xFF, 0xFF];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("const-wide/16 v68, #-1", opcode.to_string());
         assert!(matches!(opcode, ByteCode::ConstWide16(r, i) if r == 0x44 && i == -1));
@@ -1365,7 +1616,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x17, 0x44, 0xFF, 0xFF, 0x00, 0x11];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("const-wide/32 v68, #285278207", opcode.to_string());
         assert!(matches!(opcode, ByteCode::ConstWide32(r, i) if r == 0x44 && i == 285278207));
@@ -1376,7 +1627,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x18, 0x01, 0x44, 0xFF, 0xFF, 0x00, 0x44, 0xFF, 0xFF, 0x00];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("const-wide v1, #72056786600853316", opcode.to_string());
         assert!(matches!(opcode, ByteCode::ConstWide(r, i) if r == 1 && i == 72056786600853316));
@@ -1387,7 +1638,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x19, 0x01, 0xFF, 0xFF];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!(
             "const-wide/high16 v1, #-281474976710656",
@@ -1403,7 +1654,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x1A, 0x01, 0xFF, 0xFF];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("const-string v1, string@65535", opcode.to_string());
         assert!(matches!(
@@ -1416,7 +1667,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x1B, 0x01, 0xFF, 0xFF, 0x00, 0x10];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!(
             "const-string/jumbo v1, string@268500991",
@@ -1432,7 +1683,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x1C, 0x01, 0x11, 0x11];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("const-class v1, class@4369", opcode.to_string());
         assert!(matches!(
@@ -1445,7 +1696,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x1D, 0x01];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("monitor-enter v1", opcode.to_string());
         assert!(matches!(opcode, ByteCode::MonitorEnter(r) if r == 1));
@@ -1456,7 +1707,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x1E, 0x9];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("monitor-exit v9", opcode.to_string());
         assert!(matches!(opcode, ByteCode::MonitorExit(r) if r == 9));
@@ -1467,7 +1718,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x1F, 0x01, 0x11, 0x11];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("check-cast v1, type@4369", opcode.to_string());
         assert!(matches!(
@@ -1480,7 +1731,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x20, 0xA2, 0x11, 0x11];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("instance-of v2, v10, type@4369", opcode.to_string());
         assert!(matches!(
@@ -1493,7 +1744,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x21, 0x2A];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("array-length v10, v2", opcode.to_string());
         assert!(matches!(
@@ -1506,7 +1757,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x22, 0x00, 0x20, 0x00];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("new-instance v0, type@32", opcode.to_string());
         assert!(matches!(opcode, ByteCode::NewInstance(d, reference) if d == 0 && reference == 32));
@@ -1517,7 +1768,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x23, 0xA9, 0x20, 0x00];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("new-array v9, v10, type@32", opcode.to_string());
         assert!(matches!(
@@ -1530,7 +1781,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x24, 0x04, 0x20, 0x00, 0x12, 0x34];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("filled-new-array {}, type@32", opcode.to_string());
         assert!(matches!(
@@ -1538,7 +1789,7 @@ mod tests {
             ByteCode::FilledNewArray(
                 ref registers,
                 reference
-            ) if registers.len() == 0 && reference == 32));
+            ) if registers.is_empty() && reference == 32));
     }
 
     #[test]
@@ -1546,7 +1797,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x24, 0x35, 0x20, 0x00, 0x21, 0x43];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("filled-new-array {v1, v2, v3}, type@32", opcode.to_string());
         assert!(matches!(
@@ -1562,7 +1813,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x24, 0x55, 0x20, 0x00, 0x21, 0x43];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!(
             "filled-new-array {v1, v2, v3, v4, v5}, type@32",
@@ -1581,7 +1832,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x24, 0x85, 0x20, 0x00, 0x21, 0x43];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!(
             "filled-new-array {v1, v2, v3, v4, v5}, type@32",
@@ -1600,7 +1851,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x25, 0x03, 0x22, 0x22, 0x01, 0x00];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!(
             "filled-new-array/range {v1, v2, v3}, type@8738",
@@ -1612,7 +1863,7 @@ mod tests {
                 start,
                 amount,
                 reference
-            ) if start == 1 && amount == 2 && reference == 8738));
+            ) if start == 1 && amount == 3 && reference == 8738));
     }
 
     #[test]
@@ -1620,7 +1871,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x26, 0x12, 0x11, 0x22, 0x33, 0xFF];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("fill-array-data v18, -13426159", opcode.to_string());
         assert!(matches!(
@@ -1633,7 +1884,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x27, 0x12];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("throw v18", opcode.to_string());
         assert!(matches!(opcode, ByteCode::Throw(reg) if reg == 18));
@@ -1644,7 +1895,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x28, 0x03];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("goto 3", opcode.to_string());
         assert!(matches!(opcode, ByteCode::Goto(offset) if offset == 3));
@@ -1655,7 +1906,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x29, 0x00, 0x03, 0x04];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("goto/16 1027", opcode.to_string());
         assert!(matches!(opcode, ByteCode::Goto16(offset) if offset == 1027));
@@ -1666,7 +1917,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x2A, 0x00, 0x03, 0x04, 0x05, 0x06];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("goto/32 100992003", opcode.to_string());
         assert!(matches!(opcode, ByteCode::Goto32(offset) if offset == 100992003));
@@ -1677,7 +1928,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x2B, 0x04, 0x03, 0x04, 0x05, 0x06];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("packed-switch v4, 100992003", opcode.to_string());
         assert!(matches!(
@@ -1690,7 +1941,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x2C, 0x04, 0x03, 0x04, 0x05, 0x06];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("sparse-switch v4, 100992003", opcode.to_string());
         assert!(matches!(
@@ -1703,7 +1954,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x2D, 0x04, 0x03, 0x02];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("cmpl-float v4, v3, v2", opcode.to_string());
         assert!(matches!(
@@ -1716,7 +1967,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x33, 0x24, 0x03, 0x02];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("if-ne v4, v2, 515", opcode.to_string());
         assert!(matches!(
@@ -1729,7 +1980,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x3B, 0x04, 0x03, 0x02];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("if-gez v4, 515", opcode.to_string());
         assert!(matches!(
@@ -1742,7 +1993,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x4D, 0x04, 0x03, 0x02];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("aput-object v4, v3, v2", opcode.to_string());
         assert!(matches!(
@@ -1755,7 +2006,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x55, 0x34, 0x03, 0x02];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("iget-boolean v4, v3, field@515", opcode.to_string());
         assert!(matches!(
@@ -1773,7 +2024,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x6d, 0x04, 0x03, 0x02];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("sput-short v4, field@515", opcode.to_string());
         assert!(matches!(
@@ -1786,7 +2037,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x6f, 0x00, 0x00, 0x01, 0x01, 0x23];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("invoke-super {}, method@256", opcode.to_string());
         assert!(matches!(
@@ -1803,10 +2054,11 @@ mod tests {
         let raw_opcode: &[u8] = &[0x78, 0x09, 0x00, 0x01, 0x00, 0x02];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!(
-            "invoke-interface/range {v512, v513, v514, v515, v516, v517, v518, v519}, method@256",
+            "invoke-interface/range {v512, v513, v514, v515, v516, v517, v518, v519, v520}, \
+             method@256",
             opcode.to_string()
         );
         assert!(matches!(
@@ -1816,7 +2068,18 @@ mod tests {
                 first_reg,
                 amount,
                 reference
-            ) if first_reg == 512 && amount == 8 && reference == 256));
+            ) if first_reg == 512 && amount == 9 && reference == 256));
+    }
+
+    #[test]
+    fn it_can_decode_an_empty_invoke_range() {
+        let raw_opcode: &[u8] = &[0x77, 0x00, 0x10, 0x00, 0x05, 0x00];
+        let mut d = ByteCodeDecoder::new(raw_opcode);
+
+        let (_, opcode) = d.next().unwrap().unwrap();
+
+        assert_eq!("invoke-static/range {}, method@16", opcode.to_string());
+        assert!(matches!(opcode, ByteCode::InvokeRange(_, 5, 0, 16)));
     }
 
     #[test]
@@ -1824,7 +2087,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0x84, 0x83];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("long-to-int v3, v8", opcode.to_string());
         assert!(matches!(opcode, ByteCode::Unary(_, dest, src) if dest == 3 &&  src == 8));
@@ -1835,7 +2098,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0xa0, 0x0f, 0x20, 0x13];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("and-long v15, v32, v19", opcode.to_string());
         assert!(matches!(
@@ -1848,7 +2111,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0xb9, 0x2f];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("shr-int/2addr v15, v2", opcode.to_string());
         assert!(matches!(
@@ -1861,7 +2124,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0xd4, 0x2f, 0xFF, 0x00];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("rem-int/lit16 v15, v2, #255", opcode.to_string());
         assert!(matches!(
@@ -1874,7 +2137,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0xd1, 0x2f, 0xFF, 0x00];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("rsub-int v15, v2, #255", opcode.to_string());
         assert!(matches!(
@@ -1887,7 +2150,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0xe2, 0x10, 0x43, 0x01];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!("ushr-int/lit8 v16, v67, #1", opcode.to_string());
         assert!(matches!(
@@ -1900,7 +2163,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0xfa, 0x50, 0x00, 0x01, 0x21, 0x43, 0x10, 0x00];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!(
             "invoke-polymorphic {v1, v2, v3, v4, v0}, method@256 proto@16",
@@ -1917,16 +2180,16 @@ mod tests {
         let raw_opcode: &[u8] = &[0xfb, 0x04, 0x10, 0x00, 0x01, 0x00, 0x01, 0x00];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!(
-            "invoke-polymorphic/range {v1, v2, v3}, method@16 proto@1",
+            "invoke-polymorphic/range {v1, v2, v3, v4}, method@16 proto@1",
             opcode.to_string()
         );
         assert!(matches!(
             opcode,
             ByteCode::InvokePolymorphicRange(start, amount, method, proto
-        ) if method == 16 && proto == 1 && start == 1 && amount == 3));
+        ) if method == 16 && proto == 1 && start == 1 && amount == 4));
     }
 
     #[test]
@@ -1934,7 +2197,7 @@ mod tests {
         let raw_opcode: &[u8] = &[0xfc, 0x50, 0x00, 0x01, 0x21, 0x43];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!(
             "invoke-custom {v1, v2, v3, v4, v0}, call_site@256",
@@ -1951,15 +2214,171 @@ mod tests {
         let raw_opcode: &[u8] = &[0xfd, 0x04, 0x10, 0x00, 0x01, 0x00];
         let mut d = ByteCodeDecoder::new(raw_opcode);
 
-        let opcode = d.nth(0).unwrap();
+        let (_, opcode) = d.next().unwrap().unwrap();
 
         assert_eq!(
-            "invoke-custom/range {v1, v2, v3}, call_site@16",
+            "invoke-custom/range {v1, v2, v3, v4}, call_site@16",
             opcode.to_string()
         );
         assert!(matches!(
             opcode,
             ByteCode::InvokeCustomRange(first, amount, call_site
-        ) if first == 1 && amount == 3 && call_site == 16));
+        ) if first == 1 && amount == 4 && call_site == 16));
+    }
+
+    #[test]
+    fn it_can_decode_const_method_handle() {
+        let raw_opcode: &[u8] = &[0xfe, 0x03, 0x10, 0x00];
+        let mut d = ByteCodeDecoder::new(raw_opcode);
+
+        let (_, opcode) = d.next().unwrap().unwrap();
+
+        assert_eq!("const-method-handle v3, method_handle@16", opcode.to_string());
+        assert!(matches!(opcode, ByteCode::ConstMethodHandle(3, 16)));
+    }
+
+    #[test]
+    fn it_can_decode_const_method_type() {
+        let raw_opcode: &[u8] = &[0xff, 0x03, 0x10, 0x00];
+        let mut d = ByteCodeDecoder::new(raw_opcode);
+
+        let (_, opcode) = d.next().unwrap().unwrap();
+
+        assert_eq!("const-method-type v3, proto@16", opcode.to_string());
+        assert!(matches!(opcode, ByteCode::ConstMethodType(3, 16)));
+    }
+
+    #[test]
+    fn it_yields_instruction_addresses() {
+        let raw_opcode: &[u8] = &[0x12, 0x10, 0x13, 0x01, 0xFF, 0x7F, 0x0e, 0x00];
+        let d = ByteCodeDecoder::new(raw_opcode);
+
+        let addresses = d.map(|result| result.unwrap().0).collect::<Vec<_>>();
+
+        assert_eq!(addresses, [0, 1, 3]);
+    }
+
+    #[test]
+    fn it_fails_on_unknown_opcodes() {
+        let raw_opcode: &[u8] = &[0x00, 0x00, 0x73, 0x00, 0x0e, 0x00];
+        let mut d = ByteCodeDecoder::new(raw_opcode);
+
+        assert!(matches!(d.next(), Some(Ok((0, ByteCode::Nop)))));
+        let error = d.next().unwrap().unwrap_err();
+        assert!(matches!(*error.kind(), ErrorKind::UnknownOpcode(0x73, 1)));
+        assert!(d.next().is_none());
+    }
+
+    #[test]
+    fn it_fails_on_truncated_instructions() {
+        let raw_opcode: &[u8] = &[0x0e, 0x00, 0x14, 0x01, 0x34, 0x12];
+        let mut d = ByteCodeDecoder::new(raw_opcode);
+
+        assert!(matches!(d.next(), Some(Ok((0, ByteCode::ReturnVoid)))));
+        let error = d.next().unwrap().unwrap_err();
+        assert!(matches!(*error.kind(), ErrorKind::TruncatedInstruction(1)));
+        assert!(d.next().is_none());
+    }
+
+    #[test]
+    fn it_can_decode_big_endian_bytecode() {
+        let raw_opcode: &[u8] = &[0x01, 0x14, 0x56, 0x78, 0x12, 0x34];
+        let mut d = ByteCodeDecoder::<_, BigEndian>::with_byte_order(raw_opcode);
+
+        let (_, opcode) = d.next().unwrap().unwrap();
+
+        assert!(matches!(opcode, ByteCode::Const(1, 0x1234_5678)));
+    }
+
+    #[test]
+    fn it_can_decode_code_units() {
+        let units = [0x0112, 0x0213, 0xFFFF, 0x1024, 0x0011, 0x0001, 0x000e];
+        let d = ByteCodeDecoder::from_code_units(&units);
+
+        let instructions = d.map(|result| result.unwrap().1.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            instructions,
+            ["const/4 v1, #0", "const/16 v2, #-1", "filled-new-array {v1}, type@17", "return-void"]
+        );
+    }
+
+    #[test]
+    fn it_can_decode_packed_switch_payload() {
+        let units = [0x0100, 0x0002, 0xFFFF, 0xFFFF, 0x0005, 0x0000, 0xFFFE, 0xFFFF, 0x000e];
+        let mut d = ByteCodeDecoder::from_code_units(&units);
+
+        let (_, opcode) = d.next().unwrap().unwrap();
+
+        assert_eq!("packed-switch-payload {-1: 5, 0: -2}", opcode.to_string());
+        match opcode {
+            ByteCode::Payload(Payload::PackedSwitch(ref switch)) => {
+                assert_eq!(switch.keys(), [-1, 0]);
+                assert_eq!(switch.offsets(), [5, -2]);
+                assert_eq!(switch.cases(10), [(-1, 15), (0, 8)]);
+            }
+            _ => panic!("expected a packed-switch payload"),
+        }
+        assert!(matches!(d.next(), Some(Ok((8, ByteCode::ReturnVoid)))));
+    }
+
+    #[test]
+    fn it_can_decode_sparse_switch_payload() {
+        let units = [0x0200, 0x0002, 0x000A, 0x0000, 0x0000, 0x0001, 0x0003, 0x0000, 0x0007,
+                     0x0000];
+        let mut d = ByteCodeDecoder::from_code_units(&units);
+
+        let (_, opcode) = d.next().unwrap().unwrap();
+
+        assert_eq!("sparse-switch-payload {10: 3, 65536: 7}", opcode.to_string());
+        assert!(matches!(
+            opcode,
+            ByteCode::Payload(Payload::SparseSwitch(ref switch))
+                if switch.cases(2) == [(10, 5), (65_536, 9)]));
+        assert!(d.next().is_none());
+    }
+
+    #[test]
+    fn it_can_decode_fill_array_data_payload() {
+        let units = [0x0300, 0x0002, 0x0003, 0x0000, 0x0001, 0xFFFF, 0x0102, 0x000e];
+        let mut d = ByteCodeDecoder::from_code_units(&units);
+
+        let (_, opcode) = d.next().unwrap().unwrap();
+
+        assert_eq!(
+            "fill-array-data-payload 2-byte elements {1, -1, 258}",
+            opcode.to_string()
+        );
+        assert!(matches!(
+            opcode,
+            ByteCode::Payload(Payload::FillArrayData(ArrayData::Short(ref values)))
+                if *values == [1, -1, 258]));
+        assert!(matches!(d.next(), Some(Ok((7, ByteCode::ReturnVoid)))));
+    }
+
+    #[test]
+    fn it_can_decode_padded_fill_array_data_payload() {
+        let raw_opcode: &[u8] = &[0x03, 0x00, 0x00, 0x01, 0x00, 0x03, 0x00, 0x00, 0x01, 0x02,
+                                  0x03, 0x00, 0x00, 0x0e];
+        let mut d = ByteCodeDecoder::<_, BigEndian>::with_byte_order(raw_opcode);
+
+        let (_, opcode) = d.next().unwrap().unwrap();
+
+        assert!(matches!(
+            opcode,
+            ByteCode::Payload(Payload::FillArrayData(ArrayData::Byte(ref values)))
+                if *values == [1, 2, 3]));
+        assert!(matches!(d.next(), Some(Ok((6, ByteCode::ReturnVoid)))));
+    }
+
+    #[test]
+    fn it_fails_on_invalid_element_widths() {
+        let units = [0x0000, 0x0300, 0x0003, 0x0001, 0x0000, 0x0001, 0x0002];
+        let mut d = ByteCodeDecoder::from_code_units(&units);
+
+        assert!(matches!(d.next(), Some(Ok((0, ByteCode::Nop)))));
+        let error = d.next().unwrap().unwrap_err();
+        assert!(matches!(*error.kind(), ErrorKind::InvalidElementWidth(3, 1)));
     }
 }
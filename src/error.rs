@@ -2,141 +2,427 @@
 
 #![allow(missing_docs)]
 
-use std::u32;
-use sizes::HEADER_SIZE;
-use header::{ENDIAN_CONSTANT, REVERSE_ENDIAN_CONSTANT};
-
-error_chain!{
-    foreign_links {
-        Io(::std::io::Error);
-        FromUTF8(::std::string::FromUtf8Error);
-    }
+use std::fmt;
+use std::iter;
 
-    errors {
-        /// Incorrect dex magic number.
-        IncorrectMagic(dex_magic: [u8; 8]) {
-            description("incorrect dex magic number")
-            display("incorrect dex magic number: {:?}", dex_magic)
-        }
+use types::ItemType;
 
-        /// Mismatch between file size in header and real file size.
-        HeaderFileSizeMismatch(file_size: u64, size_in_header: u32) {
-            description("invalid dex file size in header")
-            display("invalid dex file size")
-        }
+pub use self::chain::*;
 
-        /// Invalid file size.
-        InvalidFileSize(file_size: u64) {
-            description("invalid dex file size")
-            display("invalid dex file size: file size must be between {} and {} bytes, \
-                     but the size of the file was {} bytes", HEADER_SIZE, u32::MAX, file_size)
-        }
+/// Errors generated by `error_chain!`, whose code overrides deprecated `Error` methods.
+#[allow(deprecated)]
+mod chain {
+    use sizes::HEADER_SIZE;
+    use header::{ENDIAN_CONSTANT, REVERSE_ENDIAN_CONSTANT};
+    use super::{to_hex, ItemLocation};
 
-        /// Invalid endian tag.
-        InvalidEndianTag(endian_tag: u32) {
-            description("invalid dex endian tag")
-            display("invalid dex endian tag: {:#010x}, it can only be `ENDIAN_CONSTANT` ({:#010x}) \
-                     or `REVERSE_ENDIAN_CONSTANT` ({:#010x})", endian_tag, ENDIAN_CONSTANT,
-                    REVERSE_ENDIAN_CONSTANT)
+    error_chain!{
+        foreign_links {
+            Io(::std::io::Error);
+            FromUTF8(::std::string::FromUtf8Error);
         }
 
-        /// Incorrect header size.
-        IncorrectHeaderSize(header_size: u32) {
-            description("incorrect header size")
-            display("invalid dex header_size: {} bytes, it can only be {} bytes", header_size,
-                    HEADER_SIZE)
-        }
+        errors {
+            /// Incorrect dex magic number.
+            IncorrectMagic(dex_magic: [u8; 8]) {
+                description("incorrect dex magic number")
+                display("incorrect dex magic number: {:?}", dex_magic)
+            }
 
-        /// Invalid offset.
-        InvalidOffset(desc: String) {
-            description("invalid offset")
-            display("invalid offset: {}", desc)
-        }
+            /// Mismatch between file size in header and real file size.
+            HeaderFileSizeMismatch(file_size: u64, size_in_header: u32) {
+                description("invalid dex file size in header")
+                display("invalid dex file size")
+            }
 
-        /// Mismatched offsets.
-        MismatchedOffsets(offset_name: &'static str, current_offset: u32, expected_offset: u32) {
-            description("mismatched offsets")
-            display("mismatched `{}` offsets: expected {:#010x}, current offset {:#010x}",
-                    offset_name, expected_offset, current_offset)
-        }
+            /// The file ends before the header of the dex file.
+            TruncatedBeforeHeader(file_size: u64, header_offset: u32) {
+                description("file ends before the dex header")
+                display("the file ends after {} bytes, before the dex header at offset {:#010x}",
+                        file_size, header_offset)
+            }
 
-        /// Unknown string index.
-        UnknownStringIndex(index: u32) {
-            description("unknown string index")
-            display("there is no string with index {}", index)
-        }
+            /// Invalid file size.
+            InvalidFileSize(file_size: u64) {
+                description("invalid dex file size")
+                display("invalid dex file size: file size must be between {} and {} bytes, \
+                         but the size of the file was {} bytes", HEADER_SIZE, u32::MAX, file_size)
+            }
 
-        /// Unknown type index.
-        UnknownTypeIndex(index: u16) {
-            description("unknown type index")
-            display("there is no type with index {}", index)
-        }
+            /// Checksum mismatch.
+            ChecksumMismatch(expected: u32, actual: u32) {
+                description("checksum mismatch")
+                display("checksum mismatch: the header has {:#010x}, but the file has {:#010x}",
+                        expected, actual)
+            }
 
-        /// Invalid type descriptor.
-        InvalidTypeDescriptor(descriptor: String) {
-            description("invalid type descriptor")
-            display("invalid type descriptor: `{}`", descriptor)
-        }
+            /// SHA-1 signature mismatch.
+            SignatureMismatch(expected: [u8; 20], actual: [u8; 20]) {
+                description("SHA-1 signature mismatch")
+                display("SHA-1 signature mismatch: the header has {}, but the file has {}",
+                        to_hex(expected), to_hex(actual))
+            }
 
-        /// Invalid shorty type.
-        InvalidShortyType(shorty_type: char) {
-            description("invalid shorty type")
-            display("invalid shorty type: `{}`", shorty_type)
-        }
+            /// Invalid endian tag.
+            InvalidEndianTag(endian_tag: u32) {
+                description("invalid dex endian tag")
+                display("invalid dex endian tag: {:#010x}, it can only be `ENDIAN_CONSTANT` \
+                         ({:#010x}) or `REVERSE_ENDIAN_CONSTANT` ({:#010x})", endian_tag,
+                        ENDIAN_CONSTANT, REVERSE_ENDIAN_CONSTANT)
+            }
 
-        // Invalid shorty descriptor.
-        InvalidShortyDescriptor(descriptor: String) {
-            description("invalid shorty descriptor")
-            display("invalid shorty descriptor: `{}`", descriptor)
-        }
+            /// Incorrect header size.
+            IncorrectHeaderSize(header_size: u32, expected_size: u32) {
+                description("incorrect header size")
+                display("invalid dex header_size: {} bytes, it can only be {} bytes", header_size,
+                        expected_size)
+            }
 
-        /// Invalid access flags.
-        InvalidAccessFlags(access_flags: u32) {
-            description("invalid access flags")
-            display("invalid access flags: {:#010x}", access_flags)
-        }
+            /// Invalid offset.
+            InvalidOffset(desc: String) {
+                description("invalid offset")
+                display("invalid offset: {}", desc)
+            }
 
-        /// Invalid item type.
-        InvalidItemType(item_type: u16) {
-            description("invalid item type")
-            display("invalid item type: {:#06x}", item_type)
-        }
+            /// Mismatched offsets.
+            MismatchedOffsets(offset_name: &'static str, current_offset: u32,
+                              expected_offset: u32) {
+                description("mismatched offsets")
+                display("mismatched `{}` offsets: expected {:#010x}, current offset {:#010x}",
+                        offset_name, expected_offset, current_offset)
+            }
 
-        /// Invalid visibility modifier.
-        InvalidVisibility(visibility: u8) {
-            description("invalid visibility modifier")
-            display("invalid visibility modifier: {:#04x}", visibility)
-        }
+            /// Unknown string index.
+            UnknownStringIndex(index: u32) {
+                description("unknown string index")
+                display("there is no string with index {}", index)
+            }
 
-        /// Invalid value.
-        InvalidValue(error: String) {
-            description("invalid value")
-            display("invalid value: {}", error)
-        }
+            /// Unknown type index.
+            UnknownTypeIndex(index: u32) {
+                description("unknown type index")
+                display("there is no type with index {}", index)
+            }
 
-        /// String size mismatch.
-        StringSizeMismatch(expected_size: u32, actual_size: usize) {
-            description("string size mismatch")
-            display("string size mismatch: expected {} characters, found {}", expected_size,
-                    actual_size)
-        }
+            /// Unknown field index.
+            UnknownFieldIndex(index: u32) {
+                description("unknown field index")
+                display("there is no field with index {}", index)
+            }
+
+            /// Unknown method index.
+            UnknownMethodIndex(index: u32) {
+                description("unknown method index")
+                display("there is no method with index {}", index)
+            }
+
+            /// Unknown class definition index.
+            UnknownClassDefIndex(index: u32) {
+                description("unknown class definition index")
+                display("there is no class definition with index {}", index)
+            }
+
+            /// The contents of the dex file were not kept after loading it.
+            ContentsNotKept {
+                description("contents not kept")
+                display("the contents of the dex file were not kept after loading it")
+            }
+
+            /// Invalid type descriptor.
+            InvalidTypeDescriptor(descriptor: String) {
+                description("invalid type descriptor")
+                display("invalid type descriptor: `{}`", descriptor)
+            }
+
+            /// Invalid shorty type.
+            InvalidShortyType(shorty_type: char) {
+                description("invalid shorty type")
+                display("invalid shorty type: `{}`", shorty_type)
+            }
+
+            // Invalid shorty descriptor.
+            InvalidShortyDescriptor(descriptor: String) {
+                description("invalid shorty descriptor")
+                display("invalid shorty descriptor: `{}`", descriptor)
+            }
+
+            /// Invalid access flags.
+            InvalidAccessFlags(access_flags: u32) {
+                description("invalid access flags")
+                display("invalid access flags: {:#010x}", access_flags)
+            }
+
+            /// Invalid item type.
+            InvalidItemType(item_type: u16) {
+                description("invalid item type")
+                display("invalid item type: {:#06x}", item_type)
+            }
+
+            /// Invalid method handle type.
+            InvalidMethodHandleType(handle_type: u16) {
+                description("invalid method handle type")
+                display("invalid method handle type: {:#06x}", handle_type)
+            }
+
+            /// Invalid hidden API flags.
+            InvalidHiddenApiFlags(flags: u32) {
+                description("invalid hidden API flags")
+                display("invalid hidden API flags: {:#010x}", flags)
+            }
+
+            /// Invalid visibility modifier.
+            InvalidVisibility(visibility: u8) {
+                description("invalid visibility modifier")
+                display("invalid visibility modifier: {:#04x}", visibility)
+            }
+
+            /// Invalid value.
+            InvalidValue(error: String) {
+                description("invalid value")
+                display("invalid value: {}", error)
+            }
+
+            /// String size mismatch.
+            StringSizeMismatch(expected_size: u32, actual_size: usize) {
+                description("string size mismatch")
+                display("string size mismatch: expected {} UTF-16 code units, found {}",
+                        expected_size, actual_size)
+            }
+
+            /// Invalid Modified UTF-8 string.
+            InvalidMutf8(error: String) {
+                description("invalid MUTF-8 string")
+                display("invalid MUTF-8 string: {}", error)
+            }
+
+            /// Invalid uleb128.
+            InvalidLeb128 {
+                description("invalid uleb128")
+                display("invalid uleb128: an uleb128 with more than 5 bytes was found")
+            }
+
+            /// Invalid ZIP archive.
+            InvalidArchive(error: String) {
+                description("invalid ZIP archive")
+                display("invalid ZIP archive: {}", error)
+            }
+
+            /// Unsupported compression method of an archive entry.
+            UnsupportedCompression(entry: String, method: u16) {
+                description("unsupported compression method")
+                display("unsupported compression method {} for archive entry {}", method, entry)
+            }
+
+            /// CRC-32 mismatch of an archive entry.
+            ArchiveCrcMismatch(entry: String, expected: u32, actual: u32) {
+                description("archive entry CRC-32 mismatch")
+                display("CRC-32 mismatch for archive entry {}: expected {:#010x}, found {:#010x}",
+                        entry, expected, actual)
+            }
+
+            /// Missing archive entry.
+            MissingArchiveEntry(entry: String) {
+                description("missing archive entry")
+                display("the archive has no {} entry", entry)
+            }
+
+            /// Invalid VDEX file.
+            InvalidVdex(error: String) {
+                description("invalid VDEX file")
+                display("invalid VDEX file: {}", error)
+            }
 
-        /// Invalid uleb128.
-        InvalidLeb128 {
-            description("invalid uleb128")
-            display("invalid uleb128: an uleb128 with more than 5 bytes was found")
+            /// Unsupported VDEX file version.
+            UnsupportedVdexVersion(version: [u8; 4]) {
+                description("unsupported VDEX version")
+                display("unsupported VDEX version: {}", String::from_utf8_lossy(&version[..3]))
+            }
+
+            /// Invalid ELF file.
+            InvalidElf(error: String) {
+                description("invalid ELF file")
+                display("invalid ELF file: {}", error)
+            }
+
+            /// Invalid OAT file.
+            InvalidOat(error: String) {
+                description("invalid OAT file")
+                display("invalid OAT file: {}", error)
+            }
+
+            /// Unsupported OAT file version.
+            UnsupportedOatVersion(version: [u8; 4]) {
+                description("unsupported OAT version")
+                display("unsupported OAT version: {}, only the OAT files of Android 5 to 7 \
+                         (versions 039 to 088) hold dex files, later ones keep them in VDEX \
+                         files",
+                        String::from_utf8_lossy(&version[..3]))
+            }
+
+            /// Unknown bytecode opcode.
+            UnknownOpcode(opcode: u8, address: u32) {
+                description("unknown opcode")
+                display("unknown opcode {:#04x} at address {:#06x}", opcode, address)
+            }
+
+            /// Instruction cut by the end of the bytecode.
+            TruncatedInstruction(address: u32) {
+                description("truncated instruction")
+                display("truncated instruction at address {:#06x}", address)
+            }
+
+            /// Invalid element width in a `fill-array-data` payload.
+            InvalidElementWidth(width: u16, address: u32) {
+                description("invalid array element width")
+                display("invalid element width {} in the array data payload at address {:#06x}",
+                        width, address)
+            }
+
+            /// Generic header error.
+            Header(error: String) {
+                description("error in dex header")
+                display("error in dex header: {}", error)
+            }
+
+            /// Generic map error.
+            Map(error: String) {
+                description("error in dex map")
+                display("error in dex map: {}", error)
+            }
+
+            /// Generic call site error.
+            CallSite(error: String) {
+                description("error in call site")
+                display("error in call site: {}", error)
+            }
+
+            /// Generic debug information error.
+            DebugInfo(error: String) {
+                description("error in debug information")
+                display("error in debug information: {}", error)
+            }
+
+            /// Error reading an item of the dex file. The error that caused it is the next one in
+            /// the chain.
+            Item(location: ItemLocation) {
+                description("could not read dex file item")
+                display("could not read {}", location)
+            }
         }
+    }
+}
+
+impl Error {
+    /// Gets the locations of the items that were being read when the error happened, from the
+    /// outermost to the innermost one.
+    ///
+    /// For example, an error in the code of a method has the location of the class definition,
+    /// the class data and the code item.
+    pub fn locations(&self) -> Vec<&ItemLocation> {
+        self.dex_errors()
+            .filter_map(|error| match *error.kind() {
+                ErrorKind::Item(ref location) => Some(location),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Gets the location of the innermost item that was being read when the error happened, if
+    /// it is known.
+    pub fn location(&self) -> Option<&ItemLocation> {
+        self.locations().pop()
+    }
+
+    /// Gets the kind of the underlying error, the last error of this crate in the chain.
+    pub fn root_kind(&self) -> &ErrorKind {
+        self.dex_errors().last().unwrap_or(self).kind()
+    }
 
-        /// Generic header error.
-        Header(error: String) {
-            description("error in dex header")
-            display("error in dex header: {}", error)
+    /// Moves the locations of the items in the error by the given offset, to make them relative
+    /// to the start of the file for dex files that don't start at its beginning.
+    pub(crate) fn with_base_offset(mut self, base_offset: u64) -> Error {
+        let mut error = Some(&mut self);
+        while let Some(current) = error {
+            if let ErrorKind::Item(ref mut location) = current.0 {
+                location.offset += base_offset;
+            }
+            error = current
+                .1
+                .next_error
+                .as_mut()
+                .and_then(|next| next.downcast_mut::<Error>());
         }
+        self
+    }
+
+    /// Iterates over the errors of this crate in the chain, starting from this one.
+    fn dex_errors(&self) -> impl Iterator<Item = &Error> {
+        iter::successors(Some(self), |error| {
+            error
+                .1
+                .next_error
+                .as_ref()
+                .and_then(|next| next.downcast_ref::<Error>())
+        })
+    }
+}
+
+impl ErrorKind {
+    /// Creates the kind of the error of the item of the given type at the given index and offset.
+    pub(crate) fn item<I: Into<Option<u32>>>(
+        item_type: ItemType,
+        index: I,
+        offset: u64,
+    ) -> ErrorKind {
+        ErrorKind::Item(ItemLocation {
+            item_type,
+            index: index.into(),
+            offset,
+        })
+    }
+}
+
+/// Location of an item of a dex file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ItemLocation {
+    item_type: ItemType,
+    index: Option<u32>,
+    offset: u64,
+}
 
-        /// Generic map error.
-        Map(error: String) {
-            description("error in dex map")
-            display("error in dex map: {}", error)
+impl ItemLocation {
+    /// Gets the type of the item.
+    pub fn item_type(&self) -> ItemType {
+        self.item_type
+    }
+
+    /// Gets the index of the item in its list, for items in the identifier lists and other
+    /// indexed lists.
+    pub fn index(&self) -> Option<u32> {
+        self.index
+    }
+
+    /// Gets the offset of the item in the file or the container the dex file was read from,
+    /// including for dex files in VDEX files.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl fmt::Display for ItemLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.index {
+            Some(index) => write!(
+                f,
+                "{} {} at offset {:#010x}",
+                self.item_type, index, self.offset
+            ),
+            None => write!(f, "{} at offset {:#010x}", self.item_type, self.offset),
         }
     }
 }
+
+/// Formats the given bytes as a lowercase hexadecimal string.
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -1,10 +1,12 @@
 //! Module containing the Dex file header.
 
 use std::path::Path;
-use std::{fmt, fs, u32};
-use std::io::{BufReader, Read};
+use std::{fmt, fs};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
 
+use adler::{self, Adler32};
 use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
+use sha1_smol::Sha1;
 
 use error::*;
 use sizes::*;
@@ -13,6 +15,60 @@ use sizes::*;
 pub const ENDIAN_CONSTANT: u32 = 0x12345678;
 /// Endianness constant representing big endian file.
 pub const REVERSE_ENDIAN_CONSTANT: u32 = 0x78563412;
+/// First dex version that can have several dex files in a container.
+pub const CONTAINER_VERSION: u8 = 41;
+/// Feature flag of compact dex files that use default methods.
+pub const COMPACT_DEX_DEFAULT_METHODS: u32 = 0x1;
+
+/// Fields of the header of compact dex files that standard dex files do not have.
+///
+/// Compact dex files (with `cdex` magic) are generated by the Android runtime from standard dex
+/// files. Offsets in their data section are relative to the start of the data section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactDexHeader {
+    feature_flags: u32,
+    debug_info_offsets_position: u32,
+    debug_info_offsets_table_offset: u32,
+    debug_info_base: u32,
+    owned_data_begin: u32,
+    owned_data_end: u32,
+}
+
+impl CompactDexHeader {
+    /// Gets the feature flags of the compact dex file.
+    pub fn feature_flags(&self) -> u32 {
+        self.feature_flags
+    }
+
+    /// Gets the position of the debug information offsets table, relative to the data section.
+    pub fn debug_info_offsets_position(&self) -> u32 {
+        self.debug_info_offsets_position
+    }
+
+    /// Gets the offset of the index of the debug information offsets table, relative to the
+    /// position of the table.
+    pub fn debug_info_offsets_table_offset(&self) -> u32 {
+        self.debug_info_offsets_table_offset
+    }
+
+    /// Gets the minimum debug information offset, that all the offsets in the table are relative
+    /// to.
+    pub fn debug_info_base(&self) -> u32 {
+        self.debug_info_base
+    }
+
+    /// Gets the start of the part of the data section owned by this dex file.
+    ///
+    /// The data section can be shared between several compact dex files in a VDEX file.
+    pub fn owned_data_begin(&self) -> u32 {
+        self.owned_data_begin
+    }
+
+    /// Gets the end of the part of the data section owned by this dex file.
+    pub fn owned_data_end(&self) -> u32 {
+        self.owned_data_end
+    }
+}
 
 /// Dex header representantion structure.
 pub struct Header {
@@ -39,6 +95,10 @@ pub struct Header {
     class_defs_offset: Option<u32>,
     data_size: u32,
     data_offset: u32,
+    container_size: Option<u32>,
+    header_offset: u32,
+    ids_end: u32,
+    compact_dex: Option<CompactDexHeader>,
 }
 
 impl Header {
@@ -54,13 +114,76 @@ impl Header {
         let header = Header::from_reader(BufReader::new(f)).chain_err(|| {
             ErrorKind::Header("there was an error reading the header of the dex file".to_owned())
         })?;
-        if file_size == header.get_file_size() as u64 {
+        let size_in_header = header.get_expected_size();
+        if file_size == size_in_header as u64 {
             Ok(header)
         } else {
-            Err(ErrorKind::HeaderFileSizeMismatch(file_size, header.get_file_size()).into())
+            Err(ErrorKind::HeaderFileSizeMismatch(file_size, size_in_header).into())
+        }
+    }
+
+    /// Obtains the headers of all the dex files in a Dex file.
+    ///
+    /// Dex files of version 041 or later are containers that can hold several dex files. Older
+    /// files hold a single one.
+    pub fn all_from_file<P: AsRef<Path>>(path: P) -> Result<Vec<Header>> {
+        let f = fs::File::open(path).chain_err(|| "could not open file")?;
+        let file_size = f.metadata()
+            .chain_err(|| "could not read file metadata")?
+            .len();
+        let headers = Header::all_from_reader(&mut BufReader::new(f))?;
+        let size_in_header = headers[0].get_expected_size();
+        if file_size == size_in_header as u64 {
+            Ok(headers)
+        } else {
+            Err(ErrorKind::HeaderFileSizeMismatch(file_size, size_in_header).into())
         }
     }
 
+    /// Obtains the headers of all the dex files in a Dex file reader.
+    ///
+    /// The reader should be positioned at the start of the file.
+    pub fn all_from_reader<R: Read + Seek>(reader: &mut R) -> Result<Vec<Header>> {
+        let mut headers: Vec<Header> = Vec::new();
+        let mut next_offset = 0_u64;
+        loop {
+            reader
+                .seek(SeekFrom::Start(next_offset))
+                .chain_err(|| format!("could not seek to offset {:#010x}", next_offset))?;
+            let header = Header::from_reader(&mut *reader).chain_err(|| {
+                ErrorKind::Header(format!(
+                    "there was an error reading the header at offset {:#010x}",
+                    next_offset
+                ))
+            })?;
+            if header.get_header_offset() as u64 != next_offset {
+                return Err(ErrorKind::MismatchedOffsets(
+                    "header_offset",
+                    header.get_header_offset(),
+                    next_offset as u32,
+                ).into());
+            }
+            if let Some(first) = headers.first() {
+                if header.get_container_size() != first.get_container_size() {
+                    return Err(ErrorKind::Header(format!(
+                        "the header at offset {:#010x} has a different container size",
+                        next_offset
+                    )).into());
+                }
+            }
+
+            next_offset = header.get_header_offset() as u64 + header.get_file_size() as u64;
+            let container_size = header.get_container_size();
+            headers.push(header);
+            match container_size {
+                Some(container_size) if next_offset < container_size as u64 => {}
+                _ => break,
+            }
+        }
+
+        Ok(headers)
+    }
+
     /// Obtains the header from a Dex file reader.
     pub fn from_reader<R: Read>(mut reader: R) -> Result<Header> {
         // Magic number
@@ -104,8 +227,15 @@ impl Header {
         }
 
         // Check header size
-        if header_size != HEADER_SIZE {
-            return Err(ErrorKind::IncorrectHeaderSize(header_size).into());
+        let expected_header_size = if Header::is_compact_magic(&magic) {
+            HEADER_SIZE_COMPACT
+        } else if Header::version_from_magic(&magic) >= CONTAINER_VERSION {
+            HEADER_SIZE_V41
+        } else {
+            HEADER_SIZE
+        };
+        if header_size != expected_header_size {
+            return Err(ErrorKind::IncorrectHeaderSize(header_size, expected_header_size).into());
         }
 
         if endian_tag == ENDIAN_CONSTANT {
@@ -148,7 +278,6 @@ impl Header {
                 None
             }
         }
-        let mut current_offset = HEADER_SIZE;
 
         // Link size
         let link_size = reader
@@ -158,22 +287,10 @@ impl Header {
         let link_offset = reader
             .read_u32::<E>()
             .chain_err(|| "could not read the link section offset")?;
-        if link_size == 0 && link_offset != 0 {
-            return Err(ErrorKind::MismatchedOffsets("link_offset", link_offset, 0).into());
-        }
-
         // Map offset
         let map_offset = reader
             .read_u32::<E>()
             .chain_err(|| "could not read the map section offset")?;
-        if map_offset == 0x00000000 {
-            return Err(ErrorKind::InvalidOffset(
-                "`map_offset` was 0x00000000, and it can never \
-                 be zero"
-                    .to_owned(),
-            ).into());
-        }
-
         // String IDs size
         let string_ids_size = reader
             .read_u32::<E>()
@@ -182,11 +299,117 @@ impl Header {
         let string_ids_offset = reader
             .read_u32::<E>()
             .chain_err(|| "could not read the string IDs list offset")?;
+        // Types IDs size
+        let type_ids_size = reader
+            .read_u32::<E>()
+            .chain_err(|| "could not read the type IDs list size")?;
+        // Types IDs offset
+        let type_ids_offset = reader
+            .read_u32::<E>()
+            .chain_err(|| "could not read the type IDs list offset")?;
+        // Prototype IDs size
+        let prototype_ids_size = reader
+            .read_u32::<E>()
+            .chain_err(|| "could not read the prototype IDs list size")?;
+        // Prototype IDs offset
+        let prototype_ids_offset = reader
+            .read_u32::<E>()
+            .chain_err(|| "could not read the prototype IDs list offset")?;
+        // Field IDs size
+        let field_ids_size = reader
+            .read_u32::<E>()
+            .chain_err(|| "could not read the field IDs list size")?;
+        // Field IDs offset
+        let field_ids_offset = reader
+            .read_u32::<E>()
+            .chain_err(|| "could not read the field IDs list offset")?;
+        // Method IDs size
+        let method_ids_size = reader
+            .read_u32::<E>()
+            .chain_err(|| "could not read the method IDs list size")?;
+        // Method IDs offset
+        let method_ids_offset = reader
+            .read_u32::<E>()
+            .chain_err(|| "could not read the method IDs list offset")?;
+        // Class defs size
+        let class_defs_size = reader
+            .read_u32::<E>()
+            .chain_err(|| "could not read the class definitions list size")?;
+        // Class defs offset
+        let class_defs_offset = reader
+            .read_u32::<E>()
+            .chain_err(|| "could not read the class definitions list offset")?;
+        // Data size
+        let data_size = reader
+            .read_u32::<E>()
+            .chain_err(|| "could not read the data section size")?;
+        // Data offset
+        let data_offset = reader
+            .read_u32::<E>()
+            .chain_err(|| "could not read the data section offset")?;
+
+        // Container fields, only present from version 041.
+        let (container_size, header_offset) = if header_size == HEADER_SIZE_V41 {
+            let container_size = reader
+                .read_u32::<E>()
+                .chain_err(|| "could not read the container size")?;
+            let header_offset = reader
+                .read_u32::<E>()
+                .chain_err(|| "could not read the header offset")?;
+            (Some(container_size), header_offset)
+        } else {
+            (None, 0)
+        };
+        let compact_dex = if Header::is_compact_magic(&magic) {
+            Some(CompactDexHeader {
+                feature_flags: reader
+                    .read_u32::<E>()
+                    .chain_err(|| "could not read the compact dex feature flags")?,
+                debug_info_offsets_position: reader
+                    .read_u32::<E>()
+                    .chain_err(|| "could not read the debug information offsets position")?,
+                debug_info_offsets_table_offset: reader
+                    .read_u32::<E>()
+                    .chain_err(|| "could not read the debug information offsets table offset")?,
+                debug_info_base: reader
+                    .read_u32::<E>()
+                    .chain_err(|| "could not read the debug information base")?,
+                owned_data_begin: reader
+                    .read_u32::<E>()
+                    .chain_err(|| "could not read the start of the owned data")?,
+                owned_data_end: reader
+                    .read_u32::<E>()
+                    .chain_err(|| "could not read the end of the owned data")?,
+            })
+        } else {
+            None
+        };
+
+        // In containers, all offsets are relative to the start of the container, so the lists
+        // start right after the header of the current dex file.
+        let mut current_offset = header_offset.checked_add(header_size).ok_or_else(|| {
+            Error::from(ErrorKind::Header(format!(
+                "invalid `header_offset`: {:#010x}",
+                header_offset
+            )))
+        })?;
+
+        if link_size == 0 && link_offset != 0 {
+            return Err(ErrorKind::MismatchedOffsets("link_offset", link_offset, 0).into());
+        }
+        if map_offset == 0x00000000 {
+            return Err(ErrorKind::InvalidOffset(
+                "`map_offset` was 0x00000000, and it can never \
+                 be zero"
+                    .to_owned(),
+            ).into());
+        }
+
         if string_ids_size > 0 && string_ids_offset != current_offset {
             return Err(ErrorKind::MismatchedOffsets(
                 "string_ids_offset",
                 string_ids_offset,
-                HEADER_SIZE,
+                current_offset,
             ).into());
         }
         if string_ids_size == 0 && string_ids_offset != 0 {
@@ -194,16 +417,13 @@ impl Header {
                 ErrorKind::MismatchedOffsets("string_ids_offset", string_ids_offset, 0).into(),
             );
         }
-        current_offset += string_ids_size * STRING_ID_ITEM_SIZE;
+        current_offset = Header::list_end(
+            current_offset,
+            string_ids_size,
+            STRING_ID_ITEM_SIZE,
+            "string_ids_size",
+        )?;
 
-        // Types IDs size
-        let type_ids_size = reader
-            .read_u32::<E>()
-            .chain_err(|| "could not read the type IDs list size")?;
-        // Types IDs offset
-        let type_ids_offset = reader
-            .read_u32::<E>()
-            .chain_err(|| "could not read the type IDs list offset")?;
         if type_ids_size > 0 && type_ids_offset != current_offset {
             return Err(ErrorKind::MismatchedOffsets(
                 "type_ids_offset",
@@ -214,16 +434,13 @@ impl Header {
         if type_ids_size == 0 && type_ids_offset != 0 {
             return Err(ErrorKind::MismatchedOffsets("type_ids_offset", type_ids_offset, 0).into());
         }
-        current_offset += type_ids_size * TYPE_ID_ITEM_SIZE;
+        current_offset = Header::list_end(
+            current_offset,
+            type_ids_size,
+            TYPE_ID_ITEM_SIZE,
+            "type_ids_size",
+        )?;
 
-        // Prototype IDs size
-        let prototype_ids_size = reader
-            .read_u32::<E>()
-            .chain_err(|| "could not read the prototype IDs list size")?;
-        // Prototype IDs offset
-        let prototype_ids_offset = reader
-            .read_u32::<E>()
-            .chain_err(|| "could not read the prototype IDs list offset")?;
         if prototype_ids_size > 0 && prototype_ids_offset != current_offset {
             return Err(ErrorKind::MismatchedOffsets(
                 "prototype_ids_offset",
@@ -238,16 +455,13 @@ impl Header {
                 0,
             ).into());
         }
-        current_offset += prototype_ids_size * PROTO_ID_ITEM_SIZE;
+        current_offset = Header::list_end(
+            current_offset,
+            prototype_ids_size,
+            PROTO_ID_ITEM_SIZE,
+            "prototype_ids_size",
+        )?;
 
-        // Field IDs size
-        let field_ids_size = reader
-            .read_u32::<E>()
-            .chain_err(|| "could not read the field IDs list size")?;
-        // Field IDs offset
-        let field_ids_offset = reader
-            .read_u32::<E>()
-            .chain_err(|| "could not read the field IDs list offset")?;
         if field_ids_size > 0 && field_ids_offset != current_offset {
             return Err(ErrorKind::MismatchedOffsets(
                 "field_ids_offset",
@@ -260,16 +474,13 @@ impl Header {
                 ErrorKind::MismatchedOffsets("field_ids_offset", field_ids_offset, 0).into(),
             );
         }
-        current_offset += field_ids_size * FIELD_ID_ITEM_SIZE;
+        current_offset = Header::list_end(
+            current_offset,
+            field_ids_size,
+            FIELD_ID_ITEM_SIZE,
+            "field_ids_size",
+        )?;
 
-        // Method IDs size
-        let method_ids_size = reader
-            .read_u32::<E>()
-            .chain_err(|| "could not read the method IDs list size")?;
-        // Method IDs offset
-        let method_ids_offset = reader
-            .read_u32::<E>()
-            .chain_err(|| "could not read the method IDs list offset")?;
         if method_ids_size > 0 && method_ids_offset != current_offset {
             return Err(ErrorKind::MismatchedOffsets(
                 "method_ids_offset",
@@ -282,16 +493,13 @@ impl Header {
                 ErrorKind::MismatchedOffsets("method_ids_offset", method_ids_offset, 0).into(),
             );
         }
-        current_offset += method_ids_size * METHOD_ID_ITEM_SIZE;
+        current_offset = Header::list_end(
+            current_offset,
+            method_ids_size,
+            METHOD_ID_ITEM_SIZE,
+            "method_ids_size",
+        )?;
 
-        // Class defs size
-        let class_defs_size = reader
-            .read_u32::<E>()
-            .chain_err(|| "could not read the class definitions list size")?;
-        // Class defs offset
-        let class_defs_offset = reader
-            .read_u32::<E>()
-            .chain_err(|| "could not read the class definitions list offset")?;
         if class_defs_size > 0 && class_defs_offset != current_offset {
             return Err(ErrorKind::MismatchedOffsets(
                 "class_defs_offset",
@@ -304,12 +512,84 @@ impl Header {
                 ErrorKind::MismatchedOffsets("class_defs_offset", class_defs_offset, 0).into(),
             );
         }
-        current_offset += class_defs_size * CLASS_DEF_ITEM_SIZE;
+        current_offset = Header::list_end(
+            current_offset,
+            class_defs_size,
+            CLASS_DEF_ITEM_SIZE,
+            "class_defs_size",
+        )?;
 
-        // Data size
-        let data_size = reader
-            .read_u32::<E>()
-            .chain_err(|| "could not read the data section size")?;
+        if compact_dex.is_some() {
+            Header::check_compact_data(map_offset, data_size, data_offset, current_offset)?;
+        } else if let Some(container_size) = container_size {
+            Header::check_container(
+                container_size,
+                header_offset,
+                file_size,
+                map_offset,
+                current_offset,
+            )?;
+        } else {
+            Header::check_data(
+                link_size,
+                link_offset,
+                map_offset,
+                data_size,
+                data_offset,
+                file_size,
+            )?;
+        }
+
+        Ok(Header {
+            magic,
+            checksum,
+            signature,
+            file_size,
+            header_size,
+            endian_tag,
+            link_size,
+            link_offset: some_if(link_offset, link_offset != 0),
+            map_offset,
+            string_ids_size,
+            string_ids_offset: some_if(string_ids_offset, string_ids_offset > 0),
+            type_ids_size,
+            type_ids_offset: some_if(type_ids_offset, type_ids_offset > 0),
+            prototype_ids_size,
+            prototype_ids_offset: some_if(prototype_ids_offset, prototype_ids_size > 0),
+            field_ids_size,
+            field_ids_offset: some_if(field_ids_offset, field_ids_size > 0),
+            method_ids_size,
+            method_ids_offset: some_if(method_ids_offset, method_ids_size > 0),
+            class_defs_size,
+            class_defs_offset: some_if(class_defs_offset, class_defs_size > 0),
+            data_size,
+            data_offset,
+            container_size,
+            header_offset,
+            ids_end: current_offset,
+            compact_dex,
+        })
+    }
+
+    /// Gets the offset where a list of the given number of items of the given size ends, if it
+    /// starts at the given offset.
+    fn list_end(offset: u32, size: u32, item_size: u32, size_field: &str) -> Result<u32> {
+        size.checked_mul(item_size)
+            .and_then(|list_size| offset.checked_add(list_size))
+            .ok_or_else(|| {
+                ErrorKind::Header(format!("invalid `{}`: {:#010x}", size_field, size)).into()
+            })
+    }
+
+    /// Checks the data and link sections of a dex file that is not in a container.
+    fn check_data(
+        link_size: u32,
+        link_offset: u32,
+        map_offset: u32,
+        data_size: u32,
+        data_offset: u32,
+        file_size: u32,
+    ) -> Result<()> {
         if data_size & 0b11 != 0 {
             return Err(ErrorKind::Header(format!(
                 "`data_size` must be a 4-byte multiple, but \
@@ -317,24 +597,15 @@ impl Header {
                 data_size
             )).into());
         }
-
-        // Data offset
-        let data_offset = reader
-            .read_u32::<E>()
-            .chain_err(|| "could not read the data section offset")?;
-        if data_offset != current_offset {
-            // return Err(Error::mismatched_offsets("data_offset", data_offset, current_offset));
-            // TODO seems that there is more information after the class definitions.
-            if cfg!(feature = "debug") {
-                println!(
-                    "{} bytes of unknown data were found.",
-                    data_offset - current_offset
-                );
-            }
-            current_offset = data_offset;
-        }
-        current_offset += data_size;
-        if map_offset < data_offset || map_offset > data_offset + data_size {
+        // There can be unknown data after the identifier lists, that is reported as a warning when
+        // the file is read.
+        let current_offset = data_offset.checked_add(data_size).ok_or_else(|| {
+            Error::from(ErrorKind::Header(format!(
+                "invalid `data_size`: {:#010x}",
+                data_size
+            )))
+        })?;
+        if map_offset < data_offset || map_offset > current_offset {
             return Err(ErrorKind::InvalidOffset(format!(
                 "`map_offset` section must be in the \
                  `data` section (between {:#010x} and \
@@ -361,7 +632,7 @@ impl Header {
                     current_offset,
                 ).into());
             }
-            if link_offset + link_size != file_size {
+            if link_offset.checked_add(link_size) != Some(file_size) {
                 return Err(ErrorKind::Header(
                     "`link_data` section must end at the end \
                      of file"
@@ -370,48 +641,110 @@ impl Header {
             }
         }
 
-        Ok(Header {
-            magic: magic,
-            checksum: checksum,
-            signature: signature,
-            file_size: file_size,
-            header_size: header_size,
-            endian_tag: endian_tag,
-            link_size: link_size,
-            link_offset: some_if(link_offset, link_offset != 0),
-            map_offset: map_offset,
-            string_ids_size: string_ids_size,
-            string_ids_offset: some_if(string_ids_offset, string_ids_offset > 0),
-            type_ids_size: type_ids_size,
-            type_ids_offset: some_if(type_ids_offset, type_ids_offset > 0),
-            prototype_ids_size: prototype_ids_size,
-            prototype_ids_offset: some_if(prototype_ids_offset, prototype_ids_size > 0),
-            field_ids_size: field_ids_size,
-            field_ids_offset: some_if(field_ids_offset, field_ids_size > 0),
-            method_ids_size: method_ids_size,
-            method_ids_offset: some_if(method_ids_offset, method_ids_size > 0),
-            class_defs_size: class_defs_size,
-            class_defs_offset: some_if(class_defs_offset, class_defs_size > 0),
-            data_size: data_size,
-            data_offset: data_offset,
-        })
+        Ok(())
+    }
+
+    /// Checks the layout of a dex file inside a container (version 041 or later).
+    ///
+    /// In containers, `file_size` is the distance from the start of the header to the next header
+    /// or to the end of the container, and the data section fields are not used.
+    fn check_container(
+        container_size: u32,
+        header_offset: u32,
+        file_size: u32,
+        map_offset: u32,
+        ids_end: u32,
+    ) -> Result<()> {
+        let dex_end = header_offset as u64 + file_size as u64;
+        if header_offset & 0b11 != 0 {
+            return Err(ErrorKind::Header(format!(
+                "`header_offset` must be 4-byte aligned, but it was {:#010x}",
+                header_offset
+            )).into());
+        }
+        if dex_end > container_size as u64 {
+            return Err(ErrorKind::Header(format!(
+                "the dex file at {:#010x} ends at {:#010x}, after the end of the container \
+                 ({:#010x})",
+                header_offset, dex_end, container_size
+            )).into());
+        }
+        if ids_end as u64 > dex_end {
+            return Err(ErrorKind::Header(format!(
+                "the identifier lists end at {:#010x}, after the end of the dex file \
+                 ({:#010x})",
+                ids_end, dex_end
+            )).into());
+        }
+        if map_offset < ids_end || map_offset as u64 >= dex_end {
+            return Err(ErrorKind::InvalidOffset(format!(
+                "`map_offset` must be in the dex file, after the identifier lists (between \
+                 {:#010x} and {:#010x}) but it was at {:#010x}",
+                ids_end, dex_end, map_offset
+            )).into());
+        }
+
+        Ok(())
+    }
+
+    /// Checks the data section of a compact dex file.
+    ///
+    /// The data section of compact dex files can be after the end of the file, shared with other
+    /// dex files, and the map list offset is relative to its start.
+    fn check_compact_data(
+        map_offset: u32,
+        data_size: u32,
+        data_offset: u32,
+        ids_end: u32,
+    ) -> Result<()> {
+        if data_offset < ids_end {
+            return Err(ErrorKind::InvalidOffset(format!(
+                "the data section must be after the identifier lists (at {:#010x} or later) but \
+                 it was at {:#010x}",
+                ids_end, data_offset
+            )).into());
+        }
+        if map_offset >= data_size {
+            return Err(ErrorKind::InvalidOffset(format!(
+                "`map_offset` must be in the data section (before {:#010x}) but it was at \
+                 {:#010x}",
+                data_size, map_offset
+            )).into());
+        }
+
+        Ok(())
     }
 
     /// Checks if the dex magic number given is valid.
+    ///
+    /// Both standard (`dex\n`) and compact (`cdex`) dex files are accepted.
     fn is_magic_valid(magic: &[u8; 8]) -> bool {
-        &magic[0..4] == &[0x64, 0x65, 0x78, 0x0a] && magic[7] == 0x00 && magic[4] >= 0x30
+        (magic[0..4] == [0x64, 0x65, 0x78, 0x0a] || Header::is_compact_magic(magic))
+            && magic[7] == 0x00 && magic[4] >= 0x30
             && magic[5] >= 0x30 && magic[6] >= 0x30 && magic[4] <= 0x39 && magic[5] <= 0x39
             && magic[6] <= 0x39
     }
 
+    /// Checks if the given magic number is the one of compact dex files.
+    fn is_compact_magic(magic: &[u8; 8]) -> bool {
+        magic[0..4] == [0x63, 0x64, 0x65, 0x78]
+    }
+
     /// Gets the magic value.
     pub fn get_magic(&self) -> &[u8; 8] {
         &self.magic
     }
 
+    /// Gets the Dex version from a valid magic number.
+    fn version_from_magic(magic: &[u8; 8]) -> u8 {
+        (magic[4] - 0x30) * 100 + (magic[5] - 0x30) * 10 + (magic[6] - 0x30)
+    }
+
     /// Gets Dex version.
+    ///
+    /// For compact dex files, this is the version of the compact dex format.
     pub fn get_dex_version(&self) -> u8 {
-        (self.magic[4] - 0x30) * 100 + (self.magic[5] - 0x30) * 10 + (self.magic[6] - 0x30)
+        Header::version_from_magic(&self.magic)
     }
 
     /// Gets file checksum.
@@ -425,13 +758,16 @@ impl Header {
     }
 
     /// Gets file size.
+    ///
+    /// For dex files inside a container, this is the size from the start of the header to the
+    /// next header or to the end of the container.
     pub fn get_file_size(&self) -> u32 {
         self.file_size
     }
 
     /// Gets header size, in bytes.
     ///
-    /// This must be 0x70.
+    /// This must be 0x70, or 0x78 from version 041.
     pub fn get_header_size(&self) -> u32 {
         self.header_size
     }
@@ -538,17 +874,292 @@ impl Header {
         self.data_offset
     }
 
-    // /// Verifies the file at the given path.
-    // pub fn verify_file<P: AsRef<Path>>(&self, path: P) -> bool {
-    //     unimplemented!() // TODO
-    // }
-    //
-    // /// Verifies the file in the given reader.
-    // ///
-    // /// The reader should be positioned at the start of the file.
-    // pub fn verify_reader<R: Read>(&self, mut reader: R) -> bool {
-    //     unimplemented!() // TODO
-    // }
+    /// Gets the size of the container this dex file is in, if it is in a container.
+    ///
+    /// Only dex files of version 041 or later are in containers.
+    pub fn get_container_size(&self) -> Option<u32> {
+        self.container_size
+    }
+
+    /// Gets the offset of this header from the start of the container.
+    ///
+    /// This is always 0 for dex files that are not in a container.
+    pub fn get_header_offset(&self) -> u32 {
+        self.header_offset
+    }
+
+    /// Gets whether the dex file is in a container (version 041 or later).
+    pub fn is_in_container(&self) -> bool {
+        self.container_size.is_some()
+    }
+
+    /// Gets whether the dex file is a compact dex file (`cdex` magic).
+    pub fn is_compact_dex(&self) -> bool {
+        self.compact_dex.is_some()
+    }
+
+    /// Gets the fields of the header that only compact dex files have.
+    pub fn get_compact_dex_header(&self) -> Option<&CompactDexHeader> {
+        self.compact_dex.as_ref()
+    }
+
+    /// Gets the offset that offsets to items in the data section are relative to.
+    ///
+    /// In standard dex files, offsets are relative to the start of the file, or of the container,
+    /// so this is 0. In compact dex files, they are relative to the start of the data section.
+    pub fn get_data_base(&self) -> u32 {
+        if self.compact_dex.is_some() {
+            self.data_offset
+        } else {
+            0
+        }
+    }
+
+    /// Gets the offset where the identifier lists end, right after the class definitions.
+    pub fn get_ids_end(&self) -> u32 {
+        self.ids_end
+    }
+
+    /// Gets the size the file holding this dex file should have.
+    ///
+    /// The data section of compact dex files can be after the end of the dex file.
+    pub(crate) fn get_expected_size(&self) -> u32 {
+        match self.container_size {
+            Some(container_size) => container_size,
+            None if self.compact_dex.is_some() => self.file_size
+                .max(self.data_offset.saturating_add(self.data_size)),
+            None => self.file_size,
+        }
+    }
+
+    /// Verifies the checksum and the SHA-1 signature of the file at the given path against the
+    /// ones in this header.
+    pub fn verify_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let f = fs::File::open(path).chain_err(|| "could not open file")?;
+        self.verify_reader(BufReader::new(f))
+    }
+
+    /// Verifies the checksum and the SHA-1 signature of the file in the given reader against the
+    /// ones in this header.
+    ///
+    /// The Adler-32 checksum covers everything in the dex file after the checksum field, and the
+    /// signature everything after the signature field. The checksum is checked first, and the
+    /// error says which check failed, with the expected and the actual value.
+    ///
+    /// The reader should be positioned at the start of the file. For dex files in a container, it
+    /// should be positioned at the start of the container.
+    ///
+    /// Compact dex files have no signature, and their checksum also covers their data section, so
+    /// only the checksum is checked, as the Android runtime does.
+    pub fn verify_reader<R: Read>(&self, mut reader: R) -> Result<()> {
+        if self.is_compact_dex() {
+            let mut contents = Vec::with_capacity(self.get_expected_size() as usize);
+            let _ = reader
+                .read_to_end(&mut contents)
+                .chain_err(|| "could not read file contents")?;
+            let checksum = if self.is_little_endian() {
+                Header::compact_checksum::<LittleEndian>(&contents)?
+            } else {
+                Header::compact_checksum::<BigEndian>(&contents)?
+            };
+            if checksum != self.checksum {
+                return Err(ErrorKind::ChecksumMismatch(self.checksum, checksum).into());
+            }
+            return Ok(());
+        }
+
+        let skipped = io::copy(
+            &mut (&mut reader).take(u64::from(self.header_offset)),
+            &mut io::sink(),
+        ).chain_err(|| "could not read the file up to the header")?;
+        if skipped != u64::from(self.header_offset) {
+            return Err(ErrorKind::TruncatedBeforeHeader(skipped, self.header_offset).into());
+        }
+
+        // Magic, checksum and signature.
+        let mut signed_header = [0_u8; 32];
+        reader
+            .read_exact(&mut signed_header)
+            .chain_err(|| "could not read the start of the header")?;
+        let mut checksum = Adler32::new();
+        checksum.write_slice(&signed_header[12..]);
+        let mut signature = Sha1::new();
+
+        let mut signed_data = reader.take(u64::from(self.file_size).saturating_sub(32));
+        let mut buffer = [0_u8; 8192];
+        let mut size = signed_header.len() as u64;
+        loop {
+            let read = signed_data
+                .read(&mut buffer)
+                .chain_err(|| "could not read file contents")?;
+            if read == 0 {
+                break;
+            }
+            checksum.write_slice(&buffer[..read]);
+            signature.update(&buffer[..read]);
+            size += read as u64;
+        }
+        if size != u64::from(self.file_size) {
+            return Err(ErrorKind::HeaderFileSizeMismatch(size, self.file_size).into());
+        }
+
+        let checksum = checksum.checksum();
+        if checksum != self.checksum {
+            return Err(ErrorKind::ChecksumMismatch(self.checksum, checksum).into());
+        }
+        let signature = signature.digest().bytes();
+        if signature != self.signature {
+            return Err(ErrorKind::SignatureMismatch(self.signature, signature).into());
+        }
+
+        Ok(())
+    }
+
+    /// Rewrites the `file_size`, the SHA-1 `signature` and the Adler-32 `checksum` in the header
+    /// of the dex file in the given buffer, so that they match its contents.
+    ///
+    /// The fields are rewritten in that order, since the signature covers the file size and the
+    /// checksum covers the signature, and in the byte order given by the endian tag of the file.
+    ///
+    /// The buffer must hold the whole file. In containers (version 041 or later), every dex file
+    /// is patched, the `container_size` is set to the size of the buffer, and only the
+    /// `file_size` of the last dex file changes, to reach the end of the buffer.
+    pub fn patch_buffer(dex: &mut [u8]) -> Result<()> {
+        if dex.len() < HEADER_SIZE as usize || dex.len() > u32::MAX as usize {
+            return Err(ErrorKind::InvalidFileSize(dex.len() as u64).into());
+        }
+
+        let mut header_offset = 0;
+        loop {
+            let next_offset = Header::patch_dex_at(dex, header_offset).chain_err(|| {
+                format!(
+                    "could not patch the dex file with header at offset {:#010x}",
+                    header_offset
+                )
+            })?;
+            match next_offset {
+                Some(next_offset) => header_offset = next_offset,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Patches the header of the dex file at the given offset of the buffer.
+    ///
+    /// Returns the offset of the next dex file in the container, if there is one.
+    fn patch_dex_at(container: &mut [u8], header_offset: usize) -> Result<Option<usize>> {
+        if container.len() - header_offset < HEADER_SIZE as usize {
+            return Err(ErrorKind::Header(
+                "the buffer is too small for the dex file header".to_owned(),
+            ).into());
+        }
+
+        let mut magic = [0_u8; 8];
+        magic.copy_from_slice(&container[header_offset..header_offset + 8]);
+        if !Header::is_magic_valid(&magic) {
+            return Err(ErrorKind::IncorrectMagic(magic).into());
+        }
+        match LittleEndian::read_u32(&container[header_offset + 40..header_offset + 44]) {
+            ENDIAN_CONSTANT => Header::patch_endian_dex::<LittleEndian>(container, header_offset),
+            REVERSE_ENDIAN_CONSTANT => {
+                Header::patch_endian_dex::<BigEndian>(container, header_offset)
+            }
+            endian_tag => Err(ErrorKind::InvalidEndianTag(endian_tag).into()),
+        }
+    }
+
+    /// Patches the header of the dex file at the given offset of the buffer, in the correct
+    /// endianness.
+    fn patch_endian_dex<B: ByteOrder>(
+        container: &mut [u8],
+        header_offset: usize,
+    ) -> Result<Option<usize>> {
+        let container_size = container.len();
+        let dex = &mut container[header_offset..];
+        let mut magic = [0_u8; 8];
+        magic.copy_from_slice(&dex[..8]);
+
+        if Header::is_compact_magic(&magic) {
+            let checksum = Header::compact_checksum::<B>(dex)?;
+            B::write_u32(&mut dex[8..12], checksum);
+            return Ok(None);
+        }
+
+        let (file_size, next_offset) = if Header::version_from_magic(&magic) >= CONTAINER_VERSION {
+            if dex.len() < HEADER_SIZE_V41 as usize {
+                return Err(ErrorKind::Header(
+                    "the buffer is too small for the dex file header".to_owned(),
+                ).into());
+            }
+            if B::read_u32(&dex[0x74..0x78]) as usize != header_offset {
+                return Err(ErrorKind::MismatchedOffsets(
+                    "header_offset",
+                    B::read_u32(&dex[0x74..0x78]),
+                    header_offset as u32,
+                ).into());
+            }
+            B::write_u32(&mut dex[0x70..0x74], container_size as u32);
+
+            // The dex file is followed by another one if there is a header after it.
+            let file_size = B::read_u32(&dex[32..36]) as usize;
+            let next_magic = dex.get(file_size..file_size.saturating_add(8));
+            match next_magic {
+                Some(next_magic)
+                    if file_size >= HEADER_SIZE_V41 as usize
+                        && next_magic[..4] == magic[..4] =>
+                {
+                    (file_size, Some(header_offset + file_size))
+                }
+                _ => (dex.len(), None),
+            }
+        } else {
+            (dex.len(), None)
+        };
+
+        B::write_u32(&mut dex[32..36], file_size as u32);
+        let signature = Sha1::from(&dex[32..file_size]).digest().bytes();
+        dex[12..32].copy_from_slice(&signature);
+        let checksum = adler::adler32_slice(&dex[12..file_size]);
+        B::write_u32(&mut dex[8..12], checksum);
+
+        Ok(next_offset)
+    }
+
+    /// Computes the checksum of the compact dex file at the start of the given buffer.
+    ///
+    /// The checksum combines the Adler-32 checksums of the header, with the checksum and the data
+    /// section fields set to zero, of the rest of the dex file, and of its data section.
+    fn compact_checksum<B: ByteOrder>(dex: &[u8]) -> Result<u32> {
+        let header_size = HEADER_SIZE_COMPACT as usize;
+        if dex.len() < header_size {
+            return Err(ErrorKind::Header(
+                "the buffer is too small for the compact dex file header".to_owned(),
+            ).into());
+        }
+        let file_size = B::read_u32(&dex[32..36]) as usize;
+        let data_size = B::read_u32(&dex[0x68..0x6c]) as usize;
+        let data_offset = B::read_u32(&dex[0x6c..0x70]) as usize;
+        let (main, data) = match (
+            dex.get(header_size..file_size),
+            dex.get(data_offset..data_offset.saturating_add(data_size)),
+        ) {
+            (Some(main), Some(data)) => (main, data),
+            _ => {
+                return Err(ErrorKind::Header(
+                    "the compact dex file or its data section do not fit in the buffer"
+                        .to_owned(),
+                ).into())
+            }
+        };
+
+        let mut header = [0_u8; HEADER_SIZE_COMPACT as usize];
+        header.copy_from_slice(&dex[..header_size]);
+        header[8..12].copy_from_slice(&[0; 4]);
+        header[0x68..0x70].copy_from_slice(&[0; 8]);
+        let checksum = adler::adler32_slice(&header);
+        let checksum = checksum.wrapping_mul(31) ^ adler::adler32_slice(main);
+        Ok(checksum.wrapping_mul(31) ^ adler::adler32_slice(data))
+    }
 }
 
 impl fmt::Debug for Header {
@@ -558,7 +1169,7 @@ impl fmt::Debug for Header {
             "Header {{ magic: [ {} ] (version: {}), checksum: {:#x}, SHA-1 signature: {}, \
              file_size: {} bytes, header_size: {} bytes, endian_tag: {:#x} ({} endian), {}, \
              map_offset: {:#x}, {}, {}, {}, {}, {}, {}, data_size: {} bytes, data_offset: \
-             {:#x} }}",
+             {:#x}{} }}",
             {
                 let mut magic_vec = Vec::with_capacity(8);
                 for b in &self.magic {
@@ -641,7 +1252,27 @@ impl fmt::Debug for Header {
                 String::from("no classes")
             },
             self.data_size,
-            self.data_offset
+            self.data_offset,
+            if let Some(container_size) = self.container_size {
+                format!(
+                    ", container_size: {} bytes, header_offset: {:#x}",
+                    container_size, self.header_offset
+                )
+            } else if let Some(compact_dex) = self.compact_dex {
+                format!(
+                    ", feature_flags: {:#x}, debug_info_offsets_position: {:#x}, \
+                     debug_info_offsets_table_offset: {:#x}, debug_info_base: {:#x}, \
+                     owned_data_begin: {:#x}, owned_data_end: {:#x}",
+                    compact_dex.feature_flags,
+                    compact_dex.debug_info_offsets_position,
+                    compact_dex.debug_info_offsets_table_offset,
+                    compact_dex.debug_info_base,
+                    compact_dex.owned_data_begin,
+                    compact_dex.owned_data_end
+                )
+            } else {
+                String::new()
+            }
         )
     }
 }
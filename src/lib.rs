@@ -1,9 +1,10 @@
 //! Dalvik executable file format parser.
 
 // Allowing these at least for now.
-#![allow(unknown_lints, missing_docs_in_private_items, stutter, option_unwrap_used,
-         result_unwrap_used, integer_arithmetic, cast_possible_truncation, cast_possible_wrap,
-         indexing_slicing, cast_precision_loss, cast_sign_loss)]
+#![allow(clippy::missing_docs_in_private_items, clippy::module_name_repetitions,
+         clippy::unwrap_used, clippy::arithmetic_side_effects, clippy::cast_possible_truncation,
+         clippy::cast_possible_wrap, clippy::indexing_slicing, clippy::cast_precision_loss,
+         clippy::cast_sign_loss)]
 // #![forbid(deprecated, overflowing_literals, stable_features, trivial_casts,
 // unconditional_recursion,
 //     plugin_as_library, unused_allocation, trivial_numeric_casts, unused_features, while_truem,
@@ -14,53 +15,154 @@
 //     filter_map, used_underscore_binding, option_map_unwrap_or, option_map_unwrap_or_else,
 //     mutex_integer, mut_mut, mem_forget, print_stdout)]
 // #![deny(unused_qualifications, unused, unused_attributes)]
-#![warn(missing_docs, variant_size_differences, enum_glob_use, if_not_else,
-        invalid_upcast_comparisons, items_after_statements, non_ascii_literal, nonminimal_bool,
-        pub_enum_variant_names, shadow_reuse, shadow_same, shadow_unrelated, similar_names,
-        single_match_else, string_add, string_add_assign, unicode_not_nfc,
-        unseparated_literal_suffix, use_debug, wrong_pub_self_convention)]
+#![warn(missing_docs, variant_size_differences, clippy::enum_glob_use, clippy::if_not_else,
+        clippy::invalid_upcast_comparisons, clippy::items_after_statements,
+        clippy::non_ascii_literal, clippy::nonminimal_bool, clippy::similar_names,
+        clippy::single_match_else, clippy::string_add, clippy::string_add_assign,
+        clippy::unicode_not_nfc, clippy::unseparated_literal_suffix, clippy::use_debug)]
 // `error_chain!` can recurse deeply
 #![recursion_limit = "1024"]
 
+extern crate adler;
 #[macro_use]
 extern crate bitflags;
 extern crate byteorder;
 #[macro_use]
 extern crate error_chain;
+extern crate sha1_smol;
+
+#[cfg(feature = "parallel")]
+extern crate rayon;
+#[cfg(feature = "archive")]
+extern crate miniz_oxide;
 
 #[cfg(test)]
 #[macro_use]
 extern crate matches;
 
 use std::path::Path;
-use std::{fs, u32};
+use std::fs;
 use std::io::prelude::*;
 use std::io::BufReader;
+use std::mem;
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 pub mod error;
 pub mod header;
 pub mod types;
 pub mod bytecode;
+pub mod verify;
+pub mod set;
+pub mod oat;
+pub mod vdex;
+pub mod warning;
+#[cfg(feature = "archive")]
+pub mod archive;
 
 mod sizes;
 mod read;
 
 use error::*;
 pub use header::Header;
-use read::DexReader;
-use sizes::HEADER_SIZE;
+pub use set::DexSet;
+use read::{ClassReader, Contents, DexReader};
+use sizes::{CLASS_DEF_ITEM_SIZE, HEADER_SIZE};
+use types::{CallSite, Class, DexString, Map, MethodHandle, Prototype, Type};
+use types::read::{FieldIdData, MethodIdData};
+use verify::{Verifier, Violation};
+use warning::{describe, Warning, WarningKind};
+
+/// Options to parse dex files.
+///
+/// By default, files are parsed eagerly and in strict mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Reads each class the first time it is accessed, instead of when the file is loaded.
+    ///
+    /// Only the header, the map and the ID lists are read up front, and classes are cached once
+    /// read, so loading a file to look up a few classes is much faster. Classes are then read
+    /// with [`Dex::get_class`], [`Dex::try_classes`] and [`Dex::try_find_class`].
+    pub lazy: bool,
+    /// Aborts parsing on the first problem found in the file.
+    ///
+    /// If not set, recoverable problems are recorded as warnings, available with
+    /// [`Dex::warnings`], and as much of the file as possible is kept, as the Android runtime
+    /// does: file size mismatches are ignored, unknown access flag bits are dropped, invalid
+    /// strings are decoded lossily and classes that can't be read are left out.
+    pub strict: bool,
+    /// Keeps the contents the file was loaded from after reading its classes.
+    ///
+    /// [`Dex::verify`] and [`Dex::verify_structure`] need them. They are always kept when parsing
+    /// lazily, to read classes later, and when borrowed from the caller, since it costs nothing.
+    pub keep_contents: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions {
+            lazy: false,
+            strict: true,
+            keep_contents: false,
+        }
+    }
+}
 
 /// Dex file representation.
+///
+/// Dex files loaded with [`Dex::from_bytes`] borrow the string data of their strings and types
+/// from the given bytes, and the prototypes and classes referring to those types share it. The rest
+/// of the items, such as field and method IDs, class data and code, are still decoded into owned
+/// structures. Dex files loaded from files or readers own all their data.
+///
+/// With the `parallel` feature, classes and their code are read in parallel when the file is
+/// loaded, with the same result as reading them sequentially.
 #[derive(Debug)]
-pub struct Dex {
+pub struct Dex<'a> {
     header: Header,
-    strings: Vec<String>,
-    types: Vec<String>,
+    map: Map,
+    strings: Vec<DexString<'a>>,
+    types: Vec<Type<'a>>,
+    prototypes: Vec<Prototype<'a>>,
+    field_ids: Vec<FieldIdData>,
+    method_ids: Vec<MethodIdData>,
+    classes: Classes<'a>,
+    method_handles: Vec<MethodHandle>,
+    call_sites: Vec<CallSite>,
+    contents: Option<Contents<'a>>,
+    hiddenapi_class_data: Option<(u32, Box<[u32]>)>,
+    base_offset: u64,
+    strict: bool,
+    warnings: Mutex<Vec<Warning>>,
+}
+
+/// Classes of a dex file.
+#[derive(Debug)]
+enum Classes<'a> {
+    /// Classes read when the file was loaded, with the sorted class definition indexes of the
+    /// ones that were left out because they could not be read.
+    Loaded {
+        classes: Vec<Class<'a>>,
+        skipped: Vec<u32>,
+    },
+    /// Classes read the first time they are accessed, by class definition index. Classes that
+    /// could not be read in non-strict mode are stored as `None`, so they are not read again.
+    Lazy(Box<[OnceLock<Option<Class<'a>>>]>),
 }
 
-impl Dex {
+impl Dex<'static> {
     /// Reads the Dex data structure from the given path.
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Dex> {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Dex<'static>> {
+        Dex::from_file_with_options(path, ParseOptions::default())
+    }
+
+    /// Reads the Dex data structure from the given path, with the given parsing options.
+    pub fn from_file_with_options<P: AsRef<Path>>(
+        path: P,
+        options: ParseOptions,
+    ) -> Result<Dex<'static>> {
         let file = fs::File::open(path).chain_err(|| "could not open file")?;
         let file_size = file.metadata()
             .chain_err(|| "could not read file metadata")?
@@ -68,39 +170,515 @@ impl Dex {
         if file_size < HEADER_SIZE as u64 || file_size > u32::MAX as u64 {
             return Err(ErrorKind::InvalidFileSize(file_size).into());
         }
-        Dex::from_reader(BufReader::new(file), Some(file_size as usize))
+        Dex::from_reader_with_options(BufReader::new(file), Some(file_size as usize), options)
     }
 
     /// Loads a new Dex data structure from the given reader.
-    pub fn from_reader<R: BufRead>(reader: R, size: Option<usize>) -> Result<Dex> {
-        let mut dex_reader = DexReader::new(reader, size).chain_err(|| "could not create reader")?;
-        dex_reader
-            .read_data()
-            .chain_err(|| "could not read dex file")?;
-
-        Ok(dex_reader.into())
-    }
-
-    // /// Ads the file in the given path to the current Dex data structure.
-    // pub fn add_file<P: AsRef<Path>>(path: P) -> Result<()> {
-    //     unimplemented!() // TODO
-    // }
-    //
-    // /// Verifies the file at the given path.
-    // pub fn verify_file<P: AsRef<Path>>(&self, path: P) -> bool {
-    //     self.header.verify_file(path) // TODO
-    // }
-    //
-    // /// Verifies the file in the given reader.
-    // ///
-    // /// The reader should be positioned at the start of the file.
-    // pub fn verify_reader<R: Read>(&self, reader: R) -> bool {
-    //     self.header.verify_reader(reader) // TODO
-    // }
+    ///
+    /// If the file is a container with several dex files (version 041 or later), only the first
+    /// one is loaded.
+    pub fn from_reader<R: BufRead>(reader: R, size: Option<usize>) -> Result<Dex<'static>> {
+        Dex::from_reader_with_options(reader, size, ParseOptions::default())
+    }
+
+    /// Loads a new Dex data structure from the given reader, with the given parsing options.
+    ///
+    /// If the file is a container with several dex files (version 041 or later), only the first
+    /// one is loaded.
+    pub fn from_reader_with_options<R: BufRead>(
+        reader: R,
+        size: Option<usize>,
+        options: ParseOptions,
+    ) -> Result<Dex<'static>> {
+        let dex_reader = DexReader::new(reader, size).chain_err(|| "could not create reader")?;
+        Dex::read(dex_reader, options).chain_err(|| "could not read dex file")
+    }
+
+    /// Reads all the Dex data structures in the container at the given path.
+    ///
+    /// Files older than version 041 hold a single dex file.
+    pub fn all_from_file<P: AsRef<Path>>(path: P) -> Result<Vec<Dex<'static>>> {
+        Dex::all_from_file_with_options(path, ParseOptions::default())
+    }
+
+    /// Reads all the Dex data structures in the container at the given path, with the given
+    /// parsing options.
+    pub fn all_from_file_with_options<P: AsRef<Path>>(
+        path: P,
+        options: ParseOptions,
+    ) -> Result<Vec<Dex<'static>>> {
+        let file = fs::File::open(path).chain_err(|| "could not open file")?;
+        let file_size = file.metadata()
+            .chain_err(|| "could not read file metadata")?
+            .len();
+        if file_size < HEADER_SIZE as u64 || file_size > u32::MAX as u64 {
+            return Err(ErrorKind::InvalidFileSize(file_size).into());
+        }
+        Dex::all_from_reader_with_options(BufReader::new(file), Some(file_size as usize), options)
+    }
+
+    /// Reads all the Dex data structures in the VDEX file at the given path.
+    ///
+    /// The dex files can be standard or compact dex files. VDEX files that do not hold the dex
+    /// files of the application give an empty list.
+    pub fn all_from_vdex_file<P: AsRef<Path>>(path: P) -> Result<Vec<Dex<'static>>> {
+        Dex::all_from_vdex_file_with_options(path, ParseOptions::default())
+    }
+
+    /// Reads all the Dex data structures in the VDEX file at the given path, with the given
+    /// parsing options.
+    pub fn all_from_vdex_file_with_options<P: AsRef<Path>>(
+        path: P,
+        options: ParseOptions,
+    ) -> Result<Vec<Dex<'static>>> {
+        let file = fs::File::open(path).chain_err(|| "could not open file")?;
+        let file_size = file.metadata()
+            .chain_err(|| "could not read file metadata")?
+            .len();
+        let dex_readers = DexReader::new_for_vdex(BufReader::new(file), Some(file_size as usize))
+            .chain_err(|| "could not create readers")?;
+        Dex::read_all(dex_readers, options)
+    }
+
+    /// Loads all the Dex data structures in the container in the given reader.
+    ///
+    /// Files older than version 041 hold a single dex file.
+    pub fn all_from_reader<R: BufRead>(
+        reader: R,
+        size: Option<usize>,
+    ) -> Result<Vec<Dex<'static>>> {
+        Dex::all_from_reader_with_options(reader, size, ParseOptions::default())
+    }
+
+    /// Loads all the Dex data structures in the container in the given reader, with the given
+    /// parsing options.
+    pub fn all_from_reader_with_options<R: BufRead>(
+        reader: R,
+        size: Option<usize>,
+        options: ParseOptions,
+    ) -> Result<Vec<Dex<'static>>> {
+        let dex_readers =
+            DexReader::new_for_container(reader, size).chain_err(|| "could not create readers")?;
+        Dex::read_all(dex_readers, options)
+    }
+
+    /// Loads all the Dex data structures in the container in the given contents, without copying
+    /// them.
+    #[cfg(feature = "archive")]
+    pub(crate) fn all_from_contents(contents: Vec<u8>) -> Result<Vec<Dex<'static>>> {
+        let dex_readers = DexReader::new_for_container_contents(contents)
+            .chain_err(|| "could not create readers")?;
+        Dex::read_all(dex_readers, ParseOptions::default())
+    }
 }
 
-impl From<DexReader> for Dex {
-    fn from(reader: DexReader) -> Dex {
-        unimplemented!()
+impl<'a> Dex<'a> {
+    /// Loads a new Dex data structure from the given bytes, borrowing its strings and types.
+    ///
+    /// If the bytes hold a container with several dex files (version 041 or later), only the
+    /// first one is loaded.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Dex<'a>> {
+        Dex::from_bytes_with_options(bytes, ParseOptions::default())
+    }
+
+    /// Loads a new Dex data structure from the given bytes, borrowing its strings and types, with
+    /// the given parsing options.
+    pub fn from_bytes_with_options(bytes: &'a [u8], options: ParseOptions) -> Result<Dex<'a>> {
+        let dex_reader = DexReader::from_bytes(bytes).chain_err(|| "could not create reader")?;
+        Dex::read(dex_reader, options).chain_err(|| "could not read dex file")
+    }
+
+    /// Loads all the Dex data structures in the container in the given bytes, borrowing their
+    /// strings and types.
+    ///
+    /// Files older than version 041 hold a single dex file.
+    pub fn all_from_bytes(bytes: &'a [u8]) -> Result<Vec<Dex<'a>>> {
+        Dex::all_from_bytes_with_options(bytes, ParseOptions::default())
+    }
+
+    /// Loads all the Dex data structures in the container in the given bytes, borrowing their
+    /// strings and types, with the given parsing options.
+    pub fn all_from_bytes_with_options(
+        bytes: &'a [u8],
+        options: ParseOptions,
+    ) -> Result<Vec<Dex<'a>>> {
+        let dex_readers =
+            DexReader::all_from_bytes(bytes).chain_err(|| "could not create readers")?;
+        Dex::read_all(dex_readers, options)
+    }
+
+    /// Loads all the Dex data structures in the VDEX file in the given bytes, borrowing their
+    /// strings and types.
+    ///
+    /// The dex files can be standard or compact dex files. VDEX files that do not hold the dex
+    /// files of the application give an empty list.
+    pub fn all_from_vdex_bytes(bytes: &'a [u8]) -> Result<Vec<Dex<'a>>> {
+        Dex::all_from_vdex_bytes_with_options(bytes, ParseOptions::default())
+    }
+
+    /// Loads all the Dex data structures in the VDEX file in the given bytes, borrowing their
+    /// strings and types, with the given parsing options.
+    pub fn all_from_vdex_bytes_with_options(
+        bytes: &'a [u8],
+        options: ParseOptions,
+    ) -> Result<Vec<Dex<'a>>> {
+        let dex_readers =
+            DexReader::all_from_vdex_bytes(bytes).chain_err(|| "could not create readers")?;
+        Dex::read_all(dex_readers, options)
+    }
+
+    /// Reads the data of a dex file.
+    ///
+    /// Classes are only read if the parsing is not lazy.
+    fn read(mut dex_reader: DexReader<'a>, options: ParseOptions) -> Result<Dex<'a>> {
+        let base_offset = dex_reader.base_offset();
+        dex_reader
+            .read_data(options.strict)
+            .map_err(|e| e.with_base_offset(base_offset))?;
+        let mut dex: Dex = dex_reader.into();
+        if !options.lazy {
+            dex.read_classes()?;
+            dex.finish_loading();
+            if !options.keep_contents {
+                if let Some(Contents::Shared(..)) = dex.contents {
+                    dex.contents = None;
+                }
+            }
+        }
+
+        Ok(dex)
+    }
+
+    /// Moves the classes read while loading the file out of their cells.
+    fn finish_loading(&mut self) {
+        let cells = match self.classes {
+            Classes::Lazy(ref mut cells) => mem::replace(cells, Box::new([])),
+            Classes::Loaded { .. } => return,
+        };
+        let mut classes = Vec::with_capacity(cells.len());
+        let mut skipped = Vec::new();
+        for (class_def_index, cell) in cells.into_vec().into_iter().enumerate() {
+            match cell.into_inner().flatten() {
+                Some(class) => classes.push(class),
+                None => skipped.push(class_def_index as u32),
+            }
+        }
+        self.classes = Classes::Loaded { classes, skipped };
+    }
+
+    /// Reads all the classes of the dex file.
+    #[cfg(not(feature = "parallel"))]
+    fn read_classes(&self) -> Result<()> {
+        for class_def_index in 0..self.header.get_class_defs_size() {
+            self.get_class(class_def_index)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads all the classes of the dex file, in parallel.
+    ///
+    /// Each class is read from the shared contents of the file with its own cursor. If several
+    /// classes can't be read, the error of the first one is returned, as in sequential reading.
+    #[cfg(feature = "parallel")]
+    fn read_classes(&self) -> Result<()> {
+        match (0..self.header.get_class_defs_size())
+            .into_par_iter()
+            .find_map_first(|class_def_index| self.get_class(class_def_index).err())
+        {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Creates the warning for a class that could not be read in non-strict mode.
+    fn unreadable_class_warning(&self, class_def_index: u32, error: &Error) -> Warning {
+        let offset = self.base_offset + u64::from(self.header.get_class_defs_offset().unwrap_or(0))
+            + u64::from(class_def_index) * u64::from(CLASS_DEF_ITEM_SIZE);
+        Warning::new(
+            offset,
+            WarningKind::UnreadableClass {
+                class_def_index,
+                error: describe(error),
+            },
+        )
+    }
+
+    /// Records the given warnings.
+    fn add_warnings(&self, warnings: Vec<Warning>) {
+        if !warnings.is_empty() {
+            self.warnings
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .extend(warnings);
+        }
+    }
+
+    /// Gets the warnings found while parsing the file, sorted by offset.
+    ///
+    /// Warnings are only recorded for the recoverable problems that don't abort parsing, so in
+    /// strict mode only the unknown data before the data section is reported. If the file was
+    /// parsed lazily, classes read later can add more warnings.
+    pub fn warnings(&self) -> Vec<Warning> {
+        let mut warnings = self.warnings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        warnings.sort_by_key(Warning::offset);
+        warnings
+    }
+
+    /// Reads the data of the dex files of a container.
+    fn read_all(dex_readers: Vec<DexReader<'a>>, options: ParseOptions) -> Result<Vec<Dex<'a>>> {
+        let mut dex_files = Vec::with_capacity(dex_readers.len());
+        for dex_reader in dex_readers {
+            let header_offset = dex_reader.header().get_header_offset();
+            dex_files.push(Dex::read(dex_reader, options).chain_err(|| {
+                format!(
+                    "could not read dex file with header at offset {:#010x}",
+                    header_offset
+                )
+            })?);
+        }
+
+        Ok(dex_files)
+    }
+
+    /// Gets the header of the dex file.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Gets the map list of the dex file.
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
+    /// Gets the list of strings of the dex file.
+    pub fn strings(&self) -> &[DexString<'a>] {
+        &self.strings
+    }
+
+    /// Gets the string at the given index of the string IDs list, if it exists.
+    pub fn get_string(&self, index: u32) -> Option<&str> {
+        self.strings.get(index as usize).map(DexString::as_str)
+    }
+
+    /// Gets the list of types of the dex file.
+    pub fn types(&self) -> &[Type<'a>] {
+        &self.types
+    }
+
+    /// Gets the type at the given index of the type IDs list, if it exists.
+    pub fn get_type(&self, index: u32) -> Option<&Type<'a>> {
+        self.types.get(index as usize)
+    }
+
+    /// Gets the list of prototypes of the dex file.
+    pub fn prototypes(&self) -> &[Prototype<'a>] {
+        &self.prototypes
+    }
+
+    /// Gets the prototype at the given index of the prototype IDs list, if it exists.
+    pub fn get_prototype(&self, index: u32) -> Option<&Prototype<'a>> {
+        self.prototypes.get(index as usize)
+    }
+
+    /// Gets the list of field IDs of the dex file.
+    pub fn fields(&self) -> &[FieldIdData] {
+        &self.field_ids
+    }
+
+    /// Gets the field ID at the given index of the field IDs list, if it exists.
+    pub fn get_field(&self, index: u32) -> Option<&FieldIdData> {
+        self.field_ids.get(index as usize)
+    }
+
+    /// Gets the list of method IDs of the dex file.
+    pub fn methods(&self) -> &[MethodIdData] {
+        &self.method_ids
+    }
+
+    /// Gets the method ID at the given index of the method IDs list, if it exists.
+    pub fn get_method(&self, index: u32) -> Option<&MethodIdData> {
+        self.method_ids.get(index as usize)
+    }
+
+    /// Gets the list of classes read when the dex file was loaded.
+    ///
+    /// If the file was not parsed in strict mode, the classes that can't be read are left out. If
+    /// it was parsed lazily, the list is empty, and [`Dex::try_classes`] reads the classes.
+    pub fn classes(&self) -> &[Class<'a>] {
+        match self.classes {
+            Classes::Loaded { ref classes, .. } => classes,
+            Classes::Lazy(_) => &[],
+        }
+    }
+
+    /// Gets the list of classes defined in the dex file, reading them if the file was parsed
+    /// lazily.
+    ///
+    /// If the file was not parsed in strict mode, the classes that can't be read are left out.
+    pub fn try_classes(&self) -> Result<Vec<&Class<'a>>> {
+        if let Classes::Loaded { ref classes, .. } = self.classes {
+            return Ok(classes.iter().collect());
+        }
+        (0..self.header.get_class_defs_size())
+            .filter_map(|class_def_index| self.get_class(class_def_index).transpose())
+            .collect()
+    }
+
+    /// Gets the class at the given index of the class definitions list, if it exists.
+    ///
+    /// If the file was parsed lazily, the class is read the first time it is accessed. If the file
+    /// was not parsed in strict mode, classes that can't be read are not found, and a warning is
+    /// recorded the first time they are accessed.
+    pub fn get_class(&self, class_def_index: u32) -> Result<Option<&Class<'a>>> {
+        let cells = match self.classes {
+            Classes::Loaded {
+                ref classes,
+                ref skipped,
+            } => {
+                return Ok(match skipped.binary_search(&class_def_index) {
+                    Ok(_) => None,
+                    Err(skipped_before) => classes.get(class_def_index as usize - skipped_before),
+                })
+            }
+            Classes::Lazy(ref cells) => cells,
+        };
+        let cell = match cells.get(class_def_index as usize) {
+            Some(cell) => cell,
+            None => return Ok(None),
+        };
+        if let Some(class) = cell.get() {
+            return Ok(class.as_ref());
+        }
+
+        let mut reader = ClassReader::new(self)?;
+        let class = reader
+            .read_class(class_def_index)
+            .map_err(|e| e.with_base_offset(self.base_offset));
+        let (class, warnings) = match class {
+            Ok(class) => (Some(class), reader.into_warnings()),
+            Err(e) if !self.strict => {
+                (None, vec![self.unreadable_class_warning(class_def_index, &e)])
+            }
+            Err(e) => return Err(e),
+        };
+        // Another thread may have read the class in the meantime, and recorded its warnings.
+        if cell.set(class).is_ok() {
+            self.add_warnings(warnings);
+        }
+        Ok(cell.get().and_then(Option::as_ref))
+    }
+
+    /// Gets the list of method handles of the dex file.
+    pub fn method_handles(&self) -> &[MethodHandle] {
+        &self.method_handles
+    }
+
+    /// Gets the method handle at the given index of the method handles list, if it exists.
+    pub fn get_method_handle(&self, index: u32) -> Option<&MethodHandle> {
+        self.method_handles.get(index as usize)
+    }
+
+    /// Gets the list of call sites of the dex file.
+    pub fn call_sites(&self) -> &[CallSite] {
+        &self.call_sites
+    }
+
+    /// Gets the call site at the given index of the call site IDs list, if it exists.
+    pub fn get_call_site(&self, index: u32) -> Option<&CallSite> {
+        self.call_sites.get(index as usize)
+    }
+
+    /// Finds the class definition for the given type among the classes read when the dex file
+    /// was loaded.
+    ///
+    /// If the file was parsed lazily, no class is found, and [`Dex::try_find_class`] reads them.
+    pub fn find_class(&self, class_type: &Type<'_>) -> Option<&Class<'a>> {
+        self.classes()
+            .iter()
+            .find(|class| self.types.get(class.class_index() as usize) == Some(class_type))
+    }
+
+    /// Finds the class definition for the given type, if it is defined in this dex file.
+    ///
+    /// If the file was parsed lazily, only the found class is read.
+    pub fn try_find_class(&self, class_type: &Type<'_>) -> Result<Option<&Class<'a>>> {
+        if let Classes::Loaded { .. } = self.classes {
+            return Ok(self.find_class(class_type));
+        }
+        for class_def_index in 0..self.header.get_class_defs_size() {
+            if let Some(class_index) = self.get_class_index(class_def_index)? {
+                if self.types.get(class_index as usize) == Some(class_type) {
+                    return self.get_class(class_def_index);
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Gets the index in the *Type IDs* list of the class defined at the given index of the class
+    /// definitions list, without reading the rest of the class.
+    ///
+    /// Classes that could not be read have no index.
+    fn get_class_index(&self, class_def_index: u32) -> Result<Option<u32>> {
+        let cells = match self.classes {
+            Classes::Loaded { .. } => {
+                return Ok(self.get_class(class_def_index)?.map(Class::class_index))
+            }
+            Classes::Lazy(ref cells) => cells,
+        };
+        match cells.get(class_def_index as usize).and_then(OnceLock::get) {
+            Some(class) => Ok(class.as_ref().map(Class::class_index)),
+            None => ClassReader::new(self)?
+                .read_class_index(class_def_index)
+                .map(Some)
+                .map_err(|e| e.with_base_offset(self.base_offset)),
+        }
+    }
+
+    /// Gets the contents the dex file was loaded from, if they were kept.
+    fn contents(&self) -> Result<&[u8]> {
+        self.contents
+            .as_ref()
+            .map(|contents| contents.as_ref())
+            .ok_or_else(|| ErrorKind::ContentsNotKept.into())
+    }
+
+    /// Verifies the checksum and the signature of the contents the dex file was loaded from.
+    ///
+    /// The contents are only available if the file was loaded from bytes or lazily, or with
+    /// [`ParseOptions::keep_contents`].
+    pub fn verify(&self) -> Result<()> {
+        self.header.verify_reader(self.contents()?)
+    }
+
+    /// Verifies the checksum and the signature of the file at the given path against the ones in
+    /// the header of this dex file.
+    pub fn verify_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.header.verify_file(path)
+    }
+
+    /// Verifies the checksum and the signature of the file in the given reader against the ones
+    /// in the header of this dex file.
+    ///
+    /// The reader should be positioned at the start of the file.
+    pub fn verify_reader<R: Read>(&self, reader: R) -> Result<()> {
+        self.header.verify_reader(reader)
+    }
+
+    /// Verifies the structure of the dex file against the rules of the format.
+    ///
+    /// Checks that the ID lists are sorted and have no duplicates, that classes are defined after
+    /// their superclass and interfaces, that short form descriptors match their prototypes, and
+    /// that the access flags of classes, fields and methods are legal. All the violations found
+    /// are returned, with the offset of the item that breaks the rule.
+    ///
+    /// As with [`Dex::verify`], the contents the file was loaded from must have been kept.
+    pub fn verify_structure(&self) -> Result<Vec<Violation>> {
+        Verifier::new(self)?.verify()
     }
 }
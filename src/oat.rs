@@ -0,0 +1,467 @@
+//! OAT files.
+//!
+//! OAT files are the ahead of time compiled code of the Android runtime. They are ELF objects
+//! whose `oatdata` symbol points to an OAT header, followed by a table of the dex files the code
+//! was compiled from. Pre-optimized `.odex` files are OAT files too.
+//!
+//! Only the OAT files of Android 5 to 7 embed the dex files. From Android 8 on, they are kept in
+//! a separate VDEX file, that can be read with the [`vdex`](../vdex/index.html) module.
+
+use std::convert::TryFrom;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use error::*;
+
+/// Magic number of ELF files.
+const ELF_MAGIC: [u8; 4] = *b"\x7fELF";
+/// Magic number of OAT headers.
+const OAT_MAGIC: [u8; 4] = *b"oat\n";
+/// Name of the symbol that points to the OAT header.
+const OAT_DATA_SYMBOL: &[u8] = b"oatdata";
+/// Type of the sections with a symbol table.
+const SHT_SYMTAB: u32 = 2;
+/// Type of the sections with a dynamic symbol table.
+const SHT_DYNSYM: u32 = 11;
+/// Offset of the dex file count in the OAT header.
+const DEX_FILE_COUNT_OFFSET: u64 = 20;
+
+/// Dex file embedded in an OAT file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OatDexFile<'a> {
+    location: String,
+    location_checksum: u32,
+    contents: &'a [u8],
+}
+
+impl<'a> OatDexFile<'a> {
+    /// Gets the location of the dex file when the OAT file was compiled, such as
+    /// `/system/app/Foo/Foo.apk` or `/system/app/Foo/Foo.apk:classes2.dex`.
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+
+    /// Gets the checksum of the original dex file, as it was in its location.
+    pub fn location_checksum(&self) -> u32 {
+        self.location_checksum
+    }
+
+    /// Gets the contents of the dex file.
+    ///
+    /// They can be parsed with [`Dex::from_bytes`](../struct.Dex.html#method.from_bytes), or
+    /// with [`Dex::from_reader`](../struct.Dex.html#method.from_reader).
+    pub fn contents(&self) -> &'a [u8] {
+        self.contents
+    }
+}
+
+/// OAT file, with the dex files embedded in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Oat<'a> {
+    version: u32,
+    dex_files: Vec<OatDexFile<'a>>,
+}
+
+impl<'a> Oat<'a> {
+    /// Reads the OAT file in the given bytes.
+    ///
+    /// The bytes can hold an ELF file with an `oatdata` symbol, or the OAT data itself. Versions
+    /// 039 and 045 (Android 5), 064 (Android 6), and 079 and 088 (Android 7) are supported.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Oat<'a>> {
+        let oat = if bytes.get(..4) == Some(&ELF_MAGIC[..]) {
+            find_oat_data(bytes)?
+        } else {
+            bytes
+        };
+        if oat.get(..4) != Some(&OAT_MAGIC[..]) || oat.len() < 8 {
+            return Err(ErrorKind::InvalidOat("incorrect magic number".to_owned()).into());
+        }
+        let mut raw_version = [0_u8; 4];
+        raw_version.copy_from_slice(&oat[4..8]);
+
+        // Android 5 has three more trampolines in the header than Android 6 and 7, and Android 7
+        // adds the offset of a type lookup table to each dex file.
+        let (version, header_size, has_lookup_table) = match &raw_version {
+            b"039\0" => (39, 84, false),
+            b"045\0" => (45, 84, false),
+            b"064\0" => (64, 72, false),
+            b"079\0" => (79, 72, true),
+            b"088\0" => (88, 72, true),
+            _ => return Err(ErrorKind::UnsupportedOatVersion(raw_version).into()),
+        };
+
+        let dex_file_count = read_u32(oat, DEX_FILE_COUNT_OFFSET)?;
+        let key_value_store_size = read_u32(oat, header_size - 4)?;
+        let mut offset = header_size + u64::from(key_value_store_size);
+        let mut dex_files = Vec::new();
+        for index in 0..dex_file_count {
+            let location_size = read_u32(oat, offset)?;
+            let location = slice(oat, offset + 4, u64::from(location_size)).ok_or_else(|| {
+                Error::from(ErrorKind::InvalidOat(format!(
+                    "the location of dex file {} is out of the OAT data",
+                    index
+                )))
+            })?;
+            offset += 4 + u64::from(location_size);
+            let location_checksum = read_u32(oat, offset)?;
+            let dex_file_offset = read_u32(oat, offset + 4)?;
+            offset += 8;
+            if has_lookup_table {
+                offset += 4;
+            }
+
+            let dex = slice(oat, u64::from(dex_file_offset), 0x70)
+                .filter(|header| header[..4] == *b"dex\n")
+                .ok_or_else(|| {
+                    Error::from(ErrorKind::InvalidOat(format!(
+                        "there is no dex file at offset {:#010x}",
+                        dex_file_offset
+                    )))
+                })?;
+            let file_size = LittleEndian::read_u32(&dex[32..36]);
+            let contents = slice(oat, u64::from(dex_file_offset), u64::from(file_size))
+                .ok_or_else(|| {
+                    Error::from(ErrorKind::InvalidOat(format!(
+                        "the dex file at offset {:#010x} is out of the OAT data",
+                        dex_file_offset
+                    )))
+                })?;
+
+            // The offsets of the compiled classes follow, one for each class definition.
+            let class_defs_size = LittleEndian::read_u32(&dex[0x60..0x64]);
+            offset += 4 * u64::from(class_defs_size);
+
+            dex_files.push(OatDexFile {
+                location: String::from_utf8_lossy(location).into_owned(),
+                location_checksum,
+                contents,
+            });
+        }
+
+        Ok(Oat { version, dex_files })
+    }
+
+    /// Gets the version of the OAT file.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Gets the dex files embedded in the OAT file.
+    pub fn dex_files(&self) -> &[OatDexFile<'a>] {
+        &self.dex_files
+    }
+}
+
+/// Section of an ELF file.
+#[derive(Debug, Clone, Copy)]
+struct Section {
+    section_type: u32,
+    address: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+}
+
+/// Finds the OAT data, pointed to by the `oatdata` symbol, in an ELF file.
+///
+/// Only little endian ELF files are supported, since all Android targets are.
+fn find_oat_data(elf: &[u8]) -> Result<&[u8]> {
+    let is_64 = match elf.get(4) {
+        Some(1) => false,
+        Some(2) => true,
+        _ => return Err(ErrorKind::InvalidElf("invalid class".to_owned()).into()),
+    };
+    if elf.get(5) != Some(&1) {
+        return Err(
+            ErrorKind::InvalidElf("only little endian files are supported".to_owned()).into(),
+        );
+    }
+
+    let (section_offset, section_size, section_count) = if is_64 {
+        (read_u64(elf, 0x28)?, read_u16(elf, 0x3a)?, read_u16(elf, 0x3c)?)
+    } else {
+        (
+            u64::from(read_u32(elf, 0x20)?),
+            read_u16(elf, 0x2e)?,
+            read_u16(elf, 0x30)?,
+        )
+    };
+    if section_offset > elf.len() as u64 {
+        let error = "the section headers are out of the file".to_owned();
+        return Err(ErrorKind::InvalidElf(error).into());
+    }
+    let mut sections = Vec::with_capacity(usize::from(section_count));
+    for index in 0..u64::from(section_count) {
+        let header = section_offset + index * u64::from(section_size);
+        sections.push(if is_64 {
+            Section {
+                section_type: read_u32(elf, header + 4)?,
+                address: read_u64(elf, header + 0x10)?,
+                offset: read_u64(elf, header + 0x18)?,
+                size: read_u64(elf, header + 0x20)?,
+                link: read_u32(elf, header + 0x28)?,
+            }
+        } else {
+            Section {
+                section_type: read_u32(elf, header + 4)?,
+                address: u64::from(read_u32(elf, header + 0x0c)?),
+                offset: u64::from(read_u32(elf, header + 0x10)?),
+                size: u64::from(read_u32(elf, header + 0x14)?),
+                link: read_u32(elf, header + 0x18)?,
+            }
+        });
+    }
+
+    let symbol_size = if is_64 { 24 } else { 16 };
+    for table in sections
+        .iter()
+        .filter(|section| section.section_type == SHT_DYNSYM || section.section_type == SHT_SYMTAB)
+    {
+        let names = sections.get(table.link as usize).ok_or_else(|| {
+            Error::from(ErrorKind::InvalidElf(format!(
+                "unknown string table section {}",
+                table.link
+            )))
+        })?;
+        if table.offset > elf.len() as u64 {
+            return Err(ErrorKind::InvalidElf(format!(
+                "the symbol table at offset {:#x} is out of the file",
+                table.offset
+            )).into());
+        }
+        for symbol in 0..table.size.min(elf.len() as u64) / symbol_size {
+            let symbol = table.offset + symbol * symbol_size;
+            let name = u64::from(read_u32(elf, symbol)?);
+            let length = OAT_DATA_SYMBOL.len();
+            if slice(elf, names.offset.saturating_add(name), length as u64 + 1)
+                .map_or(true, |name| name[..length] != *OAT_DATA_SYMBOL || name[length] != 0)
+            {
+                continue;
+            }
+
+            let (value, size, section_index) = if is_64 {
+                (
+                    read_u64(elf, symbol + 8)?,
+                    read_u64(elf, symbol + 16)?,
+                    read_u16(elf, symbol + 6)?,
+                )
+            } else {
+                (
+                    u64::from(read_u32(elf, symbol + 4)?),
+                    u64::from(read_u32(elf, symbol + 8)?),
+                    read_u16(elf, symbol + 14)?,
+                )
+            };
+            let section = sections.get(usize::from(section_index)).ok_or_else(|| {
+                Error::from(ErrorKind::InvalidElf(format!(
+                    "the `oatdata` symbol is in the unknown section {}",
+                    section_index
+                )))
+            })?;
+            return value
+                .checked_sub(section.address)
+                .and_then(|offset| slice(elf, section.offset.saturating_add(offset), size))
+                .ok_or_else(|| {
+                    ErrorKind::InvalidElf(format!(
+                        "the `oatdata` symbol at address {:#x} is out of the file",
+                        value
+                    )).into()
+                });
+        }
+    }
+
+    Err(ErrorKind::InvalidElf("there is no `oatdata` symbol".to_owned()).into())
+}
+
+/// Gets the given number of bytes at the given offset, if they are in the buffer.
+fn slice(bytes: &[u8], offset: u64, size: u64) -> Option<&[u8]> {
+    let start = usize::try_from(offset).ok()?;
+    let end = usize::try_from(offset.checked_add(size)?).ok()?;
+    bytes.get(start..end)
+}
+
+/// Reads a little endian 16-bit field at the given offset.
+fn read_u16(bytes: &[u8], offset: u64) -> Result<u16> {
+    slice(bytes, offset, 2)
+        .map(LittleEndian::read_u16)
+        .ok_or_else(|| out_of_bounds(offset))
+}
+
+/// Reads a little endian 32-bit field at the given offset.
+fn read_u32(bytes: &[u8], offset: u64) -> Result<u32> {
+    slice(bytes, offset, 4)
+        .map(LittleEndian::read_u32)
+        .ok_or_else(|| out_of_bounds(offset))
+}
+
+/// Reads a little endian 64-bit field at the given offset.
+fn read_u64(bytes: &[u8], offset: u64) -> Result<u64> {
+    slice(bytes, offset, 8)
+        .map(LittleEndian::read_u64)
+        .ok_or_else(|| out_of_bounds(offset))
+}
+
+/// Creates the error of a field out of the file.
+fn out_of_bounds(offset: u64) -> Error {
+    ErrorKind::InvalidOat(format!("the field at offset {:#010x} is out of the file", offset)).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use Dex;
+
+    /// Builds the OAT data of the given version, with the given dex file embedded in it.
+    fn build_oat(version: &[u8; 4], dex: &[u8]) -> Vec<u8> {
+        fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let (header_fields, has_lookup_table) = match version {
+            b"039\0" | b"045\0" => (19, false),
+            b"064\0" => (16, false),
+            _ => (16, true),
+        };
+        let class_defs_size = LittleEndian::read_u32(&dex[0x60..0x64]);
+        let table_size = 4 + 8 + 8 + if has_lookup_table { 4 } else { 0 } + 4 * class_defs_size;
+        let dex_file_offset = 8 + 4 * header_fields + 12 + table_size;
+
+        let mut oat = OAT_MAGIC.to_vec();
+        oat.extend_from_slice(version);
+        for field in 0..header_fields - 1 {
+            push_u32(&mut oat, if field == 3 { 1 } else { 0 });
+        }
+        push_u32(&mut oat, 12);
+        oat.extend_from_slice(b"key\0value\0\0\0");
+
+        push_u32(&mut oat, 8);
+        oat.extend_from_slice(b"base.apk");
+        push_u32(&mut oat, 0x1234_5678);
+        push_u32(&mut oat, dex_file_offset);
+        if has_lookup_table {
+            push_u32(&mut oat, 0);
+        }
+        for _ in 0..class_defs_size {
+            push_u32(&mut oat, 0);
+        }
+        assert_eq!(dex_file_offset as usize, oat.len());
+        oat.extend_from_slice(dex);
+        oat
+    }
+
+    /// Builds an ELF file whose `oatdata` symbol points to the given OAT data.
+    fn build_elf(is_64: bool, oat: &[u8]) -> Vec<u8> {
+        fn push(bytes: &mut Vec<u8>, value: u64, size: usize) {
+            bytes.extend_from_slice(&value.to_le_bytes()[..size]);
+        }
+        let word = if is_64 { 8 } else { 4 };
+        let names = b"\0oatexec\0oatdata\0";
+        let oat_address = 0x1_0000;
+
+        let mut elf = ELF_MAGIC.to_vec();
+        elf.extend_from_slice(&[if is_64 { 2 } else { 1 }, 1, 1]);
+        elf.resize(0x100, 0);
+        elf.extend_from_slice(names);
+        elf.resize(0x120, 0);
+        // Null, `oatexec` and `oatdata` symbols.
+        for &(name, value, size) in &[(0, 0, 0), (1, oat_address + 0x100, 4), (9, oat_address, 0)] {
+            let size = if name == 9 { oat.len() as u64 } else { size };
+            push(&mut elf, name, 4);
+            if is_64 {
+                push(&mut elf, 0, 2);
+                push(&mut elf, 3, 2);
+                push(&mut elf, value, 8);
+                push(&mut elf, size, 8);
+            } else {
+                push(&mut elf, value, 4);
+                push(&mut elf, size, 4);
+                push(&mut elf, 0, 2);
+                push(&mut elf, 3, 2);
+            }
+        }
+        elf.resize(0x200, 0);
+        elf.extend_from_slice(oat);
+        let aligned = (elf.len() + 7) / 8 * 8;
+        elf.resize(aligned, 0);
+
+        let section_offset = elf.len() as u64;
+        let symbols_size = 3 * if is_64 { 24 } else { 16 };
+        let sections = [
+            (0, 0, 0, 0, 0),
+            (3, 0, 0x100, names.len() as u64, 0),
+            (SHT_DYNSYM, 0, 0x120, symbols_size, 1),
+            (1, oat_address, 0x200, oat.len() as u64, 0),
+        ];
+        for &(section_type, address, offset, size, link) in &sections {
+            push(&mut elf, 0, 4);
+            push(&mut elf, u64::from(section_type), 4);
+            push(&mut elf, 0, word);
+            push(&mut elf, address, word);
+            push(&mut elf, offset, word);
+            push(&mut elf, size, word);
+            push(&mut elf, link, 4);
+            push(&mut elf, 0, 4);
+            push(&mut elf, 0, word);
+            push(&mut elf, 0, word);
+        }
+
+        let (offset, count) = if is_64 { (0x28, 0x3a) } else { (0x20, 0x2e) };
+        elf[offset..offset + word].copy_from_slice(&section_offset.to_le_bytes()[..word]);
+        let section_size = if is_64 { 0x40_u16 } else { 0x28 };
+        elf[count..count + 2].copy_from_slice(&section_size.to_le_bytes());
+        elf[count + 2..count + 4].copy_from_slice(&(sections.len() as u16).to_le_bytes());
+        elf
+    }
+
+    #[test]
+    fn it_finds_dex_files_in_oat_data() {
+        let dex = fs::read("test.dex").unwrap();
+        for version in &[b"039\0", b"045\0", b"064\0", b"079\0", b"088\0"] {
+            let oat_data = build_oat(version, &dex);
+            let oat = Oat::from_bytes(&oat_data).unwrap();
+
+            assert_eq!(1, oat.dex_files().len());
+            assert_eq!("base.apk", oat.dex_files()[0].location());
+            assert_eq!(0x1234_5678, oat.dex_files()[0].location_checksum());
+            assert_eq!(&dex[..], oat.dex_files()[0].contents());
+        }
+    }
+
+    #[test]
+    fn it_finds_oat_data_in_elf_files() {
+        let dex = fs::read("test.dex").unwrap();
+        let oat_data = build_oat(b"088\0", &dex);
+        for &is_64 in &[false, true] {
+            let elf = build_elf(is_64, &oat_data);
+            assert_eq!(&oat_data[..], find_oat_data(&elf).unwrap());
+
+            let oat = Oat::from_bytes(&elf).unwrap();
+            assert_eq!(88, oat.version());
+            let contents = oat.dex_files()[0].contents();
+            let parsed = Dex::from_reader(contents, Some(contents.len())).unwrap();
+            assert_eq!(1791, parsed.header().get_class_defs_size());
+        }
+    }
+
+    #[test]
+    fn it_rejects_unsupported_versions() {
+        let mut oat_data = build_oat(b"088\0", &fs::read("test.dex").unwrap());
+        oat_data[4..8].copy_from_slice(b"124\0");
+
+        let error = Oat::from_bytes(&oat_data).unwrap_err();
+
+        assert!(matches!(error.kind(), ErrorKind::UnsupportedOatVersion(v) if v == b"124\0"));
+    }
+
+    #[test]
+    fn it_rejects_elf_files_without_oat_data() {
+        let mut elf = build_elf(true, &build_oat(b"088\0", &fs::read("test.dex").unwrap()));
+        elf[0x10a] = b'x';
+
+        let error = Oat::from_bytes(&elf).unwrap_err();
+
+        assert!(matches!(error.kind(), ErrorKind::InvalidElf(_)));
+    }
+}
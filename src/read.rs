@@ -1,29 +1,70 @@
 //! Dex file reader module.
 
-use std::io::{BufRead, Cursor, Read};
+use std::fmt;
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex, OnceLock};
 
 use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
 
-use header::Header;
+use {Classes, Dex};
+use header::{CompactDexHeader, Header};
 use error::*;
 use types::read::*;
 use types::*;
+use sizes::{CLASS_DEF_ITEM_SIZE, MAP_ITEM_SIZE};
+use vdex::Vdex;
+use warning::{describe, Warning, WarningKind};
+
+/// Offset of the `file_size` field in the header.
+const FILE_SIZE_FIELD_OFFSET: u32 = 0x20;
+
+/// Contents of the file being read.
+#[derive(Clone)]
+pub enum Contents<'a> {
+    /// Contents borrowed from the caller, that the strings and types of the dex file can borrow.
+    Borrowed(&'a [u8]),
+    /// Contents read into memory, shared between all the readers of the dex files in a container,
+    /// starting at the given offset.
+    Shared(Arc<Vec<u8>>, usize),
+}
+
+impl<'a> fmt::Debug for Contents<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Contents::Borrowed(contents) => write!(f, "Borrowed({} bytes)", contents.len()),
+            Contents::Shared(ref contents, start) => {
+                write!(f, "Shared({} bytes)", contents.len() - start)
+            }
+        }
+    }
+}
+
+impl<'a> AsRef<[u8]> for Contents<'a> {
+    fn as_ref(&self) -> &[u8] {
+        match *self {
+            Contents::Borrowed(contents) => contents,
+            Contents::Shared(ref contents, start) => &contents[start..],
+        }
+    }
+}
 
 /// Structure for reading a Dex file in a fast way.
 #[derive(Debug)]
-pub struct DexReader {
+pub struct DexReader<'a> {
     /// Reader to use to read data.
     ///
     /// Header must already be read before creating the object.
-    file_cursor: Cursor<Box<[u8]>>,
+    file_cursor: Cursor<Contents<'a>>,
     /// Header of the dex file.
     header: Header,
+    /// Map list of the dex file.
+    map: Map,
     /// String list.
-    strings: Vec<String>,
+    strings: Vec<DexString<'a>>,
     /// Type list.
-    types: Vec<Type>,
+    types: Vec<Type<'a>>,
     /// Prototype ID list.
-    prototypes: Vec<Prototype>,
+    prototypes: Vec<Prototype<'a>>,
     /// Field ID list.
     ///
     /// This list creates 1:1 relations between field indexes and field information offsets in the
@@ -31,28 +72,66 @@ pub struct DexReader {
     field_ids: Vec<FieldIdData>,
     /// Method ID list.
     method_ids: Vec<MethodIdData>,
-    /// List of classes.
-    classes: Vec<Class>,
-
-    /// List of lists of references to annotation set offsets.
-    annotation_set_ref_list: Vec<Box<[u32]>>,
-    /// Set of annotations.
-    annotation_sets: Vec<Box<[u32]>>,
-    /// Code segment list.
-    code_segments: Vec<(u32, CodeItem)>,
-    /// Debug information list.
-    debug_info: Vec<(u32, DebugInfo)>,
-    // /// Annotation list.
-    // annotations: Vec<(u32, AnnotationItem)>,
-    /// Array list.
-    arrays: Vec<(u32, Array)>,
-    /// Annotations directories.
-    annotations_directories: Vec<(u32, AnnotationsDirectory)>,
+    /// Method handle list.
+    method_handles: Vec<MethodHandle>,
+    /// Call site list.
+    call_sites: Vec<CallSite>,
+    /// Offset of the hidden API class data section, and offsets of the flags of each class
+    /// relative to it.
+    hiddenapi_class_data: Option<(u32, Box<[u32]>)>,
+    /// Whether recoverable problems abort reading, instead of being recorded as warnings.
+    strict: bool,
+    /// Size of the file holding the dex file, if it must match the size in the header.
+    file_size: Option<u64>,
+    /// Offset of the dex file in the VDEX file holding it, or 0.
+    base_offset: u64,
+    /// Warnings found while reading.
+    warnings: Vec<Warning>,
 }
 
-impl DexReader {
+impl DexReader<'static> {
     /// Creates a new reader with the information from the header of the file.
-    pub fn new<R: Read>(mut file: R, size: Option<usize>) -> Result<DexReader> {
+    ///
+    /// If the file is a container, the reader is created for its first dex file.
+    pub fn new<R: Read>(file: R, size: Option<usize>) -> Result<DexReader<'static>> {
+        let contents = DexReader::read_contents(file, size)?;
+        DexReader::with_contents(Contents::Shared(contents, 0))
+    }
+
+    /// Creates a reader for each of the dex files in a container.
+    ///
+    /// Files older than version 041 hold a single dex file, so only one reader is created.
+    pub fn new_for_container<R: Read>(
+        file: R,
+        size: Option<usize>,
+    ) -> Result<Vec<DexReader<'static>>> {
+        let contents = DexReader::read_contents(file, size)?;
+        DexReader::all_with_contents(Contents::Shared(contents, 0))
+    }
+
+    /// Creates a reader for each of the dex files in the container in the given contents, taking
+    /// ownership of them.
+    #[cfg(feature = "archive")]
+    pub fn new_for_container_contents(contents: Vec<u8>) -> Result<Vec<DexReader<'static>>> {
+        DexReader::all_with_contents(Contents::Shared(Arc::new(contents), 0))
+    }
+
+    /// Creates a reader for each of the dex files in the VDEX file in the given reader.
+    pub fn new_for_vdex<R: Read>(file: R, size: Option<usize>) -> Result<Vec<DexReader<'static>>> {
+        let contents = DexReader::read_contents(file, size)?;
+        let vdex = Vdex::from_bytes(&contents).chain_err(|| "could not read VDEX file")?;
+        vdex.dex_offsets()
+            .iter()
+            .map(|&offset| {
+                DexReader::with_contents(Contents::Shared(contents.clone(), offset as usize))
+                    .map(|reader| reader.in_vdex(offset))
+                    .chain_err(|| format!("could not read dex file at offset {:#010x}", offset))
+            })
+            .collect()
+    }
+
+    /// Reads the whole contents of a file.
+    fn read_contents<R: Read>(mut file: R, size: Option<usize>) -> Result<Arc<Vec<u8>>> {
         let mut file_contents = if let Some(size) = size {
             Vec::with_capacity(size)
         } else {
@@ -60,35 +139,136 @@ impl DexReader {
         };
         file.read_to_end(&mut file_contents)
             .chain_err(|| "could not read dex file contents")?;
-        let mut cursor = Cursor::new(file_contents.into_boxed_slice());
+        Ok(Arc::new(file_contents))
+    }
+}
+
+impl<'a> DexReader<'a> {
+    /// Creates a new reader for the file in the given bytes, borrowing its contents.
+    ///
+    /// If the file is a container, the reader is created for its first dex file.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<DexReader<'a>> {
+        DexReader::with_contents(Contents::Borrowed(bytes))
+    }
+
+    /// Creates a reader for each of the dex files in the container in the given bytes, borrowing
+    /// its contents.
+    pub fn all_from_bytes(bytes: &'a [u8]) -> Result<Vec<DexReader<'a>>> {
+        DexReader::all_with_contents(Contents::Borrowed(bytes))
+    }
+
+    /// Creates a reader for each of the dex files in the VDEX file in the given bytes, borrowing
+    /// its contents.
+    pub fn all_from_vdex_bytes(bytes: &'a [u8]) -> Result<Vec<DexReader<'a>>> {
+        let vdex = Vdex::from_bytes(bytes).chain_err(|| "could not read VDEX file")?;
+        vdex.dex_offsets()
+            .iter()
+            .map(|&offset| {
+                DexReader::from_bytes(&bytes[offset as usize..])
+                    .map(|reader| reader.in_vdex(offset))
+                    .chain_err(|| format!("could not read dex file at offset {:#010x}", offset))
+            })
+            .collect()
+    }
+
+    /// Marks the reader as reading a dex file at the given offset of a VDEX file, that is followed
+    /// by other data, so its size is not checked.
+    fn in_vdex(mut self, offset: u32) -> DexReader<'a> {
+        self.file_size = None;
+        self.base_offset = u64::from(offset);
+        self
+    }
+
+    /// Creates a new reader for the first dex file in the given contents.
+    fn with_contents(contents: Contents<'a>) -> Result<DexReader<'a>> {
+        let mut cursor = Cursor::new(contents);
         let header =
             Header::from_reader(&mut cursor).chain_err(|| "could not read dex file header")?;
+        Ok(DexReader::with_header(cursor, header))
+    }
+
+    /// Creates a reader for each of the dex files in the given contents.
+    fn all_with_contents(contents: Contents<'a>) -> Result<Vec<DexReader<'a>>> {
+        let mut cursor = Cursor::new(contents);
+        let headers = Header::all_from_reader(&mut cursor)
+            .chain_err(|| "could not read dex file headers")?;
+        Ok(headers
+            .into_iter()
+            .map(|header| DexReader::with_header(Cursor::new(cursor.get_ref().clone()), header))
+            .collect())
+    }
+
+    /// Creates a new reader for the dex file with the given header.
+    fn with_header(cursor: Cursor<Contents<'a>>, header: Header) -> DexReader<'a> {
         let strings = Vec::with_capacity(header.get_string_ids_size() as usize);
         let types = Vec::with_capacity(header.get_type_ids_size() as usize);
         let prototypes = Vec::with_capacity(header.get_prototype_ids_size() as usize);
         let field_ids = Vec::with_capacity(header.get_field_ids_size() as usize);
         let method_ids = Vec::with_capacity(header.get_method_ids_size() as usize);
-        Ok(DexReader {
+        let file_size = Some(cursor.get_ref().as_ref().len() as u64);
+        DexReader {
             file_cursor: cursor,
-            header: header,
-            strings: strings,
-            types: types,
-            prototypes: prototypes,
-            field_ids: field_ids,
-            method_ids: method_ids,
-            classes: Vec::new(),
-            annotation_set_ref_list: Vec::new(),
-            annotation_sets: Vec::new(),
-            code_segments: Vec::new(),
-            debug_info: Vec::new(),
-            // annotations: Vec::new(),
-            arrays: Vec::new(),
-            annotations_directories: Vec::new(),
-        })
+            header,
+            map: Map::default(),
+            strings,
+            types,
+            prototypes,
+            field_ids,
+            method_ids,
+            method_handles: Vec::new(),
+            call_sites: Vec::new(),
+            hiddenapi_class_data: None,
+            strict: true,
+            file_size,
+            base_offset: 0,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Gets the header of the dex file being read.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Gets the offset of the dex file in the VDEX file holding it, or 0 for other files.
+    pub fn base_offset(&self) -> u64 {
+        self.base_offset
     }
 
     /// Reads data from a whole file and stores its information.
-    pub fn read_data(&mut self) -> Result<()> {
+    ///
+    /// If reading is not strict, recoverable problems are recorded as warnings.
+    pub fn read_data(&mut self, strict: bool) -> Result<()> {
+        self.strict = strict;
+        if let Some(file_size) = self.file_size {
+            let size_in_header = self.header.get_expected_size();
+            if file_size != u64::from(size_in_header) {
+                if strict {
+                    return Err(ErrorKind::HeaderFileSizeMismatch(file_size, size_in_header).into());
+                }
+                self.warnings.push(Warning::new(
+                    self.base_offset + u64::from(self.header.get_header_offset())
+                        + u64::from(FILE_SIZE_FIELD_OFFSET),
+                    WarningKind::FileSizeMismatch {
+                        file_size,
+                        size_in_header,
+                    },
+                ));
+            }
+        }
+        let ids_end = self.header.get_ids_end();
+        let data_offset = self.header.get_data_offset();
+        if self.header.get_container_size().is_none() && !self.header.is_compact_dex()
+            && data_offset > ids_end
+        {
+            self.warnings.push(Warning::new(
+                self.base_offset + u64::from(ids_end),
+                WarningKind::UnknownData {
+                    size: data_offset - ids_end,
+                },
+            ));
+        }
+
         if self.header.is_little_endian() {
             self.read_endian_data::<LittleEndian>()
         } else {
@@ -96,8 +276,17 @@ impl DexReader {
         }
     }
 
+    /// Gets the position in the contents of the item in the data section at the given offset.
+    fn data_position(&self, offset: u32) -> u64 {
+        u64::from(self.header.get_data_base()) + u64::from(offset)
+    }
+
     /// Reads the data in the correct endianness.
     fn read_endian_data<B: ByteOrder>(&mut self) -> Result<()> {
+        let map_position = self.data_position(self.header.get_map_offset());
+        self.file_cursor.set_position(map_position);
+        self.read_map::<B>()
+            .chain_err(|| ErrorKind::item(ItemType::MapList, None, map_position))?;
         if let Some(offset) = self.header.get_string_ids_offset() {
             self.file_cursor.set_position(offset as u64);
             self.read_string_list::<B>()
@@ -123,10 +312,21 @@ impl DexReader {
             self.read_method_id_list::<B>()
                 .chain_err(|| "could not read method ID list")?;
         }
-        if let Some(offset) = self.header.get_class_defs_offset() {
-            self.file_cursor.set_position(offset as u64);
-            self.read_class_list::<B>()
-                .chain_err(|| "could not read class list")?;
+        if let Some(item) = self.map.get_item(ItemType::MethodHandle).copied() {
+            self.file_cursor.set_position(item.offset() as u64);
+            self.read_method_handle_list::<B>(item.size())
+                .chain_err(|| "could not read method handle list")?;
+        }
+        if let Some(item) = self.map.get_item(ItemType::CallSiteId).copied() {
+            self.file_cursor.set_position(item.offset() as u64);
+            self.read_call_site_list::<B>(item.size())
+                .chain_err(|| "could not read call site list")?;
+        }
+        if let Some(offset) = self.map.get_offset_for(ItemType::HiddenapiClassData) {
+            let position = self.data_position(offset);
+            self.file_cursor.set_position(position);
+            self.read_hiddenapi_class_data::<B>()
+                .chain_err(|| ErrorKind::item(ItemType::HiddenapiClassData, None, position))?;
         }
 
         Ok(())
@@ -134,17 +334,33 @@ impl DexReader {
 
     /// Reads the list of strings.
     fn read_string_list<B: ByteOrder>(&mut self) -> Result<()> {
-        for _ in 0..self.header.get_string_ids_size() {
+        for index in 0..self.header.get_string_ids_size() {
             let current_offset = self.file_cursor.position();
-            let offset = self.file_cursor.read_u32::<B>().chain_err(|| {
-                format!(
-                    "could not read string offset from string ID at offset {:#010x}",
-                    current_offset
-                )
-            })?;
+            let offset = self.file_cursor
+                .read_u32::<B>()
+                .chain_err(|| ErrorKind::item(ItemType::StringId, index, current_offset))?;
             let current_offset = self.file_cursor.position();
-            self.file_cursor.set_position(offset as u64);
-            let str_data = self.read_string()?;
+            let position = self.data_position(offset);
+            self.file_cursor.set_position(position);
+            let str_data = match self.read_string() {
+                Ok(str_data) => str_data,
+                Err(e) if !self.strict => {
+                    self.warnings.push(Warning::new(
+                        self.base_offset + position,
+                        WarningKind::InvalidString {
+                            index,
+                            error: describe(&e),
+                        },
+                    ));
+                    self.read_string_lossy(position)
+                }
+                Err(e) => {
+                    return Err(Error::with_chain(
+                        e,
+                        ErrorKind::item(ItemType::StringData, index, position),
+                    ))
+                }
+            };
             self.strings.push(str_data);
             self.file_cursor.set_position(current_offset);
         }
@@ -153,130 +369,134 @@ impl DexReader {
     }
 
     /// Reads an actual string.
-    fn read_string(&mut self) -> Result<String> {
+    ///
+    /// If the contents of the file are borrowed, the string borrows its data when possible.
+    fn read_string(&mut self) -> Result<DexString<'a>> {
         let (size, _) =
             read_uleb128(&mut self.file_cursor).chain_err(|| "could not read string size")?;
-        let mut data = Vec::with_capacity(size as usize);
-        if size > 0 {
-            self.file_cursor.read_until(0, &mut data)?;
-            data.pop();
-        }
+        let start = self.file_cursor.position() as usize;
+        let length = self.file_cursor
+            .get_ref()
+            .as_ref()
+            .get(start..)
+            .and_then(|data| data.iter().position(|&byte| byte == 0))
+            .ok_or_else(|| {
+                Error::from(ErrorKind::InvalidMutf8("string is not NUL-terminated".to_owned()))
+            })?;
+        self.file_cursor
+            .set_position((start + length + 1) as u64);
 
-        let string = String::from_utf8(data).chain_err(|| "error decoding UTF-8 from string data")?;
-        let char_count = string.chars().count();
-        if char_count == size as usize {
+        let string = match *self.file_cursor.get_ref() {
+            Contents::Borrowed(contents) => DexString::from_mutf8(&contents[start..start + length]),
+            Contents::Shared(ref contents, offset) => {
+                DexString::from_mutf8(&contents[offset + start..offset + start + length])
+                    .map(DexString::into_owned)
+            }
+        }.chain_err(|| "could not decode MUTF-8 string data")?;
+        let utf16_len = string.encode_utf16().count();
+        if utf16_len == size as usize {
             Ok(string)
         } else {
-            Err(ErrorKind::StringSizeMismatch(size, char_count).into())
+            Err(ErrorKind::StringSizeMismatch(size, utf16_len).into())
         }
     }
 
+    /// Reads the string at the given position as well as possible, replacing invalid data with
+    /// `U+FFFD`.
+    ///
+    /// The string ends at the first NUL byte, or at the end of the file. If its size can't be
+    /// read, the string is empty.
+    fn read_string_lossy(&mut self, position: u64) -> DexString<'a> {
+        self.file_cursor.set_position(position);
+        if read_uleb128(&mut self.file_cursor).is_err() {
+            return DexString::from(String::new());
+        }
+        let start = self.file_cursor.position() as usize;
+        let data = self.file_cursor.get_ref().as_ref().get(start..).unwrap_or(&[]);
+        let length = data.iter().position(|&byte| byte == 0).unwrap_or(data.len());
+        DexString::from(String::from_utf8_lossy(&data[..length]).into_owned())
+    }
+
     /// Reads the list of types.
     fn read_all_types<B: ByteOrder>(&mut self) -> Result<()> {
-        for _ in 0..self.header.get_type_ids_size() {
+        for index in 0..self.header.get_type_ids_size() {
             let current_offset = self.file_cursor.position();
-            let index = self.file_cursor.read_u32::<B>().chain_err(|| {
-                format!("could not read type ID at offset {:#010x}", current_offset)
-            })?;
-            let type_str = self.strings
-                .get(index as usize)
-                .ok_or_else(|| ErrorKind::UnknownStringIndex(index))?;
-            self.types.push(type_str.parse().chain_err(|| {
-                format!(
-                    "could not read type descriptor from string at index {} (`{}`)",
-                    index, type_str
-                )
-            })?);
+            let type_id = self.read_type::<B>()
+                .chain_err(|| ErrorKind::item(ItemType::TypeId, index, current_offset))?;
+            self.types.push(type_id);
         }
 
         Ok(())
     }
 
+    /// Reads a type ID and its descriptor.
+    fn read_type<B: ByteOrder>(&mut self) -> Result<Type<'a>> {
+        let index = self.file_cursor
+            .read_u32::<B>()
+            .chain_err(|| "could not read descriptor index")?;
+        let type_str = self.strings
+            .get(index as usize)
+            .ok_or(ErrorKind::UnknownStringIndex(index))?;
+        Type::from_dex_string(type_str).chain_err(|| {
+            format!(
+                "could not read type descriptor from string at index {} (`{}`)",
+                index, type_str
+            )
+        })
+    }
+
     /// Reads the list of prototype IDs.
     fn read_prototype_list<B: ByteOrder>(&mut self) -> Result<()> {
-        for _ in 0..self.header.get_prototype_ids_size() {
+        for index in 0..self.header.get_prototype_ids_size() {
             let current_offset = self.file_cursor.position();
-            let prototype_id = PrototypeIdData::from_reader::<_, B>(&mut self.file_cursor)
-                .chain_err(|| {
-                    format!(
-                        "could not read prototype ID at offset {:#010x}",
-                        current_offset
-                    )
-                })?;
-
-            let parameters = if let Some(off) = prototype_id.parameters_offset() {
-                let current_offset = self.file_cursor.position();
-                self.file_cursor.set_position(off as u64);
-                let parameters = self.read_type_list::<B>()
-                    .chain_err(|| "could not read parameter list")?;
-                self.file_cursor.set_position(current_offset);
-                Some(parameters)
-            } else {
-                None
-            };
-            let shorty_str = self.strings
-                .get(prototype_id.shorty_index() as usize)
-                .ok_or_else(|| ErrorKind::UnknownStringIndex(prototype_id.shorty_index()))?;
-            let shorty_descriptor = shorty_str.parse().chain_err(|| {
-                format!(
-                    "could not read shorty descriptor from string at index {} (`{}`)",
-                    prototype_id.shorty_index(),
-                    shorty_str
-                )
-            })?;
-            let return_type = self.types
-                .get(prototype_id.return_type_index() as usize)
-                .ok_or_else(|| {
-                    ErrorKind::UnknownTypeIndex(u16::from(prototype_id.return_type_index()))
-                })?
-                .clone();
-
-            self.prototypes
-                .push(Prototype::new(shorty_descriptor, return_type, parameters));
+            let prototype = self.read_prototype::<B>()
+                .chain_err(|| ErrorKind::item(ItemType::ProtoId, index, current_offset))?;
+            self.prototypes.push(prototype);
         }
         Ok(())
     }
 
-    /// Reads a list of types.
-    fn read_type_list<B: ByteOrder>(&mut self) -> Result<Box<[Type]>> {
-        let current_offset = self.file_cursor.position();
-        let size = self.file_cursor.read_u32::<B>().chain_err(|| {
+    /// Reads a prototype ID, with its parameter list.
+    fn read_prototype<B: ByteOrder>(&mut self) -> Result<Prototype<'a>> {
+        let prototype_id = PrototypeIdData::from_reader::<_, B>(&mut self.file_cursor)?;
+
+        let parameters = if let Some(off) = prototype_id.parameters_offset() {
+            let current_offset = self.file_cursor.position();
+            let position = self.data_position(off);
+            self.file_cursor.set_position(position);
+            let parameters = read_type_list::<B, _>(&mut self.file_cursor, &self.types)
+                .chain_err(|| ErrorKind::item(ItemType::TypeList, None, position))?;
+            self.file_cursor.set_position(current_offset);
+            Some(parameters)
+        } else {
+            None
+        };
+        let shorty_str = self.strings
+            .get(prototype_id.shorty_index() as usize)
+            .ok_or_else(|| ErrorKind::UnknownStringIndex(prototype_id.shorty_index()))?;
+        let shorty_descriptor = shorty_str.parse().chain_err(|| {
             format!(
-                "error reading the size of the type list at offset {:#010x}",
-                current_offset
+                "could not read shorty descriptor from string at index {} (`{}`)",
+                prototype_id.shorty_index(),
+                shorty_str
             )
         })?;
+        let return_type = self.types
+            .get(prototype_id.return_type_index() as usize)
+            .ok_or_else(|| ErrorKind::UnknownTypeIndex(prototype_id.return_type_index()))?
+            .clone();
 
-        let mut type_list = Vec::with_capacity(size as usize);
-        for _ in 0..size {
-            let current_offset = self.file_cursor.position();
-            let index = self.file_cursor.read_u16::<B>().chain_err(|| {
-                format!(
-                    "error reading type index for type list item at offset {:#010x}",
-                    current_offset
-                )
-            })?;
-            type_list.push(
-                self.types
-                    .get(index as usize)
-                    .ok_or_else(|| Error::from(ErrorKind::UnknownTypeIndex(index)))?
-                    .clone(),
-            );
-        }
-
-        Ok(type_list.into_boxed_slice())
+        Ok(Prototype::new(shorty_descriptor, return_type, parameters))
     }
 
     /// Reads the list of field IDs.
     fn read_field_id_list<B: ByteOrder>(&mut self) -> Result<()> {
-        for _ in 0..self.header.get_field_ids_size() {
+        for index in 0..self.header.get_field_ids_size() {
             let current_offset = self.file_cursor.position();
-            self.field_ids
-                .push(
-                    FieldIdData::from_reader::<_, B>(&mut self.file_cursor).chain_err(|| {
-                        format!("could not read field ID at offset {:#010x}", current_offset)
-                    })?,
-                );
+            self.field_ids.push(
+                FieldIdData::from_reader::<_, B>(&mut self.file_cursor)
+                    .chain_err(|| ErrorKind::item(ItemType::FieldId, index, current_offset))?,
+            );
         }
 
         Ok(())
@@ -284,92 +504,429 @@ impl DexReader {
 
     /// Reads the list of method IDs.
     fn read_method_id_list<B: ByteOrder>(&mut self) -> Result<()> {
-        for _ in 0..self.header.get_method_ids_size() {
+        for index in 0..self.header.get_method_ids_size() {
             let current_offset = self.file_cursor.position();
-            self.method_ids
-                .push(
-                    MethodIdData::from_reader::<_, B>(&mut self.file_cursor).chain_err(|| {
-                        format!(
-                            "could not read method ID at offset {:#010x}",
-                            current_offset
-                        )
-                    })?,
-                );
+            self.method_ids.push(
+                MethodIdData::from_reader::<_, B>(&mut self.file_cursor)
+                    .chain_err(|| ErrorKind::item(ItemType::MethodId, index, current_offset))?,
+            );
         }
 
         Ok(())
     }
 
-    /// Reads the list of classes.
-    fn read_class_list<B: ByteOrder>(&mut self) -> Result<()> {
-        for _ in 0..self.header.get_class_defs_size() {
-            let class_offset = self.file_cursor.position();
-            let class_def =
-                ClassDefData::from_reader::<_, B>(&mut self.file_cursor).chain_err(|| {
-                    format!(
-                        "could not read class definition data at offset {:#010x}",
-                        class_offset
-                    )
-                })?;
+    /// Reads the list of method handles.
+    fn read_method_handle_list<B: ByteOrder>(&mut self, size: u32) -> Result<()> {
+        self.method_handles.reserve_exact(size as usize);
+        for index in 0..size {
+            let current_offset = self.file_cursor.position();
+            let method_handle = self.read_method_handle::<B>()
+                .chain_err(|| ErrorKind::item(ItemType::MethodHandle, index, current_offset))?;
+            self.method_handles.push(method_handle);
+        }
 
-            let new_offset = self.file_cursor.position();
-            let interfaces = if let Some(offset) = class_def.interfaces_offset() {
-                self.file_cursor.set_position(offset as u64);
-                self.read_type_list::<B>().chain_err(|| {
-                    format!(
-                        "could not read interfaces list at offset {:#010x} for class at offset \
-                         {:#010x}",
-                        offset, class_offset
-                    )
-                })?
-            // TODO check that all are classes (Fully Qualified Names) and no duplicates.
-            } else {
-                Vec::new().into_boxed_slice()
-            };
-            let annotations = if let Some(offset) = class_def.annotations_offset() {
-                self.file_cursor.set_position(offset as u64);
-                Some(self.read_annotations_directory::<B>().chain_err(|| {
-                    format!(
-                        "could not read annotation list at offset {:#010x} for class \
-                         at offset {:#010x}",
-                        offset, class_offset
-                    )
-                })?)
-            } else {
-                None
-            };
-            let class_data = if let Some(offset) = class_def.class_data_offset() {
-                self.file_cursor.set_position(offset as u64);
-                Some(ClassData::from_reader(&mut self.file_cursor).chain_err(|| {
-                    format!(
-                        "could not read class data at offset {:#010x} for \
-                         class at offset {:#010x}",
-                        offset, class_offset
-                    )
-                })?)
+        Ok(())
+    }
+
+    /// Reads a method handle, and checks the field or method it references.
+    fn read_method_handle<B: ByteOrder>(&mut self) -> Result<MethodHandle> {
+        let method_handle = MethodHandle::from_reader::<_, B>(&mut self.file_cursor)?;
+        let index = method_handle.field_or_method_index();
+        if method_handle.handle_type().is_field_accessor() {
+            if index as usize >= self.field_ids.len() {
+                return Err(ErrorKind::UnknownFieldIndex(u32::from(index)).into());
+            }
+        } else if index as usize >= self.method_ids.len() {
+            return Err(ErrorKind::UnknownMethodIndex(u32::from(index)).into());
+        }
+        Ok(method_handle)
+    }
+
+    /// Reads the list of call sites.
+    fn read_call_site_list<B: ByteOrder>(&mut self, size: u32) -> Result<()> {
+        self.call_sites.reserve_exact(size as usize);
+        for index in 0..size {
+            let current_offset = self.file_cursor.position();
+            let call_site = self.read_call_site::<B>()
+                .chain_err(|| ErrorKind::item(ItemType::CallSiteId, index, current_offset))?;
+            self.call_sites.push(call_site);
+        }
+
+        Ok(())
+    }
+
+    /// Reads a call site ID and its call site, and checks the values it references.
+    fn read_call_site<B: ByteOrder>(&mut self) -> Result<CallSite> {
+        let call_site_offset = self.file_cursor
+            .read_u32::<B>()
+            .chain_err(|| "could not read call site offset")?;
+        let current_offset = self.file_cursor.position();
+        let position = self.data_position(call_site_offset);
+        self.file_cursor.set_position(position);
+        let call_site = CallSite::from_reader(&mut self.file_cursor)
+            .chain_err(|| ErrorKind::item(ItemType::EncodedArray, None, position))?;
+        self.file_cursor.set_position(current_offset);
+
+        if call_site.method_handle_index() as usize >= self.method_handles.len() {
+            return Err(ErrorKind::CallSite(format!(
+                "unknown method handle index {}",
+                call_site.method_handle_index()
+            )).into());
+        }
+        if call_site.method_name_index() as usize >= self.strings.len() {
+            return Err(ErrorKind::UnknownStringIndex(call_site.method_name_index()).into());
+        }
+        if call_site.method_type_index() as usize >= self.prototypes.len() {
+            return Err(ErrorKind::CallSite(format!(
+                "unknown method type index {}",
+                call_site.method_type_index()
+            )).into());
+        }
+        for argument in call_site.arguments() {
+            check_value(argument, &self.prototypes, &self.method_handles)
+                .chain_err(|| "invalid call site argument")?;
+        }
+        Ok(call_site)
+    }
+
+    /// Reads the header of the hidden API class data section.
+    fn read_hiddenapi_class_data<B: ByteOrder>(&mut self) -> Result<()> {
+        let section_offset = self.file_cursor.position();
+        let size = self.file_cursor
+            .read_u32::<B>()
+            .chain_err(|| "could not read the size of the section")?;
+        if section_offset + size as u64 > self.file_cursor.get_ref().as_ref().len() as u64 {
+            return Err(ErrorKind::InvalidOffset(format!(
+                "the hidden API class data section at offset {:#010x} does not fit in the file",
+                section_offset
+            )).into());
+        }
+
+        let mut offsets = Vec::with_capacity(self.header.get_class_defs_size() as usize);
+        for class_def_index in 0..self.header.get_class_defs_size() {
+            let offset = self.file_cursor.read_u32::<B>().chain_err(|| {
+                format!(
+                    "could not read the flags offset of class definition {}",
+                    class_def_index
+                )
+            })?;
+            if offset >= size {
+                return Err(ErrorKind::InvalidOffset(format!(
+                    "class flags offset {:#010x} is out of the hidden API class data section",
+                    offset
+                )).into());
+            }
+            offsets.push(offset);
+        }
+
+        self.hiddenapi_class_data = Some((section_offset as u32, offsets.into_boxed_slice()));
+        Ok(())
+    }
+
+    /// Reads the map of the dex file and checks it against the header.
+    fn read_map<B: ByteOrder>(&mut self) -> Result<()> {
+        let map_offset = self.file_cursor.position();
+        let file_size = self.file_cursor.get_ref().as_ref().len() as u64;
+        let map = Map::from_reader::<_, B>(&mut self.file_cursor)?;
+        if map_offset + 4 + MAP_ITEM_SIZE as u64 * map.items().len() as u64 > file_size {
+            return Err(ErrorKind::Map(format!(
+                "the map list at offset {:#010x} does not fit in the file",
+                map_offset
+            )).into());
+        }
+
+        let mut last_position = None;
+        for (i, item) in map.items().iter().enumerate() {
+            if map.items()[..i]
+                .iter()
+                .any(|prev| prev.item_type() == item.item_type())
+            {
+                return Err(ErrorKind::Map(format!(
+                    "item type {:?} appears more than once",
+                    item.item_type()
+                )).into());
+            }
+            // In compact dex files, the offsets of the items in the data section are relative to
+            // it, so items are compared by their position in the file.
+            let position = if is_in_data_section(item.item_type()) {
+                self.data_position(item.offset())
             } else {
-                None
+                u64::from(item.offset())
             };
-            let static_values = if let Some(offset) = class_def.static_values_offset() {
-                self.file_cursor.set_position(offset as u64);
-                Some(Array::from_reader(&mut self.file_cursor).chain_err(|| {
-                    format!("could not read encoded array at offset {:#010x}", offset)
-                })?)
+            if let Some(last_position) = last_position {
+                if position <= last_position {
+                    return Err(ErrorKind::Map(format!(
+                        "offset {:#010x} of item type {:?} is not in ascending order",
+                        item.offset(),
+                        item.item_type()
+                    )).into());
+                }
+            }
+            if position >= file_size {
+                return Err(ErrorKind::Map(format!(
+                    "offset {:#010x} of item type {:?} is out of the file",
+                    item.offset(),
+                    item.item_type()
+                )).into());
+            }
+            last_position = Some(position);
+        }
+
+        check_map_item(
+            &map,
+            ItemType::Header,
+            1,
+            Some(self.header.get_header_offset()),
+        )?;
+        check_map_item(
+            &map,
+            ItemType::MapList,
+            1,
+            Some(self.header.get_map_offset()),
+        )?;
+        check_map_item(
+            &map,
+            ItemType::StringId,
+            self.header.get_string_ids_size(),
+            self.header.get_string_ids_offset(),
+        )?;
+        check_map_item(
+            &map,
+            ItemType::TypeId,
+            self.header.get_type_ids_size(),
+            self.header.get_type_ids_offset(),
+        )?;
+        check_map_item(
+            &map,
+            ItemType::ProtoId,
+            self.header.get_prototype_ids_size(),
+            self.header.get_prototype_ids_offset(),
+        )?;
+        check_map_item(
+            &map,
+            ItemType::FieldId,
+            self.header.get_field_ids_size(),
+            self.header.get_field_ids_offset(),
+        )?;
+        check_map_item(
+            &map,
+            ItemType::MethodId,
+            self.header.get_method_ids_size(),
+            self.header.get_method_ids_offset(),
+        )?;
+        check_map_item(
+            &map,
+            ItemType::ClassDef,
+            self.header.get_class_defs_size(),
+            self.header.get_class_defs_offset(),
+        )?;
+
+        self.map = map;
+        Ok(())
+    }
+
+}
+
+/// Reader for the classes of a dex file.
+///
+/// Classes are read once the ID lists of the dex file are loaded, so they can be read when the
+/// file is loaded or the first time they are accessed.
+#[derive(Debug)]
+pub struct ClassReader<'r, 'a: 'r> {
+    /// Dex file the classes belong to.
+    dex: &'r Dex<'a>,
+    /// Reader to use to read data.
+    file_cursor: Cursor<&'r [u8]>,
+    /// Warnings found while reading.
+    warnings: Vec<Warning>,
+}
+
+impl<'r, 'a> ClassReader<'r, 'a> {
+    /// Creates a new class reader for the given dex file.
+    pub fn new(dex: &'r Dex<'a>) -> Result<ClassReader<'r, 'a>> {
+        Ok(ClassReader {
+            dex,
+            file_cursor: Cursor::new(dex.contents()?),
+            warnings: Vec::new(),
+        })
+    }
+
+    /// Gets the warnings found while reading, if the dex file is not read in strict mode.
+    pub fn into_warnings(self) -> Vec<Warning> {
+        self.warnings
+    }
+
+    /// Records a warning for each of the given fields and methods of the class data at the given
+    /// position, that have unknown bits in their access flags.
+    fn add_invalid_flags(&mut self, position: u64, invalid_flags: Vec<InvalidMemberFlags>) {
+        let position = self.dex.base_offset + position;
+        self.warnings.extend(invalid_flags.into_iter().map(|invalid| {
+            Warning::new(
+                position + u64::from(invalid.offset),
+                WarningKind::InvalidAccessFlags {
+                    item_type: invalid.item_type,
+                    index: invalid.index,
+                    access_flags: invalid.access_flags,
+                },
+            )
+        }));
+    }
+
+    /// Gets the position in the contents of the item in the data section at the given offset.
+    fn data_position(&self, offset: u32) -> u64 {
+        u64::from(self.dex.header.get_data_base()) + u64::from(offset)
+    }
+
+    /// Reads the index in the type IDs list of the class defined at the given index of the class
+    /// definitions list.
+    pub fn read_class_index(&mut self, class_def_index: u32) -> Result<u32> {
+        self.seek_class_def(class_def_index)?;
+        let current_offset = self.file_cursor.position();
+        let class_index = if self.dex.header.is_little_endian() {
+            self.file_cursor.read_u32::<LittleEndian>()
+        } else {
+            self.file_cursor.read_u32::<BigEndian>()
+        };
+
+        class_index
+            .chain_err(|| ErrorKind::item(ItemType::ClassDef, class_def_index, current_offset))
+    }
+
+    /// Reads the class defined at the given index of the class definitions list.
+    pub fn read_class(&mut self, class_def_index: u32) -> Result<Class<'a>> {
+        self.seek_class_def(class_def_index)?;
+        let class_offset = self.file_cursor.position();
+        if self.dex.header.is_little_endian() {
+            self.read_endian_class::<LittleEndian>(class_def_index)
+        } else {
+            self.read_endian_class::<BigEndian>(class_def_index)
+        }.chain_err(|| ErrorKind::item(ItemType::ClassDef, class_def_index, class_offset))
+    }
+
+    /// Moves the reader to the class definition at the given index.
+    fn seek_class_def(&mut self, class_def_index: u32) -> Result<()> {
+        match self.dex.header.get_class_defs_offset() {
+            Some(offset) if class_def_index < self.dex.header.get_class_defs_size() => {
+                self.file_cursor.set_position(
+                    u64::from(offset) + u64::from(class_def_index) * u64::from(CLASS_DEF_ITEM_SIZE),
+                );
+                Ok(())
+            }
+            _ => Err(ErrorKind::UnknownClassDefIndex(class_def_index).into()),
+        }
+    }
+
+    /// Reads the class definition at the current position, in the correct endianness.
+    fn read_endian_class<B: ByteOrder>(&mut self, class_def_index: u32) -> Result<Class<'a>> {
+        let class_offset = self.file_cursor.position();
+        let mut invalid_flags = Vec::new();
+        let class_def = if self.dex.strict {
+            ClassDefData::from_reader::<_, B>(&mut self.file_cursor)
+        } else {
+            ClassDefData::from_reader_lenient::<_, B>(&mut self.file_cursor, &mut invalid_flags)
+        }?;
+        let warning_offset = self.dex.base_offset + class_offset;
+        self.warnings.extend(invalid_flags.into_iter().map(|access_flags| {
+            Warning::new(
+                warning_offset,
+                WarningKind::InvalidAccessFlags {
+                    item_type: ItemType::ClassDef,
+                    index: class_def_index,
+                    access_flags,
+                },
+            )
+        }));
+
+        let interfaces = if let Some(offset) = class_def.interfaces_offset() {
+            let position = self.data_position(offset);
+            self.file_cursor.set_position(position);
+            read_type_list::<B, _>(&mut self.file_cursor, &self.dex.types)
+                .chain_err(|| ErrorKind::item(ItemType::TypeList, None, position))?
+        } else {
+            Vec::new().into_boxed_slice()
+        };
+        let annotations = if let Some(offset) = class_def.annotations_offset() {
+            let position = self.data_position(offset);
+            self.file_cursor.set_position(position);
+            Some(self.read_annotations_directory::<B>()
+                .chain_err(|| ErrorKind::item(ItemType::AnnotationsDirectory, None, position))?)
+        } else {
+            None
+        };
+        let class_data = if let Some(offset) = class_def.class_data_offset() {
+            let position = self.data_position(offset);
+            self.file_cursor.set_position(position);
+            let mut invalid_flags = Vec::new();
+            let mut class_data = if self.dex.strict {
+                ClassData::from_reader(&mut self.file_cursor)
             } else {
-                None
-            };
-            self.file_cursor.set_position(new_offset);
-
-            self.classes.push(Class::new(
-                class_def.class_index(),
-                class_def.access_flags(),
-                class_def.superclass_index(),
-                interfaces,
-                class_def.source_file_index(),
-                annotations,
-                class_data,
-                static_values,
-            ));
+                ClassData::from_reader_lenient(&mut self.file_cursor, &mut invalid_flags)
+            }.chain_err(|| ErrorKind::item(ItemType::ClassData, None, position))?;
+            self.add_invalid_flags(position, invalid_flags);
+            self.read_methods_code::<B>(class_data.direct_methods_mut())
+                .chain_err(|| "could not read code of direct methods")?;
+            self.read_methods_code::<B>(class_data.virtual_methods_mut())
+                .chain_err(|| "could not read code of virtual methods")?;
+            self.read_hiddenapi_flags(class_def_index, &mut class_data)
+                .chain_err(|| "could not read hidden API flags")?;
+            Some(class_data)
+        } else {
+            None
+        };
+        let static_values = if let Some(offset) = class_def.static_values_offset() {
+            let position = self.data_position(offset);
+            self.file_cursor.set_position(position);
+            let static_values = Array::from_reader(&mut self.file_cursor)
+                .chain_err(|| ErrorKind::item(ItemType::EncodedArray, None, position))?;
+            for value in static_values.iter() {
+                check_value(value, &self.dex.prototypes, &self.dex.method_handles)
+                    .chain_err(|| "invalid static value")
+                    .chain_err(|| ErrorKind::item(ItemType::EncodedArray, None, position))?;
+            }
+            Some(static_values)
+        } else {
+            None
+        };
+
+        Ok(Class::new(
+            class_def.class_index(),
+            class_def.access_flags(),
+            class_def.superclass_index(),
+            interfaces,
+            class_def.source_file_index(),
+            annotations,
+            class_data,
+            static_values,
+        ))
+    }
+
+    /// Reads the hidden API flags of the fields and methods of a class, if the dex file has them.
+    fn read_hiddenapi_flags(
+        &mut self,
+        class_def_index: u32,
+        class_data: &mut ClassData,
+    ) -> Result<()> {
+        let flags_offset = match self.dex.hiddenapi_class_data {
+            Some((section_offset, ref offsets)) => match offsets[class_def_index as usize] {
+                0 => return Ok(()),
+                offset => section_offset + offset,
+            },
+            None => return Ok(()),
+        };
+        self.file_cursor.set_position(flags_offset as u64);
+
+        // Flags are in the same order as the members in the class data.
+        for field in class_data.static_fields_mut() {
+            field.set_hiddenapi_flags(read_hiddenapi_flags(&mut self.file_cursor)?);
+        }
+        for field in class_data.instance_fields_mut() {
+            field.set_hiddenapi_flags(read_hiddenapi_flags(&mut self.file_cursor)?);
+        }
+        for method in class_data.direct_methods_mut() {
+            method.set_hiddenapi_flags(read_hiddenapi_flags(&mut self.file_cursor)?);
+        }
+        for method in class_data.virtual_methods_mut() {
+            method.set_hiddenapi_flags(read_hiddenapi_flags(&mut self.file_cursor)?);
         }
 
         Ok(())
@@ -377,46 +934,35 @@ impl DexReader {
 
     /// Reads an annotations directory.
     fn read_annotations_directory<B: ByteOrder>(&mut self) -> Result<AnnotationsDirectory> {
-        let current_offset = self.file_cursor.position();
-        let read = AnnotationsDirectoryOffsets::from_reader::<_, B>(&mut self.file_cursor)
-            .chain_err(|| {
-                format!(
-                    "could not read annotation directory at offset {:#010x}",
-                    current_offset
-                )
-            })?;
+        let read = AnnotationsDirectoryOffsets::from_reader::<_, B>(&mut self.file_cursor)?;
 
         let class_annotations = if let Some(off) = read.class_annotations_offset() {
-            self.file_cursor.set_position(off as u64);
-            self.read_annotation_set::<B>()
+            self.read_annotation_set::<B>(off)
                 .chain_err(|| "could not read class annotations set")?
         } else {
             Vec::new().into_boxed_slice()
         };
         let mut field_annotations = Vec::with_capacity(read.field_annotations().len());
         for fa_off in read.field_annotations() {
-            self.file_cursor.set_position(fa_off.offset() as u64);
             field_annotations.push(FieldAnnotations::new(
                 fa_off.field_index(),
-                self.read_annotation_set::<B>()
+                self.read_annotation_set::<B>(fa_off.offset())
                     .chain_err(|| "could not read field annotations set")?,
             ));
         }
         let mut method_annotations = Vec::with_capacity(read.method_annotations().len());
         for ma_off in read.method_annotations() {
-            self.file_cursor.set_position(ma_off.offset() as u64);
             method_annotations.push(MethodAnnotations::new(
                 ma_off.method_index(),
-                self.read_annotation_set::<B>()
+                self.read_annotation_set::<B>(ma_off.offset())
                     .chain_err(|| "could not read method annotations set")?,
             ));
         }
         let mut parameter_annotations = Vec::with_capacity(read.parameter_annotations().len());
         for pa_off in read.parameter_annotations() {
-            self.file_cursor.set_position(pa_off.offset() as u64);
             parameter_annotations.push(ParameterAnnotations::new(
                 pa_off.method_index(),
-                self.read_annotation_set::<B>()
+                self.read_annotation_set::<B>(pa_off.offset())
                     .chain_err(|| "could not read parameter annotations set")?,
             ));
         }
@@ -429,28 +975,25 @@ impl DexReader {
         ))
     }
 
-    /// Reads an annotation set.
-    fn read_annotation_set<B: ByteOrder>(&mut self) -> Result<Box<[Annotation]>> {
-        let current_offset = self.file_cursor.position();
-        let size = self.file_cursor.read_u32::<B>().chain_err(|| {
-            format!(
-                "error reading anotation set size at offset {:#010x}",
-                current_offset
-            )
-        })?;
-        let mut annotation_set = Vec::with_capacity(size as usize);
+    /// Reads the annotation set at the given offset.
+    fn read_annotation_set<B: ByteOrder>(&mut self, offset: u32) -> Result<Box<[Annotation]>> {
+        let set_position = self.data_position(offset);
+        self.file_cursor.set_position(set_position);
+        let size = self.file_cursor
+            .read_u32::<B>()
+            .chain_err(|| "error reading annotation set size")
+            .chain_err(|| ErrorKind::item(ItemType::AnnotationSet, None, set_position))?;
+        let mut annotation_set = Vec::new();
 
-        for _ in 0..size {
+        for index in 0..size {
+            let annotation_offset = self.file_cursor
+                .read_u32::<B>()
+                .chain_err(|| format!("error reading offset of annotation {}", index))
+                .chain_err(|| ErrorKind::item(ItemType::AnnotationSet, None, set_position))?;
             let current_offset = self.file_cursor.position();
-            let annotation_offset = self.file_cursor.read_u32::<B>().chain_err(|| {
-                format!(
-                    "error reading anotation offset at offset {:#010x}",
-                    current_offset
-                )
-            })?;
-            let current_offset = self.file_cursor.position();
-            self.file_cursor.set_position(annotation_offset as u64);
-            annotation_set.push(self.read_annotation()?);
+            self.file_cursor.set_position(self.data_position(annotation_offset));
+            annotation_set.push(self.read_annotation()
+                .chain_err(|| ErrorKind::item(ItemType::AnnotationSet, None, set_position))?);
             self.file_cursor.set_position(current_offset);
         }
 
@@ -460,95 +1003,249 @@ impl DexReader {
     /// Reads an annotation.
     fn read_annotation(&mut self) -> Result<Annotation> {
         let current_offset = self.file_cursor.position();
-        let annotation = Annotation::from_reader(&mut self.file_cursor).chain_err(|| {
-            format!("could not read annotation at offset {:#010x}", {
+        let annotation = Annotation::from_reader(&mut self.file_cursor)
+            .chain_err(|| ErrorKind::item(ItemType::Annotation, None, current_offset))?;
+
+        Ok(annotation)
+    }
+
+    /// Reads the code of each of the given methods and attaches it to the method.
+    fn read_methods_code<B: ByteOrder>(&mut self, methods: &mut [Method]) -> Result<()> {
+        for method in methods {
+            if method.method_index() as usize >= self.dex.method_ids.len() {
+                return Err(ErrorKind::UnknownMethodIndex(method.method_index()).into());
+            }
+            if let Some(offset) = method.code_offset() {
+                let code = self.read_code_item::<B>(offset, method.method_index())
+                    .chain_err(|| {
+                    format!(
+                        "could not read code for method with index {}",
+                        method.method_index()
+                    )
+                })?;
+                method.set_code(code);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the code item at the given offset, of the method with the given index.
+    ///
+    /// The debug information offset of compact code items is read from the debug information
+    /// offsets table of the file.
+    fn read_code_item<B: ByteOrder>(
+        &mut self,
+        offset: u32,
+        method_index: u32,
+    ) -> Result<CodeItem> {
+        let dex = self.dex;
+        let position = self.data_position(offset);
+        let code_item = if let Some(compact_dex) = dex.header.get_compact_dex_header() {
+            let debug_info_offset = self.read_debug_info_offset::<B>(compact_dex, method_index)
+                .chain_err(|| "could not read debug information offset")?;
+            self.file_cursor.set_position(position);
+            CodeItem::from_compact_reader::<_, B>(&mut self.file_cursor, debug_info_offset)
+        } else {
+            self.file_cursor.set_position(position);
+            CodeItem::from_reader::<_, B>(&mut self.file_cursor)
+        };
+        let mut code_item =
+            code_item.chain_err(|| ErrorKind::item(ItemType::Code, None, position))?;
+        if let Some(offset) = code_item.debug_info_offset() {
+            self.file_cursor.set_position(self.data_position(offset));
+            code_item.set_debug_info(self.read_debug_info()?);
+        }
+
+        Ok(code_item)
+    }
+
+    /// Reads the offset of the debug information of the method with the given index from the
+    /// debug information offsets table of a compact dex file.
+    ///
+    /// The table has a block for each 16 methods, with a bit mask of the methods that have debug
+    /// information followed by the uleb128 deltas between their offsets.
+    fn read_debug_info_offset<B: ByteOrder>(
+        &mut self,
+        compact_dex: &CompactDexHeader,
+        method_index: u32,
+    ) -> Result<u32> {
+        let table_position = self.data_position(compact_dex.debug_info_offsets_position());
+        self.file_cursor.set_position(
+            table_position + u64::from(compact_dex.debug_info_offsets_table_offset())
+                + u64::from(method_index / 16) * 4,
+        );
+        let block_offset = self.file_cursor
+            .read_u32::<B>()
+            .chain_err(|| format!("could not read table entry for method {}", method_index))?;
+        self.file_cursor
+            .set_position(table_position + u64::from(block_offset));
+        let bit_mask = self.file_cursor
+            .read_u16::<BigEndian>()
+            .chain_err(|| format!("could not read bit mask for method {}", method_index))?;
+
+        let bit = method_index % 16;
+        if bit_mask & (1 << bit) == 0 {
+            return Ok(0);
+        }
+        let mut offset = compact_dex.debug_info_base();
+        for _ in 0..(bit_mask & (0xffff >> (15 - bit))).count_ones() {
+            let (delta, _) = read_uleb128(&mut self.file_cursor)
+                .chain_err(|| format!("could not read offset delta for method {}", method_index))?;
+            offset = offset.wrapping_add(delta);
+        }
+
+        Ok(offset)
+    }
+
+    /// Reads debug information.
+    fn read_debug_info(&mut self) -> Result<DebugInfo> {
+        let current_offset = self.file_cursor.position();
+        let (debug_info, _) = DebugInfo::from_reader(&mut self.file_cursor)
+            .chain_err(|| ErrorKind::item(ItemType::DebugInfo, None, current_offset))?;
+
+        Ok(debug_info)
+    }
+}
+
+impl<'a> From<DexReader<'a>> for Dex<'a> {
+    fn from(reader: DexReader<'a>) -> Dex<'a> {
+        let classes = (0..reader.header.get_class_defs_size())
+            .map(|_| OnceLock::new())
+            .collect();
+        Dex {
+            header: reader.header,
+            map: reader.map,
+            strings: reader.strings,
+            types: reader.types,
+            prototypes: reader.prototypes,
+            field_ids: reader.field_ids,
+            method_ids: reader.method_ids,
+            classes: Classes::Lazy(classes),
+            method_handles: reader.method_handles,
+            call_sites: reader.call_sites,
+            contents: Some(reader.file_cursor.into_inner()),
+            hiddenapi_class_data: reader.hiddenapi_class_data,
+            base_offset: reader.base_offset,
+            strict: reader.strict,
+            warnings: Mutex::new(reader.warnings),
+        }
+    }
+}
+
+/// Checks that the map item of the given type matches the size and offset in the header.
+fn check_map_item(map: &Map, item_type: ItemType, size: u32, offset: Option<u32>) -> Result<()> {
+    match (map.get_item(item_type), offset) {
+        (Some(item), Some(offset)) if size > 0 => {
+            if item.size() != size {
+                Err(ErrorKind::Map(format!(
+                    "the map has {} items of type {:?}, but the header has {}",
+                    item.size(),
+                    item_type,
+                    size
+                )).into())
+            } else if item.offset() != offset {
+                Err(ErrorKind::Map(format!(
+                    "the map places items of type {:?} at offset {:#010x}, but the header \
+                     places them at {:#010x}",
+                    item_type,
+                    item.offset(),
+                    offset
+                )).into())
+            } else {
+                Ok(())
+            }
+        }
+        (None, _) if size == 0 => Ok(()),
+        (None, _) => Err(ErrorKind::Map(format!(
+            "item type {:?} is missing from the map",
+            item_type
+        )).into()),
+        (Some(_), _) => Err(ErrorKind::Map(format!(
+            "item type {:?} is in the map, but not in the header",
+            item_type
+        )).into()),
+    }
+}
+
+/// Checks if the items of the given type are in the data section of a dex file.
+fn is_in_data_section(item_type: ItemType) -> bool {
+    !matches!(
+        item_type,
+        ItemType::Header | ItemType::StringId | ItemType::TypeId | ItemType::ProtoId
+            | ItemType::FieldId | ItemType::MethodId | ItemType::ClassDef
+            | ItemType::CallSiteId | ItemType::MethodHandle
+    )
+}
+
+/// Reads a list of types from a reader, resolving them in the given type list.
+fn read_type_list<'a, B: ByteOrder, T: AsRef<[u8]>>(
+    file_cursor: &mut Cursor<T>,
+    types: &[Type<'a>],
+) -> Result<Box<[Type<'a>]>> {
+    let current_offset = file_cursor.position();
+    let size = file_cursor.read_u32::<B>().chain_err(|| {
+        format!(
+            "error reading the size of the type list at offset {:#010x}",
+            current_offset
+        )
+    })?;
+
+    let mut type_list = Vec::new();
+    for _ in 0..size {
+        let current_offset = file_cursor.position();
+        let index = file_cursor.read_u16::<B>().chain_err(|| {
+            format!(
+                "error reading type index for type list item at offset {:#010x}",
                 current_offset
-            })
+            )
         })?;
+        type_list.push(
+            types
+                .get(index as usize)
+                .ok_or_else(|| Error::from(ErrorKind::UnknownTypeIndex(u32::from(index))))?
+                .clone(),
+        );
+    }
 
-        Ok(annotation)
+    Ok(type_list.into_boxed_slice())
+}
+
+/// Checks that the method types and method handles in a value exist.
+fn check_value(
+    value: &Value,
+    prototypes: &[Prototype<'_>],
+    method_handles: &[MethodHandle],
+) -> Result<()> {
+    match *value {
+        Value::MethodType(index) if index as usize >= prototypes.len() => {
+            return Err(ErrorKind::InvalidValue(format!(
+                "unknown prototype index {} for method type",
+                index
+            )).into());
+        }
+        Value::MethodHandle(index) if index as usize >= method_handles.len() => {
+            return Err(ErrorKind::InvalidValue(format!(
+                "unknown method handle index {}",
+                index
+            )).into());
+        }
+        Value::Array(ref array) => for value in array.iter() {
+            check_value(value, prototypes, method_handles)?;
+        },
+        Value::Annotation(ref annotation) => for element in annotation.elements() {
+            check_value(element, prototypes, method_handles)?;
+        },
+        _ => {}
     }
 
-    // /// Reads the map of the dex file.
-    // fn read_map<B: ByteOrder>(&mut self) -> Result<()> {
-    //     let map = Map::from_reader::<_, E>(&mut self.reader, &mut self.offset_map).chain_err(|| {
-    //             format!("error reading map at offset {:#010x}", self.current_offset)
-    //         })?;
-    //     if let Some(count) = map.get_num_items_for(ItemType::TypeList) {
-    //         self.type_lists.reserve_exact(count);
-    //     }
-    //     if let Some(count) = map.get_num_items_for(ItemType::AnnotationSetList) {
-    //         self.annotation_set_ref_list.reserve_exact(count);
-    //     }
-    //     if let Some(count) = map.get_num_items_for(ItemType::AnnotationSet) {
-    //         self.annotation_sets.reserve_exact(count);
-    //     }
-    //     if let Some(count) = map.get_num_items_for(ItemType::Annotation) {
-    //         self.annotations.reserve_exact(count);
-    //     }
-    //     if let Some(count) = map.get_num_items_for(ItemType::EncodedArray) {
-    //         self.arrays.reserve_exact(count);
-    //     }
-    //     if let Some(count) = map.get_num_items_for(ItemType::AnnotationsDirectory) {
-    //         self.annotations_directories.reserve_exact(count);
-    //     }
-    //     self.current_offset += 4 + MAP_ITEM_SIZE * map.get_item_list().len() as u32;
-    //
-    //     Ok(())
-    // }
-
-    // /// Reads a list of annotation sets.
-    // fn read_annotation_set_list<B: ByteOrder>(&mut self) -> Result<()> {
-    //     let size = self.reader
-    //         .read_u32::<E>()
-    //         .chain_err(|| {
-    //             format!("error reading annotation set list size at offset {:#010x}",
-    //                     self.current_offset)
-    //         })?;
-    //     let mut annotation_set_list = Vec::with_capacity(size as usize);
-    //
-    //     for _ in 0..size {
-    //         let annotation_set_offset = self.reader
-    //             .read_u32::<E>()
-    //             .chain_err(|| {
-    //                 format!("could not read annotation set offset for an anotation set in the \
-    //                         anotation set list at offset {:#010x}",
-    //                         self.current_offset)
-    //             })?;
-    //         self.offset_map.insert(annotation_set_offset, OffsetType::AnnotationSet);
-    //         annotation_set_list.push(annotation_set_offset);
-    //     }
-    //     self.annotation_set_ref_list.push(annotation_set_list.into_boxed_slice());
-    //     self.current_offset += 4 + ANNOTATION_SET_REF_SIZE * size;
-    //
-    //     Ok(())
-    // }
-
-    // /// Reads code information.
-    // fn read_code_item<B: ByteOrder>(&mut self) -> Result<()> {
-    //     let (code_item, read) = CodeItem::from_reader::<_, E>(&mut self.reader,
-    //                                                           &mut self.offset_map)
-    // .chain_err(|| {
-    //             format!("could not read code item at offset {:#010x}",
-    //                     self.current_offset)
-    //         })?;
-    //     self.code_segments.push((self.current_offset, code_item));
-    //     self.current_offset += read;
-    //
-    //     Ok(())
-    // }
-
-    // /// Reads debug information.
-    // fn read_debug_info(&mut self) -> Result<()> {
-    //     let (debug_info, read) = DebugInfo::from_reader(&mut self.reader).chain_err(|| {
-    //             format!("could not read debug information at offset {:#010x}",
-    //                     self.current_offset)
-    //         })?;
-    //     self.debug_info.push((self.current_offset, debug_info));
-    //     self.current_offset += read;
-    //
-    //     Ok(())
-    // }
+    Ok(())
+}
+
+/// Reads the hidden API flags of a member from a reader.
+fn read_hiddenapi_flags<R: Read>(reader: &mut R) -> Result<HiddenApiFlags> {
+    let (flags, _) = read_uleb128(reader).chain_err(|| "could not read hidden API flags")?;
+    HiddenApiFlags::from_u32(flags)
 }
 
 /// Reads a uleb128 from a reader.
@@ -556,21 +1253,18 @@ impl DexReader {
 /// Returns the u32 represented by the uleb128 and the number of bytes read.
 pub fn read_uleb128<R: Read>(reader: &mut R) -> Result<(u32, u32)> {
     let mut result = 0;
-    let mut read = 0;
-    for (i, byte) in reader.bytes().enumerate() {
-        let byte = byte.chain_err(|| format!("could not read byte {}", i))?;
+    for i in 0..5 {
+        let byte = reader
+            .read_u8()
+            .chain_err(|| format!("could not read byte {}", i))?;
         let payload = (byte & 0b01111111) as u32;
-        match i {
-            0...4 => result |= payload << (i * 7),
-            _ => return Err(ErrorKind::InvalidLeb128.into()),
-        }
+        result |= payload << (i * 7);
 
         if byte & 0b10000000 == 0x00 {
-            read = i + 1;
-            break;
+            return Ok((result, i + 1));
         }
     }
-    Ok((result, read as u32))
+    Err(ErrorKind::InvalidLeb128.into())
 }
 
 /// Reads a uleb128p1 from a reader.
@@ -589,9 +1283,48 @@ pub fn read_sleb128<R: Read>(reader: &mut R) -> Result<(i32, u32)> {
     let s_bits = read * 7;
     let mut signed = uleb128 as i32;
 
-    if (signed & 1 << s_bits) != 0 {
+    // The sign bit is the most significant bit of the last 7-bit group. Values that span five
+    // bytes already have all 32 bits set.
+    if s_bits < 32 && (signed & 1 << (s_bits - 1)) != 0 {
         signed |= -1 << s_bits;
     }
 
     Ok((signed, read))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_uleb128() {
+        assert_eq!((0, 1), read_uleb128(&mut Cursor::new([0x00])).unwrap());
+        assert_eq!((0x7f, 1), read_uleb128(&mut Cursor::new([0x7f])).unwrap());
+        assert_eq!((0x80, 2), read_uleb128(&mut Cursor::new([0x80, 0x01])).unwrap());
+        assert_eq!(
+            (0xffff_ffff, 5),
+            read_uleb128(&mut Cursor::new([0xff, 0xff, 0xff, 0xff, 0x0f])).unwrap()
+        );
+        assert!(read_uleb128(&mut Cursor::new([0x80, 0x80, 0x80, 0x80, 0x80])).is_err());
+    }
+
+    #[test]
+    fn it_reads_uleb128p1() {
+        assert_eq!((0xffff_ffff, 1), read_uleb128p1(&mut Cursor::new([0x00])).unwrap());
+        assert_eq!((0x7e, 1), read_uleb128p1(&mut Cursor::new([0x7f])).unwrap());
+    }
+
+    #[test]
+    fn it_reads_sleb128() {
+        assert_eq!((0, 1), read_sleb128(&mut Cursor::new([0x00])).unwrap());
+        assert_eq!((1, 1), read_sleb128(&mut Cursor::new([0x01])).unwrap());
+        assert_eq!((-1, 1), read_sleb128(&mut Cursor::new([0x7f])).unwrap());
+        assert_eq!((-128, 2), read_sleb128(&mut Cursor::new([0x80, 0x7f])).unwrap());
+        assert_eq!((63, 1), read_sleb128(&mut Cursor::new([0x3f])).unwrap());
+        assert_eq!((-64, 1), read_sleb128(&mut Cursor::new([0x40])).unwrap());
+        assert_eq!(
+            (i32::MIN, 5),
+            read_sleb128(&mut Cursor::new([0x80, 0x80, 0x80, 0x80, 0x78])).unwrap()
+        );
+    }
+}
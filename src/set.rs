@@ -0,0 +1,375 @@
+//! Sets of dex files.
+//!
+//! Applications usually ship their code in several dex files (`classes.dex`, `classes2.dex` and
+//! so on), and code in one of them can use classes, fields and methods defined in another. A
+//! [`DexSet`] loads all of them and resolves references across the whole set.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+
+use Dex;
+use error::*;
+use types::{Class, Type};
+
+/// Location of a class definition in a dex set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClassLocation {
+    dex_index: usize,
+    class_def_index: u32,
+}
+
+impl ClassLocation {
+    /// Gets the index of the dex file that defines the class in the set.
+    pub fn dex_index(&self) -> usize {
+        self.dex_index
+    }
+
+    /// Gets the index of the class in the class definitions list of its dex file.
+    pub fn class_def_index(&self) -> u32 {
+        self.class_def_index
+    }
+}
+
+/// Location of the definition of a field or a method in a dex set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MemberLocation {
+    class: ClassLocation,
+    index: u32,
+}
+
+impl MemberLocation {
+    /// Gets the location of the class that defines the member.
+    pub fn class(&self) -> ClassLocation {
+        self.class
+    }
+
+    /// Gets the index of the member in the *Field IDs* or *Method IDs* list of the dex file that
+    /// defines it.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+}
+
+/// Reference that no dex file of the set defines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnresolvedReference {
+    /// Class type, or array of a class type, that no dex file defines.
+    Type {
+        /// Index of the dex file with the reference.
+        dex_index: usize,
+        /// Index of the type in the *Type IDs* list of the dex file.
+        type_index: u32,
+    },
+    /// Field that no class of the set, or its superclasses and interfaces, defines.
+    Field {
+        /// Index of the dex file with the reference.
+        dex_index: usize,
+        /// Index of the field in the *Field IDs* list of the dex file.
+        field_index: u32,
+    },
+    /// Method that no class of the set, or its superclasses and interfaces, defines.
+    Method {
+        /// Index of the dex file with the reference.
+        dex_index: usize,
+        /// Index of the method in the *Method IDs* list of the dex file.
+        method_index: u32,
+    },
+}
+
+/// Set of dex files that reference each other.
+///
+/// Classes are looked up in the order the files were added. If a class is defined in more than
+/// one file, the first definition is used, as the runtime does with multidex applications.
+#[derive(Debug, Default)]
+pub struct DexSet<'a> {
+    dexes: Vec<Dex<'a>>,
+    classes: HashMap<Type<'a>, ClassLocation>,
+}
+
+impl DexSet<'static> {
+    /// Loads the dex files at the given paths, in order.
+    pub fn from_files<P: AsRef<Path>, I: IntoIterator<Item = P>>(
+        paths: I,
+    ) -> Result<DexSet<'static>> {
+        let mut set = DexSet::new();
+        for path in paths {
+            set.add_file(path)?;
+        }
+        Ok(set)
+    }
+
+    /// Loads the dex file at the given path and adds it to the set.
+    ///
+    /// If the file is a container with several dex files (version 041 or later), all of them are
+    /// added.
+    pub fn add_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        for dex in Dex::all_from_file(path)
+            .chain_err(|| format!("could not load dex file {}", path.display()))?
+        {
+            self.add(dex)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> DexSet<'a> {
+    /// Creates a new, empty, dex set.
+    pub fn new() -> DexSet<'a> {
+        DexSet {
+            dexes: Vec::new(),
+            classes: HashMap::new(),
+        }
+    }
+
+    /// Adds a dex file to the set.
+    pub fn add(&mut self, dex: Dex<'a>) -> Result<()> {
+        let dex_index = self.dexes.len();
+        for class_def_index in 0..dex.header().get_class_defs_size() {
+            let class_index = match dex.get_class_index(class_def_index)? {
+                Some(class_index) => class_index,
+                None => continue,
+            };
+            let class_type = dex.get_type(class_index)
+                .ok_or_else(|| Error::from(ErrorKind::UnknownTypeIndex(class_index)))?;
+            let _ = self.classes
+                .entry(class_type.clone())
+                .or_insert(ClassLocation {
+                    dex_index,
+                    class_def_index,
+                });
+        }
+        self.dexes.push(dex);
+        Ok(())
+    }
+
+    /// Gets the dex files of the set, in the order they were added.
+    pub fn dexes(&self) -> &[Dex<'a>] {
+        &self.dexes
+    }
+
+    /// Gets the dex file at the given index of the set, if it exists.
+    pub fn get_dex(&self, dex_index: usize) -> Option<&Dex<'a>> {
+        self.dexes.get(dex_index)
+    }
+
+    /// Finds the location of the definition of the given class type, if the set defines it.
+    pub fn find_class(&self, class_type: &Type<'a>) -> Option<ClassLocation> {
+        self.classes.get(class_type).cloned()
+    }
+
+    /// Gets the class defined at the given location.
+    pub fn get_class(&self, location: ClassLocation) -> Result<Option<&Class<'a>>> {
+        match self.dexes.get(location.dex_index) {
+            Some(dex) => dex.get_class(location.class_def_index),
+            None => Ok(None),
+        }
+    }
+
+    /// Resolves the type at the given index of the given dex file to its class definition.
+    ///
+    /// Returns `None` if the type is not a class, or if no dex file of the set defines it.
+    pub fn resolve_type(&self, dex_index: usize, type_index: u32) -> Option<ClassLocation> {
+        self.dexes
+            .get(dex_index)
+            .and_then(|dex| dex.get_type(type_index))
+            .and_then(|class_type| self.find_class(class_type))
+    }
+
+    /// Resolves the field at the given index of the given dex file to its definition.
+    ///
+    /// The field is looked up in its class, then in its interfaces, and then in its superclass,
+    /// recursively, as the runtime does. Returns `None` if no class of the set defines it, or if
+    /// the field reference is not valid.
+    pub fn resolve_field(
+        &self,
+        dex_index: usize,
+        field_index: u32,
+    ) -> Result<Option<MemberLocation>> {
+        let dex = match self.dexes.get(dex_index) {
+            Some(dex) => dex,
+            None => return Ok(None),
+        };
+        let field = match dex.get_field(field_index) {
+            Some(field) => field,
+            None => return Ok(None),
+        };
+        let (name, field_type, class_type) = match (
+            dex.strings().get(field.name_index()),
+            dex.types().get(field.type_index()),
+            dex.types().get(field.class_index()),
+        ) {
+            (Some(name), Some(field_type), Some(class_type)) => (name, field_type, class_type),
+            _ => return Ok(None),
+        };
+
+        self.find_member(class_type, LookupOrder::InterfacesFirst, |class_dex, class| {
+            let class_data = class.class_data()?;
+            class_data
+                .static_fields()
+                .iter()
+                .chain(class_data.instance_fields())
+                .map(|field| field.field_index())
+                .find(|&index| {
+                    class_dex.get_field(index).is_some_and(|candidate| {
+                        class_dex.strings().get(candidate.name_index()) == Some(name)
+                            && class_dex.types().get(candidate.type_index()) == Some(field_type)
+                    })
+                })
+        })
+    }
+
+    /// Resolves the method at the given index of the given dex file to its definition.
+    ///
+    /// The method is looked up in its class, then in its superclasses, and then in its
+    /// interfaces, as the runtime does. Returns `None` if no class of the set defines it, or if
+    /// the method reference is not valid.
+    pub fn resolve_method(
+        &self,
+        dex_index: usize,
+        method_index: u32,
+    ) -> Result<Option<MemberLocation>> {
+        let dex = match self.dexes.get(dex_index) {
+            Some(dex) => dex,
+            None => return Ok(None),
+        };
+        let method = match dex.get_method(method_index) {
+            Some(method) => method,
+            None => return Ok(None),
+        };
+        let (name, prototype, class_type) = match (
+            dex.strings().get(method.name_index()),
+            dex.prototypes().get(method.prototype_index()),
+            dex.types().get(method.class_index()),
+        ) {
+            (Some(name), Some(prototype), Some(class_type)) => (name, prototype, class_type),
+            _ => return Ok(None),
+        };
+
+        self.find_member(class_type, LookupOrder::SuperclassesFirst, |class_dex, class| {
+            let class_data = class.class_data()?;
+            class_data
+                .direct_methods()
+                .iter()
+                .chain(class_data.virtual_methods())
+                .map(|method| method.method_index())
+                .find(|&index| {
+                    class_dex.get_method(index).is_some_and(|candidate| {
+                        class_dex.strings().get(candidate.name_index()) == Some(name)
+                            && class_dex
+                                .prototypes()
+                                .get(candidate.prototype_index())
+                                .is_some_and(|candidate_prototype| {
+                                    candidate_prototype.return_type() == prototype.return_type()
+                                        && candidate_prototype.parameters().unwrap_or(&[])
+                                            == prototype.parameters().unwrap_or(&[])
+                                })
+                    })
+                })
+        })
+    }
+
+    /// Finds the references of all the dex files of the set that no file defines.
+    pub fn unresolved_references(&self) -> Result<Vec<UnresolvedReference>> {
+        let mut unresolved = Vec::new();
+        for (dex_index, dex) in self.dexes.iter().enumerate() {
+            for (type_index, referenced_type) in dex.types().iter().enumerate() {
+                let element_type = match *referenced_type {
+                    Type::Array { ref array_type, .. } => array_type,
+                    ref other => other,
+                };
+                if let Type::FullyQualifiedName(_) = *element_type {
+                    if self.find_class(element_type).is_none() {
+                        unresolved.push(UnresolvedReference::Type {
+                            dex_index,
+                            type_index: type_index as u32,
+                        });
+                    }
+                }
+            }
+
+            for field_index in 0..dex.fields().len() as u32 {
+                if self.resolve_field(dex_index, field_index)?.is_none() {
+                    unresolved.push(UnresolvedReference::Field {
+                        dex_index,
+                        field_index,
+                    });
+                }
+            }
+
+            for method_index in 0..dex.methods().len() as u32 {
+                if self.resolve_method(dex_index, method_index)?.is_none() {
+                    unresolved.push(UnresolvedReference::Method {
+                        dex_index,
+                        method_index,
+                    });
+                }
+            }
+        }
+        Ok(unresolved)
+    }
+
+    /// Looks for a member in the given class and its ancestors, in the given order, with the
+    /// given function that finds the member in a class of a dex file.
+    fn find_member<F>(
+        &self,
+        class_type: &Type<'a>,
+        order: LookupOrder,
+        find: F,
+    ) -> Result<Option<MemberLocation>>
+    where
+        F: Fn(&Dex<'a>, &Class<'a>) -> Option<u32>,
+    {
+        let mut pending = VecDeque::new();
+        let mut visited = HashSet::new();
+        pending.extend(self.find_class(class_type));
+        while let Some(location) = pending.pop_front() {
+            if !visited.insert(location) {
+                continue;
+            }
+            let dex = &self.dexes[location.dex_index];
+            let class = match dex.get_class(location.class_def_index)? {
+                Some(class) => class,
+                None => continue,
+            };
+            if let Some(index) = find(dex, class) {
+                return Ok(Some(MemberLocation {
+                    class: location,
+                    index,
+                }));
+            }
+
+            let superclass = class
+                .superclass_index()
+                .and_then(|index| self.resolve_type(location.dex_index, index));
+            let interfaces = class.interfaces().iter().filter_map(|i| self.find_class(i));
+            match order {
+                // Superclasses go first, so that interfaces are only searched after the whole
+                // superclass chain.
+                LookupOrder::SuperclassesFirst => {
+                    if let Some(superclass) = superclass {
+                        pending.push_front(superclass);
+                    }
+                    pending.extend(interfaces);
+                }
+                // Each interface, with its own interfaces, is searched before the superclass.
+                LookupOrder::InterfacesFirst => {
+                    let ancestors = interfaces.chain(superclass).collect::<Vec<_>>();
+                    for ancestor in ancestors.into_iter().rev() {
+                        pending.push_front(ancestor);
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Order in which the ancestors of a class are searched for a member.
+#[derive(Debug, Clone, Copy)]
+enum LookupOrder {
+    /// The whole superclass chain, and then the interfaces, as for methods.
+    SuperclassesFirst,
+    /// The interfaces of each class, and then its superclass, as for fields.
+    InterfacesFirst,
+}
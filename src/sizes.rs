@@ -1,11 +1,13 @@
 pub const HEADER_SIZE: u32 = 0x70;
+pub const HEADER_SIZE_V41: u32 = 0x78;
+pub const HEADER_SIZE_COMPACT: u32 = 0x88;
 pub const STRING_ID_ITEM_SIZE: u32 = 0x04;
 pub const TYPE_ID_ITEM_SIZE: u32 = 0x04;
 pub const PROTO_ID_ITEM_SIZE: u32 = 0x0c;
 pub const FIELD_ID_ITEM_SIZE: u32 = 0x08;
 pub const METHOD_ID_ITEM_SIZE: u32 = 0x08;
 pub const CLASS_DEF_ITEM_SIZE: u32 = 0x20;
-// pub const MAP_ITEM_SIZE: u32 = 12;
+pub const MAP_ITEM_SIZE: u32 = 12;
 // pub const TYPE_ITEM_SIZE: u32 = 2;
 // pub const ANNOTATION_SET_REF_SIZE: u32 = 4;
 // pub const ANNOTATION_SET_ITEM_SIZE: u32 = 4;
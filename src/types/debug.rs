@@ -0,0 +1,540 @@
+//! Debug information interpretation module.
+
+use Dex;
+use error::*;
+use super::{AccessFlags, Type};
+use super::read::{DebugInfo, DebugInstruction, Method};
+
+/// Value used in the debug information when an index is not present.
+const NO_INDEX: u32 = 0xffff_ffff;
+/// Smallest line number increment of a special opcode.
+const DBG_LINE_BASE: i32 = -4;
+/// Number of line increments represented by special opcodes.
+const DBG_LINE_RANGE: u8 = 15;
+/// First special opcode.
+const DBG_FIRST_SPECIAL: u8 = 0x0a;
+
+/// Entry of the positions table of a method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    address: u32,
+    line: u32,
+    source_file_index: Option<u32>,
+    prologue_end: bool,
+    epilogue_begin: bool,
+}
+
+impl Position {
+    /// Gets the address of the position, in 16-bit code units from the start of the method.
+    pub fn address(&self) -> u32 {
+        self.address
+    }
+
+    /// Gets the source line of the position.
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// Gets the index in the string list of the source file of the position.
+    ///
+    /// If it's `None`, the position is in the source file of the class.
+    pub fn source_file_index(&self) -> Option<u32> {
+        self.source_file_index
+    }
+
+    /// Checks if the position is the end of the method prologue.
+    pub fn is_prologue_end(&self) -> bool {
+        self.prologue_end
+    }
+
+    /// Checks if the position is the beginning of the method epilogue.
+    pub fn is_epilogue_begin(&self) -> bool {
+        self.epilogue_begin
+    }
+}
+
+/// Positions table of a method, mapping code addresses to source lines.
+#[derive(Debug, Clone)]
+pub struct PositionTable {
+    positions: Box<[Position]>,
+}
+
+impl PositionTable {
+    /// Gets the list of positions, ordered by address.
+    pub fn positions(&self) -> &[Position] {
+        &self.positions
+    }
+
+    /// Gets the position that contains the given address, if any.
+    ///
+    /// This is the last position whose address is lower or equal to the given one.
+    pub fn position_for_address(&self, address: u32) -> Option<&Position> {
+        self.positions
+            .iter()
+            .take_while(|position| position.address <= address)
+            .last()
+    }
+
+    /// Gets the source line of the given address, if it is known.
+    pub fn line_for_address(&self, address: u32) -> Option<u32> {
+        self.position_for_address(address).map(|position| position.line)
+    }
+
+    /// Gets the addresses where the code for the given source line starts.
+    pub fn addresses_for_line(&self, line: u32) -> Vec<u32> {
+        self.positions
+            .iter()
+            .filter(|position| position.line == line)
+            .map(|position| position.address)
+            .collect()
+    }
+}
+
+/// Local variable of a method, live in a register for a range of addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalVariable {
+    register: u32,
+    start_address: u32,
+    end_address: u32,
+    name: String,
+    local_type: Type<'static>,
+    signature: Option<String>,
+}
+
+impl LocalVariable {
+    /// Gets the register that holds the local variable.
+    pub fn register(&self) -> u32 {
+        self.register
+    }
+
+    /// Gets the first address, in 16-bit code units, where the local variable is live.
+    pub fn start_address(&self) -> u32 {
+        self.start_address
+    }
+
+    /// Gets the address, in 16-bit code units, where the local variable stops being live.
+    ///
+    /// The end address is exclusive.
+    pub fn end_address(&self) -> u32 {
+        self.end_address
+    }
+
+    /// Gets the name of the local variable.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Gets the type of the local variable.
+    pub fn local_type(&self) -> &Type<'static> {
+        &self.local_type
+    }
+
+    /// Gets the generic type signature of the local variable, if it has one.
+    pub fn signature(&self) -> Option<&str> {
+        self.signature.as_deref()
+    }
+
+    /// Checks if the local variable is live at the given address.
+    pub fn is_live_at(&self, address: u32) -> bool {
+        self.start_address <= address && address < self.end_address
+    }
+}
+
+/// Local variable table of a method.
+#[derive(Debug, Clone)]
+pub struct LocalVariableTable {
+    locals: Box<[LocalVariable]>,
+}
+
+impl LocalVariableTable {
+    /// Gets the list of local variables, ordered by start address and register.
+    pub fn locals(&self) -> &[LocalVariable] {
+        &self.locals
+    }
+
+    /// Gets the local variables held in the given register, ordered by start address.
+    pub fn locals_for_register(&self, register: u32) -> Vec<&LocalVariable> {
+        self.locals
+            .iter()
+            .filter(|local| local.register == register)
+            .collect()
+    }
+
+    /// Gets the local variable held in the given register at the given address, if any.
+    pub fn local_at(&self, register: u32, address: u32) -> Option<&LocalVariable> {
+        self.locals
+            .iter()
+            .find(|local| local.register == register && local.is_live_at(address))
+    }
+}
+
+/// Description of a local variable in the local variable state machine.
+#[derive(Debug, Clone)]
+struct LocalDescription {
+    name: Option<String>,
+    local_type: Option<Type<'static>>,
+    signature: Option<String>,
+}
+
+/// State of a register in the local variable state machine.
+#[derive(Debug, Clone)]
+struct RegisterState {
+    local: LocalDescription,
+    start_address: Option<u32>,
+}
+
+/// Local variable state machine.
+struct LocalsBuilder<'a, 'd: 'a> {
+    dex: &'a Dex<'d>,
+    registers: Vec<Option<RegisterState>>,
+    locals: Vec<LocalVariable>,
+}
+
+impl<'a, 'd> LocalsBuilder<'a, 'd> {
+    /// Resolves the name, type and signature of a local variable in the dex file.
+    fn describe(&self, name_id: u32, type_id: u32, sig_id: u32) -> Result<LocalDescription> {
+        let name = if name_id == NO_INDEX {
+            None
+        } else {
+            Some(self.dex
+                .get_string(name_id)
+                .ok_or_else(|| Error::from(ErrorKind::UnknownStringIndex(name_id)))?
+                .to_owned())
+        };
+        let local_type = if type_id == NO_INDEX {
+            None
+        } else {
+            Some(self.dex
+                .get_type(type_id)
+                .ok_or_else(|| Error::from(ErrorKind::UnknownTypeIndex(type_id)))?
+                .clone()
+                .into_owned())
+        };
+        let signature = if sig_id == NO_INDEX {
+            None
+        } else {
+            Some(self.dex
+                .get_string(sig_id)
+                .ok_or_else(|| Error::from(ErrorKind::UnknownStringIndex(sig_id)))?
+                .to_owned())
+        };
+
+        Ok(LocalDescription {
+            name,
+            local_type,
+            signature,
+        })
+    }
+
+    /// Gets the state of the given register, checking that it exists.
+    fn register(&mut self, register: u32) -> Result<&mut Option<RegisterState>> {
+        let registers_size = self.registers.len();
+        self.registers.get_mut(register as usize).ok_or_else(|| {
+            ErrorKind::DebugInfo(format!(
+                "register v{} is out of range, the method only has {} registers",
+                register, registers_size
+            )).into()
+        })
+    }
+
+    /// Starts a local variable in a register, ending the one that was live in it, if any.
+    fn start(&mut self, register: u32, local: LocalDescription, address: u32) -> Result<()> {
+        self.end(register, address)?;
+        *self.register(register)? = Some(RegisterState {
+            local,
+            start_address: Some(address),
+        });
+        Ok(())
+    }
+
+    /// Ends the local variable live in a register, if any.
+    fn end(&mut self, register: u32, address: u32) -> Result<()> {
+        let ended = match *self.register(register)? {
+            Some(ref mut state) => state
+                .start_address
+                .take()
+                .map(|start_address| (state.local.clone(), start_address)),
+            None => None,
+        };
+        if let Some((local, start_address)) = ended {
+            // Locals without a name or a type can't be shown, so they are not in the table.
+            if let (Some(name), Some(local_type)) = (local.name, local.local_type) {
+                self.locals.push(LocalVariable {
+                    register,
+                    start_address,
+                    end_address: address,
+                    name,
+                    local_type,
+                    signature: local.signature,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Restarts the last local variable that was live in a register.
+    fn restart(&mut self, register: u32, address: u32) -> Result<()> {
+        match *self.register(register)? {
+            Some(ref mut state) => if state.start_address.is_none() {
+                state.start_address = Some(address);
+            },
+            None => {
+                return Err(ErrorKind::DebugInfo(format!(
+                    "register v{} was restarted without a previous local",
+                    register
+                )).into())
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DebugInfo {
+    /// Gets the names of the parameters of the method, resolved in the string list of the given
+    /// dex file.
+    ///
+    /// Parameters without a name are `None`.
+    pub fn resolve_parameter_names<'a>(&self, dex: &'a Dex<'_>) -> Result<Vec<Option<&'a str>>> {
+        self.parameter_names()
+            .iter()
+            .map(|&name_id| {
+                if name_id == NO_INDEX {
+                    Ok(None)
+                } else {
+                    dex.get_string(name_id)
+                        .map(Some)
+                        .ok_or_else(|| ErrorKind::UnknownStringIndex(name_id).into())
+                }
+            })
+            .collect()
+    }
+
+    /// Runs the debug state machine and builds the local variable table of the given method.
+    ///
+    /// The method must be the one this debug information belongs to, and the dex file the one it
+    /// was read from, since the names, types and parameters of the locals are resolved in it.
+    pub fn local_variables(&self, dex: &Dex<'_>, method: &Method) -> Result<LocalVariableTable> {
+        let code = method.code().ok_or_else(|| {
+            Error::from(ErrorKind::DebugInfo(format!(
+                "method with index {} has no code",
+                method.method_index()
+            )))
+        })?;
+        let method_id = dex.get_method(method.method_index())
+            .ok_or_else(|| Error::from(ErrorKind::UnknownMethodIndex(method.method_index())))?;
+        let prototype = dex.get_prototype(method_id.prototype_index() as u32)
+            .ok_or_else(|| {
+                Error::from(ErrorKind::DebugInfo(format!(
+                    "unknown prototype index {}",
+                    method_id.prototype_index()
+                )))
+            })?;
+
+        let mut builder = LocalsBuilder {
+            dex,
+            registers: vec![None; code.registers_size() as usize],
+            locals: Vec::new(),
+        };
+
+        // Parameters are in the last registers of the method, starting with `this`.
+        let mut register = code.registers_size()
+            .checked_sub(code.ins_size())
+            .map(u32::from)
+            .ok_or_else(|| {
+                Error::from(ErrorKind::DebugInfo(format!(
+                    "method with index {} has more incoming words than registers",
+                    method.method_index()
+                )))
+            })?;
+        if !method.access_flags().contains(AccessFlags::ACC_STATIC) {
+            let this_local = LocalDescription {
+                name: Some("this".to_owned()),
+                local_type: dex.get_type(method_id.class_index() as u32)
+                    .cloned()
+                    .map(Type::into_owned),
+                signature: None,
+            };
+            builder.start(register, this_local, 0)?;
+            register += 1;
+        }
+        let parameters = prototype.parameters().unwrap_or(&[]);
+        for (i, parameter) in parameters.iter().enumerate() {
+            let name_id = self.parameter_names().get(i).cloned().unwrap_or(NO_INDEX);
+            let mut parameter_local = builder.describe(name_id, NO_INDEX, NO_INDEX)?;
+            parameter_local.local_type = Some(parameter.clone().into_owned());
+            builder.start(register, parameter_local, 0)?;
+            register += match *parameter {
+                Type::Long | Type::Double => 2,
+                _ => 1,
+            };
+        }
+
+        let mut address = 0_u32;
+        for instruction in self.bytecode() {
+            match *instruction {
+                DebugInstruction::EndSequence => break,
+                DebugInstruction::AdvancePc { addr_diff } => {
+                    address = address.wrapping_add(addr_diff);
+                }
+                DebugInstruction::StartLocal {
+                    register_num,
+                    name_id,
+                    type_id,
+                } => {
+                    let local = builder.describe(name_id, type_id, NO_INDEX)?;
+                    builder.start(register_num, local, address)?;
+                }
+                DebugInstruction::StartLocalExtended {
+                    register_num,
+                    name_id,
+                    type_id,
+                    sig_id,
+                } => {
+                    let local = builder.describe(name_id, type_id, sig_id)?;
+                    builder.start(register_num, local, address)?;
+                }
+                DebugInstruction::EndLocal { register_num } => {
+                    builder.end(register_num, address)?;
+                }
+                DebugInstruction::RestartLocal { register_num } => {
+                    builder.restart(register_num, address)?;
+                }
+                DebugInstruction::SpecialOpcode { opcode } => {
+                    let adjusted_opcode = opcode - DBG_FIRST_SPECIAL;
+                    address = address.wrapping_add((adjusted_opcode / DBG_LINE_RANGE) as u32);
+                }
+                DebugInstruction::AdvanceLine { .. }
+                | DebugInstruction::SetPrologueEnd
+                | DebugInstruction::SetEpilogueBegin
+                | DebugInstruction::SetFile { .. } => {}
+            }
+        }
+
+        // Locals still live at the end of the sequence last until the end of the code.
+        let end_address = code.insns().len() as u32;
+        for register in 0..u32::from(code.registers_size()) {
+            builder.end(register, end_address)?;
+        }
+
+        let mut locals = builder.locals;
+        locals.sort_by_key(|local| (local.start_address, local.register));
+        Ok(LocalVariableTable {
+            locals: locals.into_boxed_slice(),
+        })
+    }
+
+    /// Runs the debug state machine and builds the positions table of the method.
+    pub fn positions(&self) -> PositionTable {
+        let mut positions = Vec::new();
+        let mut address = 0_u32;
+        let mut line = self.line_start();
+        let mut source_file_index = None;
+        let mut prologue_end = false;
+        let mut epilogue_begin = false;
+
+        for instruction in self.bytecode() {
+            match *instruction {
+                DebugInstruction::EndSequence => break,
+                DebugInstruction::AdvancePc { addr_diff } => {
+                    address = address.wrapping_add(addr_diff);
+                }
+                DebugInstruction::AdvanceLine { line_diff } => {
+                    line = line.wrapping_add(line_diff as u32);
+                }
+                DebugInstruction::SetPrologueEnd => prologue_end = true,
+                DebugInstruction::SetEpilogueBegin => epilogue_begin = true,
+                DebugInstruction::SetFile { name_id } => {
+                    source_file_index = if name_id == NO_INDEX {
+                        None
+                    } else {
+                        Some(name_id)
+                    };
+                }
+                DebugInstruction::SpecialOpcode { opcode } => {
+                    let adjusted_opcode = opcode - DBG_FIRST_SPECIAL;
+                    line = line.wrapping_add(
+                        (DBG_LINE_BASE + (adjusted_opcode % DBG_LINE_RANGE) as i32) as u32,
+                    );
+                    address = address.wrapping_add((adjusted_opcode / DBG_LINE_RANGE) as u32);
+
+                    positions.push(Position {
+                        address,
+                        line,
+                        source_file_index,
+                        prologue_end,
+                        epilogue_begin,
+                    });
+                    prologue_end = false;
+                    epilogue_begin = false;
+                }
+                DebugInstruction::StartLocal { .. }
+                | DebugInstruction::StartLocalExtended { .. }
+                | DebugInstruction::EndLocal { .. }
+                | DebugInstruction::RestartLocal { .. } => {}
+            }
+        }
+
+        PositionTable {
+            positions: positions.into_boxed_slice(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn it_builds_the_positions_table() {
+        let raw = [
+            0x0a, // line_start = 10
+            0x00, // parameters_size = 0
+            0x07, // DBG_SET_PROLOGUE_END
+            0x0e, // special: line += 0, address += 0
+            0x2e, // special: line += 2, address += 2
+            0x09, 0x03, // DBG_SET_FILE "2"
+            0x01, 0x03, // DBG_ADVANCE_PC 3
+            0x02, 0x7e, // DBG_ADVANCE_LINE -2
+            0x08, // DBG_SET_EPILOGUE_BEGIN
+            0x0f, // special: line += 1, address += 0
+            0x00, // DBG_END_SEQUENCE
+        ];
+        let (debug_info, _) = DebugInfo::from_reader(&mut Cursor::new(raw)).unwrap();
+        let table = debug_info.positions();
+        let positions = table.positions();
+
+        assert_eq!(3, positions.len());
+        assert_eq!(0, positions[0].address());
+        assert_eq!(10, positions[0].line());
+        assert!(positions[0].is_prologue_end());
+        assert!(!positions[0].is_epilogue_begin());
+        assert_eq!(None, positions[0].source_file_index());
+
+        assert_eq!(2, positions[1].address());
+        assert_eq!(12, positions[1].line());
+        assert!(!positions[1].is_prologue_end());
+
+        assert_eq!(5, positions[2].address());
+        assert_eq!(11, positions[2].line());
+        assert!(positions[2].is_epilogue_begin());
+        assert_eq!(Some(2), positions[2].source_file_index());
+
+        assert_eq!(Some(10), table.line_for_address(0));
+        assert_eq!(Some(12), table.line_for_address(4));
+        assert_eq!(Some(11), table.line_for_address(7));
+        assert_eq!(vec![2], table.addresses_for_line(12));
+        assert!(table.addresses_for_line(42).is_empty());
+    }
+
+    #[test]
+    fn it_has_no_line_before_the_first_position() {
+        let raw = [0x01, 0x00, 0x01, 0x04, 0x0e, 0x00];
+        let (debug_info, _) = DebugInfo::from_reader(&mut Cursor::new(raw)).unwrap();
+        let table = debug_info.positions();
+
+        assert_eq!(None, table.line_for_address(3));
+        assert_eq!(Some(1), table.line_for_address(4));
+    }
+}
@@ -1,18 +1,135 @@
 //! Types module.
 
 pub mod read;
+pub mod debug;
 
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::str::FromStr;
 use std::ops::Deref;
 use std::fmt::{Display, Formatter};
 use std::fmt;
 
+use Dex;
 use error::*;
 use self::read::ClassData;
 
-#[derive(Debug, Clone)]
+/// String of a dex file.
+///
+/// Dex strings are sequences of UTF-16 code units, so they can contain unpaired surrogates that
+/// can't be represented in a Rust `String`. In that case the original code units are kept, and
+/// the unpaired surrogates are replaced by `U+FFFD` in the `str` representation of the string.
+///
+/// When the string data of the dex file is also valid UTF-8, the string borrows it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DexString<'a> {
+    string: Cow<'a, str>,
+    utf16: Option<Box<[u16]>>,
+}
+
+impl<'a> DexString<'a> {
+    /// Creates a new dex string from its UTF-16 code units.
+    pub fn from_utf16(utf16: Vec<u16>) -> DexString<'a> {
+        match String::from_utf16(&utf16) {
+            Ok(string) => DexString {
+                string: Cow::Owned(string),
+                utf16: None,
+            },
+            Err(_) => DexString {
+                string: Cow::Owned(String::from_utf16_lossy(&utf16)),
+                utf16: Some(utf16.into_boxed_slice()),
+            },
+        }
+    }
+
+    /// Checks if the string borrows its data from the dex file.
+    pub fn is_borrowed(&self) -> bool {
+        matches!(self.string, Cow::Borrowed(_))
+    }
+
+    /// Converts the string into one that owns its data.
+    pub fn into_owned(self) -> DexString<'static> {
+        DexString {
+            string: Cow::Owned(self.string.into_owned()),
+            utf16: self.utf16,
+        }
+    }
+
+    /// Gets the string as a `str`, with unpaired surrogates replaced by `U+FFFD`.
+    pub fn as_str(&self) -> &str {
+        &self.string
+    }
+
+    /// Checks if the string is valid Unicode, this is, if it has no unpaired surrogates.
+    ///
+    /// If it is, its `str` representation is lossless.
+    pub fn is_valid_unicode(&self) -> bool {
+        self.utf16.is_none()
+    }
+
+    /// Gets the UTF-16 code units of the string, exactly as they are in the dex file.
+    pub fn to_utf16(&self) -> Cow<'_, [u16]> {
+        match self.utf16 {
+            Some(ref utf16) => Cow::Borrowed(utf16),
+            None => Cow::Owned(self.string.encode_utf16().collect()),
+        }
+    }
+}
+
+impl<'a> From<String> for DexString<'a> {
+    fn from(string: String) -> DexString<'a> {
+        DexString {
+            string: Cow::Owned(string),
+            utf16: None,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for DexString<'a> {
+    fn from(string: &'a str) -> DexString<'a> {
+        DexString {
+            string: Cow::Borrowed(string),
+            utf16: None,
+        }
+    }
+}
+
+impl<'a> Deref for DexString<'a> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.string
+    }
+}
+
+impl<'a> PartialOrd for DexString<'a> {
+    fn partial_cmp(&self, other: &DexString<'a>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Dex strings are ordered by their UTF-16 code units, as the string IDs of a dex file are.
+impl<'a> Ord for DexString<'a> {
+    fn cmp(&self, other: &DexString<'a>) -> Ordering {
+        if self.utf16.is_none() && other.utf16.is_none() {
+            self.string.encode_utf16().cmp(other.string.encode_utf16())
+        } else {
+            self.to_utf16().cmp(&other.to_utf16())
+        }
+    }
+}
+
+impl<'a> Display for DexString<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.string)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 /// Basic built-in types.
-pub enum Type {
+///
+/// Fully qualified names borrow the string data of the dex file when possible.
+pub enum Type<'a> {
     /// Void type.
     Void,
     /// Boolean.
@@ -34,20 +151,20 @@ pub enum Type {
     /// Fully qualified named type.
     ///
     /// Example: an object.
-    FullyQualifiedName(String),
+    FullyQualifiedName(Cow<'a, str>),
     /// Array.
     Array {
         /// Array dimensions.
         dimensions: u8,
         /// Type of the array.
-        array_type: Box<Type>,
+        array_type: Box<Type<'a>>,
     },
 }
 
-impl FromStr for Type {
-    type Err = Error;
-    fn from_str(s: &str) -> Result<Type> {
-        let mut chars = s.chars();
+impl<'a> Type<'a> {
+    /// Parses a type descriptor, borrowing the fully qualified names from it.
+    pub fn from_descriptor(descriptor: &'a str) -> Result<Type<'a>> {
+        let mut chars = descriptor.chars();
         match chars.next() {
             Some('V') => Ok(Type::Void),
             Some('Z') => Ok(Type::Boolean),
@@ -59,40 +176,86 @@ impl FromStr for Type {
             Some('F') => Ok(Type::Float),
             Some('D') => Ok(Type::Double),
             Some('[') => {
-                let mut dimensions = 1;
-                loop {
-                    match chars.next() {
-                        Some('[') => dimensions += 1,
-                        Some(t) => {
-                            let mut type_str = String::with_capacity(s.len() - dimensions as usize);
-                            type_str.push(t);
-                            type_str.push_str(chars.as_str());
-                            return Ok(Type::Array {
-                                dimensions: dimensions,
-                                array_type: Box::new(type_str.parse()?),
-                            });
-                        }
-                        None => return Err(ErrorKind::InvalidTypeDescriptor(s.to_owned()).into()),
-                    }
+                let array_type = descriptor.trim_start_matches('[');
+                let dimensions = descriptor.len() - array_type.len();
+                if array_type.is_empty() || dimensions > usize::from(u8::MAX) {
+                    return Err(ErrorKind::InvalidTypeDescriptor(descriptor.to_owned()).into());
                 }
+                Ok(Type::Array {
+                    dimensions: dimensions as u8,
+                    array_type: Box::new(Type::from_descriptor(array_type)?),
+                })
+            }
+            Some('L') => Ok(Type::FullyQualifiedName(Cow::Borrowed(chars.as_str()))),
+            _ => Err(ErrorKind::InvalidTypeDescriptor(descriptor.to_owned()).into()),
+        }
+    }
+
+    /// Parses the type descriptor in the given dex string.
+    ///
+    /// The type borrows the string data of the dex file if the dex string does.
+    pub fn from_dex_string(descriptor: &DexString<'a>) -> Result<Type<'a>> {
+        match descriptor.string {
+            Cow::Borrowed(descriptor) => Type::from_descriptor(descriptor),
+            Cow::Owned(ref descriptor) => Type::from_descriptor(descriptor).map(Type::into_owned),
+        }
+    }
+
+    /// Converts the type into one that owns its data.
+    pub fn into_owned(self) -> Type<'static> {
+        match self {
+            Type::Void => Type::Void,
+            Type::Boolean => Type::Boolean,
+            Type::Byte => Type::Byte,
+            Type::Short => Type::Short,
+            Type::Char => Type::Char,
+            Type::Int => Type::Int,
+            Type::Long => Type::Long,
+            Type::Float => Type::Float,
+            Type::Double => Type::Double,
+            Type::FullyQualifiedName(name) => {
+                Type::FullyQualifiedName(Cow::Owned(name.into_owned()))
             }
-            Some('L') => Ok(Type::FullyQualifiedName(chars.as_str().to_owned())),
-            _ => Err(ErrorKind::InvalidTypeDescriptor(s.to_owned()).into()),
+            Type::Array {
+                dimensions,
+                array_type,
+            } => Type::Array {
+                dimensions,
+                array_type: Box::new(array_type.into_owned()),
+            },
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-enum ShortyReturnType {
+impl FromStr for Type<'static> {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Type<'static>> {
+        Type::from_descriptor(s).map(Type::into_owned)
+    }
+}
+
+/// Return type in a short form type descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShortyReturnType {
+    /// Void type.
     Void,
+    /// Boolean.
     Boolean,
+    /// Byte (8 bits).
     Byte,
+    /// Short (16 bits).
     Short,
+    /// Char (16 bits).
     Char,
+    /// Int (32 bits).
     Int,
+    /// Long (64 bits).
     Long,
+    /// Float (32 bits).
     Float,
+    /// Double (64 bits).
     Double,
+    /// Reference (objects and arrays).
     Reference,
 }
 
@@ -114,7 +277,7 @@ impl ShortyReturnType {
     }
 }
 
-impl From<Type> for ShortyReturnType {
+impl<'a> From<Type<'a>> for ShortyReturnType {
     fn from(t: Type) -> ShortyReturnType {
         match t {
             Type::Void => ShortyReturnType::Void,
@@ -148,16 +311,26 @@ impl From<ShortyFieldType> for ShortyReturnType {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-enum ShortyFieldType {
+/// Parameter type in a short form type descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShortyFieldType {
+    /// Boolean.
     Boolean,
+    /// Byte (8 bits).
     Byte,
+    /// Short (16 bits).
     Short,
+    /// Char (16 bits).
     Char,
+    /// Int (32 bits).
     Int,
+    /// Long (64 bits).
     Long,
+    /// Float (32 bits).
     Float,
+    /// Double (64 bits).
     Double,
+    /// Reference (objects and arrays).
     Reference,
 }
 
@@ -199,33 +372,60 @@ impl FromStr for ShortyDescriptor {
             field_types.push(ShortyFieldType::from_char(c)?);
         }
         Ok(ShortyDescriptor {
-            return_type: return_type,
+            return_type,
             field_types: field_types.into_boxed_slice(),
         })
     }
 }
 
+impl ShortyDescriptor {
+    /// Gets the return type of the descriptor.
+    pub fn return_type(&self) -> ShortyReturnType {
+        self.return_type
+    }
+
+    /// Gets the parameter types of the descriptor.
+    pub fn field_types(&self) -> &[ShortyFieldType] {
+        &self.field_types
+    }
+}
+
 /// Prototype implementation.
 #[derive(Debug)]
-pub struct Prototype {
+pub struct Prototype<'a> {
     descriptor: ShortyDescriptor,
-    return_type: Type,
-    parameters: Option<Box<[Type]>>,
+    return_type: Type<'a>,
+    parameters: Option<Box<[Type<'a>]>>,
 }
 
-impl Prototype {
+impl<'a> Prototype<'a> {
     /// Creates a new prototype.
-    pub fn new<TA: Into<Option<Box<[Type]>>>>(
+    pub fn new<TA: Into<Option<Box<[Type<'a>]>>>>(
         descriptor: ShortyDescriptor,
-        return_type: Type,
+        return_type: Type<'a>,
         parameters: TA,
-    ) -> Prototype {
+    ) -> Prototype<'a> {
         Prototype {
             descriptor,
             return_type,
             parameters: parameters.into(),
         }
     }
+
+    /// Gets the short form descriptor of the prototype.
+    pub fn descriptor(&self) -> &ShortyDescriptor {
+        &self.descriptor
+    }
+
+    /// Gets the return type of the prototype.
+    pub fn return_type(&self) -> &Type<'a> {
+        &self.return_type
+    }
+
+    /// Gets the list of parameter types of the prototype, if it has any.
+    pub fn parameters(&self) -> Option<&[Type<'a>]> {
+        self.parameters.as_ref().map(|p| p.as_ref())
+    }
 }
 
 /// Annotation visibility.
@@ -266,6 +466,10 @@ pub enum Value {
     Method(u32),
     /// Enum with the index into the fiels IDs list.
     Enum(u32),
+    /// Method type, with the index into the prototype IDs list.
+    MethodType(u32),
+    /// Method handle, with the index into the method handles list.
+    MethodHandle(u32),
     /// An array of values.
     Array(Array),
     /// Annotation.
@@ -276,12 +480,43 @@ pub enum Value {
     Boolean(bool),
 }
 
+impl Value {
+    /// Gets the prototype referenced by a `MethodType` value in the given dex file.
+    ///
+    /// Returns `None` if the value is not a `MethodType` or if the prototype does not exist.
+    pub fn method_type<'a, 'd>(&self, dex: &'a Dex<'d>) -> Option<&'a Prototype<'d>> {
+        match *self {
+            Value::MethodType(index) => dex.get_prototype(index),
+            _ => None,
+        }
+    }
+
+    /// Gets the method handle referenced by a `MethodHandle` value in the given dex file.
+    ///
+    /// Returns `None` if the value is not a `MethodHandle` or if the method handle does not
+    /// exist.
+    pub fn method_handle<'a>(&self, dex: &'a Dex<'_>) -> Option<&'a MethodHandle> {
+        match *self {
+            Value::MethodHandle(index) => dex.get_method_handle(index),
+            _ => None,
+        }
+    }
+}
+
 /// Array.
 #[derive(Debug)]
 pub struct Array {
     inner: Box<[Value]>,
 }
 
+impl Deref for Array {
+    type Target = [Value];
+
+    fn deref(&self) -> &[Value] {
+        &self.inner
+    }
+}
+
 /// Annotation element.
 #[derive(Debug)]
 pub struct AnnotationElement {
@@ -602,29 +837,30 @@ impl Display for AccessFlags {
 
 /// Structure representing a class.
 #[derive(Debug)]
-pub struct Class {
+pub struct Class<'a> {
     class_index: u32,
     access_flags: AccessFlags,
     superclass_index: Option<u32>,
-    interfaces: Box<[Type]>,
+    interfaces: Box<[Type<'a>]>,
     source_file_index: Option<u32>,
     annotations: Option<AnnotationsDirectory>,
     class_data: Option<ClassData>,
     static_values: Option<Array>,
 }
 
-impl Class {
+impl<'a> Class<'a> {
     /// Creates a new class.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         class_index: u32,
         access_flags: AccessFlags,
         superclass_index: Option<u32>,
-        interfaces: Box<[Type]>,
+        interfaces: Box<[Type<'a>]>,
         source_file_index: Option<u32>,
         annotations: Option<AnnotationsDirectory>,
         class_data: Option<ClassData>,
         static_values: Option<Array>,
-    ) -> Class {
+    ) -> Class<'a> {
         Class {
             class_index,
             access_flags,
@@ -653,7 +889,7 @@ impl Class {
     }
 
     /// Gets the list of interfaces implemented by the class.
-    pub fn interfaces(&self) -> &[Type] {
+    pub fn interfaces(&self) -> &[Type<'a>] {
         &self.interfaces
     }
 
@@ -679,8 +915,324 @@ impl Class {
     pub fn static_values(&self) -> Option<&Array> {
         self.static_values.as_ref()
     }
+}
+
+/// Restriction of a hidden API member, as recorded in the hidden API class data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HiddenApiRestriction {
+    /// Public SDK member, with no restrictions (whitelist).
+    Whitelist,
+    /// Unsupported member that can still be used (greylist).
+    Greylist,
+    /// Blocked member, that can't be used (blacklist).
+    Blacklist,
+    /// Member that can only be used by apps targeting Android 8.x or lower (greylist-max-o).
+    GreylistMaxO,
+    /// Member that can only be used by apps targeting Android 9 or lower (greylist-max-p).
+    GreylistMaxP,
+    /// Member that can only be used by apps targeting Android 10 or lower (greylist-max-q).
+    GreylistMaxQ,
+    /// Member that can only be used by apps targeting Android 11 or lower (greylist-max-r).
+    GreylistMaxR,
+    /// Member that can only be used by apps targeting Android 12 or lower (greylist-max-s).
+    GreylistMaxS,
+}
+
+/// Hidden API flags of a field or method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HiddenApiFlags {
+    restriction: HiddenApiRestriction,
+    core_platform_api: bool,
+    test_api: bool,
+}
+
+impl HiddenApiFlags {
+    /// Creates new hidden API flags.
+    pub fn new(
+        restriction: HiddenApiRestriction,
+        core_platform_api: bool,
+        test_api: bool,
+    ) -> HiddenApiFlags {
+        HiddenApiFlags {
+            restriction,
+            core_platform_api,
+            test_api,
+        }
+    }
+
+    /// Gets the restriction of the member.
+    pub fn restriction(&self) -> HiddenApiRestriction {
+        self.restriction
+    }
+
+    /// Checks if the member is part of the core platform API.
+    pub fn is_core_platform_api(&self) -> bool {
+        self.core_platform_api
+    }
+
+    /// Checks if the member is part of the test API.
+    pub fn is_test_api(&self) -> bool {
+        self.test_api
+    }
+}
+
+/// Type of a method handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MethodHandleType {
+    /// Method handle is a static field setter (accessor).
+    StaticPut,
+    /// Method handle is a static field getter (accessor).
+    StaticGet,
+    /// Method handle is an instance field setter (accessor).
+    InstancePut,
+    /// Method handle is an instance field getter (accessor).
+    InstanceGet,
+    /// Method handle is a static method invoker.
+    InvokeStatic,
+    /// Method handle is an instance method invoker.
+    InvokeInstance,
+    /// Method handle is a constructor method invoker.
+    InvokeConstructor,
+    /// Method handle is a direct method invoker.
+    InvokeDirect,
+    /// Method handle is an interface method invoker.
+    InvokeInterface,
+}
+
+impl MethodHandleType {
+    /// Checks if the method handle accesses a field, instead of invoking a method.
+    pub fn is_field_accessor(&self) -> bool {
+        matches!(
+            *self,
+            MethodHandleType::StaticPut
+                | MethodHandleType::StaticGet
+                | MethodHandleType::InstancePut
+                | MethodHandleType::InstanceGet
+        )
+    }
+}
+
+/// Method handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MethodHandle {
+    handle_type: MethodHandleType,
+    field_or_method_index: u16,
+}
+
+impl MethodHandle {
+    /// Creates a new method handle.
+    pub fn new(handle_type: MethodHandleType, field_or_method_index: u16) -> MethodHandle {
+        MethodHandle {
+            handle_type,
+            field_or_method_index,
+        }
+    }
+
+    /// Gets the type of the method handle.
+    pub fn handle_type(&self) -> MethodHandleType {
+        self.handle_type
+    }
+
+    /// Gets the index of the field or method referenced by the method handle.
+    ///
+    /// If the method handle is a field accessor, it's an index into the field IDs list. If not,
+    /// it's an index into the method IDs list.
+    pub fn field_or_method_index(&self) -> u16 {
+        self.field_or_method_index
+    }
+
+    /// Gets the index in the field IDs list of the field accessed by the method handle, if it is
+    /// a field accessor.
+    pub fn field_index(&self) -> Option<u16> {
+        if self.handle_type.is_field_accessor() {
+            Some(self.field_or_method_index)
+        } else {
+            None
+        }
+    }
+
+    /// Gets the index in the method IDs list of the method invoked by the method handle, if it is
+    /// a method invoker.
+    pub fn method_index(&self) -> Option<u16> {
+        if self.handle_type.is_field_accessor() {
+            None
+        } else {
+            Some(self.field_or_method_index)
+        }
+    }
+}
+
+/// Call site, used by `invoke-custom` instructions.
+#[derive(Debug)]
+pub struct CallSite {
+    method_handle_index: u32,
+    method_name_index: u32,
+    method_type_index: u32,
+    arguments: Box<[Value]>,
+}
+
+impl CallSite {
+    /// Gets the index in the method handles list of the bootstrap linker method.
+    pub fn method_handle_index(&self) -> u32 {
+        self.method_handle_index
+    }
 
-    // static_values: static_values,
+    /// Gets the index in the string list of the name of the method to link.
+    pub fn method_name_index(&self) -> u32 {
+        self.method_name_index
+    }
+
+    /// Gets the index in the prototype IDs list of the type of the method to link.
+    pub fn method_type_index(&self) -> u32 {
+        self.method_type_index
+    }
+
+    /// Gets the extra arguments passed to the bootstrap linker method.
+    pub fn arguments(&self) -> &[Value] {
+        &self.arguments
+    }
+}
+
+/// Type of an item in the map list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ItemType {
+    /// Header item (`header_item`).
+    Header,
+    /// String identifier item (`string_id_item`).
+    StringId,
+    /// Type identifier item (`type_id_item`).
+    TypeId,
+    /// Prototype identifier item (`proto_id_item`).
+    ProtoId,
+    /// Field identifier item (`field_id_item`).
+    FieldId,
+    /// Method identifier item (`method_id_item`).
+    MethodId,
+    /// Class definition item (`class_def_item`).
+    ClassDef,
+    /// Call site identifier item (`call_site_id_item`).
+    CallSiteId,
+    /// Method handle item (`method_handle_item`).
+    MethodHandle,
+    /// Map list (`map_list`).
+    MapList,
+    /// Type list (`type_list`).
+    TypeList,
+    /// Annotation set reference list (`annotation_set_ref_list`).
+    AnnotationSetRefList,
+    /// Annotation set item (`annotation_set_item`).
+    AnnotationSet,
+    /// Class data item (`class_data_item`).
+    ClassData,
+    /// Code item (`code_item`).
+    Code,
+    /// String data item (`string_data_item`).
+    StringData,
+    /// Debug information item (`debug_info_item`).
+    DebugInfo,
+    /// Annotation item (`annotation_item`).
+    Annotation,
+    /// Encoded array item (`encoded_array_item`).
+    EncodedArray,
+    /// Annotations directory item (`annotations_directory_item`).
+    AnnotationsDirectory,
+    /// Hidden API class data item (`hiddenapi_class_data_item`).
+    HiddenapiClassData,
+}
+
+impl Display for ItemType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ItemType::Header => "header_item",
+            ItemType::StringId => "string_id_item",
+            ItemType::TypeId => "type_id_item",
+            ItemType::ProtoId => "proto_id_item",
+            ItemType::FieldId => "field_id_item",
+            ItemType::MethodId => "method_id_item",
+            ItemType::ClassDef => "class_def_item",
+            ItemType::CallSiteId => "call_site_id_item",
+            ItemType::MethodHandle => "method_handle_item",
+            ItemType::MapList => "map_list",
+            ItemType::TypeList => "type_list",
+            ItemType::AnnotationSetRefList => "annotation_set_ref_list",
+            ItemType::AnnotationSet => "annotation_set_item",
+            ItemType::ClassData => "class_data_item",
+            ItemType::Code => "code_item",
+            ItemType::StringData => "string_data_item",
+            ItemType::DebugInfo => "debug_info_item",
+            ItemType::Annotation => "annotation_item",
+            ItemType::EncodedArray => "encoded_array_item",
+            ItemType::AnnotationsDirectory => "annotations_directory_item",
+            ItemType::HiddenapiClassData => "hiddenapi_class_data_item",
+        })
+    }
+}
+
+/// Item of the map list.
+///
+/// It describes the type, the number of items and the offset of one of the sections of the dex
+/// file.
+#[derive(Debug, Clone, Copy)]
+pub struct MapItem {
+    item_type: ItemType,
+    size: u32,
+    offset: u32,
+}
+
+impl MapItem {
+    /// Creates a new map item.
+    pub fn new(item_type: ItemType, size: u32, offset: u32) -> MapItem {
+        MapItem {
+            item_type,
+            size,
+            offset,
+        }
+    }
+
+    /// Gets the type of the items.
+    pub fn item_type(&self) -> ItemType {
+        self.item_type
+    }
+
+    /// Gets the number of items to be found at the offset.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Gets the offset from the start of the file to the items in question.
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+}
+
+/// Map of the dex file.
+///
+/// It lists the entire contents of the file, in order.
+#[derive(Debug, Default)]
+pub struct Map {
+    items: Box<[MapItem]>,
+}
+
+impl Map {
+    /// Gets the list of items in the map, sorted by offset.
+    pub fn items(&self) -> &[MapItem] {
+        &self.items
+    }
+
+    /// Gets the map item for the given item type, if the dex file contains items of that type.
+    pub fn get_item(&self, item_type: ItemType) -> Option<&MapItem> {
+        self.items.iter().find(|item| item.item_type == item_type)
+    }
+
+    /// Gets the number of items of the given type in the dex file, if there is any.
+    pub fn get_num_items_for(&self, item_type: ItemType) -> Option<usize> {
+        self.get_item(item_type).map(|item| item.size as usize)
+    }
+
+    /// Gets the offset of the section containing the given item type, if it exists.
+    pub fn get_offset_for(&self, item_type: ItemType) -> Option<u32> {
+        self.get_item(item_type).map(MapItem::offset)
+    }
 }
 
 #[cfg(test)]
@@ -705,4 +1257,48 @@ mod test {
 
         assert_eq!("protected static abstract", display);
     }
+
+    #[test]
+    fn it_orders_strings_by_utf16_code_units() {
+        // U+FFFD is after U+1F600 in UTF-8, but its high surrogate is before it in UTF-16.
+        let emoji = DexString::from("\u{1f600}");
+        let replacement = DexString::from("\u{fffd}");
+        let unpaired = DexString::from_utf16(vec![0xd800]);
+
+        assert!(emoji < replacement);
+        assert!(unpaired < emoji);
+        assert!(DexString::from("a") < DexString::from("ab"));
+    }
+
+    #[test]
+    fn it_can_parse_a_borrowed_type_descriptor() {
+        let descriptor = "[[Ljava/lang/String;";
+
+        let parsed = Type::from_descriptor(descriptor).unwrap();
+
+        match parsed {
+            Type::Array {
+                dimensions,
+                ref array_type,
+            } => {
+                assert_eq!(2, dimensions);
+                match **array_type {
+                    Type::FullyQualifiedName(Cow::Borrowed(name)) => {
+                        assert_eq!("java/lang/String;", name)
+                    }
+                    ref t => panic!("unexpected array type {:?}", t),
+                }
+            }
+            ref t => panic!("unexpected type {:?}", t),
+        }
+        assert_eq!(parsed, descriptor.parse().unwrap());
+    }
+
+    #[test]
+    fn it_returns_error_if_type_descriptor_is_not_valid() {
+        assert!(Type::from_descriptor("").is_err());
+        assert!(Type::from_descriptor("[[").is_err());
+        assert!(Type::from_descriptor(&format!("{}I", "[".repeat(256))).is_err());
+        assert!(Type::from_descriptor("X").is_err());
+    }
 }
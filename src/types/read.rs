@@ -1,6 +1,7 @@
 //! Types used for reading Dex files.
 
-use std::io::{Read, Seek};
+use std::io::{Read, Seek, SeekFrom};
+use std::str;
 
 use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
 
@@ -8,6 +9,10 @@ use error::*;
 use read::{read_sleb128, read_uleb128, read_uleb128p1};
 use super::{Annotation, EncodedAnnotation, Value, Visibility};
 use super::{AccessFlags, AnnotationElement, Array};
+use super::{ItemType, Map, MapItem};
+use super::{CallSite, MethodHandle, MethodHandleType};
+use super::{HiddenApiFlags, HiddenApiRestriction};
+use super::DexString;
 
 /// Data structure representing the `proto_id_item` type.
 #[derive(Debug)]
@@ -30,8 +35,8 @@ impl PrototypeIdData {
             .read_u32::<B>()
             .chain_err(|| "could not read the parameters_offset field")?;
         Ok(PrototypeIdData {
-            shorty_index: shorty_index,
-            return_type_index: return_type_index,
+            shorty_index,
+            return_type_index,
             parameters_offset: if parameters_offset == 0 {
                 None
             } else {
@@ -88,9 +93,9 @@ impl FieldIdData {
             .read_u32::<B>()
             .chain_err(|| "could not read the name_index field")?;
         Ok(FieldIdData {
-            class_index: class_index,
-            type_index: type_index,
-            name_index: name_index,
+            class_index,
+            type_index,
+            name_index,
         })
     }
 
@@ -139,9 +144,9 @@ impl MethodIdData {
             .read_u32::<B>()
             .chain_err(|| "could not read the name_index field")?;
         Ok(MethodIdData {
-            class_index: class_index,
-            prototype_index: prototype_index,
-            name_index: name_index,
+            class_index,
+            prototype_index,
+            name_index,
         })
     }
 
@@ -187,6 +192,23 @@ pub struct ClassDefData {
 impl ClassDefData {
     /// Creates a new `ClassDefData` from a reader.
     pub fn from_reader<R: Read, B: ByteOrder>(reader: &mut R) -> Result<ClassDefData> {
+        ClassDefData::read::<_, B>(reader, None)
+    }
+
+    /// Creates a new `ClassDefData` from a reader, ignoring the unknown bits of its access flags.
+    ///
+    /// The access flags are added to the given list if they have unknown bits.
+    pub(crate) fn from_reader_lenient<R: Read, B: ByteOrder>(
+        reader: &mut R,
+        invalid_flags: &mut Vec<u32>,
+    ) -> Result<ClassDefData> {
+        ClassDefData::read::<_, B>(reader, Some(invalid_flags))
+    }
+
+    fn read<R: Read, B: ByteOrder>(
+        reader: &mut R,
+        invalid_flags: Option<&mut Vec<u32>>,
+    ) -> Result<ClassDefData> {
         #[inline]
         fn some_if(value: u32, condition: bool) -> Option<u32> {
             if condition {
@@ -222,9 +244,8 @@ impl ClassDefData {
             .chain_err(|| "could not read the static_values_offset field")?;
 
         Ok(ClassDefData {
-            class_index: class_index,
-            access_flags: AccessFlags::from_bits(access_flags)
-                .ok_or_else(|| Error::from(ErrorKind::InvalidAccessFlags(access_flags)))?,
+            class_index,
+            access_flags: read_access_flags(access_flags, invalid_flags, access_flags)?,
             superclass_index: some_if(superclass_index, superclass_index != NO_INDEX),
             interfaces_offset: some_if(interfaces_offset, interfaces_offset != 0),
             source_file_index: some_if(source_file_index, source_file_index != NO_INDEX),
@@ -276,6 +297,287 @@ impl ClassDefData {
     }
 }
 
+const METHOD_HANDLE_TYPE_STATIC_PUT: u16 = 0x00;
+const METHOD_HANDLE_TYPE_STATIC_GET: u16 = 0x01;
+const METHOD_HANDLE_TYPE_INSTANCE_PUT: u16 = 0x02;
+const METHOD_HANDLE_TYPE_INSTANCE_GET: u16 = 0x03;
+const METHOD_HANDLE_TYPE_INVOKE_STATIC: u16 = 0x04;
+const METHOD_HANDLE_TYPE_INVOKE_INSTANCE: u16 = 0x05;
+const METHOD_HANDLE_TYPE_INVOKE_CONSTRUCTOR: u16 = 0x06;
+const METHOD_HANDLE_TYPE_INVOKE_DIRECT: u16 = 0x07;
+const METHOD_HANDLE_TYPE_INVOKE_INTERFACE: u16 = 0x08;
+
+impl MethodHandleType {
+    fn from_u16(value: u16) -> Result<MethodHandleType> {
+        match value {
+            METHOD_HANDLE_TYPE_STATIC_PUT => Ok(MethodHandleType::StaticPut),
+            METHOD_HANDLE_TYPE_STATIC_GET => Ok(MethodHandleType::StaticGet),
+            METHOD_HANDLE_TYPE_INSTANCE_PUT => Ok(MethodHandleType::InstancePut),
+            METHOD_HANDLE_TYPE_INSTANCE_GET => Ok(MethodHandleType::InstanceGet),
+            METHOD_HANDLE_TYPE_INVOKE_STATIC => Ok(MethodHandleType::InvokeStatic),
+            METHOD_HANDLE_TYPE_INVOKE_INSTANCE => Ok(MethodHandleType::InvokeInstance),
+            METHOD_HANDLE_TYPE_INVOKE_CONSTRUCTOR => Ok(MethodHandleType::InvokeConstructor),
+            METHOD_HANDLE_TYPE_INVOKE_DIRECT => Ok(MethodHandleType::InvokeDirect),
+            METHOD_HANDLE_TYPE_INVOKE_INTERFACE => Ok(MethodHandleType::InvokeInterface),
+            v => Err(ErrorKind::InvalidMethodHandleType(v).into()),
+        }
+    }
+}
+
+impl MethodHandle {
+    /// Reads a method handle item from a reader.
+    pub fn from_reader<R: Read, B: ByteOrder>(reader: &mut R) -> Result<MethodHandle> {
+        let handle_type = reader
+            .read_u16::<B>()
+            .chain_err(|| "could not read the method_handle_type field")?;
+        let _unused = reader
+            .read_u16::<B>()
+            .chain_err(|| "could not read the first unused field")?;
+        let field_or_method_index = reader
+            .read_u16::<B>()
+            .chain_err(|| "could not read the field_or_method_id field")?;
+        let _unused = reader
+            .read_u16::<B>()
+            .chain_err(|| "could not read the second unused field")?;
+
+        Ok(MethodHandle::new(
+            MethodHandleType::from_u16(handle_type)?,
+            field_or_method_index,
+        ))
+    }
+}
+
+impl CallSite {
+    /// Reads a call site item from a reader.
+    ///
+    /// The call site item is an encoded array, whose first three elements are the bootstrap
+    /// method handle, the method name and the method type.
+    pub fn from_reader<R: Read>(reader: &mut R) -> Result<CallSite> {
+        let array = Array::from_reader(reader).chain_err(|| "could not read call site array")?;
+        let mut values = array.inner.into_vec().into_iter();
+
+        let method_handle_index = match values.next() {
+            Some(Value::MethodHandle(index)) => index,
+            v => {
+                return Err(ErrorKind::CallSite(format!(
+                    "the first element must be a method handle, found {:?}",
+                    v
+                )).into())
+            }
+        };
+        let method_name_index = match values.next() {
+            Some(Value::String(index)) => index,
+            v => {
+                return Err(ErrorKind::CallSite(format!(
+                    "the second element must be a string, found {:?}",
+                    v
+                )).into())
+            }
+        };
+        let method_type_index = match values.next() {
+            Some(Value::MethodType(index)) => index,
+            v => {
+                return Err(ErrorKind::CallSite(format!(
+                    "the third element must be a method type, found {:?}",
+                    v
+                )).into())
+            }
+        };
+
+        Ok(CallSite {
+            method_handle_index,
+            method_name_index,
+            method_type_index,
+            arguments: values.collect::<Vec<_>>().into_boxed_slice(),
+        })
+    }
+}
+
+const HIDDENAPI_FLAG_WHITELIST: u32 = 0x00;
+const HIDDENAPI_FLAG_GREYLIST: u32 = 0x01;
+const HIDDENAPI_FLAG_BLACKLIST: u32 = 0x02;
+const HIDDENAPI_FLAG_GREYLIST_MAX_O: u32 = 0x03;
+const HIDDENAPI_FLAG_GREYLIST_MAX_P: u32 = 0x04;
+const HIDDENAPI_FLAG_GREYLIST_MAX_Q: u32 = 0x05;
+const HIDDENAPI_FLAG_GREYLIST_MAX_R: u32 = 0x06;
+const HIDDENAPI_FLAG_GREYLIST_MAX_S: u32 = 0x07;
+const HIDDENAPI_RESTRICTION_MASK: u32 = 0x07;
+const HIDDENAPI_FLAG_CORE_PLATFORM_API: u32 = 0x08;
+const HIDDENAPI_FLAG_TEST_API: u32 = 0x10;
+
+impl HiddenApiFlags {
+    /// Decodes the hidden API flags of a member.
+    pub fn from_u32(flags: u32) -> Result<HiddenApiFlags> {
+        if flags
+            & !(HIDDENAPI_RESTRICTION_MASK | HIDDENAPI_FLAG_CORE_PLATFORM_API
+                | HIDDENAPI_FLAG_TEST_API) != 0
+        {
+            return Err(ErrorKind::InvalidHiddenApiFlags(flags).into());
+        }
+        let restriction = match flags & HIDDENAPI_RESTRICTION_MASK {
+            HIDDENAPI_FLAG_WHITELIST => HiddenApiRestriction::Whitelist,
+            HIDDENAPI_FLAG_GREYLIST => HiddenApiRestriction::Greylist,
+            HIDDENAPI_FLAG_BLACKLIST => HiddenApiRestriction::Blacklist,
+            HIDDENAPI_FLAG_GREYLIST_MAX_O => HiddenApiRestriction::GreylistMaxO,
+            HIDDENAPI_FLAG_GREYLIST_MAX_P => HiddenApiRestriction::GreylistMaxP,
+            HIDDENAPI_FLAG_GREYLIST_MAX_Q => HiddenApiRestriction::GreylistMaxQ,
+            HIDDENAPI_FLAG_GREYLIST_MAX_R => HiddenApiRestriction::GreylistMaxR,
+            HIDDENAPI_FLAG_GREYLIST_MAX_S => HiddenApiRestriction::GreylistMaxS,
+            _ => unreachable!(),
+        };
+
+        Ok(HiddenApiFlags::new(
+            restriction,
+            flags & HIDDENAPI_FLAG_CORE_PLATFORM_API != 0,
+            flags & HIDDENAPI_FLAG_TEST_API != 0,
+        ))
+    }
+}
+
+const TYPE_HEADER_ITEM: u16 = 0x0000;
+const TYPE_STRING_ID_ITEM: u16 = 0x0001;
+const TYPE_TYPE_ID_ITEM: u16 = 0x0002;
+const TYPE_PROTO_ID_ITEM: u16 = 0x0003;
+const TYPE_FIELD_ID_ITEM: u16 = 0x0004;
+const TYPE_METHOD_ID_ITEM: u16 = 0x0005;
+const TYPE_CLASS_DEF_ITEM: u16 = 0x0006;
+const TYPE_CALL_SITE_ID_ITEM: u16 = 0x0007;
+const TYPE_METHOD_HANDLE_ITEM: u16 = 0x0008;
+const TYPE_MAP_LIST: u16 = 0x1000;
+const TYPE_TYPE_LIST: u16 = 0x1001;
+const TYPE_ANNOTATION_SET_REF_LIST: u16 = 0x1002;
+const TYPE_ANNOTATION_SET_ITEM: u16 = 0x1003;
+const TYPE_CLASS_DATA_ITEM: u16 = 0x2000;
+const TYPE_CODE_ITEM: u16 = 0x2001;
+const TYPE_STRING_DATA_ITEM: u16 = 0x2002;
+const TYPE_DEBUG_INFO_ITEM: u16 = 0x2003;
+const TYPE_ANNOTATION_ITEM: u16 = 0x2004;
+const TYPE_ENCODED_ARRAY_ITEM: u16 = 0x2005;
+const TYPE_ANNOTATIONS_DIRECTORY_ITEM: u16 = 0x2006;
+const TYPE_HIDDENAPI_CLASS_DATA_ITEM: u16 = 0xF000;
+
+impl ItemType {
+    fn from_u16(value: u16) -> Result<ItemType> {
+        match value {
+            TYPE_HEADER_ITEM => Ok(ItemType::Header),
+            TYPE_STRING_ID_ITEM => Ok(ItemType::StringId),
+            TYPE_TYPE_ID_ITEM => Ok(ItemType::TypeId),
+            TYPE_PROTO_ID_ITEM => Ok(ItemType::ProtoId),
+            TYPE_FIELD_ID_ITEM => Ok(ItemType::FieldId),
+            TYPE_METHOD_ID_ITEM => Ok(ItemType::MethodId),
+            TYPE_CLASS_DEF_ITEM => Ok(ItemType::ClassDef),
+            TYPE_CALL_SITE_ID_ITEM => Ok(ItemType::CallSiteId),
+            TYPE_METHOD_HANDLE_ITEM => Ok(ItemType::MethodHandle),
+            TYPE_MAP_LIST => Ok(ItemType::MapList),
+            TYPE_TYPE_LIST => Ok(ItemType::TypeList),
+            TYPE_ANNOTATION_SET_REF_LIST => Ok(ItemType::AnnotationSetRefList),
+            TYPE_ANNOTATION_SET_ITEM => Ok(ItemType::AnnotationSet),
+            TYPE_CLASS_DATA_ITEM => Ok(ItemType::ClassData),
+            TYPE_CODE_ITEM => Ok(ItemType::Code),
+            TYPE_STRING_DATA_ITEM => Ok(ItemType::StringData),
+            TYPE_DEBUG_INFO_ITEM => Ok(ItemType::DebugInfo),
+            TYPE_ANNOTATION_ITEM => Ok(ItemType::Annotation),
+            TYPE_ENCODED_ARRAY_ITEM => Ok(ItemType::EncodedArray),
+            TYPE_ANNOTATIONS_DIRECTORY_ITEM => Ok(ItemType::AnnotationsDirectory),
+            TYPE_HIDDENAPI_CLASS_DATA_ITEM => Ok(ItemType::HiddenapiClassData),
+            v => Err(ErrorKind::InvalidItemType(v).into()),
+        }
+    }
+}
+
+impl Map {
+    /// Reads the map list from a reader.
+    pub fn from_reader<R: Read, B: ByteOrder>(reader: &mut R) -> Result<Map> {
+        let size = reader
+            .read_u32::<B>()
+            .chain_err(|| "could not read the size of the map list")?;
+        let mut items = Vec::new();
+        for i in 0..size {
+            let item_type = reader
+                .read_u16::<B>()
+                .chain_err(|| format!("could not read the type of map item {}", i))?;
+            let _unused = reader
+                .read_u16::<B>()
+                .chain_err(|| format!("could not read map item {}", i))?;
+            let item_size = reader
+                .read_u32::<B>()
+                .chain_err(|| format!("could not read the size of map item {}", i))?;
+            let offset = reader
+                .read_u32::<B>()
+                .chain_err(|| format!("could not read the offset of map item {}", i))?;
+            items.push(MapItem::new(
+                ItemType::from_u16(item_type)
+                    .chain_err(|| format!("invalid type for map item {}", i))?,
+                item_size,
+                offset,
+            ));
+        }
+
+        Ok(Map {
+            items: items.into_boxed_slice(),
+        })
+    }
+}
+
+impl<'a> DexString<'a> {
+    /// Decodes a string in Modified UTF-8 (MUTF-8) format.
+    ///
+    /// The bytes must not include the trailing NUL byte. MUTF-8 encodes the NUL character as
+    /// `C0 80`, and supplementary characters as two separately encoded UTF-16 surrogates.
+    ///
+    /// If the bytes are also valid UTF-8, which is the case for most strings, the string borrows
+    /// them instead of being decoded.
+    pub fn from_mutf8(bytes: &'a [u8]) -> Result<DexString<'a>> {
+        // Valid UTF-8 without NUL bytes or 4-byte sequences is also valid MUTF-8.
+        if bytes.iter().all(|&byte| byte != 0x00 && byte < 0xf0) {
+            if let Ok(string) = str::from_utf8(bytes) {
+                return Ok(string.into());
+            }
+        }
+
+        let mut utf16 = Vec::with_capacity(bytes.len());
+        let mut bytes_iter = bytes.iter().cloned().enumerate();
+        while let Some((i, byte)) = bytes_iter.next() {
+            let mut next_continuation = || match bytes_iter.next() {
+                Some((_, next)) if next & 0b1100_0000 == 0b1000_0000 => {
+                    Ok(u16::from(next & 0b0011_1111))
+                }
+                _ => Err(Error::from(ErrorKind::InvalidMutf8(format!(
+                    "truncated sequence starting at byte {}",
+                    i
+                )))),
+            };
+            let unit = match byte {
+                0x00 => {
+                    return Err(
+                        ErrorKind::InvalidMutf8(format!("unexpected NUL byte at byte {}", i))
+                            .into(),
+                    )
+                }
+                0x01..=0x7f => u16::from(byte),
+                0xc0..=0xdf => {
+                    let second = next_continuation()?;
+                    u16::from(byte & 0b0001_1111) << 6 | second
+                }
+                0xe0..=0xef => {
+                    let second = next_continuation()?;
+                    let third = next_continuation()?;
+                    u16::from(byte & 0b0000_1111) << 12 | second << 6 | third
+                }
+                _ => {
+                    return Err(ErrorKind::InvalidMutf8(format!(
+                        "invalid byte {:#04x} at byte {}",
+                        byte, i
+                    )).into())
+                }
+            };
+            utf16.push(unit);
+        }
+
+        Ok(DexString::from_utf16(utf16))
+    }
+}
+
 /// Build visibility.
 const VISIBILITY_BUILD: u8 = 0x00;
 /// Runtime visibility.
@@ -454,7 +756,7 @@ impl Value {
                 }
             }
             VALUE_FLOAT => match arg {
-                c @ 0...3 => {
+                c @ 0..=3 => {
                     let mut bytes = [0_u8; 4];
                     reader
                         .read_exact(&mut bytes[..c as usize + 1])
@@ -466,7 +768,7 @@ impl Value {
                 ),
             },
             VALUE_DOUBLE => match arg {
-                c @ 0...7 => {
+                c @ 0..=7 => {
                     let mut bytes = [0_u8; 8];
                     reader
                         .read_exact(&mut bytes[..c as usize + 1])
@@ -475,8 +777,16 @@ impl Value {
                 }
                 _ => unreachable!(),
             },
-            VALUE_METHOD_TYPE => unimplemented!(),
-            VALUE_METHOD_HANDLE => unimplemented!(),
+            VALUE_METHOD_TYPE => {
+                let prototype_index =
+                    Value::read_u32(reader, arg).chain_err(|| "could not read MethodType index")?;
+                Ok(Value::MethodType(prototype_index))
+            }
+            VALUE_METHOD_HANDLE => {
+                let method_handle_index = Value::read_u32(reader, arg)
+                    .chain_err(|| "could not read MethodHandle index")?;
+                Ok(Value::MethodHandle(method_handle_index))
+            }
             VALUE_STRING => {
                 let string_index =
                     Value::read_u32(reader, arg).chain_err(|| "could not read String index")?;
@@ -544,7 +854,7 @@ impl Array {
     /// Creates an array from a reader.
     pub fn from_reader<R: Read>(reader: &mut R) -> Result<Array> {
         let (size, _) = read_uleb128(reader).chain_err(|| "could not read array size")?;
-        let mut array = Vec::with_capacity(size as usize);
+        let mut array = Vec::new();
         for _ in 0..size {
             let value = Value::from_reader(reader).chain_err(|| "could not read value")?;
             array.push(value);
@@ -561,18 +871,18 @@ impl EncodedAnnotation {
     pub fn from_reader<R: Read>(reader: &mut R) -> Result<EncodedAnnotation> {
         let (type_id, _) = read_uleb128(reader).chain_err(|| "could not read type ID")?;
         let (size, _) = read_uleb128(reader).chain_err(|| "could not read size")?;
-        let mut elements = Vec::with_capacity(size as usize);
+        let mut elements = Vec::new();
         for _ in 0..size {
             let (name_id, _) =
                 read_uleb128(reader).chain_err(|| "could not read element's name_id")?;
             let value = Value::from_reader(reader).chain_err(|| "could not read element's value")?;
             elements.push(AnnotationElement {
                 name: name_id,
-                value: value,
+                value,
             });
         }
         Ok(EncodedAnnotation {
-            type_id: type_id,
+            type_id,
             elements: elements.into_boxed_slice(),
         })
     }
@@ -589,10 +899,10 @@ impl Annotation {
         let visibility = Visibility::from_u8(visibility[0])?;
         let annotation =
             EncodedAnnotation::from_reader(reader).chain_err(|| "could not read annotation")?;
-        Ok((Annotation {
-            visibility: visibility,
-            annotation: annotation,
-        }))
+        Ok(Annotation {
+            visibility,
+            annotation,
+        })
     }
 }
 
@@ -683,7 +993,7 @@ impl AnnotationsDirectoryOffsets {
             .chain_err(|| "could not read parameter annotations size")?
             as usize;
 
-        let mut field_annotations = Vec::with_capacity(field_annotations_size);
+        let mut field_annotations = Vec::new();
         for _ in 0..field_annotations_size {
             let field_id = reader
                 .read_u32::<B>()
@@ -692,11 +1002,11 @@ impl AnnotationsDirectoryOffsets {
                 .read_u32::<B>()
                 .chain_err(|| "could not read field annotation offset")?;
             field_annotations.push(FieldAnnotationsOffset {
-                field_id: field_id,
-                offset: offset,
+                field_id,
+                offset,
             });
         }
-        let mut method_annotations = Vec::with_capacity(method_annotations_size);
+        let mut method_annotations = Vec::new();
         for _ in 0..method_annotations_size {
             let method_id = reader
                 .read_u32::<B>()
@@ -705,11 +1015,11 @@ impl AnnotationsDirectoryOffsets {
                 .read_u32::<B>()
                 .chain_err(|| "could not read method annotation offset")?;
             method_annotations.push(MethodAnnotationsOffset {
-                method_id: method_id,
-                offset: offset,
+                method_id,
+                offset,
             });
         }
-        let mut parameter_annotations = Vec::with_capacity(parameter_annotations_size);
+        let mut parameter_annotations = Vec::new();
         for _ in 0..parameter_annotations_size {
             let method_id = reader
                 .read_u32::<B>()
@@ -718,8 +1028,8 @@ impl AnnotationsDirectoryOffsets {
                 .read_u32::<B>()
                 .chain_err(|| "could not read annotation offset")?;
             parameter_annotations.push(ParameterAnnotationsOffset {
-                method_id: method_id,
-                offset: offset,
+                method_id,
+                offset,
             });
         }
         Ok(AnnotationsDirectoryOffsets {
@@ -755,17 +1065,93 @@ impl AnnotationsDirectoryOffsets {
     }
 }
 
+/// Field definition in the class data.
 #[derive(Debug)]
-struct Field {
+pub struct Field {
     field_id: u32,
     access_flags: AccessFlags,
+    hiddenapi_flags: Option<HiddenApiFlags>,
+    offset: u32,
+}
+
+impl Field {
+    /// Gets the index of the field in the *Field IDs* list.
+    pub fn field_index(&self) -> u32 {
+        self.field_id
+    }
+
+    /// Gets the offset of the field definition from the start of the class data.
+    pub(crate) fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Gets the access flags of the field.
+    pub fn access_flags(&self) -> AccessFlags {
+        self.access_flags
+    }
+
+    /// Gets the hidden API flags of the field, if the dex file has hidden API class data.
+    pub fn hiddenapi_flags(&self) -> Option<HiddenApiFlags> {
+        self.hiddenapi_flags
+    }
+
+    /// Sets the hidden API flags of the field.
+    pub(crate) fn set_hiddenapi_flags(&mut self, flags: HiddenApiFlags) {
+        self.hiddenapi_flags = Some(flags);
+    }
 }
 
+/// Method definition in the class data.
 #[derive(Debug)]
-struct Method {
+pub struct Method {
     method_id: u32,
     access_flags: AccessFlags,
     code_offset: Option<u32>,
+    code: Option<CodeItem>,
+    hiddenapi_flags: Option<HiddenApiFlags>,
+    offset: u32,
+}
+
+impl Method {
+    /// Gets the index of the method in the *Method IDs* list.
+    pub fn method_index(&self) -> u32 {
+        self.method_id
+    }
+
+    /// Gets the offset of the method definition from the start of the class data.
+    pub(crate) fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Gets the access flags of the method.
+    pub fn access_flags(&self) -> AccessFlags {
+        self.access_flags
+    }
+
+    /// Gets the offset of the code of the method, if it is not `abstract` or `native`.
+    pub fn code_offset(&self) -> Option<u32> {
+        self.code_offset
+    }
+
+    /// Gets the code of the method, if it is not `abstract` or `native`.
+    pub fn code(&self) -> Option<&CodeItem> {
+        self.code.as_ref()
+    }
+
+    /// Sets the code of the method, read from its code offset.
+    pub(crate) fn set_code(&mut self, code: CodeItem) {
+        self.code = Some(code);
+    }
+
+    /// Gets the hidden API flags of the method, if the dex file has hidden API class data.
+    pub fn hiddenapi_flags(&self) -> Option<HiddenApiFlags> {
+        self.hiddenapi_flags
+    }
+
+    /// Sets the hidden API flags of the method.
+    pub(crate) fn set_hiddenapi_flags(&mut self, flags: HiddenApiFlags) {
+        self.hiddenapi_flags = Some(flags);
+    }
 }
 
 /// Class data structure.
@@ -780,88 +1166,183 @@ pub struct ClassData {
 impl ClassData {
     /// Creates a new class data structure from a reader.
     pub fn from_reader<R: Read>(reader: &mut R) -> Result<ClassData> {
-        let (static_fields_size, _) =
+        ClassData::read(reader, None)
+    }
+
+    /// Creates a new class data structure from a reader, ignoring the unknown bits of the access
+    /// flags of its fields and methods.
+    ///
+    /// The fields and methods with unknown bits in their access flags are added to the given list.
+    pub(crate) fn from_reader_lenient<R: Read>(
+        reader: &mut R,
+        invalid_flags: &mut Vec<InvalidMemberFlags>,
+    ) -> Result<ClassData> {
+        ClassData::read(reader, Some(invalid_flags))
+    }
+
+    fn read<R: Read>(
+        reader: &mut R,
+        mut invalid_flags: Option<&mut Vec<InvalidMemberFlags>>,
+    ) -> Result<ClassData> {
+        let (static_fields_size, mut position) =
             read_uleb128(reader).chain_err(|| "could not read static_fields_size field")?;
-        let (instance_fields_size, _) =
+        let (instance_fields_size, read) =
             read_uleb128(reader).chain_err(|| "could not read instance_fields_size field")?;
-        let (direct_methods_size, _) =
+        position += read;
+        let (direct_methods_size, read) =
             read_uleb128(reader).chain_err(|| "could not read direct_methods_size field")?;
-        let (virtual_methods_size, _) =
+        position += read;
+        let (virtual_methods_size, read) =
             read_uleb128(reader).chain_err(|| "could not read virtual_methods_size field")?;
+        position += read;
+
+        let mut static_fields = Vec::new();
+        ClassData::read_fields(
+            reader,
+            static_fields_size,
+            &mut static_fields,
+            &mut position,
+            invalid_flags.as_deref_mut(),
+        ).chain_err(|| "could not read class static fields")?;
+
+        let mut instance_fields = Vec::new();
+        ClassData::read_fields(
+            reader,
+            instance_fields_size,
+            &mut instance_fields,
+            &mut position,
+            invalid_flags.as_deref_mut(),
+        ).chain_err(|| "could not read class instance fields")?;
+
+        let mut direct_methods = Vec::new();
+        ClassData::read_methods(
+            reader,
+            direct_methods_size,
+            &mut direct_methods,
+            &mut position,
+            invalid_flags.as_deref_mut(),
+        ).chain_err(|| "could not read class direct methods")?;
+
+        let mut virtual_methods = Vec::new();
+        ClassData::read_methods(
+            reader,
+            virtual_methods_size,
+            &mut virtual_methods,
+            &mut position,
+            invalid_flags,
+        ).chain_err(|| "could not read class virtual methods")?;
 
-        let mut static_fields = Vec::with_capacity(static_fields_size as usize);
-        ClassData::read_fields(reader, static_fields_size, &mut static_fields)
-            .chain_err(|| "could not read class static fields")?;
+        Ok(ClassData {
+            static_fields,
+            instance_fields,
+            direct_methods,
+            virtual_methods,
+        })
+    }
 
-        let mut instance_fields = Vec::with_capacity(instance_fields_size as usize);
-        ClassData::read_fields(reader, instance_fields_size, &mut instance_fields)
-            .chain_err(|| "could not read class instance fields")?;
+    /// Gets the list of static fields defined in the class.
+    pub fn static_fields(&self) -> &[Field] {
+        &self.static_fields
+    }
 
-        let mut direct_methods = Vec::with_capacity(direct_methods_size as usize);
-        ClassData::read_methods(reader, direct_methods_size, &mut direct_methods)
-            .chain_err(|| "could not read class direct methods")?;
+    /// Gets the list of instance fields defined in the class.
+    pub fn instance_fields(&self) -> &[Field] {
+        &self.instance_fields
+    }
 
-        let mut virtual_methods = Vec::with_capacity(virtual_methods_size as usize);
-        ClassData::read_methods(reader, virtual_methods_size, &mut virtual_methods)
-            .chain_err(|| "could not read class virtual methods")?;
+    /// Gets the list of direct methods (static, private or constructor) defined in the class.
+    pub fn direct_methods(&self) -> &[Method] {
+        &self.direct_methods
+    }
 
-        Ok(ClassData {
-            static_fields: static_fields,
-            instance_fields: instance_fields,
-            direct_methods: direct_methods,
-            virtual_methods: virtual_methods,
-        })
+    /// Gets the list of virtual methods defined in the class.
+    pub fn virtual_methods(&self) -> &[Method] {
+        &self.virtual_methods
     }
 
+    /// Gets the list of static fields defined in the class, mutably.
+    pub(crate) fn static_fields_mut(&mut self) -> &mut [Field] {
+        &mut self.static_fields
+    }
+
+    /// Gets the list of instance fields defined in the class, mutably.
+    pub(crate) fn instance_fields_mut(&mut self) -> &mut [Field] {
+        &mut self.instance_fields
+    }
+
+    /// Gets the list of direct methods defined in the class, mutably.
+    pub(crate) fn direct_methods_mut(&mut self) -> &mut [Method] {
+        &mut self.direct_methods
+    }
+
+    /// Gets the list of virtual methods defined in the class, mutably.
+    pub(crate) fn virtual_methods_mut(&mut self) -> &mut [Method] {
+        &mut self.virtual_methods
+    }
+
+    /// Reads the given number of field definitions, advancing the position in the class data.
     fn read_fields<R: Read>(
         reader: &mut R,
         field_count: u32,
         field_vec: &mut Vec<Field>,
+        position: &mut u32,
+        mut invalid_flags: Option<&mut Vec<InvalidMemberFlags>>,
     ) -> Result<()> {
-        if field_count > 0 {
-            // First field's ID is given directly.
-            let (field_id, _) = read_uleb128(reader).chain_err(|| "could not read field ID")?;
-            let (access_flags, _) =
+        // The first field's ID is given directly, and the rest by difference with the previous
+        // one.
+        let mut last_field_id = 0_u32;
+        for _ in 0..field_count {
+            let offset = *position;
+            let (field_id_diff, read) =
+                read_uleb128(reader).chain_err(|| "could not read field ID")?;
+            *position += read;
+            let (access_flags, read) =
                 read_uleb128(reader).chain_err(|| "could not read field access flags")?;
-
+            *position += read;
+
+            last_field_id += field_id_diff;
+            let invalid = InvalidMemberFlags {
+                item_type: ItemType::FieldId,
+                index: last_field_id,
+                offset,
+                access_flags,
+            };
             field_vec.push(Field {
-                field_id: field_id,
-                access_flags: AccessFlags::from_bits(access_flags)
-                    .ok_or_else(|| Error::from(ErrorKind::InvalidAccessFlags(access_flags)))?,
+                field_id: last_field_id,
+                access_flags: read_access_flags(
+                    access_flags,
+                    invalid_flags.as_deref_mut(),
+                    invalid,
+                )?,
+                hiddenapi_flags: None,
+                offset,
             });
-
-            let mut last_field_id = field_id;
-            for _ in 1..field_count {
-                let (field_id_diff, _) =
-                    read_uleb128(reader).chain_err(|| "could not read field ID")?;
-                let (access_flags, _) =
-                    read_uleb128(reader).chain_err(|| "could not read field access flags")?;
-
-                // Field IDs other than the first one are given by difference.
-                last_field_id += field_id_diff;
-
-                field_vec.push(Field {
-                    field_id: last_field_id,
-                    access_flags: AccessFlags::from_bits(access_flags)
-                        .ok_or_else(|| Error::from(ErrorKind::InvalidAccessFlags(access_flags)))?,
-                });
-            }
         }
         Ok(())
     }
 
+    /// Reads the given number of method definitions, advancing the position in the class data.
     fn read_methods<R: Read>(
         reader: &mut R,
         method_count: u32,
         method_vec: &mut Vec<Method>,
+        position: &mut u32,
+        mut invalid_flags: Option<&mut Vec<InvalidMemberFlags>>,
     ) -> Result<()> {
-        if method_count > 0 {
-            // First method's ID is given directly.
-            let (method_id, _) = read_uleb128(reader).chain_err(|| "could not read method ID")?;
-            let (access_flags, _) =
+        // The first method's ID is given directly, and the rest by difference with the previous
+        // one.
+        let mut last_method_id = 0_u32;
+        for _ in 0..method_count {
+            let offset = *position;
+            let (method_id_diff, read) =
+                read_uleb128(reader).chain_err(|| "could not read method ID")?;
+            *position += read;
+            let (access_flags, read) =
                 read_uleb128(reader).chain_err(|| "could not read method access flags")?;
-            let (code_offset, _) =
+            *position += read;
+            let (code_offset, read) =
                 read_uleb128(reader).chain_err(|| "could not read method code offset")?;
+            *position += read;
 
             let code_offset = if code_offset == 0 {
                 None
@@ -869,43 +1350,62 @@ impl ClassData {
                 Some(code_offset)
             };
 
+            last_method_id += method_id_diff;
+            let invalid = InvalidMemberFlags {
+                item_type: ItemType::MethodId,
+                index: last_method_id,
+                offset,
+                access_flags,
+            };
             method_vec.push(Method {
-                method_id: method_id,
-                access_flags: AccessFlags::from_bits(access_flags)
-                    .ok_or_else(|| Error::from(ErrorKind::InvalidAccessFlags(access_flags)))?,
-                code_offset: code_offset,
+                method_id: last_method_id,
+                access_flags: read_access_flags(
+                    access_flags,
+                    invalid_flags.as_deref_mut(),
+                    invalid,
+                )?,
+                code_offset,
+                code: None,
+                hiddenapi_flags: None,
+                offset,
             });
-
-            let mut last_method_id = method_id;
-            for _ in 1..method_count {
-                let (method_id_diff, _) =
-                    read_uleb128(reader).chain_err(|| "could not read method ID")?;
-                let (access_flags, _) =
-                    read_uleb128(reader).chain_err(|| "could not read method access flags")?;
-                let (code_offset, _) =
-                    read_uleb128(reader).chain_err(|| "could not read method code offset")?;
-
-                let code_offset = if code_offset == 0 {
-                    None
-                } else {
-                    Some(code_offset)
-                };
-
-                // Method IDs other than the first one are given by difference.
-                last_method_id += method_id_diff;
-
-                method_vec.push(Method {
-                    method_id: last_method_id,
-                    access_flags: AccessFlags::from_bits(access_flags)
-                        .ok_or_else(|| Error::from(ErrorKind::InvalidAccessFlags(access_flags)))?,
-                    code_offset: code_offset,
-                });
-            }
         }
         Ok(())
     }
 }
 
+/// Field or method with unknown bits in its access flags.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct InvalidMemberFlags {
+    /// `FieldId` for fields and `MethodId` for methods.
+    pub(crate) item_type: ItemType,
+    /// Index of the member in the *Field IDs* or *Method IDs* list.
+    pub(crate) index: u32,
+    /// Offset of the member, relative to the start of the class data.
+    pub(crate) offset: u32,
+    /// Access flags in the file.
+    pub(crate) access_flags: u32,
+}
+
+/// Converts the given access flags.
+///
+/// If a list of invalid flags is given, unknown bits are ignored and the given entry is added to
+/// the list, instead of failing.
+fn read_access_flags<T>(
+    access_flags: u32,
+    invalid_flags: Option<&mut Vec<T>>,
+    invalid: T,
+) -> Result<AccessFlags> {
+    match (AccessFlags::from_bits(access_flags), invalid_flags) {
+        (Some(flags), _) => Ok(flags),
+        (None, Some(invalid_flags)) => {
+            invalid_flags.push(invalid);
+            Ok(AccessFlags::from_bits_truncate(access_flags))
+        }
+        (None, None) => Err(ErrorKind::InvalidAccessFlags(access_flags).into()),
+    }
+}
+
 /// Debug information structure.
 #[derive(Debug)]
 pub struct DebugInfo {
@@ -923,7 +1423,7 @@ impl DebugInfo {
             read_uleb128(reader).chain_err(|| "could not read parameters_size field")?;
         read += read_p;
 
-        let mut parameter_names = Vec::with_capacity(parameters_size as usize);
+        let mut parameter_names = Vec::new();
         for _ in 0..parameters_size {
             let (name_index, read_i) =
                 read_uleb128p1(reader).chain_err(|| "could not read parameter name index")?;
@@ -937,9 +1437,9 @@ impl DebugInfo {
 
         Ok((
             DebugInfo {
-                line_start: line_start,
-                parameter_names: parameter_names,
-                bytecode: bytecode,
+                line_start,
+                parameter_names,
+                bytecode,
             },
             read,
         ))
@@ -955,6 +1455,11 @@ impl DebugInfo {
     pub fn parameter_names(&self) -> &[u32] {
         &self.parameter_names
     }
+
+    /// Gets the instructions of the debug state machine, ending with `EndSequence`.
+    pub fn bytecode(&self) -> &[DebugInstruction] {
+        &self.bytecode.bytecode
+    }
 }
 
 /// Debug bytecode.
@@ -978,43 +1483,74 @@ impl DebugBytecode {
                 break;
             }
         }
-        Ok((DebugBytecode { bytecode: bytecode }, read))
+        Ok((DebugBytecode { bytecode }, read))
     }
 }
 
 /// Debug state machine instruction.
+///
+/// Indexes that can be absent (`name_id`, `type_id` and `sig_id`) are `0xffffffff` (`NO_INDEX`)
+/// when they are not present.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum DebugInstruction {
+pub enum DebugInstruction {
+    /// Terminates the debug information sequence (`DBG_END_SEQUENCE`).
     EndSequence,
+    /// Advances the address register without emitting a positions entry (`DBG_ADVANCE_PC`).
     AdvancePc {
+        /// Amount of code units to add to the address register.
         addr_diff: u32,
     },
+    /// Advances the line register without emitting a positions entry (`DBG_ADVANCE_LINE`).
     AdvanceLine {
+        /// Amount to add to the line register.
         line_diff: i32,
     },
+    /// Introduces a local variable at the current address (`DBG_START_LOCAL`).
     StartLocal {
+        /// Register that will contain the local.
         register_num: u32,
+        /// Index in the string list of the name of the local.
         name_id: u32,
+        /// Index in the type list of the type of the local.
         type_id: u32,
     },
+    /// Introduces a local variable with a type signature at the current address
+    /// (`DBG_START_LOCAL_EXTENDED`).
     StartLocalExtended {
+        /// Register that will contain the local.
         register_num: u32,
+        /// Index in the string list of the name of the local.
         name_id: u32,
+        /// Index in the type list of the type of the local.
         type_id: u32,
+        /// Index in the string list of the type signature of the local.
         sig_id: u32,
     },
+    /// Marks a currently live local variable as out of scope (`DBG_END_LOCAL`).
     EndLocal {
+        /// Register that contained the local.
         register_num: u32,
     },
+    /// Re-introduces a local variable at the current address (`DBG_RESTART_LOCAL`).
     RestartLocal {
+        /// Register to restart.
         register_num: u32,
     },
+    /// Marks the next positions entry as the end of the method prologue
+    /// (`DBG_SET_PROLOGUE_END`).
     SetPrologueEnd,
+    /// Marks the next positions entry as the beginning of the method epilogue
+    /// (`DBG_SET_EPILOGUE_BEGIN`).
     SetEpilogueBegin,
+    /// Indicates that all subsequent line number entries refer to this source file
+    /// (`DBG_SET_FILE`).
     SetFile {
+        /// Index in the string list of the name of the source file.
         name_id: u32,
     },
+    /// Advances the line and address registers and emits a positions entry.
     SpecialOpcode {
+        /// Opcode of the instruction, between `0x0a` and `0xff`.
         opcode: u8,
     },
 }
@@ -1033,7 +1569,7 @@ impl DebugInstruction {
                     .chain_err(|| "could not read `addr_diff` for the DBG_ADVANCE_PC instruction")?;
                 read += read_ad;
                 DebugInstruction::AdvancePc {
-                    addr_diff: addr_diff,
+                    addr_diff,
                 }
             }
             0x02_u8 => {
@@ -1042,45 +1578,45 @@ impl DebugInstruction {
                 })?;
                 read += read_ld;
                 DebugInstruction::AdvanceLine {
-                    line_diff: line_diff,
+                    line_diff,
                 }
             }
             0x03_u8 => {
                 let (register_num, read_rn) = read_uleb128(reader).chain_err(|| {
                     "could not read `register_num` for the DBG_START_LOCAL instruction"
                 })?;
-                let (name_id, read_ni) = read_uleb128p1(reader)
+                let (name_id, read_name) = read_uleb128p1(reader)
                     .chain_err(|| "could not read `name_id` for the DBG_START_LOCAL instruction")?;
-                let (type_id, read_ti) = read_uleb128p1(reader)
+                let (type_id, read_type) = read_uleb128p1(reader)
                     .chain_err(|| "could not read `type_id` for the DBG_START_LOCAL instruction")?;
-                read += read_rn + read_ni + read_ti;
+                read += read_rn + read_name + read_type;
 
                 DebugInstruction::StartLocal {
-                    register_num: register_num,
-                    name_id: name_id,
-                    type_id: type_id,
+                    register_num,
+                    name_id,
+                    type_id,
                 }
             }
             0x04_u8 => {
                 let (register_num, read_rn) = read_uleb128(reader).chain_err(|| {
                     "could not read `register_num` for the DBG_START_LOCAL_EXTENDED instruction"
                 })?;
-                let (name_id, read_ni) = read_uleb128p1(reader).chain_err(|| {
+                let (name_id, read_name) = read_uleb128p1(reader).chain_err(|| {
                     "could not read `name_id` for the DBG_START_LOCAL_EXTENDED instruction"
                 })?;
-                let (type_id, read_ti) = read_uleb128p1(reader).chain_err(|| {
+                let (type_id, read_type) = read_uleb128p1(reader).chain_err(|| {
                     "could not read `type_id` for the DBG_START_LOCAL_EXTENDED instruction"
                 })?;
-                let (sig_id, read_si) = read_uleb128p1(reader).chain_err(|| {
+                let (sig_id, read_sig) = read_uleb128p1(reader).chain_err(|| {
                     "could not read `sig_id` for the DBG_START_LOCAL_EXTENDED instruction"
                 })?;
-                read += read_rn + read_ni + read_ti + read_si;
+                read += read_rn + read_name + read_type + read_sig;
 
                 DebugInstruction::StartLocalExtended {
-                    register_num: register_num,
-                    name_id: name_id,
-                    type_id: type_id,
-                    sig_id: sig_id,
+                    register_num,
+                    name_id,
+                    type_id,
+                    sig_id,
                 }
             }
             0x05_u8 => {
@@ -1089,7 +1625,7 @@ impl DebugInstruction {
                 })?;
                 read += read_rn;
                 DebugInstruction::EndLocal {
-                    register_num: register_num,
+                    register_num,
                 }
             }
             0x06_u8 => {
@@ -1098,25 +1634,35 @@ impl DebugInstruction {
                 })?;
                 read += read_rn;
                 DebugInstruction::RestartLocal {
-                    register_num: register_num,
+                    register_num,
                 }
             }
             0x07_u8 => DebugInstruction::SetPrologueEnd,
             0x08_u8 => DebugInstruction::SetEpilogueBegin,
             0x09_u8 => {
-                let (name_id, read_ni) = read_uleb128(reader)
+                let (name_id, read_name) = read_uleb128p1(reader)
                     .chain_err(|| "could not read `name_id` for the DBG_SET_FILE instruction")?;
-                read += read_ni;
-                DebugInstruction::SetFile { name_id: name_id }
+                read += read_name;
+                DebugInstruction::SetFile { name_id }
             }
-            oc @ 0x0a_u8...0xff_u8 => DebugInstruction::SpecialOpcode { opcode: oc },
-            _ => unreachable!(),
+            oc @ 0x0a_u8..=0xff_u8 => DebugInstruction::SpecialOpcode { opcode: oc },
         };
 
         Ok((instruction, read))
     }
 }
 
+/// Flag of compact code items whose registers size has a part in the pre-header.
+const PREHEADER_REGISTERS_SIZE: u16 = 0x01;
+/// Flag of compact code items whose incoming words size has a part in the pre-header.
+const PREHEADER_INS_SIZE: u16 = 0x02;
+/// Flag of compact code items whose outgoing words size has a part in the pre-header.
+const PREHEADER_OUTS_SIZE: u16 = 0x04;
+/// Flag of compact code items whose tries size has a part in the pre-header.
+const PREHEADER_TRIES_SIZE: u16 = 0x08;
+/// Flag of compact code items whose bytecode array size has a part in the pre-header.
+const PREHEADER_INSNS_SIZE: u16 = 0x10;
+
 /// Code Item structure
 #[derive(Debug)]
 pub struct CodeItem {
@@ -1127,6 +1673,7 @@ pub struct CodeItem {
     insns: Vec<u16>,
     tries: Vec<TryItem>,
     handlers: Vec<CatchHandler>,
+    debug_info: Option<DebugInfo>,
 }
 
 impl CodeItem {
@@ -1151,7 +1698,109 @@ impl CodeItem {
             .read_u32::<B>()
             .chain_err(|| "could not read the size of the bytecode array")?;
 
-        let mut insns = Vec::with_capacity(insns_size as usize);
+        CodeItem::read_contents::<_, B>(
+            reader,
+            registers_size,
+            ins_size,
+            outs_size,
+            tries_size,
+            debug_info_offset,
+            insns_size,
+        )
+    }
+
+    /// Reads a compact code item, of a compact dex file, from the given reader.
+    ///
+    /// Compact code items pack their sizes in two 16-bit fields, and the sizes that do not fit
+    /// are added from a pre-header right before the code item, so the reader must be able to
+    /// seek back. Their debug information offset is not in the code item, so it must be given.
+    pub fn from_compact_reader<R: Read + Seek, B: ByteOrder>(
+        reader: &mut R,
+        debug_info_offset: u32,
+    ) -> Result<CodeItem> {
+        /// Reads the pre-header field right before the given position, and moves the position
+        /// to it.
+        fn read_preheader<R: Read + Seek, B: ByteOrder>(
+            reader: &mut R,
+            position: &mut u64,
+        ) -> Result<u16> {
+            *position = position
+                .checked_sub(2)
+                .ok_or_else(|| Error::from("the pre-header starts before the file"))?;
+            let _ = reader
+                .seek(SeekFrom::Start(*position))
+                .chain_err(|| "could not seek to the pre-header")?;
+            reader
+                .read_u16::<B>()
+                .chain_err(|| "could not read pre-header field")
+        }
+
+        let start = reader
+            .stream_position()
+            .chain_err(|| "could not get the position of the code item")?;
+        let fields = reader
+            .read_u16::<B>()
+            .chain_err(|| "could not read packed sizes")?;
+        let insns_count_and_flags = reader
+            .read_u16::<B>()
+            .chain_err(|| "could not read the size of the bytecode array")?;
+        let mut registers_size = fields >> 12;
+        let mut ins_size = (fields >> 8) & 0xf;
+        let mut outs_size = (fields >> 4) & 0xf;
+        let mut tries_size = fields & 0xf;
+        let mut insns_size = u32::from(insns_count_and_flags >> 5);
+
+        let flags = insns_count_and_flags & 0x1f;
+        if flags != 0 {
+            let mut position = start;
+            if flags & PREHEADER_INSNS_SIZE != 0 {
+                insns_size += u32::from(read_preheader::<_, B>(reader, &mut position)?);
+                insns_size += u32::from(read_preheader::<_, B>(reader, &mut position)?) << 16;
+            }
+            if flags & PREHEADER_REGISTERS_SIZE != 0 {
+                registers_size = registers_size
+                    .wrapping_add(read_preheader::<_, B>(reader, &mut position)?);
+            }
+            if flags & PREHEADER_INS_SIZE != 0 {
+                ins_size = ins_size.wrapping_add(read_preheader::<_, B>(reader, &mut position)?);
+            }
+            if flags & PREHEADER_OUTS_SIZE != 0 {
+                outs_size = outs_size.wrapping_add(read_preheader::<_, B>(reader, &mut position)?);
+            }
+            if flags & PREHEADER_TRIES_SIZE != 0 {
+                tries_size =
+                    tries_size.wrapping_add(read_preheader::<_, B>(reader, &mut position)?);
+            }
+            let _ = reader
+                .seek(SeekFrom::Start(start + 4))
+                .chain_err(|| "could not seek back to the code item")?;
+        }
+        // The registers size in compact code items does not include the incoming words.
+        let registers_size = registers_size.wrapping_add(ins_size);
+
+        CodeItem::read_contents::<_, B>(
+            reader,
+            registers_size,
+            ins_size,
+            outs_size,
+            tries_size,
+            debug_info_offset,
+            insns_size,
+        )
+    }
+
+    /// Reads the bytecode array, the try items and the catch handlers of a code item, after its
+    /// sizes.
+    fn read_contents<R: Read, B: ByteOrder>(
+        reader: &mut R,
+        registers_size: u16,
+        ins_size: u16,
+        outs_size: u16,
+        tries_size: u16,
+        debug_info_offset: u32,
+        insns_size: u32,
+    ) -> Result<CodeItem> {
+        let mut insns = Vec::new();
         for _ in 0..insns_size {
             insns.push(reader
                 .read_u16::<B>()
@@ -1173,32 +1822,84 @@ impl CodeItem {
 
         let mut handlers = Vec::new();
         if tries_size > 0 {
-            let (handlers_size, _) =
+            let (handlers_size, mut offset) =
                 read_uleb128(reader).chain_err(|| "could not read catch handlers size")?;
 
             handlers.reserve_exact(handlers_size as usize);
             for _ in 0..handlers_size {
-                let (handler, _) =
+                let (mut handler, read) =
                     CatchHandler::from_reader(reader).chain_err(|| "could not read catch handler")?;
+                handler.offset = offset;
+                offset += read;
                 handlers.push(handler);
             }
         }
 
         Ok(CodeItem {
-            registers_size: registers_size,
-            ins_size: ins_size,
-            outs_size: outs_size,
-            debug_info_offset: debug_info_offset,
-            insns: insns,
-            tries: tries,
-            handlers: handlers,
+            registers_size,
+            ins_size,
+            outs_size,
+            debug_info_offset,
+            insns,
+            tries,
+            handlers,
+            debug_info: None,
         })
     }
+
+    /// Gets the number of registers used by the code.
+    pub fn registers_size(&self) -> u16 {
+        self.registers_size
+    }
+
+    /// Gets the number of words of incoming arguments to the method.
+    pub fn ins_size(&self) -> u16 {
+        self.ins_size
+    }
+
+    /// Gets the number of words of outgoing argument space required for method invocation.
+    pub fn outs_size(&self) -> u16 {
+        self.outs_size
+    }
+
+    /// Gets the offset of the debug information of the code, if it has any.
+    pub fn debug_info_offset(&self) -> Option<u32> {
+        if self.debug_info_offset == 0 {
+            None
+        } else {
+            Some(self.debug_info_offset)
+        }
+    }
+
+    /// Gets the bytecode array, in 16-bit code units.
+    pub fn insns(&self) -> &[u16] {
+        &self.insns
+    }
+
+    /// Gets the list of try blocks of the code.
+    pub fn tries(&self) -> &[TryItem] {
+        &self.tries
+    }
+
+    /// Gets the list of catch handlers of the code.
+    pub fn handlers(&self) -> &[CatchHandler] {
+        &self.handlers
+    }
+
+    /// Gets the debug information of the code, if it has any.
+    pub fn debug_info(&self) -> Option<&DebugInfo> {
+        self.debug_info.as_ref()
+    }
+
+    /// Sets the debug information of the code, read from its debug information offset.
+    pub(crate) fn set_debug_info(&mut self, debug_info: DebugInfo) {
+        self.debug_info = Some(debug_info);
+    }
 }
 
 /// Try item structure.
 #[derive(Debug)]
-struct TryItem {
+pub struct TryItem {
     start_address: u32,
     insn_count: u16,
     handler_offset: u16,
@@ -1218,16 +1919,33 @@ impl TryItem {
             .chain_err(|| "could not read catch handler offset")?;
 
         Ok(TryItem {
-            start_address: start_address,
-            insn_count: insn_count,
-            handler_offset: handler_offset,
+            start_address,
+            insn_count,
+            handler_offset,
         })
     }
+
+    /// Gets the address of the first code unit covered by this entry.
+    pub fn start_address(&self) -> u32 {
+        self.start_address
+    }
+
+    /// Gets the number of code units covered by this entry.
+    pub fn insn_count(&self) -> u16 {
+        self.insn_count
+    }
+
+    /// Gets the offset in bytes from the start of the encoded catch handler list to the handler of
+    /// this entry.
+    pub fn handler_offset(&self) -> u16 {
+        self.handler_offset
+    }
 }
 
 /// Struct representing a catch handler.
 #[derive(Debug)]
-struct CatchHandler {
+pub struct CatchHandler {
+    offset: u32,
     handlers: Vec<HandlerInfo>,
     catch_all_addr: Option<u32>,
 }
@@ -1238,8 +1956,8 @@ impl CatchHandler {
         let (size, mut read) =
             read_sleb128(reader).chain_err(|| "could not read the catch handler size")?;
 
-        let abs_size = size.abs() as usize;
-        let mut handlers = Vec::with_capacity(abs_size);
+        let abs_size = size.unsigned_abs() as usize;
+        let mut handlers = Vec::new();
         for _ in 0..abs_size {
             let (handler_info, read_hi) = HandlerInfo::from_reader(reader)
                 .chain_err(|| "could not read handler information")?;
@@ -1258,16 +1976,35 @@ impl CatchHandler {
 
         Ok((
             CatchHandler {
-                handlers: handlers,
-                catch_all_addr: catch_all_addr,
+                offset: 0,
+                handlers,
+                catch_all_addr,
             },
             read,
         ))
     }
+
+    /// Gets the offset in bytes of the handler from the start of the encoded catch handler list.
+    ///
+    /// Try blocks reference their handler by this offset.
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Gets the list of typed handlers, in the order they should be tested.
+    pub fn handlers(&self) -> &[HandlerInfo] {
+        &self.handlers
+    }
+
+    /// Gets the address of the catch-all handler, if there is one.
+    pub fn catch_all_addr(&self) -> Option<u32> {
+        self.catch_all_addr
+    }
 }
 
+/// Handler for a given exception type.
 #[derive(Debug)]
-struct HandlerInfo {
+pub struct HandlerInfo {
     type_id: u32,
     addr: u32,
 }
@@ -1280,12 +2017,22 @@ impl HandlerInfo {
 
         Ok((
             HandlerInfo {
-                type_id: type_id,
-                addr: addr,
+                type_id,
+                addr,
             },
             read_t + read_a,
         ))
     }
+
+    /// Gets the index in the *Type IDs* list of the exception type to catch.
+    pub fn type_index(&self) -> u32 {
+        self.type_id
+    }
+
+    /// Gets the address of the handler.
+    pub fn addr(&self) -> u32 {
+        self.addr
+    }
 }
 
 #[cfg(test)]
@@ -1294,6 +2041,205 @@ mod tests {
     use std::io::Cursor;
     use types::Value;
 
+    #[test]
+    fn it_can_decode_a_method_handle() {
+        let raw = [0x04, 0x00, 0x00, 0x00, 0x2a, 0x01, 0x00, 0x00];
+        let method_handle =
+            MethodHandle::from_reader::<_, LittleEndian>(&mut Cursor::new(raw)).unwrap();
+
+        assert_eq!(MethodHandleType::InvokeStatic, method_handle.handle_type());
+        assert_eq!(0x012a, method_handle.field_or_method_index());
+        assert_eq!(Some(0x012a), method_handle.method_index());
+        assert_eq!(None, method_handle.field_index());
+    }
+
+    #[test]
+    fn it_returns_error_if_method_handle_type_is_not_valid() {
+        let raw = [0x09, 0x00, 0x00, 0x00, 0x2a, 0x01, 0x00, 0x00];
+        let method_handle_result =
+            MethodHandle::from_reader::<_, LittleEndian>(&mut Cursor::new(raw));
+
+        assert!(method_handle_result.is_err());
+        assert!(matches!(
+            *method_handle_result.err().unwrap().kind(),
+            ErrorKind::InvalidMethodHandleType(0x09)
+        ));
+    }
+
+    #[test]
+    fn it_can_decode_a_call_site() {
+        let raw = [0x04, 0x16, 0x02, 0x37, 0x10, 0x01, 0x35, 0x05, 0x00, 0x04, 0x07];
+        let call_site = CallSite::from_reader(&mut Cursor::new(raw)).unwrap();
+
+        assert_eq!(2, call_site.method_handle_index());
+        assert_eq!(0x0110, call_site.method_name_index());
+        assert_eq!(5, call_site.method_type_index());
+        assert_eq!(1, call_site.arguments().len());
+        assert!(matches!(call_site.arguments()[0], Value::Int(7)));
+    }
+
+    #[test]
+    fn it_returns_error_if_call_site_does_not_start_with_a_method_handle() {
+        let raw = [0x03, 0x17, 0x02, 0x17, 0x10, 0x15, 0x05];
+        let call_site_result = CallSite::from_reader(&mut Cursor::new(raw));
+
+        assert!(call_site_result.is_err());
+        assert!(matches!(
+            *call_site_result.err().unwrap().kind(),
+            ErrorKind::CallSite(_)
+        ));
+    }
+
+    #[test]
+    fn it_can_decode_a_compact_code_item_with_a_preheader() {
+        // The high and low halves of the extra bytecode array size, then the code item.
+        let raw = [0x00, 0x00, 0x01, 0x00, 0x00, 0x11, 0x30, 0x00, 0x00, 0x00, 0x0e, 0x00];
+        let mut reader = Cursor::new(raw);
+        reader.set_position(4);
+        let code = CodeItem::from_compact_reader::<_, LittleEndian>(&mut reader, 0).unwrap();
+
+        assert_eq!(2, code.registers_size());
+        assert_eq!(1, code.ins_size());
+        assert_eq!(&[0x0000, 0x000e], code.insns());
+        assert_eq!(None, code.debug_info_offset());
+        assert_eq!(raw.len() as u64, reader.position());
+    }
+
+    #[test]
+    fn it_can_decode_hiddenapi_flags() {
+        let flags = HiddenApiFlags::from_u32(0x00).unwrap();
+        assert_eq!(HiddenApiRestriction::Whitelist, flags.restriction());
+        assert!(!flags.is_core_platform_api());
+        assert!(!flags.is_test_api());
+
+        let flags = HiddenApiFlags::from_u32(0x02).unwrap();
+        assert_eq!(HiddenApiRestriction::Blacklist, flags.restriction());
+
+        let flags = HiddenApiFlags::from_u32(0x0b).unwrap();
+        assert_eq!(HiddenApiRestriction::GreylistMaxO, flags.restriction());
+        assert!(flags.is_core_platform_api());
+        assert!(!flags.is_test_api());
+
+        let flags = HiddenApiFlags::from_u32(0x11).unwrap();
+        assert_eq!(HiddenApiRestriction::Greylist, flags.restriction());
+        assert!(flags.is_test_api());
+    }
+
+    #[test]
+    fn it_returns_error_if_hiddenapi_flags_are_not_valid() {
+        let flags_result = HiddenApiFlags::from_u32(0x20);
+
+        assert!(flags_result.is_err());
+        assert!(matches!(
+            *flags_result.err().unwrap().kind(),
+            ErrorKind::InvalidHiddenApiFlags(0x20)
+        ));
+    }
+
+    #[test]
+    fn it_can_decode_an_ascii_mutf8_string() {
+        let string = DexString::from_mutf8(b"Ljava/lang/Object;").unwrap();
+
+        assert_eq!("Ljava/lang/Object;", string.as_str());
+        assert!(string.is_valid_unicode());
+        assert!(string.is_borrowed());
+        assert_eq!(18, string.to_utf16().len());
+    }
+
+    #[test]
+    fn it_can_decode_a_mutf8_string_with_nul() {
+        let string = DexString::from_mutf8(&[0x61, 0xc0, 0x80, 0x62]).unwrap();
+
+        assert_eq!("a\u{0}b", string.as_str());
+        assert!(!string.is_borrowed());
+        assert_eq!(3, string.to_utf16().len());
+    }
+
+    #[test]
+    fn it_can_decode_a_mutf8_string_with_two_and_three_byte_characters() {
+        let string = DexString::from_mutf8(&[0xc3, 0xb1, 0xe2, 0x82, 0xac]).unwrap();
+
+        assert_eq!("\u{f1}\u{20ac}", string.as_str());
+        assert!(string.is_borrowed());
+        assert_eq!(2, string.to_utf16().len());
+    }
+
+    #[test]
+    fn it_can_decode_a_mutf8_string_with_a_surrogate_pair() {
+        // U+1F600, encoded as the surrogates D83D and DE00.
+        let raw = [0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80];
+        let string = DexString::from_mutf8(&raw).unwrap();
+
+        assert_eq!("\u{1f600}", string.as_str());
+        assert!(string.is_valid_unicode());
+        assert_eq!(&[0xd83d, 0xde00][..], &*string.to_utf16());
+    }
+
+    #[test]
+    fn it_keeps_unpaired_surrogates() {
+        let raw = [0x61, 0xed, 0xa0, 0xbd];
+        let string = DexString::from_mutf8(&raw).unwrap();
+
+        assert_eq!("a\u{fffd}", string.as_str());
+        assert!(!string.is_valid_unicode());
+        assert_eq!(&[0x61, 0xd83d][..], &*string.to_utf16());
+    }
+
+    #[test]
+    fn it_returns_error_if_mutf8_is_not_valid() {
+        assert!(DexString::from_mutf8(&[0x61, 0x00]).is_err());
+        assert!(DexString::from_mutf8(&[0xc3]).is_err());
+        assert!(DexString::from_mutf8(&[0xe2, 0x82, 0x41]).is_err());
+        assert!(DexString::from_mutf8(&[0xf0, 0x9f, 0x98, 0x80]).is_err());
+    }
+
+    #[test]
+    fn it_can_decode_a_map() {
+        let raw = [
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x70, 0x00, 0x00, 0x00,
+        ];
+        let map = Map::from_reader::<_, LittleEndian>(&mut Cursor::new(raw)).unwrap();
+
+        assert_eq!(2, map.items().len());
+        assert_eq!(ItemType::Header, map.items()[0].item_type());
+        assert_eq!(0, map.items()[0].offset());
+        assert_eq!(Some(1), map.get_num_items_for(ItemType::MapList));
+        assert_eq!(Some(0x70), map.get_offset_for(ItemType::MapList));
+        assert!(map.get_item(ItemType::Code).is_none());
+    }
+
+    #[test]
+    fn it_returns_error_if_map_item_type_is_not_valid() {
+        let raw = [
+            0x01, 0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+        let map_result = Map::from_reader::<_, LittleEndian>(&mut Cursor::new(raw));
+
+        assert!(map_result.is_err());
+        assert!(matches!(
+            *map_result.err().unwrap().kind(),
+            ErrorKind::Msg(_)
+        ));
+    }
+
+    #[test]
+    fn it_returns_error_if_map_is_truncated() {
+        let raw = [0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00];
+        let map_result = Map::from_reader::<_, LittleEndian>(&mut Cursor::new(raw));
+
+        assert!(map_result.is_err());
+    }
+
+    #[test]
+    fn it_returns_error_if_array_is_truncated() {
+        let raw = [0xff, 0xff, 0xff, 0xff, 0x0f, 0x00, 0x01];
+        let array_result = Array::from_reader(&mut Cursor::new(raw));
+
+        assert!(array_result.is_err());
+    }
+
     #[test]
     fn it_returns_error_if_value_type_is_not_valid() {
         let raw = [0x01];
@@ -1438,6 +2384,66 @@ mod tests {
         assert_eq!("invalid value", value_result.err().unwrap().description());
     }
 
+    #[test]
+    fn it_can_decode_a_method_type_value_one_byte() {
+        let raw = [VALUE_METHOD_TYPE, 12];
+        let value_result = Value::from_reader(&mut Cursor::new(raw));
+
+        match value_result.unwrap() {
+            Value::MethodType(res) => assert_eq!(res, 12),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn it_can_decode_a_method_type_value_four_bytes() {
+        let raw = [VALUE_METHOD_TYPE | 3 << 5, 1, 2, 3, 4];
+        let value_result = Value::from_reader(&mut Cursor::new(raw));
+
+        match value_result.unwrap() {
+            Value::MethodType(res) => assert_eq!(res, 0x04030201),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn it_can_not_decode_a_method_type_value_if_arg_is_more_than_3() {
+        let raw = [VALUE_METHOD_TYPE | 4 << 5, 1, 2, 3, 4, 5];
+        let value_result = Value::from_reader(&mut Cursor::new(raw));
+
+        assert!(value_result.is_err());
+    }
+
+    #[test]
+    fn it_can_decode_a_method_handle_value_one_byte() {
+        let raw = [VALUE_METHOD_HANDLE, 3];
+        let value_result = Value::from_reader(&mut Cursor::new(raw));
+
+        match value_result.unwrap() {
+            Value::MethodHandle(res) => assert_eq!(res, 3),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn it_can_decode_a_method_handle_value_two_bytes() {
+        let raw = [VALUE_METHOD_HANDLE | 1 << 5, 0x34, 0x12];
+        let value_result = Value::from_reader(&mut Cursor::new(raw));
+
+        match value_result.unwrap() {
+            Value::MethodHandle(res) => assert_eq!(res, 0x1234),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn it_can_not_decode_a_method_handle_value_if_arg_is_more_than_3() {
+        let raw = [VALUE_METHOD_HANDLE | 5 << 5, 1, 2, 3, 4, 5, 6];
+        let value_result = Value::from_reader(&mut Cursor::new(raw));
+
+        assert!(value_result.is_err());
+    }
+
     #[test]
     fn it_can_decode_a_long_value_one_byte() {
         let raw = [VALUE_LONG, 128];
@@ -0,0 +1,204 @@
+//! VDEX files.
+//!
+//! The Android runtime keeps the dex files it executes, once verified, in VDEX files next to the
+//! compiled OAT files. Depending on the Android release, they hold standard dex files or compact
+//! dex files (`cdex` magic), which can share a single data section.
+//!
+//! Dex files in VDEX files from Android 8 to 11 can be quickened: some of their instructions are
+//! replaced with runtime specific opcodes, that are not decoded.
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use error::*;
+
+/// Magic number of VDEX files.
+const VDEX_MAGIC: [u8; 4] = *b"vdex";
+/// Kind of the section with the dex files, in VDEX files with a section table.
+const DEX_FILE_SECTION: u32 = 1;
+/// Kind of the section with the checksums of the dex files, in VDEX files with a section table.
+const CHECKSUM_SECTION: u32 = 0;
+
+/// Layout of a VDEX file, with the location of each of its dex files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vdex {
+    version: u32,
+    checksums: Box<[u32]>,
+    dex_offsets: Box<[u32]>,
+}
+
+impl Vdex {
+    /// Reads the layout of the VDEX file in the given bytes.
+    ///
+    /// Versions 006 and 010 (Android 8), 019 (Android 9), 021 (Android 10 and 11) and 027
+    /// (Android 12 and later) are supported. VDEX files without dex files, such as the ones of
+    /// applications whose dex files are stored uncompressed in the APK, are valid.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Vdex> {
+        if bytes.len() < 12 || bytes[..4] != VDEX_MAGIC {
+            return Err(ErrorKind::InvalidVdex("incorrect magic number".to_owned()).into());
+        }
+        let mut raw_version = [0_u8; 4];
+        raw_version.copy_from_slice(&bytes[4..8]);
+        let version = parse_version(raw_version)
+            .ok_or_else(|| Error::from(ErrorKind::UnsupportedVdexVersion(raw_version)))?;
+
+        // Dex files in Android 9 to 11 are preceded by the offset of their quickening table.
+        let (checksums, dex_start, dex_size, prefixed) = match version {
+            6 | 10 => {
+                let count = read_u32(bytes, 8)?;
+                let checksums = read_checksums(bytes, 24, count)?;
+                (checksums, 24 + 4 * count, read_u32(bytes, 12)?, false)
+            }
+            19 => {
+                let count = read_u32(bytes, 8)?;
+                let checksums = read_checksums(bytes, 28, count)?;
+                (checksums, 28 + 4 * count, read_u32(bytes, 12)?, true)
+            }
+            21 => {
+                let count = read_u32(bytes, 12)?;
+                let checksums = read_checksums(bytes, 28, count)?;
+                if bytes[8..12] == *b"000\0" {
+                    (checksums, 0, 0, true)
+                } else {
+                    let dex_section_header = 28 + 4 * count;
+                    let dex_size = read_u32(bytes, dex_section_header)?;
+                    (checksums, dex_section_header + 12, dex_size, true)
+                }
+            }
+            27 => {
+                let section_count = read_u32(bytes, 8)?;
+                let mut checksums = Vec::new().into_boxed_slice();
+                let (mut dex_start, mut dex_size) = (0, 0);
+                for section in 0..section_count {
+                    let section_header = 12 + 12 * section;
+                    let offset = read_u32(bytes, section_header + 4)?;
+                    let size = read_u32(bytes, section_header + 8)?;
+                    match read_u32(bytes, section_header)? {
+                        CHECKSUM_SECTION => checksums = read_checksums(bytes, offset, size / 4)?,
+                        DEX_FILE_SECTION => {
+                            dex_start = offset;
+                            dex_size = size;
+                        }
+                        _ => {}
+                    }
+                }
+                (checksums, dex_start, dex_size, false)
+            }
+            _ => return Err(ErrorKind::UnsupportedVdexVersion(raw_version).into()),
+        };
+
+        let dex_offsets = if dex_size == 0 {
+            Vec::new().into_boxed_slice()
+        } else {
+            find_dex_files(bytes, dex_start, dex_size, checksums.len(), prefixed)?
+        };
+        Ok(Vdex {
+            version,
+            checksums,
+            dex_offsets,
+        })
+    }
+
+    /// Gets the version of the VDEX file.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Gets the checksums of the original dex files the VDEX file was generated from.
+    pub fn checksums(&self) -> &[u32] {
+        &self.checksums
+    }
+
+    /// Gets the offsets of the dex files in the VDEX file.
+    ///
+    /// The list is empty if the VDEX file does not hold the dex files.
+    pub fn dex_offsets(&self) -> &[u32] {
+        &self.dex_offsets
+    }
+}
+
+/// Parses a version field, three decimal digits followed by a NUL byte.
+fn parse_version(version: [u8; 4]) -> Option<u32> {
+    if version[3] != 0 || !version[..3].iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    Some(
+        version[..3]
+            .iter()
+            .fold(0, |version, digit| version * 10 + u32::from(digit - b'0')),
+    )
+}
+
+/// Finds the offsets of the given number of dex files in the section of the VDEX file that holds
+/// them.
+///
+/// Dex files are one after the other, aligned to 4 bytes, each one possibly preceded by a 32-bit
+/// field.
+fn find_dex_files(
+    bytes: &[u8],
+    start: u32,
+    size: u32,
+    count: usize,
+    prefixed: bool,
+) -> Result<Box<[u32]>> {
+    let end = u64::from(start) + u64::from(size);
+    if end > bytes.len() as u64 {
+        return Err(ErrorKind::InvalidVdex(format!(
+            "the dex file section ends at {:#010x}, after the end of the file",
+            end
+        )).into());
+    }
+
+    let mut dex_offsets = Vec::with_capacity(count);
+    let mut offset = u64::from(start);
+    while dex_offsets.len() < count {
+        if prefixed {
+            offset += 4;
+        }
+        if offset + 36 > end {
+            return Err(ErrorKind::InvalidVdex(format!(
+                "expected {} dex files, but only {} fit in the dex file section",
+                count,
+                dex_offsets.len()
+            )).into());
+        }
+        let dex = &bytes[offset as usize..];
+        if dex[..4] != *b"dex\n" && dex[..4] != *b"cdex" {
+            return Err(ErrorKind::InvalidVdex(format!(
+                "there is no dex file at offset {:#010x}",
+                offset
+            )).into());
+        }
+        dex_offsets.push(offset as u32);
+        let file_size = LittleEndian::read_u32(&dex[32..36]);
+        offset = (offset + u64::from(file_size) + 3) / 4 * 4;
+    }
+
+    Ok(dex_offsets.into_boxed_slice())
+}
+
+/// Reads the checksums of the dex files, at the given offset.
+fn read_checksums(bytes: &[u8], offset: u32, count: u32) -> Result<Box<[u32]>> {
+    if u64::from(offset) + 4 * u64::from(count) > bytes.len() as u64 {
+        return Err(ErrorKind::InvalidVdex(format!(
+            "the {} dex file checksums at offset {:#010x} do not fit in the file",
+            count, offset
+        )).into());
+    }
+    (0..count)
+        .map(|index| read_u32(bytes, offset + 4 * index))
+        .collect()
+}
+
+/// Reads a little endian 32-bit field at the given offset.
+fn read_u32(bytes: &[u8], offset: u32) -> Result<u32> {
+    let offset = offset as usize;
+    bytes
+        .get(offset..offset + 4)
+        .map(LittleEndian::read_u32)
+        .ok_or_else(|| {
+            ErrorKind::InvalidVdex(format!(
+                "the field at offset {:#010x} is out of the file",
+                offset
+            )).into()
+        })
+}
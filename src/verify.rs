@@ -0,0 +1,1701 @@
+//! Structural verification of dex files.
+//!
+//! The parser only checks what it needs to read the file. The verifier checks the rest of the
+//! structural rules of ART's dex file verifier, such as the ordering of the ID lists, and reports
+//! every violation it finds instead of stopping at the first one.
+//!
+//! The bytecode of each method can also be verified on its own with [`CodeItem::verify`], given
+//! the dex file it belongs to.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::io::Cursor;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
+
+use Dex;
+use bytecode::{ArrayOperation, BinaryOperation, ByteCode, CompareType, InvokeKind, Payload,
+               UnaryOperation};
+use error::*;
+use sizes::{CLASS_DEF_ITEM_SIZE, FIELD_ID_ITEM_SIZE, METHOD_ID_ITEM_SIZE, PROTO_ID_ITEM_SIZE,
+            STRING_ID_ITEM_SIZE, TYPE_ID_ITEM_SIZE};
+use types::{AccessFlags, ItemType, Prototype, ShortyFieldType, ShortyReturnType};
+use types::read::{ClassData, ClassDefData, CodeItem, Method, PrototypeIdData};
+use warning::describe;
+
+/// Violation of a structural rule of the dex format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    item_type: ItemType,
+    index: u32,
+    offset: u64,
+    kind: ViolationKind,
+}
+
+impl Violation {
+    /// Gets the type of the item that violates the rule.
+    pub fn item_type(&self) -> ItemType {
+        self.item_type
+    }
+
+    /// Gets the index of the item in its list.
+    ///
+    /// For class data and type lists, it is the index of the class definition or prototype that
+    /// refers to them.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Gets the offset of the item in the file, including for dex files in VDEX files.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Gets the kind of the violation.
+    pub fn kind(&self) -> &ViolationKind {
+        &self.kind
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} at offset {:#010x}: {}",
+            self.item_type, self.index, self.offset, self.kind
+        )
+    }
+}
+
+/// Kind of violation of a structural rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// The item is not sorted after the previous item of its list.
+    Unsorted,
+    /// The item is equal to the previous item of its list.
+    Duplicate,
+    /// The class was already defined in the class definition with the given index.
+    DuplicateClassDef(u32),
+    /// The class is its own superclass or implements itself.
+    SelfInheritance,
+    /// The superclass is defined after the class, in the class definition with the given index.
+    SuperclassDefinedAfter(u32),
+    /// An interface is defined after the class, in the class definition with the given index.
+    InterfaceDefinedAfter(u32),
+    /// The short form descriptor does not match the return and parameter types of the prototype.
+    ShortyMismatch,
+    /// The item could not be read, with the given error, so its rules were not checked.
+    Unreadable(String),
+    /// The access flags of the class are not legal.
+    IllegalClassFlags {
+        /// Access flags of the class.
+        flags: AccessFlags,
+        /// Rule that the flags break.
+        reason: &'static str,
+    },
+    /// The access flags of a field defined in the class data are not legal.
+    IllegalFieldFlags {
+        /// Index of the field in the *Field IDs* list.
+        field_index: u32,
+        /// Access flags of the field.
+        flags: AccessFlags,
+        /// Rule that the flags break.
+        reason: &'static str,
+    },
+    /// The access flags of a method defined in the class data are not legal.
+    IllegalMethodFlags {
+        /// Index of the method in the *Method IDs* list.
+        method_index: u32,
+        /// Access flags of the method.
+        flags: AccessFlags,
+        /// Rule that the flags break.
+        reason: &'static str,
+    },
+}
+
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ViolationKind::Unsorted => write!(f, "item is not sorted after the previous item"),
+            ViolationKind::Duplicate => write!(f, "item is equal to the previous item"),
+            ViolationKind::DuplicateClassDef(index) => {
+                write!(f, "class already defined in class definition {}", index)
+            }
+            ViolationKind::SelfInheritance => write!(f, "class inherits from itself"),
+            ViolationKind::SuperclassDefinedAfter(index) => write!(
+                f,
+                "superclass is defined after the class, in class definition {}",
+                index
+            ),
+            ViolationKind::InterfaceDefinedAfter(index) => write!(
+                f,
+                "interface is defined after the class, in class definition {}",
+                index
+            ),
+            ViolationKind::ShortyMismatch => {
+                write!(f, "short form descriptor does not match the prototype")
+            }
+            ViolationKind::Unreadable(ref error) => write!(f, "item could not be read: {}", error),
+            ViolationKind::IllegalClassFlags { flags, reason } => {
+                write!(f, "illegal class access flags `{}`: {}", flags, reason)
+            }
+            ViolationKind::IllegalFieldFlags {
+                field_index,
+                flags,
+                reason,
+            } => write!(
+                f,
+                "illegal access flags `{}` for field {}: {}",
+                flags, field_index, reason
+            ),
+            ViolationKind::IllegalMethodFlags {
+                method_index,
+                flags,
+                reason,
+            } => write!(
+                f,
+                "illegal access flags `{}` for method {}: {}",
+                flags, method_index, reason
+            ),
+        }
+    }
+}
+
+/// Flags that can be set in a class definition.
+const CLASS_FLAGS: AccessFlags = AccessFlags::from_bits_truncate(
+    AccessFlags::ACC_PUBLIC.bits() | AccessFlags::ACC_FINAL.bits()
+        | AccessFlags::ACC_SYNCHRONIZED.bits() | AccessFlags::ACC_INTERFACE.bits()
+        | AccessFlags::ACC_ABSTRACT.bits() | AccessFlags::ACC_SYNTHETIC.bits()
+        | AccessFlags::ACC_ANNOTATION.bits() | AccessFlags::ACC_ENUM.bits(),
+);
+
+/// Flags that can be set in a field definition.
+const FIELD_FLAGS: AccessFlags = AccessFlags::from_bits_truncate(
+    AccessFlags::ACC_PUBLIC.bits() | AccessFlags::ACC_PRIVATE.bits()
+        | AccessFlags::ACC_PROTECTED.bits() | AccessFlags::ACC_STATIC.bits()
+        | AccessFlags::ACC_FINAL.bits() | AccessFlags::ACC_VOLATILE.bits()
+        | AccessFlags::ACC_TRANSIENT.bits() | AccessFlags::ACC_SYNTHETIC.bits()
+        | AccessFlags::ACC_ENUM.bits(),
+);
+
+/// Flags that can be set in a method definition.
+const METHOD_FLAGS: AccessFlags = AccessFlags::from_bits_truncate(
+    AccessFlags::ACC_PUBLIC.bits() | AccessFlags::ACC_PRIVATE.bits()
+        | AccessFlags::ACC_PROTECTED.bits() | AccessFlags::ACC_STATIC.bits()
+        | AccessFlags::ACC_FINAL.bits() | AccessFlags::ACC_SYNCHRONIZED.bits()
+        | AccessFlags::ACC_BRIDGE.bits() | AccessFlags::ACC_VARARGS.bits()
+        | AccessFlags::ACC_NATIVE.bits() | AccessFlags::ACC_ABSTRACT.bits()
+        | AccessFlags::ACC_STRICT.bits() | AccessFlags::ACC_SYNTHETIC.bits()
+        | AccessFlags::ACC_CONSTRUCTOR.bits()
+        | AccessFlags::ACC_DECLARED_SYNCHRONIZED.bits(),
+);
+
+/// Flags that can be set in a constructor, apart from `static` in class initializers.
+const CONSTRUCTOR_FLAGS: AccessFlags = AccessFlags::from_bits_truncate(
+    AccessFlags::ACC_PUBLIC.bits() | AccessFlags::ACC_PRIVATE.bits()
+        | AccessFlags::ACC_PROTECTED.bits() | AccessFlags::ACC_STRICT.bits()
+        | AccessFlags::ACC_VARARGS.bits() | AccessFlags::ACC_SYNTHETIC.bits()
+        | AccessFlags::ACC_CONSTRUCTOR.bits()
+        | AccessFlags::ACC_DECLARED_SYNCHRONIZED.bits(),
+);
+
+/// Flags that can't be set in an abstract method.
+const ABSTRACT_FORBIDDEN_FLAGS: AccessFlags = AccessFlags::from_bits_truncate(
+    AccessFlags::ACC_PRIVATE.bits() | AccessFlags::ACC_STATIC.bits()
+        | AccessFlags::ACC_FINAL.bits() | AccessFlags::ACC_NATIVE.bits()
+        | AccessFlags::ACC_STRICT.bits() | AccessFlags::ACC_SYNCHRONIZED.bits(),
+);
+
+/// Structural verifier of a dex file.
+pub(crate) struct Verifier<'r, 'a: 'r> {
+    dex: &'r Dex<'a>,
+    file_cursor: Cursor<&'r [u8]>,
+    violations: Vec<Violation>,
+}
+
+impl<'r, 'a> Verifier<'r, 'a> {
+    /// Creates a new verifier for the given dex file.
+    pub fn new(dex: &'r Dex<'a>) -> Result<Verifier<'r, 'a>> {
+        Ok(Verifier {
+            dex,
+            file_cursor: Cursor::new(dex.contents()?),
+            violations: Vec::new(),
+        })
+    }
+
+    /// Verifies the structure of the dex file, and returns all the violations found.
+    pub fn verify(mut self) -> Result<Vec<Violation>> {
+        if self.dex.header.is_little_endian() {
+            self.verify_endian::<LittleEndian>()?;
+        } else {
+            self.verify_endian::<BigEndian>()?;
+        }
+        Ok(self.violations)
+    }
+
+    fn verify_endian<B: ByteOrder>(&mut self) -> Result<()> {
+        self.verify_strings();
+        self.verify_types::<B>()
+            .chain_err(|| "could not verify the type IDs")?;
+        self.verify_prototypes::<B>()
+            .chain_err(|| "could not verify the prototype IDs")?;
+        self.verify_fields();
+        self.verify_methods();
+        self.verify_classes::<B>()
+            .chain_err(|| "could not verify the class definitions")?;
+        Ok(())
+    }
+
+    /// Records a violation of the item at the given offset of the dex file.
+    fn push(&mut self, item_type: ItemType, index: usize, offset: u64, kind: ViolationKind) {
+        self.violations.push(Violation {
+            item_type,
+            index: index as u32,
+            offset: self.dex.base_offset + offset,
+            kind,
+        });
+    }
+
+    /// Checks the ordering of a list of IDs, given the key of each of them.
+    ///
+    /// Items without a key could not be read, and are not compared with their neighbours.
+    fn verify_order<K: Ord, I: IntoIterator<Item = Option<K>>>(
+        &mut self,
+        item_type: ItemType,
+        list_offset: Option<u32>,
+        item_size: u32,
+        keys: I,
+    ) {
+        let list_offset = match list_offset {
+            Some(offset) => offset,
+            None => return,
+        };
+        let mut previous: Option<K> = None;
+        for (index, key) in keys.into_iter().enumerate() {
+            if let (Some(previous), Some(key)) = (previous.as_ref(), key.as_ref()) {
+                let kind = match previous.cmp(key) {
+                    ::std::cmp::Ordering::Less => None,
+                    ::std::cmp::Ordering::Equal => Some(ViolationKind::Duplicate),
+                    ::std::cmp::Ordering::Greater => Some(ViolationKind::Unsorted),
+                };
+                if let Some(kind) = kind {
+                    let offset = u64::from(list_offset) + index as u64 * u64::from(item_size);
+                    self.push(item_type, index, offset, kind);
+                }
+            }
+            previous = key;
+        }
+    }
+
+    /// Checks that the strings are sorted by their UTF-16 code units, with no duplicates.
+    fn verify_strings(&mut self) {
+        let dex = self.dex;
+        self.verify_order(
+            ItemType::StringId,
+            dex.header.get_string_ids_offset(),
+            STRING_ID_ITEM_SIZE,
+            dex.strings.iter().map(Some),
+        );
+    }
+
+    /// Gets the position in the contents of the item in the data section at the given offset.
+    fn data_position(&self, offset: u32) -> u64 {
+        u64::from(self.dex.header.get_data_base()) + u64::from(offset)
+    }
+
+    /// Checks that the types are sorted by their descriptor index, with no duplicates.
+    fn verify_types<B: ByteOrder>(&mut self) -> Result<()> {
+        let offset = match self.dex.header.get_type_ids_offset() {
+            Some(offset) => offset,
+            None => return Ok(()),
+        };
+        self.file_cursor.set_position(u64::from(offset));
+        let mut descriptors = Vec::with_capacity(self.dex.types.len());
+        for _ in 0..self.dex.header.get_type_ids_size() {
+            descriptors.push(self.file_cursor
+                .read_u32::<B>()
+                .chain_err(|| "could not read descriptor index")?);
+        }
+        self.verify_order(
+            ItemType::TypeId,
+            Some(offset),
+            TYPE_ID_ITEM_SIZE,
+            descriptors.into_iter().map(Some),
+        );
+        Ok(())
+    }
+
+    /// Checks that the prototypes are sorted by return type and parameters, with no duplicates,
+    /// and that their short form descriptors match their types.
+    fn verify_prototypes<B: ByteOrder>(&mut self) -> Result<()> {
+        let list_offset = match self.dex.header.get_prototype_ids_offset() {
+            Some(offset) => offset,
+            None => return Ok(()),
+        };
+        let mut keys = Vec::with_capacity(self.dex.prototypes.len());
+        for index in 0..self.dex.header.get_prototype_ids_size() {
+            self.file_cursor.set_position(
+                u64::from(list_offset) + u64::from(index) * u64::from(PROTO_ID_ITEM_SIZE),
+            );
+            let proto_id = PrototypeIdData::from_reader::<_, B>(&mut self.file_cursor)
+                .chain_err(|| format!("could not read prototype ID {}", index))?;
+            let parameters = match proto_id.parameters_offset() {
+                Some(offset) => self.read_type_list::<B>(index as usize, offset),
+                None => Some(Vec::new()),
+            };
+            keys.push(parameters.map(|parameters| (proto_id.return_type_index(), parameters)));
+        }
+        self.verify_order(ItemType::ProtoId, Some(list_offset), PROTO_ID_ITEM_SIZE, keys);
+
+        let dex = self.dex;
+        for (index, prototype) in dex.prototypes.iter().enumerate() {
+            let descriptor = prototype.descriptor();
+            let parameters = prototype.parameters().unwrap_or(&[]);
+            let matches = descriptor.return_type()
+                == ShortyReturnType::from(prototype.return_type().clone())
+                && descriptor.field_types().len() == parameters.len()
+                && descriptor.field_types().iter().zip(parameters).all(|(&field_type, t)| {
+                    ShortyReturnType::from(field_type) == ShortyReturnType::from(t.clone())
+                });
+            if !matches {
+                let offset =
+                    u64::from(list_offset) + index as u64 * u64::from(PROTO_ID_ITEM_SIZE);
+                self.push(ItemType::ProtoId, index, offset, ViolationKind::ShortyMismatch);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the fields are sorted by class, name and type, with no duplicates.
+    fn verify_fields(&mut self) {
+        let dex = self.dex;
+        let keys = dex.field_ids
+            .iter()
+            .map(|f| Some((f.class_index(), f.name_index(), f.type_index())));
+        self.verify_order(
+            ItemType::FieldId,
+            dex.header.get_field_ids_offset(),
+            FIELD_ID_ITEM_SIZE,
+            keys,
+        );
+    }
+
+    /// Checks that the methods are sorted by class, name and prototype, with no duplicates.
+    fn verify_methods(&mut self) {
+        let dex = self.dex;
+        let keys = dex.method_ids
+            .iter()
+            .map(|m| Some((m.class_index(), m.name_index(), m.prototype_index())));
+        self.verify_order(
+            ItemType::MethodId,
+            dex.header.get_method_ids_offset(),
+            METHOD_ID_ITEM_SIZE,
+            keys,
+        );
+    }
+
+    /// Checks that each class is defined once, after its superclass and interfaces, and that the
+    /// access flags of the class and its members are legal.
+    fn verify_classes<B: ByteOrder>(&mut self) -> Result<()> {
+        let list_offset = match self.dex.header.get_class_defs_offset() {
+            Some(offset) => offset,
+            None => return Ok(()),
+        };
+        let mut class_defs = Vec::with_capacity(self.dex.header.get_class_defs_size() as usize);
+        for index in 0..self.dex.header.get_class_defs_size() {
+            self.file_cursor.set_position(
+                u64::from(list_offset) + u64::from(index) * u64::from(CLASS_DEF_ITEM_SIZE),
+            );
+            class_defs.push(ClassDefData::from_reader::<_, B>(&mut self.file_cursor)
+                .chain_err(|| format!("could not read class definition {}", index))?);
+        }
+
+        let mut defined = HashMap::with_capacity(class_defs.len());
+        for (index, class_def) in class_defs.iter().enumerate() {
+            let offset = u64::from(list_offset) + index as u64 * u64::from(CLASS_DEF_ITEM_SIZE);
+            match defined.entry(class_def.class_index()) {
+                Entry::Occupied(entry) => {
+                    let kind = ViolationKind::DuplicateClassDef(*entry.get());
+                    self.push(ItemType::ClassDef, index, offset, kind);
+                }
+                Entry::Vacant(entry) => {
+                    let _ = entry.insert(index as u32);
+                }
+            }
+        }
+
+        for (index, class_def) in class_defs.iter().enumerate() {
+            let offset = u64::from(list_offset) + index as u64 * u64::from(CLASS_DEF_ITEM_SIZE);
+            let interfaces = class_def
+                .interfaces_offset()
+                .and_then(|interfaces_offset| self.read_type_list::<B>(index, interfaces_offset))
+                .unwrap_or_default();
+            let ancestors = class_def
+                .superclass_index()
+                .map(|superclass| (superclass, true))
+                .into_iter()
+                .chain(interfaces.into_iter().map(|i| (u32::from(i), false)));
+            for (ancestor, is_superclass) in ancestors {
+                let kind = match defined.get(&ancestor) {
+                    _ if ancestor == class_def.class_index() => ViolationKind::SelfInheritance,
+                    Some(&def) if def as usize > index && is_superclass => {
+                        ViolationKind::SuperclassDefinedAfter(def)
+                    }
+                    Some(&def) if def as usize > index => ViolationKind::InterfaceDefinedAfter(def),
+                    _ => continue,
+                };
+                self.push(ItemType::ClassDef, index, offset, kind);
+            }
+
+            if let Some(reason) = check_class_flags(class_def.access_flags()) {
+                let kind = ViolationKind::IllegalClassFlags {
+                    flags: class_def.access_flags(),
+                    reason,
+                };
+                self.push(ItemType::ClassDef, index, offset, kind);
+            }
+
+            if let Some(class_data_offset) = class_def.class_data_offset() {
+                let position = self.data_position(class_data_offset);
+                self.file_cursor.set_position(position);
+                match ClassData::from_reader(&mut self.file_cursor) {
+                    Ok(class_data) => {
+                        self.verify_class_data(index, position, class_def, &class_data)
+                    }
+                    Err(e) => {
+                        let kind = ViolationKind::Unreadable(describe(&e));
+                        self.push(ItemType::ClassData, index, position, kind);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the type list at the given offset, referred to by the item at the given index.
+    ///
+    /// If the list can't be read, the violation is recorded and `None` is returned.
+    fn read_type_list<B: ByteOrder>(&mut self, index: usize, offset: u32) -> Option<Vec<u16>> {
+        let position = self.data_position(offset);
+        self.file_cursor.set_position(position);
+        match read_type_index_list::<B>(&mut self.file_cursor) {
+            Ok(type_list) => Some(type_list),
+            Err(e) => {
+                let kind = ViolationKind::Unreadable(describe(&e));
+                self.push(ItemType::TypeList, index, position, kind);
+                None
+            }
+        }
+    }
+
+    /// Checks the access flags of the fields and methods defined in a class, given the offset of
+    /// its class data.
+    fn verify_class_data(
+        &mut self,
+        index: usize,
+        offset: u64,
+        class_def: &ClassDefData,
+        class_data: &ClassData,
+    ) {
+        let class_flags = class_def.access_flags();
+        let fields = class_data
+            .static_fields()
+            .iter()
+            .map(|field| (field, true))
+            .chain(class_data.instance_fields().iter().map(|field| (field, false)));
+        for (field, in_static_list) in fields {
+            let flags = field.access_flags();
+            if let Some(reason) = check_field_flags(flags, class_flags, in_static_list) {
+                let kind = ViolationKind::IllegalFieldFlags {
+                    field_index: field.field_index(),
+                    flags,
+                    reason,
+                };
+                let offset = offset + u64::from(field.offset());
+                self.push(ItemType::ClassData, index, offset, kind);
+            }
+        }
+
+        let methods = class_data
+            .direct_methods()
+            .iter()
+            .map(|method| (method, true))
+            .chain(class_data.virtual_methods().iter().map(|method| (method, false)));
+        for (method, in_direct_list) in methods {
+            let name = self.dex
+                .method_ids
+                .get(method.method_index() as usize)
+                .and_then(|method_id| self.dex.get_string(method_id.name_index() as u32));
+            if let Some(reason) = check_method_flags(method, name, class_flags, in_direct_list) {
+                let kind = ViolationKind::IllegalMethodFlags {
+                    method_index: method.method_index(),
+                    flags: method.access_flags(),
+                    reason,
+                };
+                let offset = offset + u64::from(method.offset());
+                self.push(ItemType::ClassData, index, offset, kind);
+            }
+        }
+    }
+}
+
+/// Reads a list of type indexes, without resolving them.
+fn read_type_index_list<B: ByteOrder>(file_cursor: &mut Cursor<&[u8]>) -> Result<Vec<u16>> {
+    let size = file_cursor
+        .read_u32::<B>()
+        .chain_err(|| "could not read the size of the type list")?;
+    let mut type_list = Vec::new();
+    for _ in 0..size {
+        type_list.push(file_cursor
+            .read_u16::<B>()
+            .chain_err(|| "could not read type index")?);
+    }
+    Ok(type_list)
+}
+
+/// Checks that at most one of `public`, `private` and `protected` is set.
+fn has_one_visibility(flags: AccessFlags) -> bool {
+    (flags & (AccessFlags::ACC_PUBLIC | AccessFlags::ACC_PRIVATE | AccessFlags::ACC_PROTECTED))
+        .bits()
+        .count_ones() <= 1
+}
+
+/// Checks the access flags of a class, and returns the broken rule, if any.
+fn check_class_flags(flags: AccessFlags) -> Option<&'static str> {
+    if !CLASS_FLAGS.contains(flags) {
+        Some("flags not allowed in a class")
+    } else if flags.contains(AccessFlags::ACC_INTERFACE)
+        && !flags.contains(AccessFlags::ACC_ABSTRACT)
+    {
+        Some("interface is not abstract")
+    } else if flags.contains(AccessFlags::ACC_INTERFACE)
+        && flags.intersects(AccessFlags::ACC_FINAL | AccessFlags::ACC_ENUM)
+    {
+        Some("interface is final or enum")
+    } else if flags.contains(AccessFlags::ACC_ANNOTATION)
+        && !flags.contains(AccessFlags::ACC_INTERFACE)
+    {
+        Some("annotation is not an interface")
+    } else if flags.contains(AccessFlags::ACC_ABSTRACT | AccessFlags::ACC_FINAL) {
+        Some("class is both abstract and final")
+    } else {
+        None
+    }
+}
+
+/// Checks the access flags of a field, and returns the broken rule, if any.
+fn check_field_flags(
+    flags: AccessFlags,
+    class_flags: AccessFlags,
+    in_static_list: bool,
+) -> Option<&'static str> {
+    let interface_flags =
+        AccessFlags::ACC_PUBLIC | AccessFlags::ACC_STATIC | AccessFlags::ACC_FINAL;
+    if !FIELD_FLAGS.contains(flags) {
+        Some("flags not allowed in a field")
+    } else if !has_one_visibility(flags) {
+        Some("more than one visibility")
+    } else if flags.contains(AccessFlags::ACC_STATIC) != in_static_list {
+        Some("static flag does not match the field list")
+    } else if flags.contains(AccessFlags::ACC_FINAL | AccessFlags::ACC_VOLATILE) {
+        Some("field is both final and volatile")
+    } else if class_flags.contains(AccessFlags::ACC_INTERFACE) && !flags.contains(interface_flags)
+    {
+        Some("interface field is not public, static and final")
+    } else {
+        None
+    }
+}
+
+/// Checks the access flags of a method, and returns the broken rule, if any.
+///
+/// Abstract methods in classes that are not abstract are accepted, since the Android runtime only
+/// logs them.
+fn check_method_flags(
+    method: &Method,
+    name: Option<&str>,
+    class_flags: AccessFlags,
+    in_direct_list: bool,
+) -> Option<&'static str> {
+    let flags = method.access_flags();
+    let is_static_initializer = name == Some("<clinit>");
+    let is_constructor = is_static_initializer || name == Some("<init>");
+    let is_abstract = flags.contains(AccessFlags::ACC_ABSTRACT);
+    let is_direct = flags.intersects(
+        AccessFlags::ACC_STATIC | AccessFlags::ACC_PRIVATE | AccessFlags::ACC_CONSTRUCTOR,
+    );
+    if !METHOD_FLAGS.contains(flags) {
+        Some("flags not allowed in a method")
+    } else if !has_one_visibility(flags) {
+        Some("more than one visibility")
+    } else if flags.contains(AccessFlags::ACC_CONSTRUCTOR) != is_constructor {
+        Some("constructor flag does not match the method name")
+    } else if is_constructor && !CONSTRUCTOR_FLAGS.contains(flags - AccessFlags::ACC_STATIC) {
+        Some("flags not allowed in a constructor")
+    } else if is_constructor && flags.contains(AccessFlags::ACC_STATIC) != is_static_initializer
+    {
+        Some("only class initializers can be static constructors")
+    } else if is_direct != in_direct_list {
+        Some("direct flags do not match the method list")
+    } else if is_abstract && flags.intersects(ABSTRACT_FORBIDDEN_FLAGS) {
+        Some("abstract method has forbidden flags")
+    } else if class_flags.contains(AccessFlags::ACC_INTERFACE) && !in_direct_list
+        && !flags.contains(AccessFlags::ACC_PUBLIC)
+    {
+        Some("interface method is not public")
+    } else if method.code_offset().is_some()
+        == flags.intersects(AccessFlags::ACC_ABSTRACT | AccessFlags::ACC_NATIVE)
+    {
+        Some("code does not match the abstract and native flags")
+    } else {
+        None
+    }
+}
+
+/// Violation of a rule of the bytecode of a method.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeViolation {
+    address: u32,
+    kind: CodeViolationKind,
+}
+
+impl CodeViolation {
+    /// Gets the address, in code units, of the instruction or try block that violates the rule.
+    pub fn address(&self) -> u32 {
+        self.address
+    }
+
+    /// Gets the kind of the violation.
+    pub fn kind(&self) -> &CodeViolationKind {
+        &self.kind
+    }
+}
+
+impl fmt::Display for CodeViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at address {:#06x}: {}", self.address, self.kind)
+    }
+}
+
+/// Kind of violation of a rule of the bytecode of a method.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodeViolationKind {
+    /// The code unit is not the start of a valid instruction or payload.
+    InvalidInstruction(u16),
+    /// The register is not below the number of registers of the method.
+    RegisterOutOfRange(u32),
+    /// The register is read as a single register, but it can hold half of a wide pair.
+    ExpectedNarrowRegister(u32),
+    /// The register is read as the start of a wide pair, but it can hold something else.
+    ExpectedWideRegister(u32),
+    /// The branch target address is not the start of an instruction.
+    InvalidBranchTarget(i64),
+    /// The switch target address is not the start of an instruction.
+    InvalidSwitchTarget(i64),
+    /// The address does not hold an aligned payload of the kind the instruction needs.
+    InvalidPayload(i64),
+    /// Execution can continue past the end of the code.
+    FallsOffEnd,
+    /// Execution can continue into a payload.
+    FallsIntoPayload,
+    /// The try block does not cover whole instructions inside the code.
+    InvalidTryRange,
+    /// The try block overlaps with, or comes before, the previous try block.
+    OverlappingTry,
+    /// The handler offset of the try block is not the offset of a catch handler.
+    InvalidHandlerOffset(u16),
+    /// The handler address is not the start of an instruction.
+    InvalidHandlerAddress(u32),
+}
+
+impl fmt::Display for CodeViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CodeViolationKind::InvalidInstruction(unit) => {
+                write!(f, "invalid instruction {:#06x}", unit)
+            }
+            CodeViolationKind::RegisterOutOfRange(register) => {
+                write!(f, "register v{} out of range", register)
+            }
+            CodeViolationKind::ExpectedNarrowRegister(register) => {
+                write!(f, "register v{} can hold half of a wide pair", register)
+            }
+            CodeViolationKind::ExpectedWideRegister(register) => {
+                write!(f, "register v{} can hold something else than a wide pair", register)
+            }
+            CodeViolationKind::InvalidBranchTarget(target) => {
+                write!(f, "invalid branch target {}", target)
+            }
+            CodeViolationKind::InvalidSwitchTarget(target) => {
+                write!(f, "invalid switch target {}", target)
+            }
+            CodeViolationKind::InvalidPayload(address) => {
+                write!(f, "invalid payload address {}", address)
+            }
+            CodeViolationKind::FallsOffEnd => write!(f, "execution can fall off the end"),
+            CodeViolationKind::FallsIntoPayload => write!(f, "execution can fall into a payload"),
+            CodeViolationKind::InvalidTryRange => write!(f, "invalid try block range"),
+            CodeViolationKind::OverlappingTry => write!(f, "overlapping try block"),
+            CodeViolationKind::InvalidHandlerOffset(offset) => {
+                write!(f, "invalid catch handler offset {}", offset)
+            }
+            CodeViolationKind::InvalidHandlerAddress(address) => {
+                write!(f, "invalid catch handler address {:#06x}", address)
+            }
+        }
+    }
+}
+
+impl CodeItem {
+    /// Verifies the bytecode of the method, and returns all the violations found.
+    ///
+    /// Checks that registers are in range and that wide register pairs are used consistently,
+    /// that branches, switches and catch handlers target instructions, that try blocks cover
+    /// whole instructions, and that execution can't fall off the end of the code.
+    ///
+    /// The widths of the arguments of invocations are checked against the prototypes of the
+    /// callees in the given dex file.
+    pub fn verify(&self, dex: &Dex<'_>) -> Vec<CodeViolation> {
+        CodeVerifier::new(self, dex).verify()
+    }
+}
+
+/// Kind of a payload pseudo-instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PayloadKind {
+    PackedSwitch,
+    SparseSwitch,
+    FillArrayData,
+}
+
+/// Instruction or payload of the bytecode.
+enum Instruction {
+    Code(ByteCode),
+    Payload(Payload),
+}
+
+/// Width of the value a register operand holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Width {
+    Narrow,
+    Wide,
+    Any,
+}
+
+/// Register operand of an instruction.
+struct Operand {
+    register: u32,
+    width: Width,
+    write: bool,
+}
+
+impl Operand {
+    fn read<T: Into<u32>>(register: T, width: Width) -> Operand {
+        Operand {
+            register: register.into(),
+            width,
+            write: false,
+        }
+    }
+
+    fn write<T: Into<u32>>(register: T, width: Width) -> Operand {
+        Operand {
+            register: register.into(),
+            width,
+            write: true,
+        }
+    }
+}
+
+/// What a register can hold at a given instruction, as far as wide pairs are concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RegisterState {
+    Unknown,
+    Narrow,
+    WideLow,
+    WideHigh,
+    Conflict,
+}
+
+impl RegisterState {
+    fn merge(self, other: RegisterState) -> RegisterState {
+        if self == other {
+            self
+        } else if self == RegisterState::Unknown || other == RegisterState::Unknown {
+            RegisterState::Unknown
+        } else {
+            RegisterState::Conflict
+        }
+    }
+}
+
+/// Verifier of the bytecode of a method.
+struct CodeVerifier<'c, 'a: 'c> {
+    code: &'c CodeItem,
+    dex: &'c Dex<'a>,
+    instructions: Vec<(u32, Instruction)>,
+    index_at: Vec<Option<usize>>,
+    violations: Vec<CodeViolation>,
+}
+
+impl<'c, 'a> CodeVerifier<'c, 'a> {
+    fn new(code: &'c CodeItem, dex: &'c Dex<'a>) -> CodeVerifier<'c, 'a> {
+        CodeVerifier {
+            code,
+            dex,
+            instructions: Vec::new(),
+            index_at: vec![None; code.insns().len()],
+            violations: Vec::new(),
+        }
+    }
+
+    fn verify(mut self) -> Vec<CodeViolation> {
+        self.decode();
+        for index in 0..self.instructions.len() {
+            self.verify_instruction(index);
+        }
+        self.verify_tries();
+        self.verify_flow();
+        self.violations.sort_by_key(|violation| violation.address);
+        self.violations
+    }
+
+    fn push(&mut self, address: u32, kind: CodeViolationKind) {
+        self.violations.push(CodeViolation { address, kind });
+    }
+
+    /// Splits the code in instructions and payloads, until the end or an invalid code unit.
+    fn decode(&mut self) {
+        let mut decoder = self.code.decode();
+        loop {
+            let address = decoder.address();
+            let instruction = match decoder.next() {
+                Some(Ok((_, ByteCode::Payload(payload)))) => Instruction::Payload(payload),
+                Some(Ok((_, code))) => Instruction::Code(code),
+                Some(Err(_)) => {
+                    let unit = self.code.insns()[address as usize];
+                    self.push(address, CodeViolationKind::InvalidInstruction(unit));
+                    return;
+                }
+                None => return,
+            };
+            self.index_at[address as usize] = Some(self.instructions.len());
+            self.instructions.push((address, instruction));
+        }
+    }
+
+    /// Checks if there is an instruction, not a payload, at the given address.
+    fn is_instruction(&self, address: i64) -> bool {
+        self.instruction_at(address)
+            .is_some_and(|index| matches!(self.instructions[index].1, Instruction::Code(_)))
+    }
+
+    fn instruction_at(&self, address: i64) -> Option<usize> {
+        if address < 0 {
+            return None;
+        }
+        self.index_at.get(address as usize).and_then(|index| *index)
+    }
+
+    /// Gets the payload of the given kind at the given offset, if it's valid.
+    fn payload_at(&self, address: u32, offset: i32, kind: PayloadKind) -> Option<&Payload> {
+        let target = i64::from(address) + i64::from(offset);
+        match self.instruction_at(target) {
+            Some(index) if target % 2 == 0 => match self.instructions[index].1 {
+                Instruction::Payload(ref payload) if payload_kind(payload) == kind => {
+                    Some(payload)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Checks the registers, branch targets and payloads of an instruction.
+    fn verify_instruction(&mut self, index: usize) {
+        let address = self.instructions[index].0;
+        let code = match self.instructions[index].1 {
+            Instruction::Code(ref code) => code,
+            Instruction::Payload(_) => return,
+        };
+
+        let registers_size = u32::from(self.code.registers_size());
+        let mut violations = Vec::new();
+        for operand in self.operands(code) {
+            let last = if operand.width == Width::Wide {
+                operand.register + 1
+            } else {
+                operand.register
+            };
+            if last >= registers_size {
+                violations.push(CodeViolationKind::RegisterOutOfRange(last));
+            }
+        }
+
+        if let Some(offset) = branch_offset(code) {
+            let target = i64::from(address) + i64::from(offset);
+            if !self.is_instruction(target) {
+                violations.push(CodeViolationKind::InvalidBranchTarget(target));
+            }
+        }
+
+        if let Some((offset, kind)) = payload_offset(code) {
+            match self.payload_at(address, offset, kind) {
+                Some(&Payload::PackedSwitch(ref switch))
+                | Some(&Payload::SparseSwitch(ref switch)) => {
+                    for (_, target) in switch.cases(address) {
+                        if !self.is_instruction(target) {
+                            violations.push(CodeViolationKind::InvalidSwitchTarget(target));
+                        }
+                    }
+                }
+                Some(_) => {}
+                None => violations.push(CodeViolationKind::InvalidPayload(
+                    i64::from(address) + i64::from(offset),
+                )),
+            }
+        }
+
+        for kind in violations {
+            self.push(address, kind);
+        }
+    }
+
+    /// Checks the ranges and the handlers of the try blocks.
+    fn verify_tries(&mut self) {
+        let code = self.code;
+        let insns_size = i64::from(code.insns().len() as u32);
+        let mut previous_end = 0;
+        for try_item in code.tries() {
+            let start = i64::from(try_item.start_address());
+            let end = start + i64::from(try_item.insn_count());
+            let address = try_item.start_address();
+            if try_item.insn_count() == 0 || !self.is_instruction(start)
+                || (end != insns_size && !self.is_instruction(end))
+            {
+                self.push(address, CodeViolationKind::InvalidTryRange);
+            }
+            if start < previous_end {
+                self.push(address, CodeViolationKind::OverlappingTry);
+            }
+            previous_end = end;
+
+            let handler_offset = try_item.handler_offset();
+            if !code
+                .handlers()
+                .iter()
+                .any(|handler| handler.offset() == u32::from(handler_offset))
+            {
+                self.push(address, CodeViolationKind::InvalidHandlerOffset(handler_offset));
+            }
+        }
+
+        for handler in code.handlers() {
+            let addresses = handler
+                .handlers()
+                .iter()
+                .map(|info| info.addr())
+                .chain(handler.catch_all_addr());
+            for handler_address in addresses {
+                if !self.is_instruction(i64::from(handler_address)) {
+                    self.push(
+                        handler_address,
+                        CodeViolationKind::InvalidHandlerAddress(handler_address),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Follows the control flow of the method to check that wide register pairs are used
+    /// consistently and that execution can't fall off the end of the code.
+    fn verify_flow(&mut self) {
+        if self.instructions.is_empty() {
+            return;
+        }
+        let registers_size = self.code.registers_size() as usize;
+        let mut states: Vec<Option<Vec<RegisterState>>> = Vec::new();
+        states.resize_with(self.instructions.len(), || None);
+        let unknown = vec![RegisterState::Unknown; registers_size];
+
+        // Registers are unknown at the start of the method and of the catch handlers.
+        let mut pending = vec![0];
+        states[0] = Some(unknown.clone());
+        for handler in self.code.handlers() {
+            let addresses = handler
+                .handlers()
+                .iter()
+                .map(|info| info.addr())
+                .chain(handler.catch_all_addr());
+            for address in addresses {
+                if let Some(index) = self.instruction_at(i64::from(address)) {
+                    if states[index].is_none() {
+                        states[index] = Some(unknown.clone());
+                        pending.push(index);
+                    }
+                }
+            }
+        }
+
+        while let Some(index) = pending.pop() {
+            let mut state = states[index].clone().unwrap_or_default();
+            let _ = self.transfer(index, &mut state);
+            for successor in self.successors(index) {
+                let changed = if let Some(ref mut current) = states[successor] {
+                    let mut changed = false;
+                    for (current, &new) in current.iter_mut().zip(&state) {
+                        let merged = current.merge(new);
+                        changed |= merged != *current;
+                        *current = merged;
+                    }
+                    changed
+                } else {
+                    states[successor] = Some(state.clone());
+                    true
+                };
+                if changed && !pending.contains(&successor) {
+                    pending.push(successor);
+                }
+            }
+        }
+
+        for (index, state) in states.into_iter().enumerate() {
+            if let Some(mut state) = state {
+                let address = self.instructions[index].0;
+                for kind in self.transfer(index, &mut state) {
+                    self.push(address, kind);
+                }
+                if let Instruction::Code(ref code) = self.instructions[index].1 {
+                    if continues(code) {
+                        let next = index + 1;
+                        if next == self.instructions.len() {
+                            self.push(address, CodeViolationKind::FallsOffEnd);
+                        } else if let Instruction::Payload(_) = self.instructions[next].1 {
+                            self.push(address, CodeViolationKind::FallsIntoPayload);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Gets the indexes of the instructions that can be executed after the given one.
+    fn successors(&self, index: usize) -> Vec<usize> {
+        let (address, code) = match self.instructions[index] {
+            (address, Instruction::Code(ref code)) => (address, code),
+            (_, Instruction::Payload(_)) => return Vec::new(),
+        };
+        let mut targets = Vec::new();
+        if continues(code) {
+            targets.push(i64::from(address) + i64::from(self.width(index)));
+        }
+        if let Some(offset) = branch_offset(code) {
+            targets.push(i64::from(address) + i64::from(offset));
+        }
+        if let Some((offset, kind)) = payload_offset(code) {
+            match self.payload_at(address, offset, kind) {
+                Some(&Payload::PackedSwitch(ref switch))
+                | Some(&Payload::SparseSwitch(ref switch)) => {
+                    targets.extend(switch.cases(address).into_iter().map(|(_, target)| target));
+                }
+                _ => {}
+            }
+        }
+        targets
+            .into_iter()
+            .filter(|&target| self.is_instruction(target))
+            .filter_map(|target| self.instruction_at(target))
+            .collect()
+    }
+
+    /// Gets the size in code units of the instruction at the given index.
+    fn width(&self, index: usize) -> u32 {
+        let end = self.instructions
+            .get(index + 1)
+            .map_or(self.code.insns().len() as u32, |&(address, _)| address);
+        end - self.instructions[index].0
+    }
+
+    /// Gets the register operands of an instruction.
+    ///
+    /// The widths of the arguments of an invocation are taken from the prototype of the callee. If
+    /// it can't be found, or the arguments don't match it, their widths are not checked.
+    fn operands(&self, code: &ByteCode) -> Vec<Operand> {
+        let dex = self.dex;
+        let method_prototype = |method: u32| {
+            dex.get_method(method)
+                .and_then(|method_id| dex.get_prototype(method_id.prototype_index() as u32))
+        };
+        let call_site_prototype = |call_site: u32| {
+            dex.get_call_site(call_site)
+                .and_then(|call_site| dex.get_prototype(call_site.method_type_index()))
+        };
+        let (registers, receiver, prototype) = match *code {
+            ByteCode::Invoke(ref kind, ref registers, method) => (
+                registers.iter().map(|&register| u32::from(register)).collect(),
+                !matches!(*kind, InvokeKind::Static),
+                method_prototype(method),
+            ),
+            ByteCode::InvokeRange(ref kind, first, count, method) => (
+                range_registers(first, count),
+                !matches!(*kind, InvokeKind::Static),
+                method_prototype(method),
+            ),
+            ByteCode::InvokePolymorphic(ref registers, _, prototype) => (
+                registers.iter().map(|&register| u32::from(register)).collect(),
+                true,
+                dex.get_prototype(prototype),
+            ),
+            ByteCode::InvokePolymorphicRange(first, count, _, prototype) => {
+                (range_registers(first, count), true, dex.get_prototype(prototype))
+            }
+            ByteCode::InvokeCustom(ref registers, call_site) => (
+                registers.iter().map(|&register| u32::from(register)).collect(),
+                false,
+                call_site_prototype(call_site),
+            ),
+            ByteCode::InvokeCustomRange(first, count, call_site) => {
+                (range_registers(first, count), false, call_site_prototype(call_site))
+            }
+            _ => return operands(code),
+        };
+        prototype
+            .and_then(|prototype| arguments(&registers, receiver, prototype))
+            .unwrap_or_else(|| operands(code))
+    }
+
+    /// Applies the register operands of an instruction to the register states, and returns the
+    /// wide pair violations of the instruction.
+    fn transfer(&self, index: usize, state: &mut [RegisterState]) -> Vec<CodeViolationKind> {
+        let code = match self.instructions[index].1 {
+            Instruction::Code(ref code) => code,
+            Instruction::Payload(_) => return Vec::new(),
+        };
+        let operands = self.operands(code);
+        let mut violations = Vec::new();
+        let size = state.len();
+        for operand in operands.iter().filter(|operand| !operand.write) {
+            let register = operand.register as usize;
+            match operand.width {
+                Width::Narrow if register < size => match state[register] {
+                    RegisterState::Unknown | RegisterState::Narrow => {}
+                    _ => violations.push(CodeViolationKind::ExpectedNarrowRegister(
+                        operand.register,
+                    )),
+                },
+                Width::Wide if register + 1 < size => {
+                    let low = state[register];
+                    let high = state[register + 1];
+                    if (low != RegisterState::Unknown && low != RegisterState::WideLow)
+                        || (high != RegisterState::Unknown && high != RegisterState::WideHigh)
+                    {
+                        violations.push(CodeViolationKind::ExpectedWideRegister(
+                            operand.register,
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+        for operand in operands.iter().filter(|operand| operand.write) {
+            let register = operand.register as usize;
+            match operand.width {
+                Width::Wide if register + 1 < size => {
+                    clobber(state, register);
+                    clobber(state, register + 1);
+                    state[register] = RegisterState::WideLow;
+                    state[register + 1] = RegisterState::WideHigh;
+                }
+                Width::Narrow if register < size => {
+                    clobber(state, register);
+                    state[register] = RegisterState::Narrow;
+                }
+                _ => {}
+            }
+        }
+        violations
+    }
+}
+
+/// Breaks the wide pair the register is part of, if any, before the register is written.
+fn clobber(state: &mut [RegisterState], register: usize) {
+    match state[register] {
+        RegisterState::WideLow if register + 1 < state.len() => {
+            state[register + 1] = RegisterState::Conflict;
+        }
+        RegisterState::WideHigh if register > 0 => {
+            state[register - 1] = RegisterState::Conflict;
+        }
+        _ => {}
+    }
+}
+
+/// Gets the kind of a payload.
+fn payload_kind(payload: &Payload) -> PayloadKind {
+    match *payload {
+        Payload::PackedSwitch(_) => PayloadKind::PackedSwitch,
+        Payload::SparseSwitch(_) => PayloadKind::SparseSwitch,
+        Payload::FillArrayData(_) => PayloadKind::FillArrayData,
+    }
+}
+
+/// Checks if execution can continue with the next instruction.
+fn continues(code: &ByteCode) -> bool {
+    !matches!(
+        *code,
+        ByteCode::ReturnVoid | ByteCode::Return(_) | ByteCode::ReturnWide(_)
+            | ByteCode::ReturnObject(_) | ByteCode::Throw(_) | ByteCode::Goto(_)
+            | ByteCode::Goto16(_) | ByteCode::Goto32(_)
+    )
+}
+
+/// Gets the offset of the target of a branch instruction.
+fn branch_offset(code: &ByteCode) -> Option<i32> {
+    match *code {
+        ByteCode::Goto(offset) => Some(i32::from(offset)),
+        ByteCode::Goto16(offset) | ByteCode::If(_, _, _, offset) | ByteCode::If0(_, _, offset) => {
+            Some(i32::from(offset))
+        }
+        ByteCode::Goto32(offset) => Some(offset),
+        _ => None,
+    }
+}
+
+/// Gets the offset and the kind of the payload of an instruction.
+fn payload_offset(code: &ByteCode) -> Option<(i32, PayloadKind)> {
+    match *code {
+        ByteCode::PackedSwitch(_, offset) => Some((offset, PayloadKind::PackedSwitch)),
+        ByteCode::SparseSwitch(_, offset) => Some((offset, PayloadKind::SparseSwitch)),
+        ByteCode::FillArrayData(_, offset) => Some((offset, PayloadKind::FillArrayData)),
+        _ => None,
+    }
+}
+
+/// Gets the width of a value given its wideness.
+fn width(wide: bool) -> Width {
+    if wide {
+        Width::Wide
+    } else {
+        Width::Narrow
+    }
+}
+
+/// Gets the register operands of an instruction, and the width of their values.
+///
+/// The widths of the arguments of invocations depend on the callee, so they are not given.
+fn operands(code: &ByteCode) -> Vec<Operand> {
+    use self::Width::{Any, Narrow, Wide};
+
+    match *code {
+        ByteCode::Move(dest, src) | ByteCode::MoveObject(dest, src) => {
+            vec![Operand::write(dest, Narrow), Operand::read(src, Narrow)]
+        }
+        ByteCode::MoveFrom16(dest, src) | ByteCode::MoveObjectFrom16(dest, src) => {
+            vec![Operand::write(dest, Narrow), Operand::read(src, Narrow)]
+        }
+        ByteCode::Move16(dest, src) | ByteCode::MoveObject16(dest, src) => {
+            vec![Operand::write(dest, Narrow), Operand::read(src, Narrow)]
+        }
+        ByteCode::MoveWide(dest, src) => {
+            vec![Operand::write(dest, Wide), Operand::read(src, Wide)]
+        }
+        ByteCode::MoveWideFrom16(dest, src) => {
+            vec![Operand::write(dest, Wide), Operand::read(src, Wide)]
+        }
+        ByteCode::MoveWide16(dest, src) => {
+            vec![Operand::write(dest, Wide), Operand::read(src, Wide)]
+        }
+        ByteCode::MoveResult(dest) | ByteCode::MoveResultObject(dest)
+        | ByteCode::MoveException(dest) | ByteCode::Const4(dest, _)
+        | ByteCode::Const16(dest, _) | ByteCode::Const(dest, _)
+        | ByteCode::ConstHigh16(dest, _) | ByteCode::ConstString(dest, _)
+        | ByteCode::ConstStringJumbo(dest, _) | ByteCode::ConstClass(dest, _)
+        | ByteCode::NewInstance(dest, _) | ByteCode::ConstMethodHandle(dest, _)
+        | ByteCode::ConstMethodType(dest, _) => vec![Operand::write(dest, Narrow)],
+        ByteCode::MoveResultWide(dest) | ByteCode::ConstWide16(dest, _)
+        | ByteCode::ConstWide32(dest, _) | ByteCode::ConstWide(dest, _)
+        | ByteCode::ConstWideHigh16(dest, _) => vec![Operand::write(dest, Wide)],
+        ByteCode::Return(src) | ByteCode::ReturnObject(src) | ByteCode::MonitorEnter(src)
+        | ByteCode::MonitorExit(src) | ByteCode::CheckCast(src, _)
+        | ByteCode::FillArrayData(src, _) | ByteCode::Throw(src)
+        | ByteCode::PackedSwitch(src, _) | ByteCode::SparseSwitch(src, _)
+        | ByteCode::If0(_, src, _) => vec![Operand::read(src, Narrow)],
+        ByteCode::ReturnWide(src) => vec![Operand::read(src, Wide)],
+        ByteCode::InstanceOf(dest, src, _) | ByteCode::ArrayLength(dest, src)
+        | ByteCode::NewArray(dest, src, _) | ByteCode::BinaryLit16(_, dest, src, _)
+        | ByteCode::BinaryLit8(_, dest, src, _) => {
+            vec![Operand::write(dest, Narrow), Operand::read(src, Narrow)]
+        }
+        ByteCode::If(_, first, second, _) => {
+            vec![Operand::read(first, Narrow), Operand::read(second, Narrow)]
+        }
+        ByteCode::Compare(ref compare, dest, first, second) => {
+            let wide = match *compare {
+                CompareType::LittleThanDouble | CompareType::GreaterThanDouble
+                | CompareType::Long => Wide,
+                _ => Narrow,
+            };
+            vec![
+                Operand::write(dest, Narrow),
+                Operand::read(first, wide),
+                Operand::read(second, wide),
+            ]
+        }
+        ByteCode::Array(ref operation, value, array, index) => {
+            let (value_width, write) = array_operation(operation);
+            vec![
+                Operand::read(array, Narrow),
+                Operand::read(index, Narrow),
+                Operand { register: value.into(), width: value_width, write },
+            ]
+        }
+        ByteCode::Instance(ref operation, value, object, _) => {
+            let (value_width, write) = array_operation(operation);
+            vec![
+                Operand::read(object, Narrow),
+                Operand { register: value.into(), width: value_width, write },
+            ]
+        }
+        ByteCode::Static(ref operation, value, _) => {
+            let (value_width, write) = array_operation(operation);
+            vec![Operand { register: value.into(), width: value_width, write }]
+        }
+        ByteCode::FilledNewArray(ref registers, _) => registers
+            .iter()
+            .map(|&register| Operand::read(register, Narrow))
+            .collect(),
+        ByteCode::Invoke(_, ref registers, _) | ByteCode::InvokePolymorphic(ref registers, _, _)
+        | ByteCode::InvokeCustom(ref registers, _) => registers
+            .iter()
+            .map(|&register| Operand::read(register, Any))
+            .collect(),
+        ByteCode::FilledNewArrayRange(first, count, _) => range(first, count, Narrow),
+        ByteCode::InvokeRange(_, first, count, _)
+        | ByteCode::InvokePolymorphicRange(first, count, _, _)
+        | ByteCode::InvokeCustomRange(first, count, _) => range(first, count, Any),
+        ByteCode::Unary(ref operation, dest, src) => {
+            let (dest_wide, src_wide) = match *operation {
+                UnaryOperation::NegateLong | UnaryOperation::NotLong
+                | UnaryOperation::NegateDouble | UnaryOperation::LongToDouble
+                | UnaryOperation::DoubleToLong => (true, true),
+                UnaryOperation::IntToLong | UnaryOperation::IntToDouble
+                | UnaryOperation::FloatToLong | UnaryOperation::FloatToDouble => (true, false),
+                UnaryOperation::LongToInt | UnaryOperation::LongToFloat
+                | UnaryOperation::DoubleToInt | UnaryOperation::DoubleToFloat => (false, true),
+                _ => (false, false),
+            };
+            vec![Operand::write(dest, width(dest_wide)), Operand::read(src, width(src_wide))]
+        }
+        ByteCode::Binary(ref operation, dest, first, second) => {
+            let (wide, second_wide) = binary_operation(operation);
+            vec![
+                Operand::write(dest, width(wide)),
+                Operand::read(first, width(wide)),
+                Operand::read(second, width(second_wide)),
+            ]
+        }
+        ByteCode::Binary2Addr(ref operation, dest, src) => {
+            let (wide, src_wide) = binary_operation(operation);
+            vec![
+                Operand::read(dest, width(wide)),
+                Operand::read(src, width(src_wide)),
+                Operand::write(dest, width(wide)),
+            ]
+        }
+        ByteCode::Nop | ByteCode::ReturnVoid | ByteCode::Goto(_) | ByteCode::Goto16(_)
+        | ByteCode::Goto32(_) | ByteCode::Payload(_) => Vec::new(),
+    }
+}
+
+/// Gets the registers of a range, given its first register and the number of registers.
+fn range(first: u16, count: u8, width: Width) -> Vec<Operand> {
+    range_registers(first, count)
+        .into_iter()
+        .map(|register| Operand::read(register, width))
+        .collect()
+}
+
+/// Gets the register numbers of a range, given its first register and the number of registers.
+fn range_registers(first: u16, count: u8) -> Vec<u32> {
+    (0..u32::from(count))
+        .map(|offset| u32::from(first) + offset)
+        .collect()
+}
+
+/// Gets the operands of the arguments of an invocation of a method with the given prototype, if
+/// the registers match it.
+///
+/// Wide arguments take two consecutive registers, and the receiver of non static calls takes the
+/// first one.
+fn arguments(registers: &[u32], receiver: bool, prototype: &Prototype<'_>) -> Option<Vec<Operand>> {
+    let mut registers = registers.iter();
+    let mut operands = Vec::new();
+    if receiver {
+        operands.push(Operand::read(*registers.next()?, Width::Narrow));
+    }
+    for field_type in prototype.descriptor().field_types() {
+        let register = *registers.next()?;
+        match *field_type {
+            ShortyFieldType::Long | ShortyFieldType::Double => {
+                if *registers.next()? != register + 1 {
+                    return None;
+                }
+                operands.push(Operand::read(register, Width::Wide));
+            }
+            _ => operands.push(Operand::read(register, Width::Narrow)),
+        }
+    }
+    if registers.next().is_some() {
+        return None;
+    }
+    Some(operands)
+}
+
+/// Gets the width of the value of an array, instance or static field operation, and if the value
+/// is written to the register.
+fn array_operation(operation: &ArrayOperation) -> (Width, bool) {
+    match *operation {
+        ArrayOperation::GetWide => (Width::Wide, true),
+        ArrayOperation::PutWide => (Width::Wide, false),
+        ArrayOperation::Get | ArrayOperation::GetObject | ArrayOperation::GetBoolean
+        | ArrayOperation::GetByte | ArrayOperation::GetChar | ArrayOperation::GetShort => {
+            (Width::Narrow, true)
+        }
+        _ => (Width::Narrow, false),
+    }
+}
+
+/// Gets if the result and first operand of a binary operation are wide, and if the second
+/// operand is wide.
+fn binary_operation(operation: &BinaryOperation) -> (bool, bool) {
+    match *operation {
+        BinaryOperation::ShlLong | BinaryOperation::ShrLong | BinaryOperation::UshrLong => {
+            (true, false)
+        }
+        BinaryOperation::AddLong | BinaryOperation::SubLong | BinaryOperation::MulLong
+        | BinaryOperation::DivLong | BinaryOperation::RemLong | BinaryOperation::AndLong
+        | BinaryOperation::OrLong | BinaryOperation::XorLong | BinaryOperation::AddDouble
+        | BinaryOperation::SubDouble | BinaryOperation::MulDouble
+        | BinaryOperation::DivDouble | BinaryOperation::RemDouble => (true, true),
+        _ => (false, false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ParseOptions;
+    use byteorder::{LittleEndian, WriteBytesExt};
+
+    /// Builds a code item with the given number of registers, bytecode and try block, with a
+    /// catch-all handler at the given address for the try block.
+    fn code_item(registers: u16, insns: &[u16], try_item: Option<(u32, u16, u16, u8)>) -> CodeItem {
+        let mut bytes = Vec::new();
+        bytes.write_u16::<LittleEndian>(registers).unwrap();
+        bytes.write_u16::<LittleEndian>(0).unwrap();
+        bytes.write_u16::<LittleEndian>(0).unwrap();
+        bytes.write_u16::<LittleEndian>(try_item.map_or(0, |_| 1)).unwrap();
+        bytes.write_u32::<LittleEndian>(0).unwrap();
+        bytes.write_u32::<LittleEndian>(insns.len() as u32).unwrap();
+        for &unit in insns {
+            bytes.write_u16::<LittleEndian>(unit).unwrap();
+        }
+        if let Some((start, count, handler_offset, catch_all)) = try_item {
+            if insns.len() % 2 != 0 {
+                bytes.write_u16::<LittleEndian>(0).unwrap();
+            }
+            bytes.write_u32::<LittleEndian>(start).unwrap();
+            bytes.write_u16::<LittleEndian>(count).unwrap();
+            bytes.write_u16::<LittleEndian>(handler_offset).unwrap();
+            // One handler, with only a catch-all address.
+            bytes.extend_from_slice(&[1, 0, catch_all]);
+        }
+        CodeItem::from_reader::<_, LittleEndian>(&mut Cursor::new(bytes)).unwrap()
+    }
+
+    /// Loads the test dex file, whose methods are the callees of the invocations in the tests.
+    fn test_dex() -> Dex<'static> {
+        let options = ParseOptions {
+            lazy: true,
+            ..Default::default()
+        };
+        Dex::from_file_with_options("test.dex", options).unwrap()
+    }
+
+    fn violation(address: u32, kind: CodeViolationKind) -> CodeViolation {
+        CodeViolation { address, kind }
+    }
+
+    #[test]
+    fn it_accepts_valid_code() {
+        // const/4 v1, #0; return-void
+        let code = code_item(2, &[0x0112, 0x000e], None);
+
+        assert!(code.verify(&test_dex()).is_empty());
+    }
+
+    #[test]
+    fn it_rejects_registers_out_of_range() {
+        // const/4 v3, #0; const-wide/16 v1, #1; return-void
+        let code = code_item(2, &[0x0312, 0x0116, 0x0001, 0x000e], None);
+
+        assert_eq!(
+            vec![
+                violation(0, CodeViolationKind::RegisterOutOfRange(3)),
+                violation(1, CodeViolationKind::RegisterOutOfRange(2)),
+            ],
+            code.verify(&test_dex())
+        );
+    }
+
+    #[test]
+    fn it_rejects_falling_off_the_end() {
+        // nop
+        let code = code_item(1, &[0x0000], None);
+
+        assert_eq!(
+            vec![violation(0, CodeViolationKind::FallsOffEnd)],
+            code.verify(&test_dex())
+        );
+    }
+
+    #[test]
+    fn it_rejects_branches_outside_instructions() {
+        // const/16 v0, #1; goto -1; goto +5
+        let code = code_item(1, &[0x0013, 0x0001, 0xff28, 0x0528], None);
+
+        assert_eq!(
+            vec![
+                violation(2, CodeViolationKind::InvalidBranchTarget(1)),
+                violation(3, CodeViolationKind::InvalidBranchTarget(8)),
+            ],
+            code.verify(&test_dex())
+        );
+    }
+
+    #[test]
+    fn it_rejects_inconsistent_wide_pairs() {
+        // const-wide/16 v0, #1; return v1
+        let code = code_item(2, &[0x0016, 0x0001, 0x010f], None);
+        assert_eq!(
+            vec![violation(2, CodeViolationKind::ExpectedNarrowRegister(1))],
+            code.verify(&test_dex())
+        );
+
+        // const/4 v0, #0; return-wide v0
+        let code = code_item(2, &[0x0012, 0x0010], None);
+        assert_eq!(
+            vec![violation(1, CodeViolationKind::ExpectedWideRegister(0))],
+            code.verify(&test_dex())
+        );
+    }
+
+    #[test]
+    fn it_checks_invocation_argument_widths() {
+        // Method 8 takes a long, and method 37 takes an int.
+        // const-wide/16 v0, #0; invoke-static {v0, v1}, method@8; return-void
+        let code = code_item(2, &[0x0016, 0x0000, 0x2071, 8, 0x0010, 0x000e], None);
+        assert!(code.verify(&test_dex()).is_empty());
+
+        // const/4 v0, #0; const/4 v1, #0; invoke-static {v0, v1}, method@8; return-void
+        let code = code_item(2, &[0x0012, 0x0112, 0x2071, 8, 0x0010, 0x000e], None);
+        assert_eq!(
+            vec![violation(2, CodeViolationKind::ExpectedWideRegister(0))],
+            code.verify(&test_dex())
+        );
+
+        // const-wide/16 v0, #0; const/4 v2, #0; invoke-static {v1, v2}, method@8; return-void
+        let code = code_item(3, &[0x0016, 0x0000, 0x0212, 0x2071, 8, 0x0021, 0x000e], None);
+        assert_eq!(
+            vec![violation(3, CodeViolationKind::ExpectedWideRegister(1))],
+            code.verify(&test_dex())
+        );
+
+        // const-wide/16 v0, #0; invoke-static {v0}, method@37; return-void
+        let code = code_item(2, &[0x0016, 0x0000, 0x1071, 37, 0x0000, 0x000e], None);
+        assert_eq!(
+            vec![violation(2, CodeViolationKind::ExpectedNarrowRegister(0))],
+            code.verify(&test_dex())
+        );
+    }
+
+    #[test]
+    fn it_checks_switch_targets() {
+        // const/4 v0, #0; packed-switch v0, +5; return-void; nop; packed-switch-payload
+        let mut insns = vec![
+            0x0012, 0x002b, 5, 0, 0x000e, 0x0000, 0x0100, 1, 0, 0, 3, 0,
+        ];
+        assert!(code_item(1, &insns, None).verify(&test_dex()).is_empty());
+
+        insns[10] = 2;
+        assert_eq!(
+            vec![violation(1, CodeViolationKind::InvalidSwitchTarget(3))],
+            code_item(1, &insns, None).verify(&test_dex())
+        );
+    }
+
+    #[test]
+    fn it_checks_try_blocks() {
+        // const/4 v0, #0; return-void
+        let insns = [0x0012, 0x000e];
+        assert!(code_item(1, &insns, Some((0, 1, 1, 1))).verify(&test_dex()).is_empty());
+
+        assert_eq!(
+            vec![
+                violation(0, CodeViolationKind::InvalidTryRange),
+                violation(0, CodeViolationKind::InvalidHandlerOffset(2)),
+                violation(5, CodeViolationKind::InvalidHandlerAddress(5)),
+            ],
+            code_item(1, &insns, Some((0, 3, 2, 5))).verify(&test_dex())
+        );
+    }
+
+    #[test]
+    fn it_accepts_legal_class_flags() {
+        assert_eq!(None, check_class_flags(AccessFlags::ACC_PUBLIC | AccessFlags::ACC_FINAL));
+        assert_eq!(
+            None,
+            check_class_flags(
+                AccessFlags::ACC_PUBLIC | AccessFlags::ACC_INTERFACE | AccessFlags::ACC_ABSTRACT
+                    | AccessFlags::ACC_ANNOTATION
+            )
+        );
+    }
+
+    #[test]
+    fn it_rejects_illegal_class_flags() {
+        assert!(check_class_flags(AccessFlags::ACC_VOLATILE).is_some());
+        assert!(check_class_flags(AccessFlags::ACC_INTERFACE).is_some());
+        assert!(check_class_flags(AccessFlags::ACC_ANNOTATION).is_some());
+        assert!(check_class_flags(AccessFlags::ACC_ABSTRACT | AccessFlags::ACC_FINAL).is_some());
+    }
+
+    #[test]
+    fn it_checks_field_flags() {
+        let public_static = AccessFlags::ACC_PUBLIC | AccessFlags::ACC_STATIC;
+        assert_eq!(None, check_field_flags(public_static, AccessFlags::ACC_PUBLIC, true));
+        assert!(check_field_flags(public_static, AccessFlags::ACC_PUBLIC, false).is_some());
+        assert!(
+            check_field_flags(
+                AccessFlags::ACC_PUBLIC | AccessFlags::ACC_PRIVATE,
+                AccessFlags::ACC_PUBLIC,
+                false
+            ).is_some()
+        );
+        assert!(
+            check_field_flags(
+                AccessFlags::ACC_FINAL | AccessFlags::ACC_VOLATILE,
+                AccessFlags::ACC_PUBLIC,
+                false
+            ).is_some()
+        );
+        assert!(
+            check_field_flags(
+                public_static,
+                AccessFlags::ACC_INTERFACE | AccessFlags::ACC_ABSTRACT,
+                true
+            ).is_some()
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Warnings found while parsing dex files.
+//!
+//! When a dex file is not parsed in strict mode (see
+//! [`ParseOptions`](../struct.ParseOptions.html)), recoverable problems don't abort parsing.
+//! They are recorded as warnings instead, and the affected items are read as well as possible.
+
+use std::fmt;
+
+use error::*;
+use types::ItemType;
+
+/// Recoverable problem found while parsing a dex file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    offset: u64,
+    kind: WarningKind,
+}
+
+impl Warning {
+    /// Creates a new warning for the item at the given offset.
+    pub(crate) fn new(offset: u64, kind: WarningKind) -> Warning {
+        Warning { offset, kind }
+    }
+
+    /// Gets the offset in the file of the item with the problem, including for dex files in VDEX
+    /// files.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Gets the kind of the warning.
+    pub fn kind(&self) -> &WarningKind {
+        &self.kind
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at offset {:#010x}: {}", self.offset, self.kind)
+    }
+}
+
+/// Kind of warning found while parsing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WarningKind {
+    /// The size of the file does not match the one in the header.
+    FileSizeMismatch {
+        /// Actual size of the file.
+        file_size: u64,
+        /// Size of the file in the header.
+        size_in_header: u32,
+    },
+    /// There is unknown data between the identifier lists and the data section.
+    UnknownData {
+        /// Size of the unknown data.
+        size: u32,
+    },
+    /// Access flags with unknown bits, that were ignored.
+    InvalidAccessFlags {
+        /// `ClassDef` for classes, `FieldId` for fields and `MethodId` for methods.
+        item_type: ItemType,
+        /// Index of the class in the class definitions list, or of the member in the *Field IDs*
+        /// or *Method IDs* list.
+        index: u32,
+        /// Access flags in the file.
+        access_flags: u32,
+    },
+    /// String that could not be decoded. It was replaced by its lossy UTF-8 decoding, or by an
+    /// empty string if its data could not be found.
+    InvalidString {
+        /// Index of the string in the *String IDs* list.
+        index: u32,
+        /// Description of the problem.
+        error: String,
+    },
+    /// Class definition that could not be read. The class is left out of the dex file.
+    UnreadableClass {
+        /// Index of the class in the class definitions list.
+        class_def_index: u32,
+        /// Description of the problem.
+        error: String,
+    },
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WarningKind::FileSizeMismatch {
+                file_size,
+                size_in_header,
+            } => write!(
+                f,
+                "file size in header ({} bytes) does not match the size of the file ({} bytes)",
+                size_in_header, file_size
+            ),
+            WarningKind::UnknownData { size } => {
+                write!(f, "{} bytes of unknown data before the data section", size)
+            }
+            WarningKind::InvalidAccessFlags {
+                item_type,
+                index,
+                access_flags,
+            } => write!(
+                f,
+                "invalid access flags {:#010x} in {} {}",
+                access_flags, item_type, index
+            ),
+            WarningKind::InvalidString { index, ref error } => {
+                write!(f, "invalid string {}: {}", index, error)
+            }
+            WarningKind::UnreadableClass {
+                class_def_index,
+                ref error,
+            } => write!(
+                f,
+                "could not read class definition {}: {}",
+                class_def_index, error
+            ),
+        }
+    }
+}
+
+/// Describes an error and all its causes in a single line.
+pub(crate) fn describe(error: &Error) -> String {
+    error
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(": ")
+}
@@ -1,6 +1,142 @@
+extern crate adler;
 extern crate dalvik;
 
 use std::fs;
+use std::io::Cursor;
+
+use dalvik::error::ErrorKind;
+use dalvik::types::ItemType;
+
+/// Builds an empty version 041 dex file, to be placed at `header_offset` in a container.
+fn empty_container_dex(header_offset: u32, container_size: u32) -> Vec<u8> {
+    const FILE_SIZE: u32 = 0x78 + 4 + 2 * 12;
+    let map_offset = header_offset + 0x78;
+    let mut dex = b"dex\n041\0".to_vec();
+    let fields = [
+        0, // checksum
+        0, 0, 0, 0, 0, // signature
+        FILE_SIZE, 0x78, 0x12345678, // file_size, header_size, endian_tag
+        0, 0, map_offset, // link_size, link_off, map_off
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // ids
+        0, 0, // data_size, data_off
+        container_size, header_offset,
+        // map_list
+        2,
+        0x0000, 1, header_offset,
+        0x1000, 1, map_offset,
+    ];
+    for field in &fields {
+        dex.extend_from_slice(&[
+            *field as u8,
+            (*field >> 8) as u8,
+            (*field >> 16) as u8,
+            (*field >> 24) as u8,
+        ]);
+    }
+    assert_eq!(FILE_SIZE as usize, dex.len());
+    dex
+}
+
+/// Appends the given 32-bit little endian fields to a buffer.
+fn extend_u32(buffer: &mut Vec<u8>, fields: &[u32]) {
+    for field in fields {
+        buffer.extend_from_slice(&field.to_le_bytes());
+    }
+}
+
+/// Reads the 32-bit little endian field at the given offset of a buffer.
+fn read_u32(buffer: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buffer[offset],
+        buffer[offset + 1],
+        buffer[offset + 2],
+        buffer[offset + 3],
+    ])
+}
+
+/// Reads the uleb128 at the given offset of a buffer, and moves the offset past it.
+fn read_uleb128(buffer: &[u8], offset: &mut usize) -> u32 {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = buffer[*offset];
+        *offset += 1;
+        value |= u32::from(byte & 0x7f) << shift;
+        if byte < 0x80 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+/// Builds a compact dex file with a `LFoo;` class that has a `static run()V` method, with its
+/// data section at `data_offset`.
+///
+/// The dex file and its data section are returned separately, since the data section can be
+/// shared with other dex files.
+fn compact_dex(data_offset: u32) -> (Vec<u8>, Vec<u8>) {
+    const DATA_SIZE: u32 = 0xb8;
+    let mut dex = b"cdex001\0".to_vec();
+    extend_u32(&mut dex, &[
+        0, // checksum
+        0, 0, 0, 0, 0, // signature
+        0xd0, 0x88, 0x12345678, // file_size, header_size, endian_tag
+        0, 0, 0x30, // link_size, link_off, map_off
+        3, 0x88, 2, 0x94, 1, 0x9c, 0, 0, 1, 0xa8, 1, 0xb0, // ids
+        DATA_SIZE, data_offset,
+        0, 0x28, 4, 0x1c, 0, DATA_SIZE, // compact dex fields
+        0x00, 0x07, 0x0a, // string_ids
+        0, 1, // type_ids
+        1, 1, 0, // proto_ids
+        0, 2, // method_ids
+        0, 0x1, 0xffffffff, 0, 0xffffffff, 0, 0x1f, 0, // class_defs
+    ]);
+    assert_eq!(0xd0, dex.len());
+
+    let mut data = b"\x05LFoo;\0\x01V\0\x03run\0\0".to_vec();
+    // Code item, with 16 registers in the pre-header.
+    data.extend_from_slice(&[0, 0, 0x10, 0x00, 0x00, 0x10, 0x41, 0x00, 0x12, 0x10, 0x0e, 0x00]);
+    // Debug information and class data.
+    data.extend_from_slice(&[0x01, 0x00, 0x00, 0, 0, 1, 0, 0, 0x09, 0x14, 0, 0]);
+    // Debug information offsets table, and its index.
+    data.extend_from_slice(&[0x00, 0x01, 0x00, 0]);
+    extend_u32(&mut data, &[0]);
+    // Map list.
+    extend_u32(&mut data, &[
+        11,
+        0x0000, 1, 0,
+        0x0001, 3, 0x88,
+        0x0002, 2, 0x94,
+        0x0003, 1, 0x9c,
+        0x0005, 1, 0xa8,
+        0x0006, 1, 0xb0,
+        0x2002, 3, 0x00,
+        0x2001, 1, 0x14,
+        0x2003, 1, 0x1c,
+        0x2000, 1, 0x1f,
+        0x1000, 1, 0x30,
+    ]);
+    assert_eq!(DATA_SIZE as usize, data.len());
+    (dex, data)
+}
+
+/// Checks that the given dex file is the one built by `compact_dex()`.
+fn check_compact_dex(dex: &dalvik::Dex) {
+    assert!(dex.header().is_compact_dex());
+    assert_eq!(1, dex.header().get_dex_version());
+    assert_eq!(Some("run"), dex.get_string(2));
+
+    let class = dex.get_class(0).unwrap().unwrap();
+    assert_eq!(Some(&"LFoo;".parse().unwrap()), dex.get_type(class.class_index()));
+    let method = &class.class_data().unwrap().direct_methods()[0];
+    let code = method.code().unwrap();
+    assert_eq!(17, code.registers_size());
+    assert_eq!(0, code.ins_size());
+    assert_eq!(&[0x1012, 0x000e], code.insns());
+    assert_eq!(Some(0x1c), code.debug_info_offset());
+    assert_eq!(1, code.debug_info().unwrap().line_start());
+    assert!(code.verify(dex).is_empty());
+}
 
 #[test]
 fn it_header_read() {
@@ -41,20 +177,927 @@ fn it_header_read() {
     assert_eq!(0x79ff8, header.get_data_offset());
 }
 
-// #[test]
-// fn it_header_verify() {
-//     let header = dalvik::Header::from_file("test.dex").unwrap();
-//     assert!(header.verify_file("text.dex"));
-// }
+#[test]
+fn it_header_verify() {
+    let header = dalvik::Header::from_file("test.dex").unwrap();
+    header.verify_file("test.dex").unwrap();
+}
+
+#[test]
+fn it_header_verify_detects_a_wrong_checksum() {
+    let header = dalvik::Header::from_file("test.dex").unwrap();
+    let mut contents = fs::read("test.dex").unwrap();
+    *contents.last_mut().unwrap() ^= 0xff;
+
+    match *header.verify_reader(&contents[..]).unwrap_err().kind() {
+        ErrorKind::ChecksumMismatch(expected, actual) => {
+            assert_eq!(header.get_checksum(), expected);
+            assert_ne!(expected, actual);
+        }
+        ref e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn it_header_verify_detects_a_wrong_signature() {
+    let mut contents = fs::read("test.dex").unwrap();
+    *contents.last_mut().unwrap() ^= 0xff;
+    // Fix the checksum, so that only the signature is wrong.
+    let checksum = adler::adler32_slice(&contents[12..]);
+    contents[8..12].copy_from_slice(&[
+        checksum as u8,
+        (checksum >> 8) as u8,
+        (checksum >> 16) as u8,
+        (checksum >> 24) as u8,
+    ]);
+    let header = dalvik::Header::from_reader(&contents[..]).unwrap();
+
+    match *header.verify_reader(&contents[..]).unwrap_err().kind() {
+        ErrorKind::SignatureMismatch(expected, actual) => {
+            assert_eq!(header.get_signature(), &expected);
+            assert_ne!(expected, actual);
+        }
+        ref e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn it_header_verify_detects_a_truncated_file() {
+    let header = dalvik::Header::from_file("test.dex").unwrap();
+    let contents = fs::read("test.dex").unwrap();
+
+    match *header
+        .verify_reader(&contents[..contents.len() - 1])
+        .unwrap_err()
+        .kind()
+    {
+        ErrorKind::HeaderFileSizeMismatch(size, size_in_header) => {
+            assert_eq!(contents.len() as u64 - 1, size);
+            assert_eq!(contents.len() as u32, size_in_header);
+        }
+        ref e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn it_header_read_rejects_list_sizes_that_overflow() {
+    let contents = fs::read("test.dex").unwrap();
+    // Sizes of the string IDs list and of the data section.
+    for &size_offset in &[0x38, 0x68] {
+        let mut invalid = contents.clone();
+        invalid[size_offset..size_offset + 4].copy_from_slice(&[0xff, 0xff, 0xff, 0x7f]);
+        let error = dalvik::Header::from_reader(Cursor::new(&invalid)).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::Header(_)));
+    }
+}
+
+#[test]
+fn it_header_patch() {
+    let original = fs::read("test.dex").unwrap();
+    let mut contents = original.clone();
+    for byte in &mut contents[8..36] {
+        *byte = 0;
+    }
+
+    dalvik::Header::patch_buffer(&mut contents).unwrap();
+    assert_eq!(original, contents);
+
+    // Binary patch: change a byte in the data section.
+    *contents.last_mut().unwrap() ^= 0xff;
+    dalvik::Header::patch_buffer(&mut contents).unwrap();
+    let header = dalvik::Header::from_reader(&contents[..]).unwrap();
+    assert_ne!(&original[8..32], &contents[8..32]);
+    header.verify_reader(&contents[..]).unwrap();
+}
+
+#[test]
+fn it_header_patch_big_endian() {
+    let mut contents = empty_container_dex(0, 0x94);
+    // All the fields after the magic are 32-bit words.
+    for word in contents[8..].chunks_mut(4) {
+        word.reverse();
+    }
+
+    dalvik::Header::patch_buffer(&mut contents).unwrap();
+    let header = dalvik::Header::from_reader(&contents[..]).unwrap();
+    assert!(header.is_big_endian());
+    assert_eq!(&contents[8..12], &[
+        (header.get_checksum() >> 24) as u8,
+        (header.get_checksum() >> 16) as u8,
+        (header.get_checksum() >> 8) as u8,
+        header.get_checksum() as u8,
+    ]);
+    header.verify_reader(&contents[..]).unwrap();
+}
+
+#[test]
+fn it_container_patch() {
+    let mut container = empty_container_dex(0, 0);
+    container.extend(empty_container_dex(0x94, 0));
+    container.extend_from_slice(&[0; 8]);
+
+    dalvik::Header::patch_buffer(&mut container).unwrap();
+    let headers = dalvik::Header::all_from_reader(&mut Cursor::new(&container)).unwrap();
+    assert_eq!(2, headers.len());
+    assert_eq!(Some(0x94 * 2 + 8), headers[0].get_container_size());
+    assert_eq!(0x94, headers[0].get_file_size());
+    assert_eq!(0x94 + 8, headers[1].get_file_size());
+    for header in &headers {
+        header.verify_reader(&container[..]).unwrap();
+    }
+
+    match *headers[1].verify_reader(&container[..0x90]).unwrap_err().kind() {
+        ErrorKind::TruncatedBeforeHeader(size, header_offset) => {
+            assert_eq!(0x90, size);
+            assert_eq!(0x94, header_offset);
+        }
+        ref e => panic!("unexpected error: {}", e),
+    }
+}
 
 #[test]
-#[should_panic]
 fn it_file_read() {
-    dalvik::Dex::from_file("test.dex").unwrap();
+    let dex = dalvik::Dex::from_file("test.dex").unwrap();
+    assert_eq!(19939, dex.strings().len());
+    assert_eq!(2419, dex.types().len());
+    assert_eq!(3522, dex.prototypes().len());
+    assert_eq!(9942, dex.fields().len());
+    assert_eq!(19282, dex.methods().len());
+    assert_eq!(1791, dex.classes().len());
+    // DEX 035 files can't have call sites, method handles or hidden API flags.
+    assert!(dex.method_handles().is_empty());
+    assert!(dex.call_sites().is_empty());
+    assert!(dex.map().get_item(ItemType::HiddenapiClassData).is_none());
+    let class_data = dex.classes()
+        .iter()
+        .filter_map(|class| class.class_data())
+        .next()
+        .unwrap();
+    assert!(
+        class_data
+            .direct_methods()
+            .iter()
+            .all(|method| method.hiddenapi_flags().is_none())
+    );
+
+    let class = dex.get_class(0).unwrap().unwrap();
+    let class_type = dex.get_type(class.class_index()).unwrap();
+    assert_eq!(
+        class.class_index(),
+        dex.find_class(class_type).unwrap().class_index()
+    );
+}
+
+#[test]
+fn it_bytes_read() {
+    let bytes = fs::read("test.dex").unwrap();
+    let dex = dalvik::Dex::from_bytes(&bytes).unwrap();
+    let owned_dex = dalvik::Dex::from_file("test.dex").unwrap();
+    assert_eq!(owned_dex.strings(), dex.strings());
+    assert_eq!(owned_dex.types(), dex.types());
+    assert_eq!(owned_dex.classes().len(), dex.classes().len());
+    assert!(dex.strings().iter().any(|string| string.is_borrowed()));
+    assert!(owned_dex.strings().iter().all(|string| !string.is_borrowed()));
+
+    let class = dex.get_class(0).unwrap().unwrap();
+    let class_type = dex.get_type(class.class_index()).unwrap();
+    assert_eq!(
+        class.class_index(),
+        dex.find_class(class_type).unwrap().class_index()
+    );
+}
+
+#[test]
+fn it_lazy_file_read() {
+    let options = dalvik::ParseOptions {
+        lazy: true,
+        ..Default::default()
+    };
+    let dex = dalvik::Dex::from_file_with_options("test.dex", options).unwrap();
+    let eager_dex = dalvik::Dex::from_file("test.dex").unwrap();
+    assert_eq!(eager_dex.strings(), dex.strings());
+    assert!(dex.get_class(1791).unwrap().is_none());
+
+    let eager_class = eager_dex.get_class(1000).unwrap().unwrap();
+    let class_type = eager_dex.get_type(eager_class.class_index()).unwrap();
+    assert!(dex.find_class(class_type).is_none());
+    let class = dex.try_find_class(class_type).unwrap().unwrap();
+    assert_eq!(eager_class.class_index(), class.class_index());
+    assert_eq!(eager_class.access_flags(), class.access_flags());
+    assert_eq!(
+        eager_class.class_data().map(|data| data.direct_methods().len()),
+        class.class_data().map(|data| data.direct_methods().len())
+    );
+    assert!(::std::ptr::eq(class, dex.get_class(1000).unwrap().unwrap()));
+
+    assert!(dex.classes().is_empty());
+    let classes = dex.try_classes().unwrap();
+    assert_eq!(1791, classes.len());
+    assert!(::std::ptr::eq(class, classes[1000]));
+}
+
+#[test]
+fn it_eager_classes_match_lazy_classes() {
+    // With the `parallel` feature, eager classes are read in parallel, while lazy ones are read
+    // one by one.
+    let dex = dalvik::Dex::from_file("test.dex").unwrap();
+    let options = dalvik::ParseOptions {
+        lazy: true,
+        ..Default::default()
+    };
+    let lazy_dex = dalvik::Dex::from_file_with_options("test.dex", options).unwrap();
+
+    let classes = dex.classes();
+    let lazy_classes = lazy_dex.try_classes().unwrap();
+    assert_eq!(classes.len(), lazy_classes.len());
+    for (class, lazy_class) in classes.iter().zip(lazy_classes) {
+        assert_eq!(format!("{:?}", class), format!("{:?}", lazy_class));
+    }
+}
+
+#[test]
+fn it_lenient_read_records_warnings() {
+    use dalvik::warning::WarningKind;
+
+    let mut bytes = fs::read("test.dex").unwrap();
+    // There is unknown data between the class definitions and the map, that never aborts parsing.
+    let unknown_data = dalvik::Dex::from_bytes(&bytes).unwrap().warnings();
+    assert_eq!(1, unknown_data.len());
+    assert_eq!(0x67400, unknown_data[0].offset());
+    assert_eq!(&WarningKind::UnknownData { size: 76792 }, unknown_data[0].kind());
+
+    // Unknown bits in the access flags of the first class.
+    let class_defs_offset = read_u32(&bytes, 0x64) as usize;
+    let access_flags = read_u32(&bytes, class_defs_offset + 4) | 0x8000_0000;
+    bytes[class_defs_offset + 4..class_defs_offset + 8]
+        .copy_from_slice(&access_flags.to_le_bytes());
+    // Invalid MUTF-8 in the last string.
+    let string_ids_size = read_u32(&bytes, 0x38);
+    let last_string_id = read_u32(&bytes, 0x3c) as usize + 4 * (string_ids_size as usize - 1);
+    let string_offset = read_u32(&bytes, last_string_id) as usize;
+    bytes[string_offset + 1] = 0xff;
+    // Trailing data after the end of the file.
+    bytes.extend_from_slice(&[0; 4]);
+    assert!(dalvik::Dex::from_bytes(&bytes).is_err());
+
+    let options = dalvik::ParseOptions {
+        strict: false,
+        ..Default::default()
+    };
+    let dex = dalvik::Dex::from_bytes_with_options(&bytes, options).unwrap();
+    let warnings = dex.warnings();
+    assert_eq!(4, warnings.len());
+    assert_eq!(0x20, warnings[0].offset());
+    assert_eq!(
+        &WarningKind::FileSizeMismatch {
+            file_size: bytes.len() as u64,
+            size_in_header: bytes.len() as u32 - 4,
+        },
+        warnings[0].kind()
+    );
+    assert_eq!(class_defs_offset as u64, warnings[1].offset());
+    assert_eq!(
+        &WarningKind::InvalidAccessFlags {
+            item_type: ItemType::ClassDef,
+            index: 0,
+            access_flags,
+        },
+        warnings[1].kind()
+    );
+    assert_eq!(unknown_data[0], warnings[2]);
+    assert_eq!(string_offset as u64, warnings[3].offset());
+    assert!(matches!(
+        warnings[3].kind(),
+        WarningKind::InvalidString { index, .. } if *index == string_ids_size - 1
+    ));
+
+    assert_eq!(1791, dex.classes().len());
+    let class = dex.get_class(0).unwrap().unwrap();
+    assert_eq!(access_flags & 0xffff, class.access_flags().bits() & 0xffff);
+    assert!(dex.strings()[string_ids_size as usize - 1].starts_with('\u{fffd}'));
+}
+
+#[test]
+fn it_lenient_read_leaves_out_unreadable_classes() {
+    let mut bytes = fs::read("test.dex").unwrap();
+    // Class data of the first class out of the file.
+    let class_defs_offset = read_u32(&bytes, 0x64) as usize;
+    bytes[class_defs_offset + 24..class_defs_offset + 28].copy_from_slice(&[0xff; 4]);
+    assert!(dalvik::Dex::from_bytes(&bytes).is_err());
+
+    for &lazy in &[false, true] {
+        let options = dalvik::ParseOptions {
+            lazy,
+            strict: false,
+            ..Default::default()
+        };
+        let dex = dalvik::Dex::from_bytes_with_options(&bytes, options).unwrap();
+        // Lazily read classes that can't be read are reported the first time they are accessed.
+        assert_eq!(if lazy { 1 } else { 2 }, dex.warnings().len());
+        assert_eq!(1790, dex.try_classes().unwrap().len());
+        assert!(dex.get_class(0).unwrap().is_none());
+        if !lazy {
+            assert_eq!(1790, dex.classes().len());
+            assert_eq!(
+                dex.classes()[0].class_index(),
+                dex.get_class(1).unwrap().unwrap().class_index()
+            );
+        }
+        let warnings = dex.warnings();
+        assert_eq!(2, warnings.len());
+        assert_eq!(class_defs_offset as u64, warnings[0].offset());
+    }
+}
+
+#[test]
+fn it_errors_have_item_locations() {
+    let bytes = fs::read("test.dex").unwrap();
+
+    let mut invalid_string = bytes.clone();
+    let string_ids_size = read_u32(&bytes, 0x38);
+    let last_string_id = read_u32(&bytes, 0x3c) as usize + 4 * (string_ids_size as usize - 1);
+    let string_offset = read_u32(&bytes, last_string_id) as usize;
+    invalid_string[string_offset + 1] = 0xff;
+    let error = dalvik::Dex::from_bytes(&invalid_string).unwrap_err();
+    let location = error.location().unwrap();
+    assert_eq!(ItemType::StringData, location.item_type());
+    assert_eq!(Some(string_ids_size - 1), location.index());
+    assert_eq!(string_offset as u64, location.offset());
+    assert!(matches!(error.root_kind(), ErrorKind::InvalidMutf8(_)));
+
+    let mut invalid_flags = bytes.clone();
+    let class_defs_offset = read_u32(&bytes, 0x64) as usize;
+    let access_flags = read_u32(&bytes, class_defs_offset + 32 + 4) | 0x8000_0000;
+    invalid_flags[class_defs_offset + 36..class_defs_offset + 40]
+        .copy_from_slice(&access_flags.to_le_bytes());
+    let error = dalvik::Dex::from_bytes(&invalid_flags).unwrap_err();
+    let locations = error.locations();
+    assert_eq!(1, locations.len());
+    assert_eq!(ItemType::ClassDef, locations[0].item_type());
+    assert_eq!(Some(1), locations[0].index());
+    assert_eq!(class_defs_offset as u64 + 32, locations[0].offset());
+    assert!(matches!(
+        error.root_kind(),
+        ErrorKind::InvalidAccessFlags(flags) if *flags == access_flags
+    ));
+}
+
+#[test]
+fn it_map_read() {
+    let dex = dalvik::Dex::from_file("test.dex").unwrap();
+    let header = dex.header();
+    let map = dex.map();
+    assert_eq!(Some(1), map.get_num_items_for(ItemType::Header));
+    assert_eq!(Some(0), map.get_offset_for(ItemType::Header));
+    assert_eq!(
+        Some(header.get_map_offset()),
+        map.get_offset_for(ItemType::MapList)
+    );
+    assert_eq!(
+        Some(header.get_string_ids_size() as usize),
+        map.get_num_items_for(ItemType::StringId)
+    );
+    assert_eq!(
+        Some(header.get_class_defs_size() as usize),
+        map.get_num_items_for(ItemType::ClassDef)
+    );
+    assert_eq!(
+        header.get_class_defs_offset(),
+        map.get_offset_for(ItemType::ClassDef)
+    );
+}
+
+#[test]
+fn it_method_code_read() {
+    let dex = dalvik::Dex::from_file("test.dex").unwrap();
+    let methods = dex.classes()
+        .iter()
+        .filter_map(|class| class.class_data())
+        .flat_map(|data| data.direct_methods().iter().chain(data.virtual_methods()));
+
+    let mut with_code = 0;
+    for method in methods {
+        assert!(dex.get_method(method.method_index()).is_some());
+        assert_eq!(method.code_offset().is_some(), method.code().is_some());
+        if let Some(code) = method.code() {
+            assert!(!code.insns().is_empty());
+            assert!(code.ins_size() <= code.registers_size());
+            with_code += 1;
+        }
+    }
+    assert!(with_code > 0);
+}
+
+#[test]
+fn it_method_code_verify() {
+    let dex = dalvik::Dex::from_file("test.dex").unwrap();
+    let codes = dex.classes()
+        .iter()
+        .filter_map(|class| class.class_data())
+        .flat_map(|data| data.direct_methods().iter().chain(data.virtual_methods()))
+        .filter_map(|method| method.code());
+
+    for code in codes {
+        assert!(code.verify(&dex).is_empty());
+    }
+}
+
+#[test]
+fn it_method_code_decode() {
+    use dalvik::bytecode::{ByteCode, Payload};
+
+    let dex = dalvik::Dex::from_file("test.dex").unwrap();
+    let codes = dex.classes()
+        .iter()
+        .filter_map(|class| class.class_data())
+        .flat_map(|data| data.direct_methods().iter().chain(data.virtual_methods()))
+        .filter_map(|method| method.code());
+
+    let mut switches = 0;
+    for code in codes {
+        let mut decoder = code.decode();
+        let instructions = decoder
+            .by_ref()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(code.insns().len(), decoder.address() as usize);
+
+        for &(address, ref instruction) in &instructions {
+            let offset = match *instruction {
+                ByteCode::PackedSwitch(_, offset) | ByteCode::SparseSwitch(_, offset) => offset,
+                _ => continue,
+            };
+            let target = (i64::from(address) + i64::from(offset)) as u32;
+            let payload = instructions
+                .iter()
+                .find(|&&(payload_address, _)| payload_address == target);
+            match payload {
+                Some(&(_, ByteCode::Payload(Payload::PackedSwitch(ref switch))))
+                | Some(&(_, ByteCode::Payload(Payload::SparseSwitch(ref switch)))) => {
+                    assert!(!switch.keys().is_empty());
+                    assert_eq!(switch.keys().len(), switch.offsets().len());
+                }
+                _ => panic!("no switch payload at address {}", target),
+            }
+            switches += 1;
+        }
+    }
+    assert!(switches > 0);
+}
+
+#[test]
+fn it_method_positions_read() {
+    let dex = dalvik::Dex::from_file("test.dex").unwrap();
+    let debug_info = dex.classes()
+        .iter()
+        .filter_map(|class| class.class_data())
+        .flat_map(|data| data.direct_methods().iter().chain(data.virtual_methods()))
+        .filter_map(|method| method.code())
+        .filter_map(|code| code.debug_info())
+        .find(|debug_info| !debug_info.positions().positions().is_empty())
+        .unwrap();
+
+    let table = debug_info.positions();
+    let first = table.positions()[0];
+    assert_eq!(Some(first.line()), table.line_for_address(first.address()));
+    assert!(table.addresses_for_line(first.line()).contains(&first.address()));
+}
+
+#[test]
+fn it_method_local_variables_read() {
+    use dalvik::types::AccessFlags;
+
+    let dex = dalvik::Dex::from_file("test.dex").unwrap();
+    let (method, code, debug_info) = dex.classes()
+        .iter()
+        .filter_map(|class| class.class_data())
+        .flat_map(|data| data.virtual_methods())
+        .filter_map(|method| {
+            method
+                .code()
+                .and_then(|code| code.debug_info().map(|debug_info| (method, code, debug_info)))
+        })
+        .find(|&(_, code, debug_info)| {
+            !debug_info.parameter_names().is_empty() && code.ins_size() > 1
+        })
+        .unwrap();
+    assert!(!method.access_flags().contains(AccessFlags::ACC_STATIC));
+
+    let parameter_names = debug_info.resolve_parameter_names(&dex).unwrap();
+    assert_eq!(debug_info.parameter_names().len(), parameter_names.len());
+
+    let table = debug_info.local_variables(&dex, method).unwrap();
+    let this_register = u32::from(code.registers_size() - code.ins_size());
+    let this = table.local_at(this_register, 0).unwrap();
+    assert_eq!("this", this.name());
+    assert_eq!(0, this.start_address());
+    let method_id = dex.get_method(method.method_index()).unwrap();
+    assert_eq!(
+        dex.get_type(method_id.class_index() as u32),
+        Some(this.local_type())
+    );
+
+    if let Some(first_name) = parameter_names[0] {
+        let parameter = table.local_at(this_register + 1, 0).unwrap();
+        assert_eq!(first_name, parameter.name());
+    }
+    for local in table.locals() {
+        assert!(local.start_address() <= local.end_address());
+        assert!(local.end_address() as usize <= code.insns().len());
+    }
+
+    let all_methods = dex.classes()
+        .iter()
+        .filter_map(|class| class.class_data())
+        .flat_map(|data| data.direct_methods().iter().chain(data.virtual_methods()));
+    for method in all_methods {
+        if let Some(debug_info) = method.code().and_then(|code| code.debug_info()) {
+            assert!(debug_info.local_variables(&dex, method).is_ok());
+        }
+    }
+}
+
+#[test]
+fn it_container_read() {
+    let mut container = empty_container_dex(0, 0x94 * 2);
+    container.extend(empty_container_dex(0x94, 0x94 * 2));
+
+    let headers = dalvik::Header::all_from_reader(&mut Cursor::new(&container)).unwrap();
+    assert_eq!(2, headers.len());
+    assert_eq!(41, headers[0].get_dex_version());
+    assert_eq!(0x78, headers[0].get_header_size());
+    assert_eq!(Some(0x94 * 2), headers[1].get_container_size());
+    assert_eq!(0x94, headers[1].get_header_offset());
+
+    let dex_files = dalvik::Dex::all_from_reader(Cursor::new(&container), None).unwrap();
+    assert_eq!(2, dex_files.len());
+    assert_eq!(0x94, dex_files[1].header().get_header_offset());
+    assert_eq!(Some(0x94), dex_files[1].map().get_offset_for(ItemType::Header));
+
+    let first = dalvik::Dex::from_reader(Cursor::new(&container), None).unwrap();
+    assert_eq!(0, first.header().get_header_offset());
+}
+
+#[test]
+fn it_single_dex_is_a_container_of_one() {
+    let headers = dalvik::Header::all_from_file("test.dex").unwrap();
+    assert_eq!(1, headers.len());
+    assert!(!headers[0].is_in_container());
+    assert_eq!(0, headers[0].get_header_offset());
+}
+
+#[test]
+fn it_rejects_a_container_with_a_wrong_header_offset() {
+    let mut container = empty_container_dex(0, 0x94 * 2);
+    container.extend(empty_container_dex(0x90, 0x94 * 2));
+
+    assert!(dalvik::Header::all_from_reader(&mut Cursor::new(&container)).is_err());
+}
+
+#[test]
+fn it_file_verify() {
+    let file = dalvik::Dex::from_file("test.dex").unwrap();
+    assert!(matches!(
+        file.verify().unwrap_err().kind(),
+        ErrorKind::ContentsNotKept
+    ));
+    file.verify_file("test.dex").unwrap();
+
+    let options = dalvik::ParseOptions {
+        keep_contents: true,
+        ..Default::default()
+    };
+    let file = dalvik::Dex::from_file_with_options("test.dex", options).unwrap();
+    file.verify().unwrap();
+}
+
+#[test]
+fn it_file_verify_structure() {
+    let options = dalvik::ParseOptions {
+        keep_contents: true,
+        ..Default::default()
+    };
+    let file = dalvik::Dex::from_file_with_options("test.dex", options).unwrap();
+    assert!(file.verify_structure().unwrap().is_empty());
 }
 
-// #[test]
-// fn it_file_verify() {
-//     let file = dalvik::Dex::from_file("test.dex").unwrap();
-//     assert!(file.verify_file("test.dex"));
-// }
+#[test]
+fn it_verify_structure_reports_unsorted_types() {
+    use dalvik::verify::ViolationKind;
+
+    let mut bytes = fs::read("test.dex").unwrap();
+    let type_ids_offset = read_u32(&bytes, 0x44);
+    let first = type_ids_offset as usize;
+    for i in 0..4 {
+        bytes.swap(first + i, first + 4 + i);
+    }
+
+    let file = dalvik::Dex::from_bytes(&bytes).unwrap();
+    let violations = file.verify_structure().unwrap();
+    let type_violations = violations
+        .iter()
+        .filter(|v| v.item_type() == ItemType::TypeId)
+        .collect::<Vec<_>>();
+
+    assert_eq!(1, type_violations.len());
+    assert_eq!(1, type_violations[0].index());
+    assert_eq!(u64::from(type_ids_offset) + 4, type_violations[0].offset());
+    assert_eq!(&ViolationKind::Unsorted, type_violations[0].kind());
+}
+
+#[test]
+fn it_lenient_read_reports_invalid_member_flags() {
+    use dalvik::warning::WarningKind;
+
+    let mut bytes = fs::read("test.dex").unwrap();
+    let class_defs_offset = read_u32(&bytes, 0x64) as usize;
+    let class_defs_size = read_u32(&bytes, 0x60) as usize;
+    // The first direct method of a class, with its access flags encoded in three bytes, as the
+    // ones of constructors are.
+    let (method_id, method_offset, flags_offset) = (0..class_defs_size)
+        .filter_map(|index| {
+            let mut offset = read_u32(&bytes, class_defs_offset + index * 32 + 24) as usize;
+            if offset == 0 {
+                return None;
+            }
+            let sizes = (0..4).map(|_| read_uleb128(&bytes, &mut offset)).collect::<Vec<_>>();
+            for _ in 0..(sizes[0] + sizes[1]) * 2 {
+                let _ = read_uleb128(&bytes, &mut offset);
+            }
+            let method_offset = offset;
+            let method_id = read_uleb128(&bytes, &mut offset);
+            let flags_offset = offset;
+            let access_flags = read_uleb128(&bytes, &mut offset);
+            if sizes[2] > 0 && access_flags >= 0x4000 && offset - flags_offset == 3 {
+                Some((method_id, method_offset, flags_offset))
+            } else {
+                None
+            }
+        })
+        .next()
+        .unwrap();
+    // Unused 0x8000 bit.
+    bytes[flags_offset + 2] |= 0x02;
+    let access_flags = {
+        let mut offset = flags_offset;
+        read_uleb128(&bytes, &mut offset)
+    };
+
+    let options = dalvik::ParseOptions {
+        strict: false,
+        ..Default::default()
+    };
+    let dex = dalvik::Dex::from_bytes_with_options(&bytes, options).unwrap();
+    let warnings = dex.warnings();
+    let warning = warnings
+        .iter()
+        .find(|w| matches!(w.kind(), WarningKind::InvalidAccessFlags { .. }))
+        .unwrap();
+    assert_eq!(method_offset as u64, warning.offset());
+    assert_eq!(
+        &WarningKind::InvalidAccessFlags {
+            item_type: ItemType::MethodId,
+            index: method_id,
+            access_flags,
+        },
+        warning.kind()
+    );
+}
+
+#[test]
+fn it_verify_structure_continues_after_unreadable_items() {
+    use dalvik::verify::ViolationKind;
+
+    let mut bytes = fs::read("test.dex").unwrap();
+    let class_defs_offset = read_u32(&bytes, 0x64) as usize;
+    let class_defs_size = read_u32(&bytes, 0x60) as usize;
+    // Interfaces of the first class and class data of the second one out of the file.
+    bytes[class_defs_offset + 12..class_defs_offset + 16].copy_from_slice(&[0xf0; 4]);
+    bytes[class_defs_offset + 56..class_defs_offset + 60].copy_from_slice(&[0xf0; 4]);
+    // A virtual method with illegal flags, in the first class whose first virtual method has its
+    // access flags encoded in a single byte.
+    let (index, method_offset) = (2..class_defs_size)
+        .filter_map(|index| {
+            let mut offset = read_u32(&bytes, class_defs_offset + index * 32 + 24) as usize;
+            if offset == 0 {
+                return None;
+            }
+            let sizes = (0..4).map(|_| read_uleb128(&bytes, &mut offset)).collect::<Vec<_>>();
+            for _ in 0..(sizes[0] + sizes[1]) * 2 + sizes[2] * 3 {
+                let _ = read_uleb128(&bytes, &mut offset);
+            }
+            let method_offset = offset;
+            let _ = read_uleb128(&bytes, &mut offset);
+            if sizes[3] > 0 && bytes[offset] < 0x80 {
+                Some((index, method_offset))
+            } else {
+                None
+            }
+        })
+        .next()
+        .unwrap();
+    let mut flags_offset = method_offset;
+    let _ = read_uleb128(&bytes, &mut flags_offset);
+    bytes[flags_offset] = 0x03;
+
+    let options = dalvik::ParseOptions {
+        strict: false,
+        ..Default::default()
+    };
+    let file = dalvik::Dex::from_bytes_with_options(&bytes, options).unwrap();
+    let violations = file.verify_structure().unwrap();
+    let unreadable = violations
+        .iter()
+        .filter(|v| matches!(v.kind(), ViolationKind::Unreadable(_)))
+        .collect::<Vec<_>>();
+    assert_eq!(2, unreadable.len());
+    assert_eq!(ItemType::TypeList, unreadable[0].item_type());
+    assert_eq!(0, unreadable[0].index());
+    assert_eq!(0xf0f0_f0f0, unreadable[0].offset());
+    assert_eq!(ItemType::ClassData, unreadable[1].item_type());
+    assert_eq!(1, unreadable[1].index());
+
+    let method_violation = violations
+        .iter()
+        .find(|v| matches!(v.kind(), ViolationKind::IllegalMethodFlags { .. }))
+        .unwrap();
+    assert_eq!(index as u32, method_violation.index());
+    assert_eq!(method_offset as u64, method_violation.offset());
+}
+
+#[test]
+fn it_set_resolves_across_files() {
+    use dalvik::types::Type;
+
+    let set = dalvik::DexSet::from_files(["test.dex", "test.dex"]).unwrap();
+    assert_eq!(2, set.dexes().len());
+
+    // Classes defined in both files resolve to the first one.
+    let (class_def_index, class, method_index) = set.get_dex(1)
+        .unwrap()
+        .classes()
+        .iter()
+        .enumerate()
+        .filter_map(|(index, class)| {
+            let data = class.class_data()?;
+            let method = data.direct_methods().iter().chain(data.virtual_methods()).next()?;
+            Some((index as u32, class, method.method_index()))
+        })
+        .next()
+        .unwrap();
+    let location = set.resolve_type(1, class.class_index()).unwrap();
+    assert_eq!(0, location.dex_index());
+    assert_eq!(class_def_index, location.class_def_index());
+
+    let method = set.resolve_method(1, method_index).unwrap().unwrap();
+    assert_eq!(location, method.class());
+    assert_eq!(method_index, method.index());
+
+    let object: Type = "Ljava/lang/Object;".parse().unwrap();
+    let object_index = set.get_dex(0)
+        .unwrap()
+        .types()
+        .iter()
+        .position(|t| *t == object)
+        .unwrap() as u32;
+    assert_eq!(None, set.resolve_type(0, object_index));
+    assert_eq!(None, set.find_class(&object));
+}
+
+#[test]
+fn it_set_reports_unresolved_references() {
+    use dalvik::set::UnresolvedReference;
+
+    let set = dalvik::DexSet::from_files(["test.dex", "test.dex"]).unwrap();
+    let unresolved = set.unresolved_references().unwrap();
+
+    let first = unresolved
+        .iter()
+        .filter(|reference| match **reference {
+            UnresolvedReference::Type { dex_index, .. }
+            | UnresolvedReference::Field { dex_index, .. }
+            | UnresolvedReference::Method { dex_index, .. } => dex_index == 0,
+        })
+        .count();
+    assert!(first > 0);
+    assert_eq!(unresolved.len(), first * 2);
+}
+
+#[test]
+fn it_compact_dex_read() {
+    let (mut cdex, data) = compact_dex(0xd0);
+    cdex.extend(data);
+    dalvik::Header::patch_buffer(&mut cdex).unwrap();
+
+    let dex = dalvik::Dex::from_bytes(&cdex).unwrap();
+    check_compact_dex(&dex);
+    assert_eq!(0xd0, dex.header().get_data_base());
+    let compact_dex = dex.header().get_compact_dex_header().unwrap();
+    assert_eq!(0x1c, compact_dex.debug_info_base());
+    dex.verify().unwrap();
+    assert!(dex.verify_structure().unwrap().is_empty());
+
+    let owned = dalvik::Dex::from_reader(Cursor::new(&cdex), None).unwrap();
+    check_compact_dex(&owned);
+
+    dex.verify_reader(Cursor::new(&cdex)).unwrap();
+    cdex[0xd0 + 0x0b] = b'R';
+    assert!(owned.verify_reader(Cursor::new(&cdex)).is_err());
+}
+
+#[test]
+fn it_vdex_read_compact_dex_files() {
+    // Two compact dex files sharing their data section, in a VDEX file of Android 9.
+    let (first, data) = compact_dex(460 - 40);
+    let (second, _) = compact_dex(460 - 252);
+    let mut vdex = b"vdex019\0".to_vec();
+    extend_u32(&mut vdex, &[2, 424, data.len() as u32, 0, 0, 0xaaaa, 0xbbbb]);
+    extend_u32(&mut vdex, &[0]);
+    vdex.extend(first);
+    extend_u32(&mut vdex, &[0]);
+    vdex.extend(second);
+    assert_eq!(460, vdex.len());
+    vdex.extend(data);
+
+    let layout = dalvik::vdex::Vdex::from_bytes(&vdex).unwrap();
+    assert_eq!(19, layout.version());
+    assert_eq!(&[0xaaaa, 0xbbbb], layout.checksums());
+    assert_eq!(&[40, 252], layout.dex_offsets());
+
+    let dex_files = dalvik::Dex::all_from_vdex_bytes(&vdex).unwrap();
+    assert_eq!(2, dex_files.len());
+    for dex in &dex_files {
+        check_compact_dex(dex);
+    }
+
+    let path = std::env::temp_dir().join("dalvik-it-vdex-read-compact-dex-files.vdex");
+    fs::write(&path, &vdex).unwrap();
+    let owned = dalvik::Dex::all_from_vdex_file(&path);
+    fs::remove_file(&path).unwrap();
+    let owned = owned.unwrap();
+    assert_eq!(2, owned.len());
+    check_compact_dex(&owned[1]);
+}
+
+#[test]
+fn it_vdex_read_standard_dex_files() {
+    // A VDEX file of Android 12, with the checksum and dex file sections.
+    let dex = fs::read("test.dex").unwrap();
+    let mut vdex = b"vdex027\0".to_vec();
+    extend_u32(&mut vdex, &[4, 0, 60, 4, 1, 64, dex.len() as u32, 2, 0, 0, 3, 0, 0]);
+    extend_u32(&mut vdex, &[0x1234]);
+    vdex.extend(dex);
+
+    let options = dalvik::ParseOptions {
+        lazy: true,
+        ..Default::default()
+    };
+    let dex_files = dalvik::Dex::all_from_vdex_bytes_with_options(&vdex, options).unwrap();
+    assert_eq!(1, dex_files.len());
+    assert_eq!(1791, dex_files[0].header().get_class_defs_size());
+    dex_files[0].verify().unwrap();
+
+    // Error locations are offsets in the VDEX file.
+    let mut invalid_class = vdex.clone();
+    let class_defs_offset = 64 + read_u32(&vdex, 64 + 0x64) as usize;
+    invalid_class[class_defs_offset + 24..class_defs_offset + 28].copy_from_slice(&[0xff; 4]);
+    let dex_files =
+        dalvik::Dex::all_from_vdex_bytes_with_options(&invalid_class, options).unwrap();
+    let error = dex_files[0].get_class(0).unwrap_err();
+    assert_eq!(class_defs_offset as u64, error.locations()[0].offset());
+    let mut invalid_string = vdex.clone();
+    let last_string_id = 64 + read_u32(&vdex, 64 + 0x3c) as usize
+        + 4 * (read_u32(&vdex, 64 + 0x38) as usize - 1);
+    let string_offset = 64 + read_u32(&vdex, last_string_id) as usize;
+    invalid_string[string_offset + 1] = 0xff;
+    let error = dalvik::Dex::all_from_vdex_bytes(&invalid_string).unwrap_err();
+    assert_eq!(string_offset as u64, error.location().unwrap().offset());
+
+    // And so are warning and violation offsets.
+    let lenient = dalvik::ParseOptions {
+        strict: false,
+        ..options
+    };
+    let dex_files =
+        dalvik::Dex::all_from_vdex_bytes_with_options(&invalid_class, lenient).unwrap();
+    assert!(dex_files[0].get_class(0).unwrap().is_none());
+    assert_eq!(class_defs_offset as u64, dex_files[0].warnings()[0].offset());
+    let violation = &dex_files[0].verify_structure().unwrap()[0];
+    assert_eq!(ItemType::ClassData, violation.item_type());
+    assert_eq!(64 + 0xffff_ffff, violation.offset());
+    let dex_files =
+        dalvik::Dex::all_from_vdex_bytes_with_options(&invalid_string, lenient).unwrap();
+    let warnings = dex_files[0].warnings();
+    let warning = warnings
+        .iter()
+        .find(|w| matches!(w.kind(), dalvik::warning::WarningKind::InvalidString { .. }))
+        .unwrap();
+    assert_eq!(string_offset as u64, warning.offset());
+
+    let mut unsupported = vdex.clone();
+    unsupported[4..8].copy_from_slice(b"003\0");
+    assert!(matches!(
+        dalvik::vdex::Vdex::from_bytes(&unsupported).unwrap_err().kind(),
+        ErrorKind::UnsupportedVdexVersion(_)
+    ));
+}